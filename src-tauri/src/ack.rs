@@ -0,0 +1,44 @@
+use anyhow::{Context, Result};
+use quinn::RecvStream;
+use serde::Serialize;
+use std::time::Duration;
+
+use crate::error::HushError;
+
+/// The relay accepted the message.
+pub const ACK_ACCEPTED: u8 = 0x00;
+
+/// An ack frame is `[u8 status][message id]`; the id is whatever the relay assigned,
+/// up to the end of the stream, and may be empty.
+const MAX_ACK_LEN: usize = 1 + 64;
+
+/// What the relay answered to a send that required an acknowledgement.
+#[derive(Debug, Clone, Serialize)]
+pub struct AckStatus {
+    pub acknowledged: bool,
+    /// Raw status byte; anything but [`ACK_ACCEPTED`] is a relay-specific rejection.
+    pub status: u8,
+    pub message_id: Option<String>,
+    /// Time from finishing the send half until the ack arrived.
+    pub ack_us: u64,
+}
+
+/// Reads the relay's ack frame from `recv`. Running out of `timeout` fails with
+/// [`HushError::AckTimeout`] so callers can tell it apart from a rejection.
+pub async fn read(recv: &mut RecvStream, timeout: Duration) -> Result<AckStatus> {
+    let started = std::time::Instant::now();
+    let frame = match tokio::time::timeout(timeout, recv.read_to_end(MAX_ACK_LEN)).await {
+        Ok(frame) => frame.context("Failed to read relay ack")?,
+        Err(_) => return Err(HushError::AckTimeout.into()),
+    };
+    let ack_us = started.elapsed().as_micros() as u64;
+
+    let (&status, id) = frame.split_first().context("Relay closed the ack stream without a status")?;
+    let message_id = Some(String::from_utf8_lossy(id).into_owned()).filter(|id| !id.is_empty());
+    Ok(AckStatus {
+        acknowledged: status == ACK_ACCEPTED,
+        status,
+        message_id,
+        ack_us,
+    })
+}
@@ -0,0 +1,116 @@
+use anyhow::{Context, Result};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tauri::State;
+
+use crate::error::HushError;
+
+/// Blocked relay ids, in the app config directory next to the pin file.
+pub const BLOCKLIST_FILE: &str = "blocked_relays.json";
+
+#[derive(Debug, Default)]
+struct Blocked {
+    ids: BTreeSet<String>,
+    path: Option<PathBuf>,
+}
+
+impl Blocked {
+    fn persist(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let json = serde_json::to_vec(&self.ids)?;
+        std::fs::write(path, json)
+            .with_context(|| format!("Failed to write relay blocklist to {}", path.display()))
+    }
+}
+
+/// Relays the user never wants to connect to, by relay id (or `address:port` for relays
+/// without one, matching [`RelayInfo::pin_key`](crate::quic_transport::RelayInfo::pin_key)).
+/// Discovery leaves them out of every listing and circuit, and the transport refuses to
+/// dial them. Cloned handles share the same list.
+#[derive(Debug, Clone, Default)]
+pub struct RelayBlocklist {
+    blocked: Arc<Mutex<Blocked>>,
+}
+
+impl RelayBlocklist {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads the list saved in `dir` by an earlier run and persists later changes there.
+    pub fn load(&self, dir: &Path) -> Result<usize> {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create config directory {}", dir.display()))?;
+        let path = dir.join(BLOCKLIST_FILE);
+        let ids: BTreeSet<String> = if path.exists() {
+            let json = std::fs::read(&path)
+                .with_context(|| format!("Failed to read relay blocklist from {}", path.display()))?;
+            serde_json::from_slice(&json).context("Relay blocklist is corrupt")?
+        } else {
+            BTreeSet::new()
+        };
+
+        let mut blocked = self.blocked.lock()
+            .map_err(|_| anyhow::anyhow!("Relay blocklist poisoned"))?;
+        // Keep anything blocked before the file was loaded
+        blocked.ids.extend(ids);
+        blocked.path = Some(path);
+        Ok(blocked.ids.len())
+    }
+
+    /// Returns whether the relay was newly blocked.
+    pub fn block(&self, id: &str) -> Result<bool> {
+        let mut blocked = self.blocked.lock()
+            .map_err(|_| anyhow::anyhow!("Relay blocklist poisoned"))?;
+        let added = blocked.ids.insert(id.to_string());
+        if added {
+            blocked.persist()?;
+            tracing::info!("Blocked relay {}", id);
+        }
+        Ok(added)
+    }
+
+    /// Returns whether the relay was blocked.
+    pub fn unblock(&self, id: &str) -> Result<bool> {
+        let mut blocked = self.blocked.lock()
+            .map_err(|_| anyhow::anyhow!("Relay blocklist poisoned"))?;
+        let removed = blocked.ids.remove(id);
+        if removed {
+            blocked.persist()?;
+            tracing::info!("Unblocked relay {}", id);
+        }
+        Ok(removed)
+    }
+
+    /// A poisoned list counts every relay as blocked rather than silently allowing them.
+    pub fn contains(&self, id: &str) -> bool {
+        match self.blocked.lock() {
+            Ok(blocked) => blocked.ids.contains(id),
+            Err(_) => true,
+        }
+    }
+
+    pub fn list(&self) -> Vec<String> {
+        self.blocked.lock()
+            .map(|blocked| blocked.ids.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+#[tauri::command]
+pub async fn block_relay(id: String, blocklist: State<'_, RelayBlocklist>) -> Result<bool, HushError> {
+    blocklist.block(&id).map_err(HushError::from)
+}
+
+#[tauri::command]
+pub async fn unblock_relay(id: String, blocklist: State<'_, RelayBlocklist>) -> Result<bool, HushError> {
+    blocklist.unblock(&id).map_err(HushError::from)
+}
+
+#[tauri::command]
+pub async fn list_blocked_relays(blocklist: State<'_, RelayBlocklist>) -> Result<Vec<String>, HushError> {
+    Ok(blocklist.list())
+}
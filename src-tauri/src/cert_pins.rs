@@ -0,0 +1,422 @@
+use anyhow::{Context, Result};
+use rustls::pki_types::{CertificateDer, ServerName};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tokio::sync::oneshot;
+
+pub type Fingerprint = [u8; 32];
+
+/// Pin file read from the app config directory at startup.
+pub const PIN_FILE: &str = "relay_pins.txt";
+
+/// SHA-256 certificate pins keyed by relay id. A relay may carry several pins so that
+/// both the outgoing and incoming certificate are accepted while it rotates. Default
+/// pins apply to every relay that has none of its own.
+#[derive(Debug, Clone, Default)]
+pub struct RelayPins {
+    pins: HashMap<String, Vec<Fingerprint>>,
+    defaults: Vec<Fingerprint>,
+}
+
+impl RelayPins {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a pin for `relay_id`. Returns false if the pin was already present.
+    pub fn add(&mut self, relay_id: &str, pin: Fingerprint) -> bool {
+        let pins = self.pins.entry(relay_id.to_string()).or_default();
+        if pins.contains(&pin) {
+            return false;
+        }
+        pins.push(pin);
+        true
+    }
+
+    /// Removes a pin once rotation has completed. Returns false if it was not pinned.
+    pub fn prune(&mut self, relay_id: &str, pin: &Fingerprint) -> bool {
+        let Some(pins) = self.pins.get_mut(relay_id) else {
+            return false;
+        };
+
+        let before = pins.len();
+        pins.retain(|p| p != pin);
+        let removed = pins.len() != before;
+        if pins.is_empty() {
+            self.pins.remove(relay_id);
+        }
+        removed
+    }
+
+    pub fn get(&self, relay_id: &str) -> Vec<Fingerprint> {
+        self.pins.get(relay_id).cloned().unwrap_or_else(|| self.defaults.clone())
+    }
+
+    /// Adds the pins listed in `path`, one per line: either `<relay_id> <hex>` for one
+    /// relay or a bare `<hex>` pinned for every relay. Blank lines and `#` comments are
+    /// skipped. Nothing is added if any line is invalid; the error lists every bad line.
+    /// Returns the number of pins read.
+    pub fn load_file(&mut self, path: &Path) -> Result<usize> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read pin file {}", path.display()))?;
+        let entries = parse_pin_file(&text)
+            .with_context(|| format!("Invalid pin file {}", path.display()))?;
+
+        let count = entries.len();
+        for (relay_id, pin) in entries {
+            match relay_id {
+                Some(relay_id) => {
+                    self.add(&relay_id, pin);
+                }
+                None if !self.defaults.contains(&pin) => self.defaults.push(pin),
+                None => {}
+            }
+        }
+        Ok(count)
+    }
+}
+
+/// What a relay's certificate must match. Fingerprint pins take precedence; the public
+/// key the directory publishes covers relays discovered at runtime, whose certificate
+/// fingerprints aren't known in advance.
+#[derive(Debug, Clone, Default)]
+pub struct RelayTrust {
+    pub fingerprints: Vec<Fingerprint>,
+    /// Expected key, as raw key bytes or a full DER `SubjectPublicKeyInfo`.
+    pub public_key: Option<Vec<u8>>,
+}
+
+impl RelayTrust {
+    pub fn is_empty(&self) -> bool {
+        self.fingerprints.is_empty() && self.public_key.is_none()
+    }
+}
+
+/// Decodes a relay public key given as hex or standard base64.
+pub fn parse_public_key(encoded: &str) -> Result<Vec<u8>> {
+    use base64::Engine;
+
+    let encoded = encoded.trim();
+    if encoded.len().is_multiple_of(2) && encoded.chars().all(|c| c.is_ascii_hexdigit()) {
+        return (0..encoded.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&encoded[i..i + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .context("Invalid hex in public key");
+    }
+    base64::engine::general_purpose::STANDARD
+        .decode(encoded)
+        .with_context(|| format!("Public key is neither hex nor base64: {}", encoded))
+}
+
+/// Whether the certificate's `SubjectPublicKeyInfo` carries `expected`, compared
+/// against both the raw key (32 bytes for Ed25519, the SEC1 point for ECDSA) and the
+/// whole SPKI DER.
+pub fn spki_matches(cert: &CertificateDer<'_>, expected: &[u8]) -> Result<bool> {
+    let (_, parsed) = x509_parser::parse_x509_certificate(cert.as_ref())
+        .map_err(|e| anyhow::anyhow!("Failed to parse relay certificate: {}", e))?;
+    let spki = parsed.public_key();
+    Ok(spki.raw == expected || spki.subject_public_key.data.as_ref() == expected)
+}
+
+/// Extra check run inside certificate verification once a relay's pin has matched,
+/// e.g. an enterprise CRL or OCSP lookup. Returning an error vetoes the connection.
+/// No hook is installed by default.
+pub trait CertVerificationHook: Send + Sync + std::fmt::Debug {
+    fn verify(
+        &self,
+        server_name: &ServerName<'_>,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+    ) -> Result<()>;
+}
+
+/// What to do when connecting to a relay that has no pins configured.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum UnpinnedPolicy {
+    /// Refuse the connection.
+    #[default]
+    Reject,
+    /// Surface the served fingerprint to the frontend and pin it only if the user
+    /// confirms it within `timeout_secs`.
+    ConfirmFirstUse { timeout_secs: u64 },
+}
+
+/// Served fingerprint per relay id, with the sender that releases the waiting connect.
+type Pending = HashMap<String, (Fingerprint, oneshot::Sender<()>)>;
+
+/// Fingerprints shown to the user and waiting for `confirm_fingerprint`. Shared between
+/// the transport and the command layer so confirming never needs the transport lock.
+#[derive(Debug, Clone, Default)]
+pub struct PendingConfirmations {
+    pending: Arc<Mutex<Pending>>,
+}
+
+impl PendingConfirmations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers `fingerprint` as awaiting confirmation for `relay_id`, replacing any
+    /// earlier prompt for the same relay. The receiver resolves once it is confirmed.
+    pub fn register(&self, relay_id: &str, fingerprint: Fingerprint) -> oneshot::Receiver<()> {
+        let (tx, rx) = oneshot::channel();
+        if let Ok(mut pending) = self.pending.lock() {
+            pending.insert(relay_id.to_string(), (fingerprint, tx));
+        }
+        rx
+    }
+
+    /// Accepts the pending fingerprint for `relay_id`. Fails if nothing is pending or
+    /// the fingerprint differs from the one the relay served.
+    pub fn confirm(&self, relay_id: &str, fingerprint: &Fingerprint) -> Result<()> {
+        let mut pending = self.pending
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Confirmation registry poisoned"))?;
+
+        match pending.get(relay_id) {
+            None => anyhow::bail!("No fingerprint awaiting confirmation for relay {}", relay_id),
+            Some((served, _)) if served != fingerprint => {
+                anyhow::bail!("Fingerprint does not match the one served by relay {}", relay_id)
+            }
+            Some(_) => {}
+        }
+
+        let (_, tx) = pending.remove(relay_id).expect("entry checked above");
+        tx.send(()).map_err(|_| anyhow::anyhow!("Connection to relay {} is no longer waiting", relay_id))
+    }
+
+    pub fn cancel(&self, relay_id: &str) {
+        if let Ok(mut pending) = self.pending.lock() {
+            pending.remove(relay_id);
+        }
+    }
+}
+
+pub fn parse_fingerprint(hex: &str) -> Result<Fingerprint> {
+    let hex = hex.trim();
+    // Checked up front: slicing by byte offsets would split a multibyte character, and
+    // `from_str_radix` would take a leading sign
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        anyhow::bail!("Invalid hex in fingerprint: {}", hex);
+    }
+    if hex.len() != 64 {
+        anyhow::bail!("Fingerprint must be 64 hex characters, got {}", hex.len());
+    }
+
+    let mut out = [0u8; 32];
+    for (byte, pair) in out.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        *byte = (hex_value(pair[0]) << 4) | hex_value(pair[1]);
+    }
+    Ok(out)
+}
+
+/// Value of an ASCII hex digit; the caller has checked it is one.
+fn hex_value(digit: u8) -> u8 {
+    match digit {
+        b'0'..=b'9' => digit - b'0',
+        b'a'..=b'f' => digit - b'a' + 10,
+        _ => digit - b'A' + 10,
+    }
+}
+
+/// Parses pin file contents into `(relay_id, pin)` pairs; see [`RelayPins::load_file`].
+pub fn parse_pin_file(text: &str) -> Result<Vec<(Option<String>, Fingerprint)>> {
+    let mut entries = Vec::new();
+    let mut bad_lines = Vec::new();
+
+    for (index, raw) in text.lines().enumerate() {
+        let line = raw.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let mut fields = line.split_whitespace();
+        let parsed = match (fields.next(), fields.next(), fields.next()) {
+            (Some(hex), None, None) => parse_fingerprint(hex).map(|pin| (None, pin)),
+            (Some(relay_id), Some(hex), None) => {
+                parse_fingerprint(hex).map(|pin| (Some(relay_id.to_string()), pin))
+            }
+            _ => Err(anyhow::anyhow!("expected `<relay_id> <fingerprint>` or `<fingerprint>`")),
+        };
+        match parsed {
+            Ok(entry) => entries.push(entry),
+            Err(e) => bad_lines.push(format!("line {}: {} ({})", index + 1, line, e)),
+        }
+    }
+
+    if !bad_lines.is_empty() {
+        anyhow::bail!("Bad pin entries:\n{}", bad_lines.join("\n"));
+    }
+    Ok(entries)
+}
+
+pub fn format_fingerprint(pin: &Fingerprint) -> String {
+    pin.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::HushError;
+    use crate::quic_transport::{QuicTransport, RelayInfo};
+    use crate::inbound::cert_fingerprint;
+    use crate::test_relay::TestRelay;
+
+    /// Stands in for an enterprise revocation check that lists one certificate.
+    #[derive(Debug)]
+    struct Revoked(Fingerprint);
+
+    impl CertVerificationHook for Revoked {
+        fn verify(&self, _: &ServerName<'_>, end_entity: &CertificateDer<'_>, _: &[CertificateDer<'_>]) -> Result<()> {
+            if cert_fingerprint(end_entity) == self.0 {
+                anyhow::bail!("certificate is revoked");
+            }
+            Ok(())
+        }
+    }
+
+    fn as_rotating(relay: &TestRelay) -> RelayInfo {
+        RelayInfo { id: Some("rotating".to_string()), ..relay.relay_info().unwrap() }
+    }
+
+    #[tokio::test]
+    async fn both_pins_are_accepted_until_the_old_one_is_pruned() {
+        // The same relay id serving its outgoing and its incoming certificate
+        let (outgoing, incoming) = (TestRelay::start().unwrap(), TestRelay::start().unwrap());
+        let mut transport = QuicTransport::new();
+        assert!(transport.pins_mut().add("rotating", outgoing.fingerprint()));
+        assert!(transport.pins_mut().add("rotating", incoming.fingerprint()));
+        assert!(!transport.pins_mut().add("rotating", incoming.fingerprint()));
+
+        transport.connect(as_rotating(&outgoing)).await.unwrap();
+        transport.connect(as_rotating(&incoming)).await.unwrap();
+
+        assert!(transport.pins_mut().prune("rotating", &outgoing.fingerprint()));
+        assert!(!transport.pins_mut().prune("rotating", &outgoing.fingerprint()));
+        assert_eq!(transport.pins().get("rotating"), [incoming.fingerprint()]);
+        let error = transport.connect(as_rotating(&outgoing)).await.unwrap_err();
+        assert_eq!(HushError::from(error).kind(), "cert_verification");
+        transport.connect(as_rotating(&incoming)).await.unwrap();
+
+        outgoing.stop();
+        incoming.stop();
+    }
+
+    #[tokio::test]
+    async fn hook_vetoes_a_certificate_whose_pin_matches() {
+        let (revoked, trusted) = (TestRelay::start().unwrap(), TestRelay::start().unwrap());
+        let mut transport = QuicTransport::new();
+        revoked.pin(&mut transport);
+        transport.connect(revoked.relay_info().unwrap()).await.unwrap();
+        transport.disconnect();
+
+        transport.set_verification_hook(Some(Arc::new(Revoked(revoked.fingerprint()))));
+        let error = transport.connect(revoked.relay_info().unwrap()).await.unwrap_err();
+        assert!(format!("{:#}", error).contains("certificate is revoked"), "{:#}", error);
+        // A veto holds on every attempt, so it must not be retried
+        assert!(!crate::retry::is_transient(&error));
+        assert_eq!(HushError::from(error).kind(), "cert_verification");
+        assert!(transport.connection().is_none());
+
+        // Certificates the hook doesn't object to still connect
+        let trusted_info = RelayInfo { id: Some("trusted".to_string()), ..trusted.relay_info().unwrap() };
+        transport.pins_mut().add("trusted", trusted.fingerprint());
+        transport.connect(trusted_info).await.unwrap();
+
+        transport.set_verification_hook(None);
+        transport.connect(revoked.relay_info().unwrap()).await.unwrap();
+        revoked.stop();
+        trusted.stop();
+    }
+
+    fn pin_file(contents: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("hush-pins-{}.txt", uuid::Uuid::new_v4()));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn pin_file_loads_valid_hex_and_names_bad_lines() {
+        let relay_pin = "ab".repeat(32);
+        let default_pin = "0123456789ABCDEF".repeat(4);
+        let valid = pin_file(&format!(
+            "# relay pins\n\nrelay1 {}  # rotated 2026-09\n{}\n",
+            relay_pin, default_pin
+        ));
+        let mut pins = RelayPins::new();
+        assert_eq!(pins.load_file(&valid).unwrap(), 2);
+        assert_eq!(pins.get("relay1"), [[0xab; 32]]);
+        assert_eq!(pins.get("relay2"), [parse_fingerprint(&default_pin).unwrap()]);
+
+        let invalid = pin_file(&format!(
+            "relay1 {}\nrelay2 {}\nrelay3 {}zz\n",
+            relay_pin,
+            &relay_pin[..62],
+            &relay_pin[..62]
+        ));
+        let mut pins = RelayPins::new();
+        let error = format!("{:#}", pins.load_file(&invalid).unwrap_err());
+        assert!(error.contains("line 2: relay2") && error.contains("64 hex characters, got 62"), "{}", error);
+        assert!(error.contains("line 3: relay3") && error.contains("Invalid hex"), "{}", error);
+        assert!(!error.contains("line 1"), "{}", error);
+        // Nothing from a file with bad lines is pinned, not even its good lines
+        assert!(pins.get("relay1").is_empty());
+
+        let empty = pin_file("");
+        let mut pins = RelayPins::new();
+        assert_eq!(pins.load_file(&empty).unwrap(), 0);
+        assert!(pins.get("relay1").is_empty());
+
+        for path in [valid, invalid, empty] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn public_key_pins_a_relay_without_a_fingerprint() {
+        use base64::Engine;
+
+        let (keyed, other) = (TestRelay::start().unwrap(), TestRelay::start().unwrap());
+        let mut transport = QuicTransport::new();
+        let hex: String = keyed.public_key().iter().map(|b| format!("{:02x}", b)).collect();
+        let base64 = base64::engine::general_purpose::STANDARD.encode(keyed.public_key());
+        assert_eq!(parse_public_key(&hex).unwrap(), keyed.public_key());
+        assert_eq!(parse_public_key(&base64).unwrap(), keyed.public_key());
+        assert!(parse_public_key("not a key!").is_err());
+
+        let keyed_as = |relay: &TestRelay, key: &str| RelayInfo {
+            id: Some("keyed".to_string()),
+            public_key: Some(key.to_string()),
+            ..relay.relay_info().unwrap()
+        };
+        assert!(transport.pins().get("keyed").is_empty());
+        transport.connect(keyed_as(&keyed, &hex)).await.unwrap();
+        transport.connect(keyed_as(&keyed, &base64)).await.unwrap();
+        // The key names the relay: another certificate is refused under it
+        assert!(transport.connect(keyed_as(&other, &hex)).await.is_err());
+
+        keyed.stop();
+        other.stop();
+    }
+
+    #[test]
+    fn fingerprints_must_be_plain_ascii_hex() {
+        let valid = "0123456789abcdefABCDEF".repeat(3)[..64].to_string();
+        assert_eq!(format_fingerprint(&parse_fingerprint(&valid).unwrap()), valid.to_lowercase());
+
+        for invalid in [
+            format!("€{}", "a".repeat(61)),
+            format!("{}é", "a".repeat(62)),
+            "+f".repeat(32),
+            "-f".repeat(32),
+            "a".repeat(63),
+            "g".repeat(64),
+        ] {
+            assert!(parse_fingerprint(&invalid).is_err(), "accepted {:?}", invalid);
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// First byte of every chunk frame: `[magic][16 bytes msg_id][u16 index][u16 count][data]`.
+pub const CHUNK_MAGIC: u8 = 0xC7;
+pub const CHUNK_HEADER_LEN: usize = 1 + 16 + 2 + 2;
+
+pub type MessageId = [u8; 16];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkHeader {
+    pub msg_id: MessageId,
+    pub index: u16,
+    pub count: u16,
+}
+
+impl ChunkHeader {
+    pub fn encode(&self, data: &[u8]) -> Vec<u8> {
+        let mut frame = Vec::with_capacity(CHUNK_HEADER_LEN + data.len());
+        frame.push(CHUNK_MAGIC);
+        frame.extend_from_slice(&self.msg_id);
+        frame.extend_from_slice(&self.index.to_be_bytes());
+        frame.extend_from_slice(&self.count.to_be_bytes());
+        frame.extend_from_slice(data);
+        frame
+    }
+
+    pub fn decode(frame: &[u8]) -> Result<(Self, &[u8])> {
+        if !is_chunk(frame) {
+            anyhow::bail!("Not a chunk frame");
+        }
+
+        let mut msg_id = [0u8; 16];
+        msg_id.copy_from_slice(&frame[1..17]);
+        let index = u16::from_be_bytes([frame[17], frame[18]]);
+        let count = u16::from_be_bytes([frame[19], frame[20]]);
+        if count == 0 || index >= count {
+            anyhow::bail!("Invalid chunk index {} of {}", index, count);
+        }
+
+        Ok((Self { msg_id, index, count }, &frame[CHUNK_HEADER_LEN..]))
+    }
+}
+
+pub fn is_chunk(frame: &[u8]) -> bool {
+    frame.len() >= CHUNK_HEADER_LEN && frame[0] == CHUNK_MAGIC
+}
+
+/// Splits `payload` into ordered chunk frames of at most `max_frame` bytes each,
+/// header included, all tagged with `msg_id`.
+pub fn split(payload: &[u8], max_frame: usize, msg_id: MessageId) -> Result<Vec<Vec<u8>>> {
+    if max_frame <= CHUNK_HEADER_LEN {
+        anyhow::bail!("Chunk frames must be larger than the {} byte header", CHUNK_HEADER_LEN);
+    }
+    let per_chunk = max_frame - CHUNK_HEADER_LEN;
+    let count = payload.len().div_ceil(per_chunk).max(1);
+    let count = u16::try_from(count)
+        .map_err(|_| anyhow::anyhow!("Payload of {} bytes needs more than {} chunks", payload.len(), u16::MAX))?;
+
+    let frames = payload.chunks(per_chunk)
+        .enumerate()
+        .map(|(index, data)| ChunkHeader { msg_id, index: index as u16, count }.encode(data))
+        .collect::<Vec<_>>();
+    if frames.is_empty() {
+        return Ok(vec![ChunkHeader { msg_id, index: 0, count }.encode(&[])]);
+    }
+    Ok(frames)
+}
+
+/// Rebuilds a message from the complete set of its chunk frames, in any order.
+pub fn reassemble(frames: &[Vec<u8>]) -> Result<Vec<u8>> {
+    let mut reassembler = Reassembler::new(1, Duration::MAX);
+    for frame in frames {
+        if let Some(payload) = reassembler.accept(frame)? {
+            return Ok(payload);
+        }
+    }
+    anyhow::bail!("Chunk set is incomplete")
+}
+
+struct Partial {
+    chunks: Vec<Option<Vec<u8>>>,
+    received: usize,
+    first_seen: Instant,
+}
+
+/// Receive-side buffer that holds out-of-order chunks until every index of a message
+/// has arrived. Bounded by the number of incomplete messages; sets that don't complete
+/// within `timeout` are discarded.
+pub struct Reassembler {
+    partial: HashMap<MessageId, Partial>,
+    max_pending: usize,
+    timeout: Duration,
+}
+
+impl Reassembler {
+    pub fn new(max_pending: usize, timeout: Duration) -> Self {
+        Self {
+            partial: HashMap::new(),
+            max_pending,
+            timeout,
+        }
+    }
+
+    /// Feeds one chunk frame. Returns the full message once its last missing chunk
+    /// arrives. Duplicate chunks are ignored.
+    pub fn accept(&mut self, frame: &[u8]) -> Result<Option<Vec<u8>>> {
+        let (header, data) = ChunkHeader::decode(frame)?;
+        self.evict_expired();
+
+        if !self.partial.contains_key(&header.msg_id) && self.partial.len() >= self.max_pending {
+            anyhow::bail!("Reassembly buffer full ({} incomplete messages)", self.max_pending);
+        }
+
+        let partial = self.partial.entry(header.msg_id).or_insert_with(|| Partial {
+            chunks: vec![None; header.count as usize],
+            received: 0,
+            first_seen: Instant::now(),
+        });
+        if partial.chunks.len() != header.count as usize {
+            anyhow::bail!("Chunk count changed mid-message");
+        }
+
+        let slot = &mut partial.chunks[header.index as usize];
+        if slot.is_none() {
+            *slot = Some(data.to_vec());
+            partial.received += 1;
+        }
+
+        if partial.received < partial.chunks.len() {
+            return Ok(None);
+        }
+
+        let partial = self.partial.remove(&header.msg_id).expect("entry present");
+        Ok(Some(partial.chunks.into_iter().flatten().flatten().collect()))
+    }
+
+    /// Drops incomplete messages older than the timeout, returning their ids.
+    pub fn evict_expired(&mut self) -> Vec<MessageId> {
+        let timeout = self.timeout;
+        let expired: Vec<MessageId> = self.partial.iter()
+            .filter(|(_, p)| p.first_seen.elapsed() >= timeout)
+            .map(|(id, _)| *id)
+            .collect();
+
+        for id in &expired {
+            self.partial.remove(id);
+        }
+        if !expired.is_empty() {
+            tracing::debug!("Discarded {} incomplete chunked messages", expired.len());
+        }
+        expired
+    }
+
+    pub fn pending(&self) -> usize {
+        self.partial.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn out_of_order_chunks_are_reassembled() {
+        let payload = message(1000);
+        let frames = split(&payload, CHUNK_HEADER_LEN + 100, [1; 16]).unwrap();
+        assert_eq!(frames.len(), 10);
+
+        let mut reassembler = Reassembler::new(4, Duration::from_secs(30));
+        let order = [7, 2, 9, 0, 2, 5, 1, 8, 3, 6];
+        for index in order {
+            assert_eq!(reassembler.accept(&frames[index]).unwrap(), None);
+        }
+        assert_eq!(reassembler.pending(), 1);
+        assert_eq!(reassembler.accept(&frames[4]).unwrap(), Some(payload));
+        assert_eq!(reassembler.pending(), 0);
+    }
+
+    #[test]
+    fn incomplete_sets_time_out_and_are_discarded() {
+        let payload = message(300);
+        let frames = split(&payload, CHUNK_HEADER_LEN + 100, [2; 16]).unwrap();
+        let mut reassembler = Reassembler::new(1, Duration::from_millis(50));
+        reassembler.accept(&frames[2]).unwrap();
+        reassembler.accept(&frames[0]).unwrap();
+
+        // The buffer is bounded while the set is still waiting
+        let other = split(&message(10), 64, [3; 16]).unwrap();
+        assert!(reassembler.accept(&other[0]).is_err());
+
+        std::thread::sleep(Duration::from_millis(80));
+        assert_eq!(reassembler.evict_expired(), vec![[2; 16]]);
+        assert_eq!(reassembler.pending(), 0);
+
+        // The straggler starts a new set instead of completing the discarded one
+        assert_eq!(reassembler.accept(&frames[1]).unwrap(), None);
+        assert_eq!(reassembler.pending(), 1);
+    }
+
+    #[test]
+    fn multi_chunk_message_round_trips() {
+        let payload = message(2500);
+        let mut frames = split(&payload, 512, [4; 16]).unwrap();
+        assert_eq!(frames.len(), 2500usize.div_ceil(512 - CHUNK_HEADER_LEN));
+        assert!(frames.iter().all(|frame| frame.len() <= 512 && is_chunk(frame)));
+        for (index, frame) in frames.iter().enumerate() {
+            let (header, _) = ChunkHeader::decode(frame).unwrap();
+            assert_eq!((header.msg_id, header.index, header.count), ([4; 16], index as u16, frames.len() as u16));
+        }
+
+        frames.reverse();
+        assert_eq!(reassemble(&frames).unwrap(), payload);
+        assert!(reassemble(&frames[1..]).is_err());
+        assert_eq!(reassemble(&split(&[], 512, [5; 16]).unwrap()).unwrap(), Vec::<u8>::new());
+    }
+}
@@ -0,0 +1,548 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager, State};
+use tokio::sync::RwLock;
+use tokio_util::sync::CancellationToken;
+
+use crate::error::HushError;
+use crate::keepalive;
+use crate::quic_transport::QuicTransport;
+use crate::relay_client::{RelayCircuit, RelayDiscovery, SavedCircuit};
+use crate::send_queue::unix_now;
+use crate::shared_state::SharedState;
+
+pub const DEFAULT_CIRCUIT_HOPS: usize = 3;
+
+/// Circuits saved by name, in the app config directory.
+pub const SAVED_CIRCUITS_FILE: &str = "circuits.json";
+
+/// How often the watcher evaluates the rebuild triggers.
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Rebuilds are never closer together than this, whatever triggers them, so a flapping
+/// network or noisy RTT can't churn circuits.
+const MIN_REBUILD_GAP: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RebuildTrigger {
+    Initial,
+    Failure,
+    Timer,
+    NetworkChange,
+    QualityDrop,
+    Manual,
+    /// A saved circuit was loaded with `load_circuit`.
+    Loaded,
+}
+
+/// When circuits are rebuilt besides after a failure, which always rebuilds. Every
+/// other trigger is off by default.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CircuitRebuildPolicy {
+    /// Rebuild after this long regardless of health, so traffic from one long-lived
+    /// circuit can't be linked together.
+    pub rebuild_interval_secs: Option<u64>,
+    /// Rebuild when the local network changes (e.g. Wi-Fi to cellular).
+    #[serde(default)]
+    pub on_network_change: bool,
+    /// Rebuild when the relay connection's smoothed RTT exceeds this.
+    pub max_rtt_ms: Option<u64>,
+    /// Rebuild when the relay connection loses more than this share of packets.
+    pub max_loss_percent: Option<f32>,
+}
+
+impl CircuitRebuildPolicy {
+    pub fn validate(&self) -> Result<()> {
+        if self.rebuild_interval_secs == Some(0) {
+            anyhow::bail!("Rebuild interval must be greater than zero");
+        }
+        if self.max_rtt_ms == Some(0) {
+            anyhow::bail!("RTT threshold must be greater than zero");
+        }
+        if let Some(loss) = self.max_loss_percent {
+            if !(0.0..=100.0).contains(&loss) || loss == 0.0 {
+                anyhow::bail!("Loss threshold must be within (0, 100] percent");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Link quality the quality trigger is evaluated against.
+#[derive(Debug, Clone, Copy)]
+pub struct LinkQuality {
+    pub rtt_ms: u64,
+    pub loss_percent: f32,
+}
+
+impl LinkQuality {
+    pub fn of(connection: &quinn::Connection) -> Self {
+        let stats = connection.stats();
+        let loss_percent = if stats.path.sent_packets == 0 {
+            0.0
+        } else {
+            stats.path.lost_packets as f32 * 100.0 / stats.path.sent_packets as f32
+        };
+        Self {
+            rtt_ms: connection.rtt().as_millis() as u64,
+            loss_percent,
+        }
+    }
+}
+
+/// Payload of the `circuit-rebuilt` event.
+#[derive(Debug, Clone, Serialize)]
+pub struct CircuitRebuilt {
+    pub hops: Vec<String>,
+    pub reason: RebuildTrigger,
+    pub built_at: u64,
+}
+
+struct CircuitSlot {
+    policy: CircuitRebuildPolicy,
+    hops: usize,
+    circuit: Option<RelayCircuit>,
+    built_at: Option<Instant>,
+    network: Option<String>,
+    watcher: Option<CancellationToken>,
+}
+
+/// The circuit outgoing traffic is routed over, rebuilt according to a
+/// [`CircuitRebuildPolicy`]. Cloned handles share the same circuit.
+#[derive(Clone)]
+pub struct CircuitManager {
+    slot: Arc<Mutex<CircuitSlot>>,
+}
+
+impl Default for CircuitManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CircuitManager {
+    pub fn new() -> Self {
+        Self {
+            slot: Arc::new(Mutex::new(CircuitSlot {
+                policy: CircuitRebuildPolicy::default(),
+                hops: DEFAULT_CIRCUIT_HOPS,
+                circuit: None,
+                built_at: None,
+                network: None,
+                watcher: None,
+            })),
+        }
+    }
+
+    fn slot(&self) -> Result<std::sync::MutexGuard<'_, CircuitSlot>> {
+        self.slot.lock().map_err(|_| anyhow::anyhow!("Circuit state poisoned"))
+    }
+
+    pub fn set_policy(&self, policy: CircuitRebuildPolicy) -> Result<()> {
+        policy.validate()?;
+        tracing::info!("Circuit rebuild policy: {:?}", policy);
+        self.slot()?.policy = policy;
+        Ok(())
+    }
+
+    pub fn policy(&self) -> CircuitRebuildPolicy {
+        self.slot().map(|s| s.policy.clone()).unwrap_or_default()
+    }
+
+    pub fn current(&self) -> Option<Vec<String>> {
+        self.slot().ok()?.circuit.as_ref().map(RelayCircuit::hop_ids)
+    }
+
+    pub fn circuit(&self) -> Option<RelayCircuit> {
+        self.slot().ok()?.circuit.clone()
+    }
+
+    /// Which enabled trigger, if any, calls for a rebuild now. `network` is the current
+    /// network key and `quality` the relay link's, when connected.
+    pub fn due(&self, network: Option<&str>, quality: Option<LinkQuality>) -> Option<RebuildTrigger> {
+        let slot = self.slot().ok()?;
+        let Some(built_at) = slot.built_at else {
+            return Some(RebuildTrigger::Initial);
+        };
+        if built_at.elapsed() < MIN_REBUILD_GAP {
+            return None;
+        }
+
+        let policy = &slot.policy;
+        if let Some(secs) = policy.rebuild_interval_secs {
+            if built_at.elapsed() >= Duration::from_secs(secs) {
+                return Some(RebuildTrigger::Timer);
+            }
+        }
+        if policy.on_network_change {
+            if let (Some(known), Some(current)) = (slot.network.as_deref(), network) {
+                if known != current {
+                    return Some(RebuildTrigger::NetworkChange);
+                }
+            }
+        }
+        if let Some(quality) = quality {
+            let slow = policy.max_rtt_ms.is_some_and(|max| quality.rtt_ms > max);
+            let lossy = policy.max_loss_percent.is_some_and(|max| quality.loss_percent > max);
+            if slow || lossy {
+                return Some(RebuildTrigger::QualityDrop);
+            }
+        }
+        None
+    }
+
+    /// Replaces the circuit with a fresh one from `discovery` and emits `circuit-rebuilt`.
+    pub fn rebuild(
+        &self,
+        discovery: &RelayDiscovery,
+        reason: RebuildTrigger,
+        network: Option<String>,
+        app: Option<&AppHandle>,
+    ) -> Result<CircuitRebuilt> {
+        let hops = self.slot()?.hops;
+        let circuit = discovery.build_circuit(hops)?;
+        self.install(circuit, reason, network, app)
+    }
+
+    /// Makes `circuit` the current one and emits `circuit-rebuilt`. The timed triggers
+    /// count from now.
+    pub fn install(
+        &self,
+        circuit: RelayCircuit,
+        reason: RebuildTrigger,
+        network: Option<String>,
+        app: Option<&AppHandle>,
+    ) -> Result<CircuitRebuilt> {
+        let mut slot = self.slot()?;
+        let event = CircuitRebuilt {
+            hops: circuit.hop_ids(),
+            reason,
+            built_at: unix_now(),
+        };
+        slot.circuit = Some(circuit);
+        slot.built_at = Some(Instant::now());
+        if network.is_some() {
+            slot.network = network;
+        }
+        drop(slot);
+
+        tracing::info!("Circuit rebuilt ({:?}): {}", reason, event.hops.join(" -> "));
+        if let Some(app) = app {
+            if let Err(e) = app.emit("circuit-rebuilt", event.clone()) {
+                tracing::debug!("Failed to emit circuit-rebuilt: {}", e);
+            }
+        }
+        Ok(event)
+    }
+
+    /// Starts the task that evaluates the triggers every few seconds. Replaces a
+    /// previously started watcher.
+    pub fn start(
+        &self,
+        app: AppHandle,
+        transport: Arc<SharedState<QuicTransport>>,
+        discovery: Arc<RwLock<RelayDiscovery>>,
+    ) {
+        let token = CancellationToken::new();
+        if let Ok(mut slot) = self.slot() {
+            if let Some(old) = slot.watcher.replace(token.clone()) {
+                old.cancel();
+            }
+        }
+        tokio::spawn(watch(self.clone(), app, transport, discovery, token));
+    }
+
+    pub fn stop(&self) {
+        if let Ok(mut slot) = self.slot() {
+            if let Some(token) = slot.watcher.take() {
+                token.cancel();
+            }
+        }
+    }
+}
+
+async fn watch(
+    manager: CircuitManager,
+    app: AppHandle,
+    transport: Arc<SharedState<QuicTransport>>,
+    discovery: Arc<RwLock<RelayDiscovery>>,
+    shutdown: CancellationToken,
+) {
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => break,
+            _ = tokio::time::sleep(CHECK_INTERVAL) => {}
+        }
+
+        let connection = match transport.read().await {
+            Ok(transport) => transport.connection(),
+            Err(_) => continue,
+        };
+        let quality = connection.as_ref().map(LinkQuality::of);
+        // The resolved peer address, so hostname relays map to a network too
+        let network = connection.as_ref()
+            .map(|c| keepalive::network_key(c.remote_address()));
+
+        let Some(reason) = manager.due(network.as_deref(), quality) else {
+            continue;
+        };
+        let discovery = discovery.read().await;
+        if let Err(e) = manager.rebuild(&discovery, reason, network, Some(&app)) {
+            tracing::debug!("Circuit rebuild ({:?}) deferred: {:#}", reason, e);
+        }
+    }
+}
+
+/// Outcome of `load_circuit`: the hops in use, and the saved hops that no longer
+/// resolve to a known relay and were left out.
+#[derive(Debug, Clone, Serialize)]
+pub struct LoadedCircuit {
+    pub hops: Vec<String>,
+    pub missing: Vec<String>,
+}
+
+fn read_saved_circuits(dir: &Path) -> Result<BTreeMap<String, SavedCircuit>> {
+    let path = dir.join(SAVED_CIRCUITS_FILE);
+    if !path.exists() {
+        return Ok(BTreeMap::new());
+    }
+    let json = std::fs::read(&path)
+        .with_context(|| format!("Failed to read saved circuits from {}", path.display()))?;
+    serde_json::from_slice(&json).context("Saved circuits file is corrupt")
+}
+
+/// Saves `circuit` under `name` in `dir`, replacing a circuit saved under the same name.
+pub fn save_circuit_to(dir: &Path, name: &str, circuit: &RelayCircuit) -> Result<()> {
+    let mut saved = read_saved_circuits(dir)?;
+    saved.insert(name.to_string(), circuit.to_saved());
+
+    std::fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create config directory {}", dir.display()))?;
+    let path = dir.join(SAVED_CIRCUITS_FILE);
+    // Write then rename so a crash never leaves every saved circuit truncated
+    let staging = path.with_extension("json.tmp");
+    std::fs::write(&staging, serde_json::to_vec_pretty(&saved)?)
+        .with_context(|| format!("Failed to write saved circuits to {}", staging.display()))?;
+    std::fs::rename(&staging, &path)
+        .with_context(|| format!("Failed to replace saved circuits at {}", path.display()))
+}
+
+/// The circuit saved under `name` in `dir`, rebuilt from the relays `discovery` knows
+/// now, together with the saved hop ids that could not be resolved.
+pub fn load_circuit_from(
+    dir: &Path,
+    name: &str,
+    discovery: &RelayDiscovery,
+) -> Result<(RelayCircuit, Vec<String>)> {
+    let saved = read_saved_circuits(dir)?;
+    let circuit = saved.get(name).ok_or_else(|| {
+        HushError::InvalidInput(format!("No circuit saved as {:?}", name))
+    })?;
+    RelayCircuit::from_saved(circuit, discovery)
+        .with_context(|| format!("Saved circuit {:?} can't be rebuilt", name))
+}
+
+fn config_dir(app: &AppHandle) -> Result<std::path::PathBuf, HushError> {
+    app.path().app_config_dir()
+        .map_err(|e| HushError::Other(format!("No config directory: {}", e)))
+}
+
+#[tauri::command]
+pub async fn set_circuit_rebuild_policy(
+    policy: CircuitRebuildPolicy,
+    manager: State<'_, CircuitManager>,
+) -> Result<(), HushError> {
+    manager.set_policy(policy).map_err(HushError::from)
+}
+
+#[tauri::command]
+pub async fn get_circuit_rebuild_policy(
+    manager: State<'_, CircuitManager>,
+) -> Result<CircuitRebuildPolicy, HushError> {
+    Ok(manager.policy())
+}
+
+#[tauri::command]
+pub async fn current_circuit(
+    manager: State<'_, CircuitManager>,
+) -> Result<Option<Vec<String>>, HushError> {
+    Ok(manager.current())
+}
+
+/// Rebuilds immediately. `failed` marks the rebuild as caused by a circuit failure the
+/// frontend observed, rather than a manual request.
+#[tauri::command]
+pub async fn rebuild_circuit(
+    failed: Option<bool>,
+    app: AppHandle,
+    discovery: State<'_, Arc<RwLock<RelayDiscovery>>>,
+    manager: State<'_, CircuitManager>,
+) -> Result<CircuitRebuilt, HushError> {
+    let reason = if failed.unwrap_or(false) {
+        RebuildTrigger::Failure
+    } else {
+        RebuildTrigger::Manual
+    };
+
+    let discovery = discovery.read().await;
+    manager
+        .rebuild(&discovery, reason, None, Some(&app))
+        .map_err(HushError::from)
+}
+
+/// Saves the current circuit's hop ids under `name`, so the route can be restored
+/// with `load_circuit` in a later session.
+#[tauri::command]
+pub async fn save_circuit(
+    name: String,
+    app: AppHandle,
+    manager: State<'_, CircuitManager>,
+) -> Result<Vec<String>, HushError> {
+    let circuit = manager.circuit()
+        .ok_or_else(|| HushError::InvalidInput("No circuit has been built yet".to_string()))?;
+    save_circuit_to(&config_dir(&app)?, &name, &circuit).map_err(HushError::from)?;
+
+    tracing::info!("Saved circuit {:?}: {}", name, circuit.hop_ids().join(" -> "));
+    Ok(circuit.hop_ids())
+}
+
+/// Makes the circuit saved under `name` the current one. Hops whose relay is no
+/// longer known or is blocked are skipped and listed in `missing`; the circuit is then
+/// shorter than when it was saved.
+#[tauri::command]
+pub async fn load_circuit(
+    name: String,
+    app: AppHandle,
+    discovery: State<'_, Arc<RwLock<RelayDiscovery>>>,
+    manager: State<'_, CircuitManager>,
+) -> Result<LoadedCircuit, HushError> {
+    let dir = config_dir(&app)?;
+    let (circuit, missing) = {
+        let discovery = discovery.read().await;
+        load_circuit_from(&dir, &name, &discovery).map_err(HushError::from)?
+    };
+    if !missing.is_empty() {
+        tracing::warn!(
+            "Saved circuit {:?} is incomplete; unknown relays skipped: {}",
+            name,
+            missing.join(", ")
+        );
+    }
+
+    let event = manager
+        .install(circuit, RebuildTrigger::Loaded, None, Some(&app))
+        .map_err(HushError::from)?;
+    Ok(LoadedCircuit { hops: event.hops, missing })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::relay_client::RelayNode;
+
+    fn discovery() -> RelayDiscovery {
+        let mut discovery = RelayDiscovery::new();
+        for (id, address) in [("a", "198.51.100.1"), ("b", "198.51.100.2"), ("c", "198.51.100.3")] {
+            discovery.add_relay(RelayNode {
+                id: id.to_string(),
+                address: address.to_string(),
+                port: 4433,
+                public_key: String::new(),
+                latency_ms: None,
+                bandwidth_mbps: None,
+                connect_timeout_ms: None,
+                network: None,
+            });
+        }
+        discovery
+    }
+
+    /// A manager whose circuit was built on `network` and is now `age` old.
+    fn built(policy: CircuitRebuildPolicy, network: &str, age: Duration) -> CircuitManager {
+        let manager = CircuitManager::new();
+        manager.set_policy(policy).unwrap();
+        let initial = manager.due(Some(network), None).unwrap();
+        assert_eq!(initial, RebuildTrigger::Initial);
+        manager.rebuild(&discovery(), initial, Some(network.to_string()), None).unwrap();
+        manager.slot().unwrap().built_at = Some(Instant::now() - age);
+        manager
+    }
+
+    const GOOD: LinkQuality = LinkQuality { rtt_ms: 40, loss_percent: 0.5 };
+
+    #[test]
+    fn each_enabled_trigger_rebuilds_and_a_disabled_one_does_not() {
+        let all = CircuitRebuildPolicy {
+            rebuild_interval_secs: Some(60),
+            on_network_change: true,
+            max_rtt_ms: Some(300),
+            max_loss_percent: Some(5.0),
+        };
+        let cases = [
+            (Duration::from_secs(61), "wifi", GOOD, RebuildTrigger::Timer),
+            (Duration::from_secs(30), "cellular", GOOD, RebuildTrigger::NetworkChange),
+            (Duration::from_secs(30), "wifi", LinkQuality { rtt_ms: 800, ..GOOD }, RebuildTrigger::QualityDrop),
+            (Duration::from_secs(30), "wifi", LinkQuality { loss_percent: 12.0, ..GOOD }, RebuildTrigger::QualityDrop),
+        ];
+        for (age, network, quality, trigger) in cases {
+            let manager = built(all.clone(), "wifi", age);
+            assert_eq!(manager.due(Some(network), Some(quality)), Some(trigger));
+            let event = manager.rebuild(&discovery(), trigger, Some(network.to_string()), None).unwrap();
+            assert_eq!((event.reason, event.hops.len()), (trigger, DEFAULT_CIRCUIT_HOPS));
+            assert_eq!(manager.current(), Some(event.hops));
+            // Rebuilt: the timer restarts and the new network is the known one
+            assert_eq!(manager.due(Some(network), Some(GOOD)), None);
+        }
+
+        let disabled = [
+            (CircuitRebuildPolicy { rebuild_interval_secs: None, ..all.clone() }, cases[0]),
+            (CircuitRebuildPolicy { on_network_change: false, ..all.clone() }, cases[1]),
+            (CircuitRebuildPolicy { max_rtt_ms: None, ..all.clone() }, cases[2]),
+            (CircuitRebuildPolicy { max_loss_percent: None, ..all.clone() }, cases[3]),
+        ];
+        for (policy, (age, network, quality, _)) in disabled {
+            let manager = built(policy, "wifi", age);
+            assert_eq!(manager.due(Some(network), Some(quality)), None);
+        }
+
+        // However bad things look, rebuilds are spaced at least MIN_REBUILD_GAP apart
+        let manager = built(all, "wifi", Duration::ZERO);
+        assert_eq!(manager.due(Some("cellular"), Some(LinkQuality { rtt_ms: 800, ..GOOD })), None);
+    }
+
+    #[test]
+    fn saved_circuits_round_trip_and_skip_relays_that_left() {
+        let dir = std::env::temp_dir().join(format!("hush-circuits-{}", uuid::Uuid::new_v4()));
+        let known = discovery();
+        let mut circuit = RelayCircuit::new(3).with_latency_budget(500);
+        for id in ["c", "a", "b"] {
+            circuit.add_hop(known.get_relay(id).unwrap().clone()).unwrap();
+        }
+        let order = circuit.hop_ids();
+        save_circuit_to(&dir, "work", &circuit).unwrap();
+        save_circuit_to(&dir, "home", &RelayCircuit::new(3)).unwrap();
+        assert!(!dir.join(SAVED_CIRCUITS_FILE).with_extension("json.tmp").exists());
+
+        let (loaded, missing) = load_circuit_from(&dir, "work", &discovery()).unwrap();
+        assert_eq!(loaded.hop_ids(), order);
+        assert!(missing.is_empty());
+        assert_eq!(loaded.to_saved().latency_budget_ms, Some(500));
+
+        // A hop that left discovery is reported, and the rest keep their order
+        let gone = order[1].clone();
+        let mut shrunk = discovery();
+        shrunk.remove_relay(&gone);
+        let (loaded, missing) = load_circuit_from(&dir, "work", &shrunk).unwrap();
+        let remaining: Vec<String> = order.iter().filter(|id| **id != gone).cloned().collect();
+        assert_eq!(loaded.hop_ids(), remaining);
+        assert_eq!(missing, [gone]);
+
+        assert!(load_circuit_from(&dir, "missing", &discovery()).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,105 @@
+use quinn::{Connection, Endpoint, VarInt};
+use serde::Serialize;
+
+/// Application error codes sent with every connection close and stream reset, so both
+/// ends can tell why a connection went away.
+///
+/// | code | name      | reason        |
+/// |------|-----------|---------------|
+/// | 0    | Normal    | `normal`      |
+/// | 1    | Error     | `error`       |
+/// | 2    | Migration | `migration`   |
+/// | 3    | Rekey     | `rekey`       |
+/// | 4    | Shutdown  | `shutdown`    |
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AppCloseCode {
+    /// Work finished, or the user disconnected.
+    Normal = 0,
+    /// The peer sent something we couldn't handle.
+    Error = 1,
+    /// Moving to another relay or socket.
+    Migration = 2,
+    /// Reconnecting under a new identity.
+    Rekey = 3,
+    /// The app or listener is shutting down.
+    Shutdown = 4,
+}
+
+impl AppCloseCode {
+    pub fn code(self) -> VarInt {
+        VarInt::from_u32(self as u32)
+    }
+
+    pub fn reason(self) -> &'static [u8] {
+        match self {
+            Self::Normal => b"normal",
+            Self::Error => b"error",
+            Self::Migration => b"migration",
+            Self::Rekey => b"rekey",
+            Self::Shutdown => b"shutdown",
+        }
+    }
+
+    pub fn close(self, connection: &Connection) {
+        connection.close(self.code(), self.reason());
+    }
+
+    pub fn close_endpoint(self, endpoint: &Endpoint) {
+        endpoint.close(self.code(), self.reason());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quic_transport::QuicTransport;
+    use crate::test_relay::TestRelay;
+    use quinn::ConnectionError;
+    use std::time::Duration;
+    use tokio::sync::mpsc;
+
+    #[tokio::test]
+    async fn each_close_path_sends_its_documented_code_and_reason() {
+        // Relay that reports how each of its connections was closed by the client
+        let (closed, mut closes) = mpsc::unbounded_channel();
+        let relay = TestRelay::serve(Duration::ZERO, move |connection: Connection| {
+            let closed = closed.clone();
+            async move {
+                let _ = closed.send(connection.closed().await);
+            }
+        })
+        .unwrap();
+        let mut transport = QuicTransport::new();
+        relay.pin(&mut transport);
+        let info = relay.relay_info().unwrap();
+        transport.connect(info.clone()).await.unwrap();
+
+        let mut expect = async |code: AppCloseCode, number: u32, reason: &str| {
+            let closed = tokio::time::timeout(Duration::from_secs(5), closes.recv()).await.unwrap().unwrap();
+            let ConnectionError::ApplicationClosed(close) = closed else {
+                panic!("expected {:?}, got {:?}", code, closed);
+            };
+            assert_eq!(close.error_code, VarInt::from_u32(number), "{:?}", code);
+            assert_eq!(close.reason.as_ref(), reason.as_bytes(), "{:?}", code);
+            assert_eq!((code.code(), code.reason()), (close.error_code, reason.as_bytes()));
+        };
+
+        transport.connect(info.clone()).await.unwrap();
+        expect(AppCloseCode::Migration, 2, "migration").await;
+        assert!(transport.rekey().await.unwrap());
+        expect(AppCloseCode::Rekey, 3, "rekey").await;
+        transport.disconnect();
+        expect(AppCloseCode::Normal, 0, "normal").await;
+        assert!(!transport.rekey().await.unwrap());
+
+        transport.connect(info.clone()).await.unwrap();
+        transport.reset_after_panic();
+        expect(AppCloseCode::Error, 1, "error").await;
+
+        transport.connect(info).await.unwrap();
+        transport.shutdown(false, Duration::from_secs(2)).await;
+        expect(AppCloseCode::Shutdown, 4, "shutdown").await;
+        relay.stop();
+    }
+}
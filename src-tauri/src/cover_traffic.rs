@@ -0,0 +1,655 @@
+use quinn::{Connection, SendStream};
+use rand::seq::SliceRandom;
+use rand::{Rng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+use tokio_util::sync::CancellationToken;
+
+use crate::quic_transport::{QuicTransport, RelayInfo};
+use crate::relay_client::{RelayDiscovery, RelayNode};
+use crate::shared_state::SharedState;
+use crate::throttle::BandwidthLimits;
+
+pub const DEFAULT_COVER_STREAMS: usize = 1;
+pub const MAX_COVER_STREAMS: usize = 16;
+
+/// Mean gap between cover cells on one stream; each gap is jittered by ±50%.
+const COVER_CELL_INTERVAL: Duration = Duration::from_secs(2);
+const COVER_CELL_SIZE: usize = 512;
+
+/// Below every message priority so real sends are always scheduled first.
+const COVER_STREAM_PRIORITY: i32 = -2;
+
+/// Mean gap between cover packets at a cover ratio of 1.0; lower ratios stretch it.
+const COVER_PACKET_INTERVAL: Duration = Duration::from_secs(1);
+/// Sampled gaps are clamped to this range so a burst of short samples can't flood the
+/// uplink and a tiny ratio still sends now and then.
+const MIN_COVER_PACKET_GAP: Duration = Duration::from_millis(100);
+const MAX_COVER_PACKET_GAP: Duration = Duration::from_secs(600);
+
+/// Size every cover packet is padded to, so it can't be told apart from a real packet
+/// of the same bucket. One of the default padding buckets.
+pub const COVER_PACKET_SIZE: usize = 1024;
+
+/// Where cover packets are addressed. Cover traffic must look like real traffic, so
+/// it always targets relays or peers that exist rather than a sink.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "relay_ids", rename_all = "snake_case")]
+pub enum CoverDestinationPolicy {
+    /// A relay picked at random from discovery for each packet.
+    #[default]
+    RandomKnownRelay,
+    /// The relay the client is currently connected to.
+    CurrentRelay,
+    /// A fixed set of decoy relay ids, one picked at random per packet.
+    DecoySet(Vec<String>),
+}
+
+impl CoverDestinationPolicy {
+    /// Resolves the destination for the next cover packet, or `None` if the policy
+    /// has nothing to target right now (e.g. not connected under `CurrentRelay`).
+    pub fn pick(&self, current: Option<&RelayInfo>, known: &[RelayNode]) -> Option<RelayInfo> {
+        let mut rng = rand::thread_rng();
+        match self {
+            Self::RandomKnownRelay => known.choose(&mut rng).map(RelayNode::to_relay_info),
+            Self::CurrentRelay => current.cloned(),
+            Self::DecoySet(ids) => {
+                let decoys: Vec<&RelayNode> = known.iter()
+                    .filter(|r| ids.contains(&r.id))
+                    .collect();
+                decoys.choose(&mut rng).map(|r| r.to_relay_info())
+            }
+        }
+    }
+}
+
+/// How far back [`CoverStats`] looks for the observed ratio.
+pub const COVER_STATS_WINDOW: Duration = Duration::from_secs(60);
+
+/// Sends remembered for the window at most; the oldest are forgotten first, which only
+/// shortens the window under very heavy traffic.
+const MAX_WINDOW_SENDS: usize = 10_000;
+
+/// Cover and real traffic counted by [`CoverStats`]. Totals cover the whole run;
+/// `window_*` fields only the last [`COVER_STATS_WINDOW`].
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CoverTrafficStats {
+    pub cover_packets: u64,
+    pub cover_bytes: u64,
+    pub window_secs: u64,
+    pub window_cover_packets: u64,
+    pub window_cover_bytes: u64,
+    pub window_real_packets: u64,
+    pub window_real_bytes: u64,
+    /// Share of packets in the window that were cover, `cover / (cover + real)`;
+    /// `None` when nothing was sent in the window.
+    pub observed_ratio: Option<f32>,
+    /// Ratio cover traffic is configured with, for comparison.
+    pub configured_ratio: f32,
+}
+
+#[derive(Debug, Default)]
+struct StatsWindow {
+    cover_packets: u64,
+    cover_bytes: u64,
+    /// Time, size and whether it was cover, oldest first.
+    sends: VecDeque<(Instant, usize, bool)>,
+}
+
+/// Counts cover packets and cells against real sends, so the configured ratio can be
+/// checked against what actually goes on the wire. Cover streams count one packet
+/// per cell. Cloned handles share the counters.
+#[derive(Debug, Clone, Default)]
+pub struct CoverStats {
+    window: Arc<Mutex<StatsWindow>>,
+}
+
+impl CoverStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record_cover(&self, bytes: usize) {
+        self.record(bytes, true);
+    }
+
+    pub fn record_real(&self, bytes: usize) {
+        self.record(bytes, false);
+    }
+
+    fn record(&self, bytes: usize, cover: bool) {
+        let Ok(mut window) = self.window.lock() else {
+            return;
+        };
+        if cover {
+            window.cover_packets += 1;
+            window.cover_bytes += bytes as u64;
+        }
+        let now = Instant::now();
+        while window.sends.len() >= MAX_WINDOW_SENDS
+            || window.sends.front().is_some_and(|(at, _, _)| now.duration_since(*at) > COVER_STATS_WINDOW)
+        {
+            window.sends.pop_front();
+        }
+        window.sends.push_back((now, bytes, cover));
+    }
+
+    pub fn snapshot(&self, configured_ratio: f32) -> CoverTrafficStats {
+        let mut stats = CoverTrafficStats {
+            window_secs: COVER_STATS_WINDOW.as_secs(),
+            configured_ratio,
+            ..Default::default()
+        };
+        let Ok(window) = self.window.lock() else {
+            return stats;
+        };
+        stats.cover_packets = window.cover_packets;
+        stats.cover_bytes = window.cover_bytes;
+
+        let now = Instant::now();
+        for (at, bytes, cover) in &window.sends {
+            if now.duration_since(*at) > COVER_STATS_WINDOW {
+                continue;
+            }
+            if *cover {
+                stats.window_cover_packets += 1;
+                stats.window_cover_bytes += *bytes as u64;
+            } else {
+                stats.window_real_packets += 1;
+                stats.window_real_bytes += *bytes as u64;
+            }
+        }
+        let total = stats.window_cover_packets + stats.window_real_packets;
+        if total > 0 {
+            stats.observed_ratio = Some(stats.window_cover_packets as f32 / total as f32);
+        }
+        stats
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CoverStreamStatus {
+    pub configured: usize,
+    pub active: usize,
+}
+
+/// Concurrent long-lived streams carrying cover cells over the current relay
+/// connection. Several streams mimic multiplexed real usage better than one, which
+/// an observer could isolate.
+pub struct CoverStreams {
+    configured: usize,
+    transport: Option<Arc<SharedState<QuicTransport>>>,
+    running: Option<CancellationToken>,
+    active: Arc<AtomicUsize>,
+}
+
+impl Default for CoverStreams {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CoverStreams {
+    pub fn new() -> Self {
+        Self {
+            configured: DEFAULT_COVER_STREAMS,
+            transport: None,
+            running: None,
+            active: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    pub fn attach_transport(&mut self, transport: Arc<SharedState<QuicTransport>>) {
+        self.transport = Some(transport);
+    }
+
+    /// Changes the stream count, restarting the streams if they are running.
+    pub fn set_count(&mut self, count: usize) -> anyhow::Result<()> {
+        if count == 0 || count > MAX_COVER_STREAMS {
+            anyhow::bail!("Cover stream count must be between 1 and {}", MAX_COVER_STREAMS);
+        }
+
+        self.configured = count;
+        if self.running.is_some() {
+            self.start();
+        }
+        Ok(())
+    }
+
+    pub fn start(&mut self) {
+        self.stop();
+        let Some(transport) = self.transport.clone() else {
+            tracing::warn!("Cover streams not started: no transport attached");
+            return;
+        };
+
+        let token = CancellationToken::new();
+        for _ in 0..self.configured {
+            tokio::spawn(run_stream(transport.clone(), token.clone(), self.active.clone()));
+        }
+        self.running = Some(token);
+        tracing::info!("Started {} cover streams", self.configured);
+    }
+
+    pub fn stop(&mut self) {
+        if let Some(token) = self.running.take() {
+            token.cancel();
+        }
+    }
+
+    pub fn status(&self) -> CoverStreamStatus {
+        CoverStreamStatus {
+            configured: self.configured,
+            active: self.active.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Decrements the active count however the stream task ends.
+struct ActiveGuard(Arc<AtomicUsize>);
+
+impl Drop for ActiveGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Writes a random cell at jittered intervals on one stream, reopening it whenever the
+/// transport switches to a new connection.
+async fn run_stream(
+    transport: Arc<SharedState<QuicTransport>>,
+    shutdown: CancellationToken,
+    active: Arc<AtomicUsize>,
+) {
+    active.fetch_add(1, Ordering::Relaxed);
+    let _guard = ActiveGuard(active);
+    let mut stream: Option<(usize, SendStream)> = None;
+
+    loop {
+        let jitter = rand::thread_rng().gen_range(0.5..1.5);
+        tokio::select! {
+            _ = shutdown.cancelled() => break,
+            _ = tokio::time::sleep(COVER_CELL_INTERVAL.mul_f64(jitter)) => {}
+        }
+
+        let (connection, throttle, stats) = match transport.read().await {
+            Ok(transport) => (transport.connection(), relay_throttle(&transport, None), transport.cover_stats()),
+            Err(_) => (None, None, CoverStats::new()),
+        };
+        let Some(connection) = connection else {
+            stream = None;
+            continue;
+        };
+        if stream.as_ref().map(|(id, _)| *id) != Some(connection.stable_id()) {
+            stream = match connection.open_uni().await {
+                Ok(s) => {
+                    s.set_priority(COVER_STREAM_PRIORITY).ok();
+                    Some((connection.stable_id(), s))
+                }
+                Err(e) => {
+                    tracing::debug!("Failed to open cover stream: {}", e);
+                    continue;
+                }
+            };
+        }
+
+        let mut cell = vec![0u8; COVER_CELL_SIZE];
+        rand::thread_rng().fill_bytes(&mut cell);
+        if let Some((limits, relay_id)) = &throttle {
+            limits.acquire(relay_id, cell.len()).await;
+        }
+        if let Some((_, send)) = stream.as_mut() {
+            match send.write_all(&cell).await {
+                Ok(()) => stats.record_cover(cell.len()),
+                Err(e) => {
+                    tracing::debug!("Cover stream write failed: {}", e);
+                    stream = None;
+                }
+            }
+        }
+    }
+
+    if let Some((_, mut send)) = stream {
+        send.finish().ok();
+    }
+}
+
+/// Sends whole dummy packets, each on its own stream like a real message, with
+/// exponentially distributed gaps (a Poisson process) whose mean follows the cover
+/// ratio. Each packet goes to the relay the destination policy picks, joining the
+/// connection pool if it isn't connected yet. Stops by itself when the default relay
+/// connection it started on closes.
+pub struct CoverScheduler {
+    transport: Option<Arc<SharedState<QuicTransport>>>,
+    discovery: Option<Arc<RwLock<RelayDiscovery>>>,
+    destination: Arc<Mutex<CoverDestinationPolicy>>,
+    running: Option<CancellationToken>,
+}
+
+impl Default for CoverScheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CoverScheduler {
+    pub fn new() -> Self {
+        Self {
+            transport: None,
+            discovery: None,
+            destination: Arc::default(),
+            running: None,
+        }
+    }
+
+    pub fn attach_transport(&mut self, transport: Arc<SharedState<QuicTransport>>) {
+        self.transport = Some(transport);
+    }
+
+    /// Relays the random and decoy destination policies choose from. Without it only
+    /// the current relay can be targeted.
+    pub fn attach_discovery(&mut self, discovery: Arc<RwLock<RelayDiscovery>>) {
+        self.discovery = Some(discovery);
+    }
+
+    /// Applies from the next cover packet on, without a restart.
+    pub fn set_destination(&self, policy: CoverDestinationPolicy) {
+        if let Ok(mut destination) = self.destination.lock() {
+            *destination = policy;
+        }
+    }
+
+    /// (Re)starts the scheduler at `ratio`; a ratio of zero just stops it.
+    pub fn start(&mut self, ratio: f32) {
+        self.stop();
+        if ratio <= 0.0 {
+            return;
+        }
+        let Some(transport) = self.transport.clone() else {
+            tracing::warn!("Cover scheduler not started: no transport attached");
+            return;
+        };
+
+        let mean_gap_secs = COVER_PACKET_INTERVAL.as_secs_f64() / f64::from(ratio);
+        let token = CancellationToken::new();
+        let targets = CoverTargets {
+            transport,
+            discovery: self.discovery.clone(),
+            destination: self.destination.clone(),
+        };
+        tokio::spawn(run_scheduler(targets, mean_gap_secs, token.clone()));
+        self.running = Some(token);
+        tracing::info!("Cover scheduler started, mean gap {:.2}s", mean_gap_secs);
+    }
+
+    pub fn stop(&mut self) {
+        if let Some(token) = self.running.take() {
+            token.cancel();
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.as_ref().is_some_and(|token| !token.is_cancelled())
+    }
+}
+
+/// What the scheduler needs to resolve and reach each packet's destination.
+struct CoverTargets {
+    transport: Arc<SharedState<QuicTransport>>,
+    discovery: Option<Arc<RwLock<RelayDiscovery>>>,
+    destination: Arc<Mutex<CoverDestinationPolicy>>,
+}
+
+impl CoverTargets {
+    /// Picks the next destination under the policy and sends one cover packet to it.
+    /// Returns the relay id it went to, or `None` if the policy has nothing to target.
+    async fn send_next(&self) -> anyhow::Result<Option<String>> {
+        use anyhow::Context;
+
+        let policy = self.destination.lock()
+            .map_err(|_| anyhow::anyhow!("Cover destination lock poisoned"))?
+            .clone();
+        let known = match &self.discovery {
+            Some(discovery) => discovery.read().await.get_available_relays(true),
+            None => Vec::new(),
+        };
+        let current = self.transport.read().await?.connected_relay();
+        let Some(relay) = policy.pick(current.as_ref(), &known) else {
+            return Ok(None);
+        };
+
+        let relay_id = relay.pin_key();
+        let connected = self.transport.read().await?.connection_to(&relay_id);
+        let connection = match connected {
+            Some(connection) => connection,
+            None => {
+                let mut transport = self.transport.write().await?;
+                transport.connect_pooled(relay).await?;
+                transport.connection_to(&relay_id)
+                    .context("Cover destination left the pool while connecting")?
+            }
+        };
+
+        let throttle = relay_throttle(&*self.transport.read().await?, Some(&relay_id));
+        if let Some((limits, key)) = &throttle {
+            limits.acquire(key, COVER_PACKET_SIZE).await;
+        }
+        send_cover_packet(&connection).await?;
+        Ok(Some(relay_id))
+    }
+}
+
+async fn run_scheduler(targets: CoverTargets, mean_gap_secs: f64, shutdown: CancellationToken) {
+    let (connection, stats) = match targets.transport.read().await {
+        Ok(transport) => (transport.connection(), transport.cover_stats()),
+        Err(_) => (None, CoverStats::new()),
+    };
+    let Some(connection) = connection else {
+        tracing::info!("Cover scheduler stopped: not connected");
+        shutdown.cancel();
+        return;
+    };
+
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => break,
+            reason = connection.closed() => {
+                tracing::info!("Cover scheduler stopped: {}", reason);
+                break;
+            }
+            _ = tokio::time::sleep(poisson_gap(mean_gap_secs)) => {}
+        }
+
+        match targets.send_next().await {
+            Ok(Some(relay_id)) => {
+                stats.record_cover(COVER_PACKET_SIZE);
+                tracing::trace!("Cover packet sent to relay {}", relay_id);
+            }
+            Ok(None) => tracing::debug!("Cover packet skipped: no destination under the policy"),
+            Err(e) => tracing::debug!("Cover packet send failed: {:#}", e),
+        }
+    }
+    shutdown.cancel();
+}
+
+/// Bandwidth limits and the id of `relay_id`, or of the default relay, so cover
+/// traffic draws from the same budget as real sends to it.
+fn relay_throttle(transport: &QuicTransport, relay_id: Option<&str>) -> Option<(BandwidthLimits, String)> {
+    transport.throttle_key(relay_id).map(|key| (transport.bandwidth(), key))
+}
+
+/// Exponentially distributed gap with the given mean, clamped to the allowed range.
+fn poisson_gap(mean_gap_secs: f64) -> Duration {
+    let u: f64 = rand::thread_rng().gen_range(f64::EPSILON..1.0);
+    let secs = (-u.ln() * mean_gap_secs)
+        .clamp(MIN_COVER_PACKET_GAP.as_secs_f64(), MAX_COVER_PACKET_GAP.as_secs_f64());
+    Duration::from_secs_f64(secs)
+}
+
+/// A packet in the `[u32 len][payload][ikm]` layout of a real one, random throughout
+/// and padded to [`COVER_PACKET_SIZE`].
+fn cover_packet() -> Vec<u8> {
+    let mut rng = rand::thread_rng();
+    let mut packet = vec![0u8; COVER_PACKET_SIZE];
+    rng.fill_bytes(&mut packet[4..]);
+    let payload_len = rng.gen_range(0..=COVER_PACKET_SIZE - 4) as u32;
+    packet[..4].copy_from_slice(&payload_len.to_be_bytes());
+    packet
+}
+
+async fn send_cover_packet(connection: &Connection) -> anyhow::Result<()> {
+    use anyhow::Context;
+
+    let mut send = connection.open_uni().await.context("Failed to open cover stream")?;
+    send.set_priority(COVER_STREAM_PRIORITY).ok();
+    send.write_all(&cover_packet()).await.context("Failed to write cover packet")?;
+    send.finish().context("Failed to finish cover stream")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_relay::TestRelay;
+    use std::sync::atomic::AtomicUsize;
+
+    /// Relay that counts the unidirectional streams it is sent.
+    fn counting_relay(streams: Arc<AtomicUsize>) -> TestRelay {
+        TestRelay::serve(Duration::ZERO, move |connection: Connection| {
+            let streams = streams.clone();
+            async move {
+                while let Ok(mut recv) = connection.accept_uni().await {
+                    if recv.read_to_end(COVER_PACKET_SIZE * 2).await.is_ok() {
+                        streams.fetch_add(1, Ordering::SeqCst);
+                    }
+                }
+            }
+        })
+        .unwrap()
+    }
+
+    fn node(id: &str, relay: &TestRelay) -> RelayNode {
+        let addr = relay.local_addr().unwrap();
+        RelayNode {
+            id: id.to_string(),
+            address: addr.ip().to_string(),
+            port: addr.port(),
+            public_key: String::new(),
+            latency_ms: None,
+            bandwidth_mbps: None,
+            connect_timeout_ms: None,
+            network: None,
+        }
+    }
+
+    async fn wait_for(streams: &AtomicUsize, count: usize) {
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while streams.load(Ordering::SeqCst) < count {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .expect("cover packets to arrive");
+    }
+
+    #[tokio::test]
+    async fn scheduler_sends_to_the_destination_the_policy_picks() {
+        let (current_streams, decoy_streams) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+        let current = counting_relay(current_streams.clone());
+        let decoy = counting_relay(decoy_streams.clone());
+
+        let mut transport = QuicTransport::new();
+        current.pin(&mut transport);
+        transport.pins_mut().add("decoy", decoy.fingerprint());
+        transport.connect(current.relay_info().unwrap()).await.unwrap();
+        let transport = Arc::new(SharedState::new(transport));
+
+        let mut discovery = RelayDiscovery::new();
+        discovery.add_relay(node("decoy", &decoy));
+
+        let mut scheduler = CoverScheduler::new();
+        scheduler.attach_transport(transport.clone());
+        scheduler.attach_discovery(Arc::new(RwLock::new(discovery)));
+
+        scheduler.set_destination(CoverDestinationPolicy::CurrentRelay);
+        scheduler.start(10.0);
+        wait_for(&current_streams, 2).await;
+        scheduler.stop();
+        assert_eq!(decoy_streams.load(Ordering::SeqCst), 0);
+
+        scheduler.set_destination(CoverDestinationPolicy::DecoySet(vec!["decoy".to_string()]));
+        let sent_to_current = current_streams.load(Ordering::SeqCst);
+        scheduler.start(10.0);
+        wait_for(&decoy_streams, 2).await;
+        scheduler.stop();
+        // A packet already in flight when the policy changed may still land
+        assert!(current_streams.load(Ordering::SeqCst) <= sent_to_current + 1);
+        assert!(transport.read().await.unwrap().connection_to("decoy").is_some());
+
+        current.stop();
+        decoy.stop();
+    }
+
+    async fn settle(count: &AtomicUsize, expected: usize) {
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while count.load(Ordering::SeqCst) != expected {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .unwrap_or_else(|_| panic!("expected {}, still {}", expected, count.load(Ordering::SeqCst)));
+    }
+
+    #[tokio::test]
+    async fn configured_cover_streams_run_while_enabled() {
+        // Relay that counts the streams currently open towards it
+        let open = Arc::new(AtomicUsize::new(0));
+        let relay = {
+            let open = open.clone();
+            TestRelay::serve(Duration::ZERO, move |connection: Connection| {
+                let open = open.clone();
+                async move {
+                    while let Ok(mut recv) = connection.accept_uni().await {
+                        open.fetch_add(1, Ordering::SeqCst);
+                        let open = open.clone();
+                        tokio::spawn(async move {
+                            let _ = recv.read_to_end(usize::MAX).await;
+                            open.fetch_sub(1, Ordering::SeqCst);
+                        });
+                    }
+                }
+            })
+            .unwrap()
+        };
+        let mut transport = QuicTransport::new();
+        relay.pin(&mut transport);
+        transport.connect(relay.relay_info().unwrap()).await.unwrap();
+
+        let mut streams = CoverStreams::new();
+        streams.attach_transport(Arc::new(SharedState::new(transport)));
+        assert!(streams.set_count(0).is_err());
+        assert!(streams.set_count(MAX_COVER_STREAMS + 1).is_err());
+        streams.set_count(3).unwrap();
+        assert_eq!(streams.status().active, 0);
+
+        streams.start();
+        settle(&streams.active, 3).await;
+        settle(&open, 3).await;
+        assert_eq!(streams.status().configured, 3);
+
+        // Changing the count while running restarts with the new number of streams
+        streams.set_count(2).unwrap();
+        settle(&streams.active, 2).await;
+        settle(&open, 2).await;
+
+        streams.stop();
+        settle(&streams.active, 0).await;
+        settle(&open, 0).await;
+        assert_eq!(streams.status().configured, 2);
+        relay.stop();
+    }
+}
@@ -0,0 +1,201 @@
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use tauri::State;
+
+use crate::error::HushError;
+use crate::send_queue::unix_now;
+
+/// Packet digests remembered at once; the oldest is forgotten first.
+const MAX_SEEN: usize = 4096;
+
+/// How long a delivered packet suppresses identical copies. Covers relay retries and
+/// resends after a reconnect without remembering traffic indefinitely.
+const SEEN_WINDOW_SECS: u64 = 10 * 60;
+
+/// Suppressed duplicates kept for `get_dedup_stats`.
+const MAX_RECENT_SUPPRESSED: usize = 32;
+
+type Digest = [u8; 16];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SuppressedDuplicate {
+    /// Leading bytes of the packet digest, hex-encoded.
+    pub digest: String,
+    pub sender: String,
+    pub first_seen_at: u64,
+    pub suppressed_at: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DedupStats {
+    pub cache_size: usize,
+    pub capacity: usize,
+    pub window_secs: u64,
+    pub suppressed_total: u64,
+    pub recent_suppressed: Vec<SuppressedDuplicate>,
+}
+
+#[derive(Default)]
+struct DedupCache {
+    seen: HashMap<Digest, u64>,
+    order: VecDeque<Digest>,
+    suppressed_total: u64,
+    recent: VecDeque<SuppressedDuplicate>,
+}
+
+impl DedupCache {
+    fn expire(&mut self, now: u64) {
+        while let Some(oldest) = self.order.front() {
+            let fresh = self.seen.get(oldest)
+                .is_some_and(|&at| now.saturating_sub(at) < SEEN_WINDOW_SECS);
+            if fresh && self.order.len() <= MAX_SEEN {
+                break;
+            }
+            if let Some(digest) = self.order.pop_front() {
+                self.seen.remove(&digest);
+            }
+        }
+    }
+}
+
+/// Drops inbound packets whose exact ciphertext already arrived recently from the same
+/// sender. Relay retries and resends after a reconnect replay the packet byte for
+/// byte, while a message sent again is a new packet (its padding alone is random), so
+/// only true replays are dropped. Identical plaintext from different senders, or sent
+/// twice on purpose, is always delivered. Cloned handles share one cache.
+#[derive(Clone, Default)]
+pub struct InboundDedup {
+    cache: Arc<Mutex<DedupCache>>,
+}
+
+impl InboundDedup {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records `packet` as received from `sender` and returns true the first time the
+    /// pair is seen within the window. Duplicates are counted and return false.
+    pub fn admit(&self, sender: &str, packet: &[u8]) -> bool {
+        let Ok(mut cache) = self.cache.lock() else {
+            // Delivering twice beats dropping a message
+            return true;
+        };
+        let now = unix_now();
+        cache.expire(now);
+
+        let digest = digest(sender, packet);
+        if let Some(&first_seen_at) = cache.seen.get(&digest) {
+            cache.suppressed_total += 1;
+            if cache.recent.len() == MAX_RECENT_SUPPRESSED {
+                cache.recent.pop_front();
+            }
+            cache.recent.push_back(SuppressedDuplicate {
+                digest: digest.iter().map(|b| format!("{:02x}", b)).collect(),
+                sender: sender.to_string(),
+                first_seen_at,
+                suppressed_at: now,
+            });
+            tracing::debug!("Suppressed duplicate packet from {}", sender);
+            return false;
+        }
+
+        cache.seen.insert(digest, now);
+        cache.order.push_back(digest);
+        cache.expire(now);
+        true
+    }
+
+    pub fn stats(&self) -> DedupStats {
+        let (cache_size, suppressed_total, recent_suppressed) = match self.cache.lock() {
+            Ok(cache) => (cache.seen.len(), cache.suppressed_total, cache.recent.iter().cloned().collect()),
+            Err(_) => (0, 0, Vec::new()),
+        };
+        DedupStats {
+            cache_size,
+            capacity: MAX_SEEN,
+            window_secs: SEEN_WINDOW_SECS,
+            suppressed_total,
+            recent_suppressed,
+        }
+    }
+
+    /// Forgets every remembered packet so the next copy of any message is delivered.
+    /// The suppression counters are reset too.
+    pub fn clear(&self) {
+        if let Ok(mut cache) = self.cache.lock() {
+            *cache = DedupCache::default();
+        }
+        tracing::info!("Inbound dedup cache cleared");
+    }
+}
+
+fn digest(sender: &str, packet: &[u8]) -> Digest {
+    use sha2::{Digest as _, Sha256};
+
+    // Length-prefixed so no sender/packet split can collide with another
+    let hash = Sha256::new()
+        .chain_update((sender.len() as u64).to_be_bytes())
+        .chain_update(sender.as_bytes())
+        .chain_update(packet)
+        .finalize();
+    let mut digest = [0u8; 16];
+    digest.copy_from_slice(&hash[..16]);
+    digest
+}
+
+#[tauri::command]
+pub async fn get_dedup_stats(
+    dedup: State<'_, InboundDedup>,
+) -> Result<DedupStats, HushError> {
+    Ok(dedup.stats())
+}
+
+#[tauri::command]
+pub async fn clear_dedup_cache(
+    dedup: State<'_, InboundDedup>,
+) -> Result<(), HushError> {
+    dedup.clear();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn duplicates_are_counted_until_the_cache_is_cleared() {
+        let dedup = InboundDedup::new();
+        assert!(dedup.admit("sender-a", b"packet 1"));
+        assert!(dedup.admit("sender-a", b"packet 2"));
+        // The same bytes from someone else are their own message
+        assert!(dedup.admit("sender-b", b"packet 1"));
+        assert_eq!(dedup.stats().cache_size, 3);
+        assert_eq!(dedup.stats().suppressed_total, 0);
+
+        assert!(!dedup.admit("sender-b", b"packet 1"));
+        let stats = dedup.stats();
+        assert_eq!((stats.cache_size, stats.suppressed_total), (3, 1));
+        let [suppressed] = stats.recent_suppressed.as_slice() else {
+            panic!("expected one suppressed duplicate: {:?}", stats.recent_suppressed);
+        };
+        assert_eq!(suppressed.sender, "sender-b");
+        let expected: String = digest("sender-b", b"packet 1").iter().map(|b| format!("{:02x}", b)).collect();
+        assert_eq!(suppressed.digest, expected);
+
+        dedup.clear();
+        let stats = dedup.stats();
+        assert_eq!((stats.cache_size, stats.suppressed_total), (0, 0));
+        assert!(stats.recent_suppressed.is_empty());
+        assert!(dedup.admit("sender-b", b"packet 1"));
+        assert!(!dedup.admit("sender-b", b"packet 1"));
+        assert_eq!(dedup.stats().suppressed_total, 1);
+    }
+
+    #[test]
+    fn sender_and_packet_boundaries_do_not_collide() {
+        let dedup = InboundDedup::new();
+        assert!(dedup.admit("ab", b"c"));
+        assert!(dedup.admit("a", b"bc"));
+    }
+}
@@ -0,0 +1,147 @@
+use anyhow::{Context, Result};
+use quinn::{Connection, RecvStream, SendStream};
+use std::sync::{Arc, Mutex};
+use tokio_util::sync::CancellationToken;
+
+use crate::close_codes::AppCloseCode;
+
+/// Request byte a peer sends on a bidirectional stream to ask for the signed directory.
+pub const FRAME_DIRECTORY_REQUEST: u8 = 0x20;
+
+/// Largest directory blob a client will accept from a mirroring peer.
+pub const MAX_DIRECTORY_SIZE: usize = 4 * 1024 * 1024;
+
+#[derive(Debug, Default)]
+struct MirrorState {
+    enabled: bool,
+    signed_directory: Option<Arc<Vec<u8>>>,
+}
+
+/// Cached copy of the signed relay directory, re-served verbatim to peers that can't
+/// reach the directory themselves. The blob is stored and sent exactly as received so
+/// peers verify the directory authority's signature, never ours. Off by default.
+#[derive(Debug, Clone, Default)]
+pub struct DirectoryMirror {
+    state: Arc<Mutex<MirrorState>>,
+}
+
+impl DirectoryMirror {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        if let Ok(mut state) = self.state.lock() {
+            state.enabled = enabled;
+        }
+        tracing::info!("Directory mirroring: {}", enabled);
+    }
+
+    /// Replaces the cached directory with `blob`, untouched.
+    pub fn cache(&self, blob: Vec<u8>) {
+        if let Ok(mut state) = self.state.lock() {
+            state.signed_directory = Some(Arc::new(blob));
+        }
+    }
+
+    /// The blob to serve, or `None` when mirroring is off or nothing is cached.
+    pub fn serving(&self) -> Option<Arc<Vec<u8>>> {
+        let state = self.state.lock().ok()?;
+        if !state.enabled {
+            return None;
+        }
+        state.signed_directory.clone()
+    }
+}
+
+/// Answers directory requests arriving on `connection`'s bidirectional streams until the
+/// peer goes away or `shutdown` fires.
+pub async fn serve_requests(connection: Connection, mirror: DirectoryMirror, shutdown: CancellationToken) {
+    loop {
+        let (send, recv) = tokio::select! {
+            _ = shutdown.cancelled() => break,
+            stream = connection.accept_bi() => match stream {
+                Ok(stream) => stream,
+                Err(_) => break,
+            },
+        };
+
+        let mirror = mirror.clone();
+        tokio::spawn(async move {
+            if let Err(e) = serve_request(&mirror, send, recv).await {
+                tracing::debug!("Directory request failed: {:#}", e);
+            }
+        });
+    }
+}
+
+async fn serve_request(mirror: &DirectoryMirror, mut send: SendStream, mut recv: RecvStream) -> Result<()> {
+    let mut request = [0u8; 1];
+    recv.read_exact(&mut request).await.context("Failed to read directory request")?;
+    if request[0] != FRAME_DIRECTORY_REQUEST {
+        send.reset(AppCloseCode::Error.code()).ok();
+        anyhow::bail!("Unknown request type {:#04x}", request[0]);
+    }
+
+    let Some(blob) = mirror.serving() else {
+        send.reset(AppCloseCode::Error.code()).ok();
+        return Ok(());
+    };
+
+    send.write_all(&blob).await.context("Failed to send directory")?;
+    send.finish().context("Failed to finish directory stream")?;
+    tracing::debug!("Served mirrored directory ({} bytes)", blob.len());
+    Ok(())
+}
+
+/// Asks a mirroring peer for its cached signed directory. The caller must check it
+/// with [`verify_directory`](crate::discovery_backend::verify_directory) before
+/// trusting anything in it.
+pub async fn request_directory(connection: &Connection) -> Result<Vec<u8>> {
+    let (mut send, mut recv) = connection.open_bi().await
+        .context("Failed to open directory stream")?;
+    send.write_all(&[FRAME_DIRECTORY_REQUEST]).await.context("Failed to send directory request")?;
+    send.finish().context("Failed to finish directory request")?;
+
+    recv.read_to_end(MAX_DIRECTORY_SIZE).await
+        .context("Peer did not serve a directory")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quic_transport::QuicTransport;
+    use crate::test_relay::TestRelay;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn peer_receives_the_signed_directory_unmodified() {
+        let mirror = DirectoryMirror::new();
+        let shutdown = CancellationToken::new();
+        let peer = {
+            let (mirror, shutdown) = (mirror.clone(), shutdown.clone());
+            TestRelay::serve(Duration::ZERO, move |connection: Connection| {
+                serve_requests(connection, mirror.clone(), shutdown.clone())
+            })
+            .unwrap()
+        };
+        let mut transport = QuicTransport::new();
+        peer.pin(&mut transport);
+        transport.connect(peer.relay_info().unwrap()).await.unwrap();
+        let connection = transport.connection().unwrap();
+
+        // Directory JSON followed by a signature that isn't valid UTF-8
+        let mut signed = br#"{"relays":[{"id":"relay1","address":"198.51.100.1","port":4433}]}"#.to_vec();
+        signed.extend((0..64u8).map(|i| i.wrapping_mul(37) | 0x80));
+        mirror.cache(signed.clone());
+        assert!(request_directory(&connection).await.is_err(), "served while mirroring is off");
+
+        mirror.set_enabled(true);
+        assert_eq!(request_directory(&connection).await.unwrap(), signed);
+
+        mirror.set_enabled(false);
+        assert!(request_directory(&connection).await.is_err());
+        shutdown.cancel();
+        peer.stop();
+    }
+}
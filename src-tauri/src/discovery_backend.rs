@@ -0,0 +1,394 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::fmt::Debug;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+use crate::cert_pins;
+use crate::directory_mirror::MAX_DIRECTORY_SIZE;
+use crate::relay_client::RelayNode;
+
+const BOOTSTRAP_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Raw Ed25519 public key of a directory authority.
+pub type AuthorityKey = [u8; 32];
+
+pub type BackendFuture<'a> = Pin<Box<dyn Future<Output = Result<Vec<RelayNode>>> + Send + 'a>>;
+
+/// A source of relays: a static list, a signed directory, a DHT, LAN discovery.
+/// [`RelayDiscovery`](crate::relay_client::RelayDiscovery) merges every registered
+/// backend on refresh.
+pub trait DiscoveryBackend: Send + Sync + Debug {
+    /// Short name used in logs.
+    fn name(&self) -> &str;
+
+    /// Fetches the backend's current relay list.
+    fn fetch(&self) -> BackendFuture<'_>;
+
+    /// Whether this is an HTTPS bootstrap directory, which `set_bootstrap` replaces.
+    fn is_bootstrap(&self) -> bool {
+        false
+    }
+}
+
+/// Fixed relay list, e.g. the built-in defaults or a list loaded from a file.
+#[derive(Debug, Clone)]
+pub struct StaticBackend {
+    relays: Vec<RelayNode>,
+}
+
+impl StaticBackend {
+    pub fn new(relays: Vec<RelayNode>) -> Self {
+        Self { relays }
+    }
+
+    pub fn relays(&self) -> Vec<RelayNode> {
+        self.relays.clone()
+    }
+
+    /// The relays shipped with the app. They carry no public key, so connecting to one
+    /// needs a pin or the user's confirmation under the unpinned-relay policy.
+    pub fn defaults() -> Self {
+        Self::new(vec![
+            RelayNode {
+                id: "relay1".to_string(),
+                address: "relay1.taior.net".to_string(),
+                port: 4433,
+                public_key: String::new(),
+                latency_ms: None,
+                bandwidth_mbps: None,
+                connect_timeout_ms: None,
+                network: None,
+            },
+            RelayNode {
+                id: "relay2".to_string(),
+                address: "relay2.taior.net".to_string(),
+                port: 4433,
+                public_key: String::new(),
+                latency_ms: None,
+                bandwidth_mbps: None,
+                connect_timeout_ms: None,
+                network: None,
+            },
+        ])
+    }
+}
+
+impl DiscoveryBackend for StaticBackend {
+    fn name(&self) -> &str {
+        "static"
+    }
+
+    fn fetch(&self) -> BackendFuture<'_> {
+        let relays = self.relays.clone();
+        Box::pin(async move { Ok(relays) })
+    }
+}
+
+/// Relay directory published over HTTPS and signed by a directory authority; see
+/// [`verify_directory`] for the format. Lists that aren't signed by the pinned
+/// authority key are rejected.
+#[derive(Debug, Clone)]
+pub struct HttpsBackend {
+    url: reqwest::Url,
+    client: reqwest::Client,
+    authority: AuthorityKey,
+}
+
+#[derive(Deserialize)]
+struct DirectoryDocument {
+    relays: Vec<serde_json::Value>,
+}
+
+/// What the authority publishes: the directory JSON and a signature over exactly those
+/// bytes, both base64.
+#[derive(Deserialize)]
+struct SignedDirectory {
+    directory: String,
+    signature: String,
+}
+
+impl HttpsBackend {
+    pub fn new(url: &str, authority: AuthorityKey) -> Result<Self> {
+        let url = reqwest::Url::parse(url)
+            .with_context(|| format!("Invalid bootstrap URL: {}", url))?;
+        if url.scheme() != "https" {
+            anyhow::bail!("Bootstrap URL must use https: {}", url);
+        }
+        let client = reqwest::Client::builder()
+            .timeout(BOOTSTRAP_TIMEOUT)
+            .build()
+            .context("Failed to build HTTP client")?;
+        Ok(Self { url, client, authority })
+    }
+
+    async fn fetch_directory(&self) -> Result<Vec<RelayNode>> {
+        let response = self.client.get(self.url.clone())
+            .send()
+            .await
+            .context("Bootstrap request failed")?
+            .error_for_status()
+            .context("Bootstrap server returned an error")?;
+        if response.content_length().is_some_and(|len| len as usize > MAX_DIRECTORY_SIZE) {
+            anyhow::bail!("Relay directory exceeds {} bytes", MAX_DIRECTORY_SIZE);
+        }
+        let body = response.bytes().await.context("Failed to read relay directory")?;
+        if body.len() > MAX_DIRECTORY_SIZE {
+            anyhow::bail!("Relay directory exceeds {} bytes", MAX_DIRECTORY_SIZE);
+        }
+
+        parse_directory(&verify_directory(&body, &self.authority)?)
+    }
+}
+
+impl DiscoveryBackend for HttpsBackend {
+    fn name(&self) -> &str {
+        self.url.as_str()
+    }
+
+    fn fetch(&self) -> BackendFuture<'_> {
+        Box::pin(self.fetch_directory())
+    }
+
+    fn is_bootstrap(&self) -> bool {
+        true
+    }
+}
+
+/// Decodes a directory authority key given as hex or standard base64.
+pub fn parse_authority_key(encoded: &str) -> Result<AuthorityKey> {
+    let key = cert_pins::parse_public_key(encoded).context("Invalid directory authority key")?;
+    AuthorityKey::try_from(key.as_slice())
+        .map_err(|_| anyhow::anyhow!("Directory authority key must be a 32 byte Ed25519 key, got {} bytes", key.len()))
+}
+
+/// Checks a signed directory `{ "directory": base64, "signature": base64 }` against
+/// `authority` and returns the directory JSON it vouches for. The signature is Ed25519
+/// over the decoded directory bytes, so nothing is re-serialized before checking.
+pub fn verify_directory(body: &[u8], authority: &AuthorityKey) -> Result<Vec<u8>> {
+    use base64::Engine;
+    use rustls::pki_types::SignatureVerificationAlgorithm;
+
+    let signed: SignedDirectory = serde_json::from_slice(body)
+        .context("Relay directory is not signed")?;
+    let engine = base64::engine::general_purpose::STANDARD;
+    let directory = engine.decode(signed.directory.trim())
+        .context("Signed directory is not valid base64")?;
+    let signature = engine.decode(signed.signature.trim())
+        .context("Directory signature is not valid base64")?;
+
+    let ed25519 = rustls::crypto::ring::default_provider()
+        .signature_verification_algorithms
+        .mapping
+        .iter()
+        .find(|(scheme, _)| *scheme == rustls::SignatureScheme::ED25519)
+        .and_then(|(_, algorithms)| algorithms.first().copied())
+        .context("Ed25519 verification is unavailable")?;
+    ed25519.verify_signature(authority, &directory, &signature)
+        .map_err(|_| anyhow::anyhow!("Relay directory signature does not match the authority key"))?;
+    Ok(directory)
+}
+
+/// Parses a directory document, skipping (and logging) entries that don't describe a
+/// usable relay rather than rejecting the whole list.
+pub fn parse_directory(body: &[u8]) -> Result<Vec<RelayNode>> {
+    let document: DirectoryDocument = serde_json::from_slice(body)
+        .context("Relay directory is not valid JSON")?;
+
+    let mut relays = Vec::with_capacity(document.relays.len());
+    for (index, entry) in document.relays.into_iter().enumerate() {
+        let relay = match serde_json::from_value::<RelayNode>(entry) {
+            Ok(relay) => relay,
+            Err(e) => {
+                tracing::warn!("Skipping malformed directory entry {}: {}", index, e);
+                continue;
+            }
+        };
+        match validate_relay(&relay) {
+            Ok(()) => relays.push(relay),
+            Err(e) => tracing::warn!("Skipping directory entry {}: {:#}", relay.id, e),
+        }
+    }
+    Ok(relays)
+}
+
+fn validate_relay(relay: &RelayNode) -> Result<()> {
+    if relay.id.is_empty() {
+        anyhow::bail!("Relay has no id");
+    }
+    if relay.public_key.trim().is_empty() {
+        anyhow::bail!("Relay has no public key");
+    }
+    let host_port = relay.to_relay_info().host_port();
+    let url = reqwest::Url::parse(&format!("quic://{}", host_port))
+        .with_context(|| format!("Unparseable address {}", host_port))?;
+    match url.host_str() {
+        Some(host) if !host.is_empty() && relay.port != 0 => Ok(()),
+        _ => anyhow::bail!("Unparseable address {}", host_port),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::relay_client::RelayDiscovery;
+    use std::sync::{Arc, Mutex};
+
+    /// Serves whatever relay list (or failure) the test last set.
+    #[derive(Debug)]
+    struct MockBackend {
+        name: &'static str,
+        relays: Mutex<Option<Vec<RelayNode>>>,
+    }
+
+    impl MockBackend {
+        fn serving(name: &'static str, relays: &[(&str, &str)]) -> Arc<Self> {
+            let backend = Arc::new(Self { name, relays: Mutex::new(None) });
+            backend.serve(relays);
+            backend
+        }
+
+        fn serve(&self, relays: &[(&str, &str)]) {
+            let relays = relays.iter().map(|(id, address)| relay(id, address)).collect();
+            *self.relays.lock().unwrap() = Some(relays);
+        }
+
+        fn fail(&self) {
+            *self.relays.lock().unwrap() = None;
+        }
+    }
+
+    impl DiscoveryBackend for MockBackend {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn fetch(&self) -> BackendFuture<'_> {
+            let relays = self.relays.lock().unwrap().clone();
+            Box::pin(async move { relays.context("backend unreachable") })
+        }
+    }
+
+    fn relay(id: &str, address: &str) -> RelayNode {
+        RelayNode {
+            id: id.to_string(),
+            address: address.to_string(),
+            port: 4433,
+            public_key: String::new(),
+            latency_ms: None,
+            bandwidth_mbps: None,
+            connect_timeout_ms: None,
+            network: None,
+        }
+    }
+
+    /// Signs `directory` the way an authority publishes it, returning the document and
+    /// the authority key.
+    fn sign(directory: &[u8]) -> (Vec<u8>, AuthorityKey) {
+        use base64::Engine;
+        use rustls::pki_types::PrivatePkcs8KeyDer;
+
+        let authority = rcgen::KeyPair::generate(&rcgen::PKCS_ED25519).unwrap();
+        let key = PrivatePkcs8KeyDer::from(authority.serialize_der());
+        let signature = rustls::crypto::ring::sign::any_eddsa_type(&key).unwrap()
+            .choose_scheme(&[rustls::SignatureScheme::ED25519]).unwrap()
+            .sign(directory).unwrap();
+        let engine = base64::engine::general_purpose::STANDARD;
+        let document = serde_json::json!({
+            "directory": engine.encode(directory),
+            "signature": engine.encode(signature),
+        });
+        (
+            serde_json::to_vec(&document).unwrap(),
+            AuthorityKey::try_from(authority.public_key_raw()).unwrap(),
+        )
+    }
+
+    #[test]
+    fn only_directories_signed_by_the_pinned_authority_are_accepted() {
+        let directory = br#"{"relays":[{"id":"relay1","address":"198.51.100.1","port":4433,"public_key":"ab"}]}"#;
+        let (signed, authority) = sign(directory);
+
+        let verified = verify_directory(&signed, &authority).unwrap();
+        assert_eq!(verified, directory);
+        let relays = parse_directory(&verified).unwrap();
+        assert_eq!(relays.len(), 1);
+        assert_eq!(relays[0].id, "relay1");
+
+        // Another authority's key, a tampered list, and an unsigned list are all refused
+        let (_, other) = sign(directory);
+        assert!(verify_directory(&signed, &other).is_err());
+        let (tampered, _) = sign(&directory.map(|b| if b == b'1' { b'2' } else { b }));
+        let mut document: serde_json::Value = serde_json::from_slice(&tampered).unwrap();
+        document["signature"] = serde_json::from_slice::<serde_json::Value>(&signed).unwrap()["signature"].clone();
+        assert!(verify_directory(&serde_json::to_vec(&document).unwrap(), &authority).is_err());
+        assert!(verify_directory(directory, &authority).is_err());
+    }
+
+    #[test]
+    fn authority_keys_must_be_32_bytes() {
+        let key = [7u8; 32];
+        let hex: String = key.iter().map(|b| format!("{:02x}", b)).collect();
+        assert_eq!(parse_authority_key(&hex).unwrap(), key);
+        assert!(parse_authority_key(&hex[..62]).is_err());
+        assert!(parse_authority_key("not a key").is_err());
+        assert!(HttpsBackend::new("http://directory.example/relays.json", key).is_err());
+    }
+
+    fn addresses(discovery: &RelayDiscovery) -> Vec<(String, String)> {
+        let mut relays: Vec<(String, String)> = discovery.get_available_relays(false)
+            .into_iter()
+            .map(|r| (r.id, r.address))
+            .collect();
+        relays.sort();
+        relays
+    }
+
+    fn pairs(expected: &[(&str, &str)]) -> Vec<(String, String)> {
+        expected.iter().map(|(id, address)| (id.to_string(), address.to_string())).collect()
+    }
+
+    #[tokio::test]
+    async fn backends_are_aggregated_and_deduplicated_by_id() {
+        let dht = MockBackend::serving("dht", &[("a", "198.51.100.1"), ("shared", "198.51.100.2")]);
+        let lan = MockBackend::serving("lan", &[("shared", "192.168.1.9"), ("b", "192.168.1.10")]);
+        let mut discovery = RelayDiscovery::new();
+        discovery.add_backend(dht.clone());
+        discovery.add_backend(lan.clone());
+
+        // The first backend registered wins a shared id, and the built-in relays are gone
+        assert_eq!(discovery.refresh().await.unwrap(), 3);
+        assert_eq!(
+            addresses(&discovery),
+            pairs(&[("a", "198.51.100.1"), ("b", "192.168.1.10"), ("shared", "198.51.100.2")])
+        );
+
+        // A manually added relay overrides every backend
+        discovery.add_relay(relay("shared", "203.0.113.5"));
+        discovery.refresh().await.unwrap();
+        assert_eq!(
+            addresses(&discovery),
+            pairs(&[("a", "198.51.100.1"), ("b", "192.168.1.10"), ("shared", "203.0.113.5")])
+        );
+        discovery.remove_relay("shared");
+
+        // An unreachable backend is skipped; the others still contribute
+        dht.fail();
+        discovery.refresh().await.unwrap();
+        assert_eq!(addresses(&discovery), pairs(&[("b", "192.168.1.10"), ("shared", "192.168.1.9")]));
+
+        // With none reachable the built-in list stands in
+        lan.fail();
+        discovery.refresh().await.unwrap();
+        let defaults: Vec<(String, String)> = StaticBackend::defaults().relays()
+            .into_iter()
+            .map(|r| (r.id, r.address))
+            .collect();
+        assert_eq!(addresses(&discovery), defaults);
+
+        lan.serve(&[("b", "192.168.1.10")]);
+        discovery.refresh().await.unwrap();
+        assert_eq!(addresses(&discovery), pairs(&[("b", "192.168.1.10")]));
+    }
+}
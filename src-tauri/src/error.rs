@@ -0,0 +1,127 @@
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+use std::fmt;
+
+use crate::shared_state::StatePoisoned;
+
+/// Error returned by every Tauri command, serialized as `{ kind, message }` so the
+/// frontend can branch on `kind` and show `message` as is.
+///
+/// Core code keeps returning `anyhow::Result`; failures that the UI needs to tell
+/// apart are raised as a `HushError` inside the chain and keep their kind on the
+/// way out, everything else becomes [`HushError::Other`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HushError {
+    NotInitialized,
+    NotConnected,
+    InvalidAddress(String),
+    InvalidInput(String),
+    QuicConnect(String),
+    /// The relay's certificate failed its pin or the verification hook, or the relay
+    /// is unpinned and awaits confirmation. Retrying won't change the outcome.
+    CertVerification(String),
+    Routing(String),
+    /// The relay did not acknowledge a send in time; the send may be retried.
+    AckTimeout,
+    /// The send queue holds its capacity of messages; retry once it drains.
+    QueueFull(usize),
+    /// The relay is on the user's blocklist and won't be dialed.
+    RelayBlocked(String),
+    /// A panic interrupted an update; `recover_state` resets the backend.
+    StatePoisoned,
+    Other(String),
+}
+
+impl HushError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::NotInitialized => "not_initialized",
+            Self::NotConnected => "not_connected",
+            Self::InvalidAddress(_) => "invalid_address",
+            Self::InvalidInput(_) => "invalid_input",
+            Self::QuicConnect(_) => "quic_connect",
+            Self::CertVerification(_) => "cert_verification",
+            Self::Routing(_) => "routing",
+            Self::AckTimeout => "ack_timeout",
+            Self::QueueFull(_) => "queue_full",
+            Self::RelayBlocked(_) => "relay_blocked",
+            Self::StatePoisoned => "state_poisoned",
+            Self::Other(_) => "other",
+        }
+    }
+
+    /// Same kind, with `message` in place of the original one. Kinds without a
+    /// message field keep their fixed message.
+    fn with_message(&self, message: String) -> Self {
+        match self {
+            Self::InvalidAddress(_) => Self::InvalidAddress(message),
+            Self::InvalidInput(_) => Self::InvalidInput(message),
+            Self::QuicConnect(_) => Self::QuicConnect(message),
+            Self::CertVerification(_) => Self::CertVerification(message),
+            Self::Routing(_) => Self::Routing(message),
+            Self::Other(_) => Self::Other(message),
+            unit => unit.clone(),
+        }
+    }
+}
+
+impl fmt::Display for HushError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotInitialized => write!(f, "Taior not initialized"),
+            Self::NotConnected => write!(f, "Not connected to relay"),
+            Self::AckTimeout => write!(f, "Timed out waiting for the relay to acknowledge the send"),
+            Self::QueueFull(capacity) => write!(f, "Send queue is full ({} messages)", capacity),
+            Self::RelayBlocked(id) => write!(f, "Relay {} is blocked; unblock it to connect", id),
+            Self::StatePoisoned => write!(f, "{}", StatePoisoned),
+            Self::InvalidAddress(message)
+            | Self::InvalidInput(message)
+            | Self::QuicConnect(message)
+            | Self::CertVerification(message)
+            | Self::Routing(message)
+            | Self::Other(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for HushError {}
+
+impl Serialize for HushError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut error = serializer.serialize_struct("HushError", 2)?;
+        error.serialize_field("kind", self.kind())?;
+        error.serialize_field("message", &self.to_string())?;
+        error.end()
+    }
+}
+
+impl From<anyhow::Error> for HushError {
+    fn from(e: anyhow::Error) -> Self {
+        let message = format!("{:#}", e);
+        if e.downcast_ref::<StatePoisoned>().is_some() {
+            return Self::StatePoisoned;
+        }
+        match e.chain().find_map(|cause| cause.downcast_ref::<HushError>()) {
+            Some(typed) => typed.with_message(message),
+            None => Self::Other(message),
+        }
+    }
+}
+
+impl From<StatePoisoned> for HushError {
+    fn from(_: StatePoisoned) -> Self {
+        Self::StatePoisoned
+    }
+}
+
+impl From<String> for HushError {
+    fn from(message: String) -> Self {
+        Self::Other(message)
+    }
+}
+
+impl From<&str> for HushError {
+    fn from(message: &str) -> Self {
+        Self::Other(message.to_string())
+    }
+}
@@ -0,0 +1,89 @@
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+use crate::send_queue::write_private;
+
+/// Serialized Taior identity keypair, in the app data directory.
+pub const IDENTITY_FILE: &str = "taior_identity.key";
+
+/// Where the local Taior identity is kept between runs, so the address survives
+/// restarts. The key file is readable by the owner only on Unix.
+#[derive(Debug, Clone)]
+pub struct IdentityStore {
+    path: PathBuf,
+}
+
+impl IdentityStore {
+    pub fn new(dir: &Path) -> Self {
+        Self {
+            path: dir.join(IDENTITY_FILE),
+        }
+    }
+
+    /// The stored identity, or `None` if none was saved yet.
+    pub fn load(&self) -> Result<Option<Vec<u8>>> {
+        if !self.path.exists() {
+            return Ok(None);
+        }
+        let bytes = std::fs::read(&self.path)
+            .with_context(|| format!("Failed to read identity from {}", self.path.display()))?;
+        if bytes.is_empty() {
+            anyhow::bail!("Identity file {} is empty", self.path.display());
+        }
+        Ok(Some(bytes))
+    }
+
+    pub fn save(&self, identity: &[u8]) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create identity directory {}", dir.display()))?;
+        }
+        // Write then rename so a crash never leaves a truncated key behind
+        let staging = self.path.with_extension("key.tmp");
+        write_private(&staging, identity).context("Failed to write identity")?;
+        std::fs::rename(&staging, &self.path).context("Failed to replace identity file")
+    }
+
+    /// Deletes the stored identity. Returns whether there was one.
+    pub fn wipe(&self) -> Result<bool> {
+        match std::fs::remove_file(&self.path) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(anyhow::Error::new(e).context("Failed to delete identity")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identity_survives_a_reload_until_wiped() {
+        let dir = std::env::temp_dir().join(format!("hush-identity-{}", uuid::Uuid::new_v4()));
+        let store = IdentityStore::new(&dir);
+        assert!(store.load().unwrap().is_none());
+
+        store.save(b"first keypair").unwrap();
+        store.save(b"second keypair").unwrap();
+        // A fresh store stands in for the next app start
+        let reopened = IdentityStore::new(&dir);
+        assert_eq!(reopened.load().unwrap().as_deref(), Some(&b"second keypair"[..]));
+        assert!(!dir.join("taior_identity.key.tmp").exists());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(dir.join(IDENTITY_FILE)).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        assert!(reopened.wipe().unwrap());
+        assert!(!reopened.wipe().unwrap());
+        assert!(store.load().unwrap().is_none());
+
+        std::fs::write(dir.join(IDENTITY_FILE), b"").unwrap();
+        assert!(store.load().is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
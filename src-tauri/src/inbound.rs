@@ -0,0 +1,466 @@
+use anyhow::{Context, Result};
+use quinn::{Connection, Endpoint, RecvStream, ServerConfig};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use serde::Serialize;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter};
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+
+use crate::cert_pins::{self, Fingerprint};
+use crate::close_codes::AppCloseCode;
+use crate::dedup::InboundDedup;
+use crate::directory_mirror::{self, DirectoryMirror};
+use crate::error::HushError;
+use crate::receipts::{self, ReceiptTracker, ReceiptUpdate};
+use crate::receiver::{Dispatch, ReceiveFailure, ReceivedMessage};
+use crate::send_queue::write_private;
+use crate::shared_state::SharedState;
+use crate::taior_bridge::TaiorState;
+
+/// Largest direct message accepted on a single inbound stream.
+const MAX_INBOUND_MESSAGE: usize = 1024 * 1024;
+
+/// How long a peer may take to finish one stream before it is dropped.
+const STREAM_READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Node certificate and its key in the app data directory, so the fingerprint peers
+/// pin survives restarts.
+const NODE_CERT_FILE: &str = "node_cert.der";
+const NODE_KEY_FILE: &str = "node_key.der";
+
+/// Payload of the `taior-message` event for messages that arrived over a direct
+/// (non-relayed) peer connection. `sender` is the Taior address the message was
+/// decrypted from; `peer` is only the transport address it came in on.
+#[derive(Debug, Clone, Serialize)]
+pub struct InboundMessage {
+    pub sender: String,
+    pub peer: String,
+    pub payload: Vec<u8>,
+}
+
+/// What a direct peer stream turned into, emitted under [`Self::event`].
+#[derive(Debug)]
+enum Direct {
+    Message(InboundMessage),
+    Receipt(ReceiptUpdate),
+    Failure(ReceiveFailure),
+}
+
+impl Direct {
+    fn event(&self) -> &'static str {
+        match self {
+            Direct::Message(_) => "taior-message",
+            Direct::Receipt(_) => "message-receipt",
+            Direct::Failure(_) => "message-error",
+        }
+    }
+
+    fn emit(self, app: &AppHandle) {
+        let event = self.event();
+        let result = match self {
+            Direct::Message(message) => app.emit(event, message),
+            Direct::Receipt(update) => app.emit(event, update),
+            Direct::Failure(failure) => app.emit(event, failure),
+        };
+        if let Err(e) = result {
+            tracing::debug!("Failed to emit {}: {}", event, e);
+        }
+    }
+}
+
+/// Server-side QUIC endpoint accepting direct peer connections. Peers pin the
+/// node's self-signed certificate by the fingerprint reported here.
+pub struct InboundListener {
+    endpoint: Endpoint,
+    fingerprint: Fingerprint,
+    shutdown: CancellationToken,
+}
+
+impl InboundListener {
+    /// Listens on `bind` with the node certificate kept in `key_dir`, creating it on
+    /// first use. Every stream is decrypted with the current Taior identity, exactly
+    /// like packets from the relay.
+    pub fn start(
+        bind: SocketAddr,
+        key_dir: &Path,
+        app: AppHandle,
+        taior: Arc<SharedState<TaiorState>>,
+        mirror: DirectoryMirror,
+        receipts: ReceiptTracker,
+        dedup: InboundDedup,
+    ) -> Result<Self> {
+        let emit = move |direct: Direct| direct.emit(&app);
+        Self::listen(bind, key_dir, Arc::new(emit), taior, mirror, receipts, dedup)
+    }
+
+    fn listen<E>(
+        bind: SocketAddr,
+        key_dir: &Path,
+        emit: Arc<E>,
+        taior: Arc<SharedState<TaiorState>>,
+        mirror: DirectoryMirror,
+        receipts: ReceiptTracker,
+        dedup: InboundDedup,
+    ) -> Result<Self>
+    where
+        E: Fn(Direct) + Send + Sync + 'static,
+    {
+        let (cert_der, key_der) = node_certificate(key_dir)?;
+        let fingerprint = cert_fingerprint(&cert_der);
+
+        let server_config = ServerConfig::with_single_cert(vec![cert_der], key_der)
+            .context("Failed to build server config")?;
+        let endpoint = Endpoint::server(server_config, bind)
+            .with_context(|| format!("Failed to listen on {}", bind))?;
+
+        let shutdown = CancellationToken::new();
+        let inbox = Arc::new(Inbox {
+            taior,
+            dedup,
+            receipts,
+            dispatch: Mutex::new(None),
+        });
+        tokio::spawn(accept_loop(endpoint.clone(), emit, inbox, mirror, shutdown.clone()));
+
+        tracing::info!("Accepting direct connections on {}", endpoint.local_addr()?);
+        Ok(Self {
+            endpoint,
+            fingerprint,
+            shutdown,
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.endpoint.local_addr()?)
+    }
+
+    pub fn fingerprint(&self) -> String {
+        cert_pins::format_fingerprint(&self.fingerprint)
+    }
+
+    pub fn stop(self) {
+        self.shutdown.cancel();
+        AppCloseCode::Shutdown.close_endpoint(&self.endpoint);
+        tracing::info!("Stopped accepting direct connections");
+    }
+}
+
+pub fn cert_fingerprint(cert: &CertificateDer<'_>) -> Fingerprint {
+    use sha2::{Digest, Sha256};
+    Sha256::digest(cert.as_ref()).into()
+}
+
+/// Loads the node certificate from `dir`, or generates one and saves it there. The
+/// certificate is kept alongside the key because re-signing it would change the
+/// fingerprint.
+fn node_certificate(dir: &Path) -> Result<(CertificateDer<'static>, PrivateKeyDer<'static>)> {
+    let cert_path = dir.join(NODE_CERT_FILE);
+    let key_path = dir.join(NODE_KEY_FILE);
+    if cert_path.exists() && key_path.exists() {
+        let cert = std::fs::read(&cert_path).context("Failed to read node certificate")?;
+        let key = std::fs::read(&key_path).context("Failed to read node key")?;
+        return Ok((
+            CertificateDer::from(cert),
+            PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key)),
+        ));
+    }
+
+    let cert = rcgen::generate_simple_self_signed(vec!["hush.local".to_string()])
+        .context("Failed to generate node certificate")?;
+    let cert_der = cert.serialize_der()?;
+    let key_der = cert.serialize_private_key_der();
+    std::fs::create_dir_all(dir).context("Failed to create node key directory")?;
+    write_private(&key_path, &key_der).context("Failed to write node key")?;
+    write_private(&cert_path, &cert_der).context("Failed to write node certificate")?;
+    tracing::info!("Generated node certificate in {}", dir.display());
+
+    Ok((
+        CertificateDer::from(cert_der),
+        PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key_der)),
+    ))
+}
+
+/// Decrypts direct peer streams for whichever Taior identity is current.
+struct Inbox {
+    taior: Arc<SharedState<TaiorState>>,
+    dedup: InboundDedup,
+    receipts: ReceiptTracker,
+    /// Shared by every connection so chunks of one message may arrive on any of them.
+    /// Replaced once its identity is rotated or reset.
+    dispatch: Mutex<Option<Dispatch>>,
+}
+
+impl Inbox {
+    async fn open(&self, packet: &[u8]) -> Result<Option<ReceivedMessage>> {
+        let mut dispatch = self.dispatch.lock().await;
+        if dispatch.as_ref().is_none_or(Dispatch::is_stale) {
+            *dispatch = Some(Dispatch::current(self.taior.clone(), self.dedup.clone()).await?);
+        }
+        match dispatch.as_mut() {
+            Some(dispatch) => dispatch.open(packet).await,
+            None => Ok(None),
+        }
+    }
+
+    async fn handle(&self, packet: &[u8], peer: SocketAddr) -> Option<Direct> {
+        let message = match self.open(packet).await {
+            Ok(message) => message?,
+            Err(e) => {
+                tracing::warn!("Dropped {} byte direct message from {}: {:#}", packet.len(), peer, e);
+                return Some(Direct::Failure(ReceiveFailure {
+                    source: "direct",
+                    packet_size: packet.len(),
+                    error: HushError::from(e),
+                }));
+            }
+        };
+
+        if receipts::is_receipt(&message.payload) {
+            return match self.receipts.process(&message.payload) {
+                Ok(update) => update.map(Direct::Receipt),
+                Err(e) => {
+                    tracing::warn!("Rejected receipt from {}: {:#}", message.sender, e);
+                    None
+                }
+            };
+        }
+        Some(Direct::Message(InboundMessage {
+            sender: message.sender,
+            peer: peer.to_string(),
+            payload: message.payload,
+        }))
+    }
+}
+
+async fn accept_loop<E>(
+    endpoint: Endpoint,
+    emit: Arc<E>,
+    inbox: Arc<Inbox>,
+    mirror: DirectoryMirror,
+    shutdown: CancellationToken,
+) where
+    E: Fn(Direct) + Send + Sync + 'static,
+{
+    loop {
+        let incoming = tokio::select! {
+            _ = shutdown.cancelled() => break,
+            incoming = endpoint.accept() => match incoming {
+                Some(incoming) => incoming,
+                None => break,
+            },
+        };
+
+        let emit = emit.clone();
+        let inbox = inbox.clone();
+        let mirror = mirror.clone();
+        let shutdown = shutdown.clone();
+        tokio::spawn(async move {
+            match incoming.await {
+                Ok(connection) => {
+                    tokio::spawn(directory_mirror::serve_requests(
+                        connection.clone(),
+                        mirror,
+                        shutdown.clone(),
+                    ));
+                    serve_connection(connection, emit, inbox, shutdown).await
+                }
+                Err(e) => tracing::debug!("Inbound handshake failed: {}", e),
+            }
+        });
+    }
+}
+
+/// Reads each stream on its own task, so a peer trickling one stream doesn't hold up
+/// the others.
+async fn serve_connection<E>(
+    connection: Connection,
+    emit: Arc<E>,
+    inbox: Arc<Inbox>,
+    shutdown: CancellationToken,
+) where
+    E: Fn(Direct) + Send + Sync + 'static,
+{
+    let peer = connection.remote_address();
+    tracing::debug!("Direct peer connected: {}", peer);
+
+    loop {
+        let recv = tokio::select! {
+            _ = shutdown.cancelled() => break,
+            stream = connection.accept_uni() => match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    tracing::debug!("Direct peer {} closed: {}", peer, e);
+                    break;
+                }
+            },
+        };
+        tokio::spawn(read_stream(recv, peer, emit.clone(), inbox.clone()));
+    }
+}
+
+async fn read_stream<E>(mut recv: RecvStream, peer: SocketAddr, emit: Arc<E>, inbox: Arc<Inbox>)
+where
+    E: Fn(Direct) + Send + Sync + 'static,
+{
+    let packet = match tokio::time::timeout(STREAM_READ_TIMEOUT, recv.read_to_end(MAX_INBOUND_MESSAGE)).await {
+        Ok(Ok(packet)) => packet,
+        Ok(Err(e)) => {
+            tracing::warn!("Failed to read direct message from {}: {}", peer, e);
+            return;
+        }
+        Err(_) => {
+            tracing::warn!("Dropped direct stream from {} after {:?} without FIN", peer, STREAM_READ_TIMEOUT);
+            let _ = recv.stop(AppCloseCode::Error.code());
+            return;
+        }
+    };
+    if let Some(direct) = inbox.handle(&packet, peer).await {
+        emit(direct);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quic_transport::{FinishMode, QuicTransport, RelayInfo};
+    use crate::send_queue::Priority;
+    use crate::taior_bridge::TaiorConfig;
+    use std::path::PathBuf;
+    use tokio::sync::mpsc;
+
+    struct Node {
+        listener: InboundListener,
+        emitted: mpsc::UnboundedReceiver<Direct>,
+        taior: Arc<SharedState<TaiorState>>,
+        address: String,
+    }
+
+    fn node(key_dir: &Path) -> Node {
+        let (events, emitted) = mpsc::unbounded_channel();
+        let emit = move |direct: Direct| {
+            let _ = events.send(direct);
+        };
+        let mut taior = TaiorState::new();
+        let address = taior.init(TaiorConfig { bootstrap_nodes: Vec::new() }).unwrap();
+        let taior = Arc::new(SharedState::new(taior));
+        let listener = InboundListener::listen(
+            SocketAddr::from(([127, 0, 0, 1], 0)),
+            key_dir,
+            Arc::new(emit),
+            taior.clone(),
+            DirectoryMirror::new(),
+            ReceiptTracker::new(),
+            InboundDedup::new(),
+        )
+        .unwrap();
+        Node { listener, emitted, taior, address }
+    }
+
+    /// A peer that pins the node certificate by the fingerprint the listener reports.
+    async fn peer(listener: &InboundListener) -> QuicTransport {
+        let mut peer = QuicTransport::new();
+        peer.pins_mut().add("node", cert_pins::parse_fingerprint(&listener.fingerprint()).unwrap());
+        let addr = listener.local_addr().unwrap();
+        peer.connect(RelayInfo {
+            id: Some("node".to_string()),
+            address: addr.ip().to_string(),
+            port: addr.port(),
+            public_key: None,
+            connect_timeout_ms: None,
+            server_name: None,
+        })
+        .await
+        .unwrap();
+        peer
+    }
+
+    fn key_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("hush-inbound-{}", uuid::Uuid::new_v4()))
+    }
+
+    async fn next(emitted: &mut mpsc::UnboundedReceiver<Direct>) -> Direct {
+        tokio::time::timeout(Duration::from_secs(5), emitted.recv()).await.unwrap().unwrap()
+    }
+
+    #[tokio::test]
+    async fn direct_peer_message_is_decrypted_before_it_surfaces() {
+        let dir = key_dir();
+        let mut node = node(&dir);
+        let mut peer = peer(&node.listener).await;
+
+        let (packet, _) = node.taior.write().await.unwrap()
+            .send(b"hello direct", "fast", &node.address).unwrap();
+        peer.send(&packet, FinishMode::Finish, Priority::default()).await.unwrap();
+
+        let direct = next(&mut node.emitted).await;
+        assert_eq!(direct.event(), "taior-message");
+        let Direct::Message(message) = direct else {
+            panic!("expected a message, got {:?}", direct);
+        };
+        assert_eq!(message.payload, b"hello direct");
+        assert_eq!(message.sender, node.address);
+        assert!(message.peer.starts_with("127.0.0.1:"));
+
+        // Raw bytes are not a message, whoever sends them
+        peer.send(b"forged plaintext", FinishMode::Finish, Priority::default()).await.unwrap();
+        let direct = next(&mut node.emitted).await;
+        assert_eq!(direct.event(), "message-error");
+        let Direct::Failure(failure) = direct else {
+            panic!("expected a failure, got {:?}", direct);
+        };
+        assert_eq!(failure.source, "direct");
+        assert_eq!(failure.packet_size, b"forged plaintext".len());
+
+        peer.disconnect();
+        node.listener.stop();
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn node_fingerprint_survives_a_restart() {
+        let dir = key_dir();
+        let first = node(&dir);
+        let fingerprint = first.listener.fingerprint();
+        first.listener.stop();
+
+        let second = node(&dir);
+        assert_eq!(second.listener.fingerprint(), fingerprint);
+        // A peer pinned to the first run still connects
+        let mut peer = peer(&second.listener).await;
+        peer.disconnect();
+        second.listener.stop();
+
+        let other = key_dir();
+        let elsewhere = node(&other);
+        assert_ne!(elsewhere.listener.fingerprint(), fingerprint);
+        elsewhere.listener.stop();
+        let _ = std::fs::remove_dir_all(&dir);
+        let _ = std::fs::remove_dir_all(&other);
+    }
+
+    #[tokio::test]
+    async fn an_unfinished_stream_does_not_hold_up_the_next() {
+        let dir = key_dir();
+        let mut node = node(&dir);
+        let mut peer = peer(&node.listener).await;
+
+        // Never finished, so the listener is still waiting for its FIN
+        peer.send(b"trickle", FinishMode::KeepOpen, Priority::default()).await.unwrap();
+        let (packet, _) = node.taior.write().await.unwrap()
+            .send(b"after the stall", "fast", &node.address).unwrap();
+        peer.send(&packet, FinishMode::Finish, Priority::default()).await.unwrap();
+
+        let Direct::Message(message) = next(&mut node.emitted).await else {
+            panic!("expected a message");
+        };
+        assert_eq!(message.payload, b"after the stall");
+
+        peer.disconnect();
+        node.listener.stop();
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,350 @@
+use anyhow::{Context, Result};
+use quinn::{Connection, ConnectionError, IdleTimeout, TransportConfig, VarInt};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const PROFILES_FILE: &str = "keepalive.json";
+
+/// Interval used on a network we know nothing about. Long enough to spare battery on
+/// well-behaved NATs; most home routers keep UDP bindings for at least a minute.
+const INITIAL_INTERVAL_SECS: u64 = 45;
+const MIN_INTERVAL_SECS: u64 = 5;
+
+/// Connections are dropped after this long without hearing from the relay, which is
+/// how an expired NAT binding shows up locally.
+const MAX_IDLE_TIMEOUT_MS: u32 = 90_000;
+
+/// Sessions that must outlive the interval before it is considered stable. A stable
+/// interval is only shortened after two idle timeouts in a row, so one relay outage
+/// doesn't undo what was learned.
+const STABLE_AFTER: u32 = 3;
+
+/// What has been learned about one network's NAT.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeepAliveProfile {
+    pub interval_secs: u64,
+    pub idle_timeouts: u32,
+    pub stable_sessions: u32,
+}
+
+impl Default for KeepAliveProfile {
+    fn default() -> Self {
+        Self {
+            interval_secs: INITIAL_INTERVAL_SECS,
+            idle_timeouts: 0,
+            stable_sessions: 0,
+        }
+    }
+}
+
+impl KeepAliveProfile {
+    pub fn is_stable(&self) -> bool {
+        self.stable_sessions >= STABLE_AFTER
+    }
+
+    /// Shortens the interval by a third, down to the floor. Returns whether it changed.
+    fn record_idle_timeout(&mut self) -> bool {
+        self.idle_timeouts = self.idle_timeouts.saturating_add(1);
+        if self.is_stable() {
+            self.stable_sessions = 0;
+            return false;
+        }
+
+        let shorter = (self.interval_secs * 2 / 3).max(MIN_INTERVAL_SECS);
+        let changed = shorter != self.interval_secs;
+        self.interval_secs = shorter;
+        self.stable_sessions = 0;
+        changed
+    }
+
+    fn record_survived(&mut self) {
+        self.stable_sessions = self.stable_sessions.saturating_add(1);
+    }
+}
+
+/// Manual keep-alive interval and idle timeout. A field left unset keeps the adaptive
+/// behaviour: the interval learned for the network, and a 90s idle timeout.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConnectionTuning {
+    pub keep_alive_ms: Option<u64>,
+    pub idle_timeout_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NetworkKeepAlive {
+    pub network: String,
+    pub interval_secs: u64,
+    pub idle_timeouts: u32,
+    pub stable: bool,
+}
+
+#[derive(Debug, Default)]
+struct Profiles {
+    networks: HashMap<String, KeepAliveProfile>,
+    path: Option<PathBuf>,
+}
+
+impl Profiles {
+    fn persist(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let written = serde_json::to_vec(&self.networks)
+            .map_err(anyhow::Error::from)
+            .and_then(|json| std::fs::write(path, json).map_err(Into::into));
+        if let Err(e) = written {
+            tracing::warn!("Failed to persist keep-alive profiles: {:#}", e);
+        }
+    }
+}
+
+/// Keep-alive interval learned per network. Each network starts at a conservative
+/// interval that shrinks every time a connection on it dies from an idle timeout, until
+/// the NAT's binding timeout is found. Cloned handles share the same profiles.
+#[derive(Debug, Clone, Default)]
+pub struct AdaptiveKeepAlive {
+    profiles: Arc<Mutex<Profiles>>,
+    tuning: Arc<Mutex<ConnectionTuning>>,
+}
+
+impl AdaptiveKeepAlive {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads profiles learned in earlier runs and persists later changes to `dir`.
+    pub fn load(&self, dir: &Path) -> Result<()> {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create data directory {}", dir.display()))?;
+        let path = dir.join(PROFILES_FILE);
+
+        let networks = if path.exists() {
+            let json = std::fs::read(&path).context("Failed to read keep-alive profiles")?;
+            serde_json::from_slice(&json).context("Corrupt keep-alive profiles")?
+        } else {
+            HashMap::new()
+        };
+
+        let mut profiles = self.profiles.lock()
+            .map_err(|_| anyhow::anyhow!("Keep-alive profiles poisoned"))?;
+        profiles.networks = networks;
+        profiles.path = Some(path);
+        Ok(())
+    }
+
+    pub fn interval(&self, network: &str) -> Duration {
+        let secs = self.profiles.lock().ok()
+            .and_then(|p| p.networks.get(network).map(|n| n.interval_secs))
+            .unwrap_or(INITIAL_INTERVAL_SECS);
+        Duration::from_secs(secs)
+    }
+
+    /// Sets the keep-alive interval for `network` and the idle timeout it is tuned against,
+    /// or the manual values from [`Self::set_tuning`] where those are set.
+    pub fn apply(&self, network: &str, transport: &mut TransportConfig) {
+        let tuning = self.tuning();
+        let interval = tuning.keep_alive_ms
+            .map(Duration::from_millis)
+            .unwrap_or_else(|| self.interval(network));
+        transport
+            .keep_alive_interval(Some(interval))
+            .max_idle_timeout(Some(idle_timeout(tuning)));
+    }
+
+    /// [`Self::apply`] for connections whose network isn't known up front, such as the
+    /// endpoint's default client config.
+    pub fn apply_default(&self, transport: &mut TransportConfig) {
+        let (interval, _) = self.default_settings();
+        transport
+            .keep_alive_interval(Some(interval))
+            .max_idle_timeout(Some(idle_timeout(self.tuning())));
+    }
+
+    /// Keep-alive interval and idle timeout [`Self::apply_default`] sets.
+    pub fn default_settings(&self) -> (Duration, Duration) {
+        let tuning = self.tuning();
+        let interval = tuning.keep_alive_ms.unwrap_or(INITIAL_INTERVAL_SECS * 1000);
+        let idle = tuning.idle_timeout_ms.unwrap_or(MAX_IDLE_TIMEOUT_MS as u64);
+        (Duration::from_millis(interval), Duration::from_millis(idle))
+    }
+
+    /// Overrides the keep-alive interval and idle timeout for connections opened after
+    /// this call; `None` restores the adaptive value. The interval must be shorter than
+    /// the idle timeout, or the keep-alives could never keep the connection open.
+    pub fn set_tuning(&self, tuning: ConnectionTuning) -> Result<()> {
+        if tuning.keep_alive_ms == Some(0) || tuning.idle_timeout_ms == Some(0) {
+            anyhow::bail!("Keep-alive interval and idle timeout must be positive");
+        }
+        let keep_alive = tuning.keep_alive_ms.unwrap_or(INITIAL_INTERVAL_SECS * 1000);
+        let idle = tuning.idle_timeout_ms.unwrap_or(MAX_IDLE_TIMEOUT_MS as u64);
+        if keep_alive >= idle {
+            anyhow::bail!(
+                "Keep-alive interval ({}ms) must be shorter than the idle timeout ({}ms)",
+                keep_alive,
+                idle
+            );
+        }
+        if let Some(ms) = tuning.idle_timeout_ms {
+            IdleTimeout::try_from(Duration::from_millis(ms))
+                .map_err(|_| anyhow::anyhow!("Idle timeout of {}ms is too large", ms))?;
+        }
+
+        let mut current = self.tuning.lock()
+            .map_err(|_| anyhow::anyhow!("Connection tuning poisoned"))?;
+        *current = tuning;
+        tracing::info!(
+            "Connection tuning: keep-alive {:?}ms, idle timeout {:?}ms",
+            tuning.keep_alive_ms,
+            tuning.idle_timeout_ms
+        );
+        Ok(())
+    }
+
+    pub fn tuning(&self) -> ConnectionTuning {
+        self.tuning.lock().map(|t| *t).unwrap_or_default()
+    }
+
+    /// Learns from how `connection` ends: an idle timeout shortens the interval, a
+    /// session that outlived several intervals counts towards it being stable.
+    pub fn watch(&self, network: String, connection: Connection) {
+        let tuner = self.clone();
+        let interval = self.interval(&network);
+        tokio::spawn(async move {
+            let started = Instant::now();
+            match connection.closed().await {
+                ConnectionError::TimedOut => tuner.record_idle_timeout(&network, interval),
+                _ if started.elapsed() >= interval * 2 => tuner.record_survived(&network),
+                _ => {}
+            }
+        });
+    }
+
+    /// `interval` is what the failed connection used; timeouts from connections opened
+    /// before the last adjustment are ignored so one outage doesn't shrink it repeatedly.
+    pub fn record_idle_timeout(&self, network: &str, interval: Duration) {
+        let Ok(mut profiles) = self.profiles.lock() else {
+            return;
+        };
+        let profile = profiles.networks.entry(network.to_string()).or_default();
+        if profile.interval_secs != interval.as_secs() {
+            return;
+        }
+
+        if profile.record_idle_timeout() {
+            tracing::info!(
+                "Idle timeout on network {}; keep-alive interval now {}s",
+                network,
+                profile.interval_secs
+            );
+        }
+        profiles.persist();
+    }
+
+    pub fn record_survived(&self, network: &str) {
+        let Ok(mut profiles) = self.profiles.lock() else {
+            return;
+        };
+        profiles.networks.entry(network.to_string()).or_default().record_survived();
+        profiles.persist();
+    }
+
+    pub fn profiles(&self) -> Vec<NetworkKeepAlive> {
+        let Ok(profiles) = self.profiles.lock() else {
+            return Vec::new();
+        };
+        profiles.networks.iter()
+            .map(|(network, p)| NetworkKeepAlive {
+                network: network.clone(),
+                interval_secs: p.interval_secs,
+                idle_timeouts: p.idle_timeouts,
+                stable: p.is_stable(),
+            })
+            .collect()
+    }
+}
+
+fn idle_timeout(tuning: ConnectionTuning) -> IdleTimeout {
+    tuning.idle_timeout_ms
+        .and_then(|ms| IdleTimeout::try_from(Duration::from_millis(ms)).ok())
+        .unwrap_or_else(|| IdleTimeout::from(VarInt::from_u32(MAX_IDLE_TIMEOUT_MS)))
+}
+
+/// Identifies the network used to reach `relay` by the local address the OS routes
+/// through, hashed so the profiles file doesn't record it. Connecting a UDP socket
+/// sends nothing.
+pub fn network_key(relay: SocketAddr) -> String {
+    use sha2::{Digest, Sha256};
+
+    let bind = if relay.is_ipv4() {
+        SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0))
+    } else {
+        SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0))
+    };
+    let local = std::net::UdpSocket::bind(bind)
+        .and_then(|socket| {
+            socket.connect(relay)?;
+            socket.local_addr()
+        })
+        .map(|addr| addr.ip().to_string())
+        .unwrap_or_default();
+
+    Sha256::digest(local.as_bytes())[..8].iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs one session on a NAT that forgets bindings idle for longer than
+    /// `binding_timeout`: it dies of an idle timeout if the keep-alive is slower.
+    fn session(keep_alive: &AdaptiveKeepAlive, network: &str, binding_timeout: Duration) {
+        let interval = keep_alive.interval(network);
+        if interval > binding_timeout {
+            keep_alive.record_idle_timeout(network, interval);
+        } else {
+            keep_alive.record_survived(network);
+        }
+    }
+
+    fn profile(keep_alive: &AdaptiveKeepAlive, network: &str) -> NetworkKeepAlive {
+        keep_alive.profiles().into_iter().find(|p| p.network == network).unwrap()
+    }
+
+    #[test]
+    fn interval_adapts_down_to_the_nat_and_stabilizes() {
+        let dir = std::env::temp_dir().join(format!("hush-keepalive-{}", uuid::Uuid::new_v4()));
+        let keep_alive = AdaptiveKeepAlive::new();
+        keep_alive.load(&dir).unwrap();
+        let nat = Duration::from_secs(25);
+
+        let mut intervals = Vec::new();
+        for _ in 0..8 {
+            intervals.push(keep_alive.interval("aggressive-nat").as_secs());
+            session(&keep_alive, "aggressive-nat", nat);
+        }
+        assert_eq!(intervals, [45, 30, 20, 20, 20, 20, 20, 20]);
+        let learned = profile(&keep_alive, "aggressive-nat");
+        assert!(learned.stable);
+        assert_eq!(learned.idle_timeouts, 2);
+
+        // Once stable, a lone timeout (say a relay outage) doesn't shorten it, and a
+        // late report from a connection on the old interval is ignored
+        keep_alive.record_idle_timeout("aggressive-nat", Duration::from_secs(20));
+        keep_alive.record_idle_timeout("aggressive-nat", Duration::from_secs(30));
+        assert_eq!(keep_alive.interval("aggressive-nat").as_secs(), 20);
+
+        // Learned per network and kept across restarts
+        assert_eq!(keep_alive.interval("home").as_secs(), INITIAL_INTERVAL_SECS);
+        let restarted = AdaptiveKeepAlive::new();
+        restarted.load(&dir).unwrap();
+        assert_eq!(restarted.interval("aggressive-nat").as_secs(), 20);
+        assert_eq!(restarted.interval("home").as_secs(), INITIAL_INTERVAL_SECS);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,388 @@
+//! Hush backend: QUIC relay transport, AORP routing via libtaior and relay discovery.
+//!
+//! The core types (`QuicTransport`, `TaiorState`, `RelayDiscovery`) are plain async
+//! Rust and can be embedded without Tauri; the `#[tauri::command]` functions in each
+//! module are thin adapters over them, registered by [`run`].
+
+pub mod ack;
+pub mod blocklist;
+pub mod cert_pins;
+pub mod chunking;
+pub mod circuits;
+pub mod close_codes;
+pub mod cover_traffic;
+pub mod dedup;
+pub mod directory_mirror;
+pub mod discovery_backend;
+pub mod error;
+pub mod identity;
+pub mod inbound;
+pub mod keepalive;
+pub mod logging;
+pub mod metrics;
+#[cfg(feature = "network-sim")]
+pub mod network_sim;
+pub mod observer;
+pub mod onion;
+pub mod port_rotation;
+pub mod quic_transport;
+pub mod receipts;
+pub mod receiver;
+pub mod reconnect;
+pub mod relay_client;
+pub mod response;
+pub mod resumable;
+pub mod retry;
+pub mod self_test;
+pub mod send_queue;
+pub mod session_tickets;
+pub mod shared_state;
+pub mod store_forward;
+pub mod taior_bridge;
+#[cfg(any(test, feature = "test-relay"))]
+pub mod test_relay;
+pub mod throttle;
+pub mod timeouts;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tauri::{Manager, RunEvent};
+use tokio::sync::RwLock;
+use tokio_util::sync::CancellationToken;
+
+use crate::blocklist::RelayBlocklist;
+use crate::circuits::CircuitManager;
+use crate::identity::IdentityStore;
+use crate::port_rotation::PortRotation;
+use crate::quic_transport::{ConnectDedup, QuicTransport, EXIT_SHUTDOWN_TIMEOUT};
+use crate::reconnect::AutoReconnect;
+use crate::relay_client::RelayDiscovery;
+use crate::send_queue::SendQueue;
+use crate::shared_state::SharedState;
+use crate::taior_bridge::TaiorState;
+
+pub fn run() {
+    let log_level = logging::init();
+    let blocklist = RelayBlocklist::new();
+    let mut transport = QuicTransport::new();
+    transport.attach_blocklist(blocklist.clone());
+    let fingerprint_confirmations = transport.confirmations();
+    let directory_mirror = transport.directory_mirror();
+    let receipt_tracker = transport.receipts();
+    let inbound_dedup = transport.dedup();
+    let bandwidth_limits = transport.bandwidth();
+    let quic_transport = Arc::new(SharedState::new(transport));
+
+    let mut discovery = RelayDiscovery::new();
+    discovery.attach_blocklist(blocklist.clone());
+    let relay_discovery = Arc::new(RwLock::new(discovery));
+    let mut taior = TaiorState::new();
+    taior.attach_transport(quic_transport.clone());
+    taior.attach_discovery(relay_discovery.clone());
+    let taior_state = Arc::new(SharedState::new(taior));
+    let circuit_manager = CircuitManager::new();
+    let receive_shutdown = CancellationToken::new();
+    let exit_receive = receive_shutdown.clone();
+    let exit_taior = taior_state.clone();
+    let exit_transport = quic_transport.clone();
+    let exit_started = AtomicBool::new(false);
+
+    tauri::Builder::default()
+        .plugin(tauri_plugin_shell::init())
+        .manage(taior_state.clone())
+        .manage(quic_transport.clone())
+        .manage(relay_discovery.clone())
+        .manage(fingerprint_confirmations)
+        .manage(directory_mirror)
+        .manage(receipt_tracker)
+        .manage(inbound_dedup.clone())
+        .manage(blocklist.clone())
+        .manage(bandwidth_limits)
+        .manage(log_level)
+        .manage(ConnectDedup::new())
+        .manage(circuit_manager.clone())
+        .manage(PortRotation::new())
+        .manage(AutoReconnect::new())
+        .invoke_handler(tauri::generate_handler![
+            taior_bridge::taior_init,
+            taior_bridge::taior_send,
+            taior_bridge::taior_address,
+            taior_bridge::taior_rotate_identity,
+            taior_bridge::taior_reset,
+            taior_bridge::taior_reset_identity,
+            taior_bridge::taior_enable_cover_traffic,
+            taior_bridge::taior_cover_traffic_status,
+            taior_bridge::cover_traffic_stats,
+            taior_bridge::taior_routing_modes,
+            taior_bridge::taior_set_padding_buckets,
+            taior_bridge::taior_padding_buckets,
+            taior_bridge::taior_set_max_payload,
+            taior_bridge::taior_max_payload,
+            taior_bridge::benchmark_modes,
+            taior_bridge::estimate_mode,
+            taior_bridge::taior_set_cover_destination,
+            taior_bridge::taior_cover_destination,
+            taior_bridge::taior_set_cover_streams,
+            taior_bridge::taior_cover_streams,
+            quic_transport::connect_to_relay,
+            quic_transport::connect_early,
+            quic_transport::set_zero_rtt,
+            quic_transport::connect_fastest,
+            quic_transport::disconnect_relay,
+            quic_transport::migrate_to_relay,
+            quic_transport::switch_relay,
+            quic_transport::shutdown,
+            quic_transport::send_via_quic,
+            quic_transport::send_via_quic_retry,
+            quic_transport::send_recv_via_quic,
+            quic_transport::recv_via_quic,
+            quic_transport::send_datagram_via_quic,
+            quic_transport::recv_datagram_via_quic,
+            quic_transport::finish_stream,
+            quic_transport::send_multi_via_quic,
+            quic_transport::queue_send,
+            quic_transport::pending_send_count,
+            quic_transport::set_queue_capacity,
+            quic_transport::get_relay_status,
+            quic_transport::set_mtu_bounds,
+            quic_transport::get_connection_params,
+            quic_transport::get_connection_stats,
+            quic_transport::quic_capabilities,
+            quic_transport::set_timeouts,
+            quic_transport::set_connect_timeout,
+            quic_transport::get_timeouts,
+            quic_transport::keep_alive_profiles,
+            quic_transport::set_connection_tuning,
+            quic_transport::get_connection_tuning,
+            quic_transport::get_connected_fingerprint,
+            quic_transport::set_inbound_listener,
+            quic_transport::audit_pins,
+            quic_transport::add_relay_pin,
+            quic_transport::prune_relay_pin,
+            quic_transport::list_relay_pins,
+            quic_transport::set_unpinned_policy,
+            quic_transport::confirm_fingerprint,
+            quic_transport::set_network_sim,
+            quic_transport::set_source_port,
+            quic_transport::get_local_endpoint_addr,
+            port_rotation::set_port_rotation,
+            reconnect::set_auto_reconnect,
+            quic_transport::per_connection_endpoint,
+            quic_transport::send_via_rotation,
+            quic_transport::set_retry_budget,
+            quic_transport::set_relay_failover,
+            quic_transport::send_resumable,
+            quic_transport::resume_transfers,
+            quic_transport::cancel_transfer,
+            quic_transport::connectivity_matrix,
+            quic_transport::probe_relays,
+            receipts::track_message,
+            receipts::make_receipt,
+            receipts::process_receipt,
+            receipts::message_state,
+            dedup::get_dedup_stats,
+            dedup::clear_dedup_cache,
+            observer::observe,
+            metrics::metrics_prometheus,
+            self_test::run_self_test,
+            logging::set_log_level,
+            logging::get_log_level,
+            shared_state::recover_state,
+            relay_client::set_relay_rotation,
+            relay_client::set_quality_weights,
+            relay_client::get_quality_weights,
+            relay_client::list_relays,
+            relay_client::select_relays,
+            relay_client::refresh_relays,
+            relay_client::directory_fingerprint,
+            relay_client::add_relay,
+            relay_client::remove_relay,
+            relay_client::set_directory_mirroring,
+            relay_client::cache_signed_directory,
+            blocklist::block_relay,
+            blocklist::unblock_relay,
+            blocklist::list_blocked_relays,
+            throttle::set_relay_bandwidth_limit,
+            throttle::list_relay_bandwidth_limits,
+            circuits::set_circuit_rebuild_policy,
+            circuits::get_circuit_rebuild_policy,
+            circuits::current_circuit,
+            circuits::rebuild_circuit,
+            circuits::save_circuit,
+            circuits::load_circuit,
+            onion::send_via_circuit,
+        ])
+        .setup(move |app| {
+            let handle = app.handle().clone();
+            let data_dir = app.path().app_data_dir()?;
+            let config_dir = app.path().app_config_dir()?;
+
+            // Loaded before any task can reach the transport, so a connect issued at
+            // startup already sees the saved pins
+            match quic_transport.try_write() {
+                Some(mut transport) => {
+                    transport.attach_app(handle.clone());
+                    let pin_file = config_dir.join(cert_pins::PIN_FILE);
+                    if pin_file.exists() {
+                        match transport.pins_mut().load_file(&pin_file) {
+                            Ok(count) => tracing::info!("Loaded {} relay pins", count),
+                            Err(e) => tracing::error!("Failed to load relay pins: {:#}", e),
+                        }
+                    }
+                    if let Err(e) = transport.keep_alive().load(&data_dir) {
+                        tracing::warn!("Failed to load keep-alive profiles: {}", e);
+                    }
+                    match SendQueue::load(&data_dir) {
+                        Ok(queue) => transport.restore_send_queue(queue),
+                        Err(e) => tracing::warn!("Failed to load persisted send queue: {}", e),
+                    }
+                }
+                None => tracing::error!("Transport state unavailable during setup; relay pins not loaded"),
+            }
+            
+            tokio::spawn(async move {
+                tracing::info!("Hush Tauri backend initialized with QUIC + AORP");
+            });
+
+            circuit_manager.start(handle.clone(), quic_transport.clone(), relay_discovery.clone());
+            receiver::start(
+                handle.clone(),
+                taior_state.clone(),
+                quic_transport.clone(),
+                inbound_dedup.clone(),
+                receive_shutdown.clone(),
+            );
+
+            let identity_store = IdentityStore::new(&data_dir);
+            let taior = taior_state.clone();
+            tokio::spawn(async move {
+                if let Ok(mut taior) = taior.write().await {
+                    taior.attach_identity_store(identity_store);
+                }
+            });
+
+            match blocklist.load(&config_dir) {
+                Ok(count) => tracing::info!("Loaded {} blocked relays", count),
+                Err(e) => tracing::error!("Failed to load relay blocklist: {:#}", e),
+            }
+
+            Ok(())
+        })
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(move |app, event| {
+            // The event loop runs inside the async runtime, so the shutdown can't be
+            // blocked on here; exit is held back until it finishes, then requested again
+            if let RunEvent::ExitRequested { api, .. } = event {
+                if exit_started.swap(true, Ordering::SeqCst) {
+                    return;
+                }
+                api.prevent_exit();
+                exit_receive.cancel();
+                let app = app.clone();
+                let taior = exit_taior.clone();
+                let transport = exit_transport.clone();
+                tauri::async_runtime::spawn(async move {
+                    shutdown_on_exit(&taior, &transport, EXIT_SHUTDOWN_TIMEOUT).await;
+                    app.exit(0);
+                });
+            }
+        });
+}
+
+/// Stops cover traffic and closes every relay connection with the shutdown code so
+/// relays don't see an abrupt reset. Queued messages are persisted rather than
+/// flushed; the whole run, lock waits included, is bounded by `timeout`.
+async fn shutdown_on_exit(
+    taior: &SharedState<TaiorState>,
+    transport: &SharedState<QuicTransport>,
+    timeout: Duration,
+) {
+    let closing = async {
+        if let Ok(mut taior) = taior.write().await {
+            taior.shutdown();
+        }
+        let mut transport = transport.write().await?;
+        anyhow::Ok(transport.shutdown(false, timeout).await)
+    };
+    match tokio::time::timeout(timeout, closing).await {
+        Ok(Ok(report)) => tracing::info!("Closed {} relay connections on exit", report.closed),
+        Ok(Err(e)) => tracing::warn!("Shutdown on exit failed: {:#}", e),
+        Err(_) => tracing::warn!("Shutdown on exit timed out after {:?}", timeout),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::relay_client::RelayNode;
+    use crate::taior_bridge::TaiorConfig;
+    use crate::test_relay::TestRelay;
+    use std::time::Instant;
+
+    #[tokio::test]
+    async fn exit_closes_relay_connections() {
+        let relay = TestRelay::start().unwrap();
+        let mut transport = QuicTransport::new();
+        relay.pin(&mut transport);
+        transport.connect(relay.relay_info().unwrap()).await.unwrap();
+        let connection = transport.connection().unwrap();
+        let transport = SharedState::new(transport);
+
+        shutdown_on_exit(&SharedState::new(TaiorState::new()), &transport, EXIT_SHUTDOWN_TIMEOUT).await;
+
+        assert!(transport.read().await.unwrap().connection().is_none());
+        assert!(connection.close_reason().is_some());
+        relay.stop();
+    }
+
+    #[tokio::test]
+    async fn exit_is_bounded_by_a_held_lock() {
+        let taior = SharedState::new(TaiorState::new());
+        let transport = SharedState::new(QuicTransport::new());
+        let _held = transport.write().await.unwrap();
+
+        let started = Instant::now();
+        shutdown_on_exit(&taior, &transport, Duration::from_millis(200)).await;
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[tokio::test]
+    async fn core_types_carry_a_message_without_tauri() {
+        let relay = TestRelay::start().unwrap();
+        let info = relay.relay_info().unwrap();
+
+        let mut taior = TaiorState::new();
+        let address = taior.init(TaiorConfig { bootstrap_nodes: Vec::new() }).unwrap();
+        let mut transport = QuicTransport::new();
+        relay.pin(&mut transport);
+        let mut discovery = RelayDiscovery::new();
+        for known in discovery.get_available_relays(false) {
+            discovery.remove_relay(&known.id);
+        }
+        discovery.add_relay(RelayNode {
+            id: info.pin_key(),
+            address: info.address.clone(),
+            port: info.port,
+            public_key: String::new(),
+            latency_ms: None,
+            bandwidth_mbps: None,
+            connect_timeout_ms: None,
+            network: None,
+        });
+
+        let (packet, _) = taior.send(b"embedded", "fast", &address).unwrap();
+        let mut budget = transport.new_retry_budget();
+        let used = transport.send_via_discovery(&mut discovery, &packet, &mut budget).await.unwrap();
+        assert_eq!(used, info.pin_key());
+
+        let echoed = transport.recv(64 * 1024, Duration::from_secs(5)).await.unwrap();
+        let (plaintext, sender) = taior.receive(&echoed).unwrap();
+        assert_eq!(plaintext, b"embedded");
+        assert_eq!(sender, address);
+
+        assert!(transport.disconnect().is_some());
+        relay.stop();
+    }
+}
@@ -0,0 +1,68 @@
+use anyhow::Result;
+use tauri::State;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{reload, Registry};
+
+use crate::error::HushError;
+
+/// Level accepted by [`LogLevel::set`] and read from `RUST_LOG` at startup.
+const LEVELS: &[&str] = &["off", "error", "warn", "info", "debug", "trace"];
+
+/// Runtime handle on the global log filter, so a user can turn on debug logs for a bug
+/// report without restarting the app.
+#[derive(Clone)]
+pub struct LogLevel {
+    handle: reload::Handle<LevelFilter, Registry>,
+}
+
+impl LogLevel {
+    pub fn set(&self, level: &str) -> Result<()> {
+        let filter = parse_level(level)?;
+        self.handle.modify(|current| *current = filter)
+            .map_err(|e| anyhow::anyhow!("Failed to change log level: {}", e))?;
+        tracing::info!("Log level set to {}", filter);
+        Ok(())
+    }
+
+    pub fn current(&self) -> Option<String> {
+        self.handle.clone_current().map(|filter| filter.to_string())
+    }
+}
+
+fn parse_level(level: &str) -> Result<LevelFilter> {
+    let level = level.trim().to_ascii_lowercase();
+    if !LEVELS.contains(&level.as_str()) {
+        return Err(HushError::InvalidInput(format!(
+            "Unknown log level {:?}; expected one of {}",
+            level,
+            LEVELS.join(", ")
+        ))
+        .into());
+    }
+    Ok(level.parse().expect("listed levels parse"))
+}
+
+/// Installs the global subscriber at the level named by `RUST_LOG`, or `info`.
+pub fn init() -> LogLevel {
+    let initial = std::env::var("RUST_LOG").ok()
+        .and_then(|level| parse_level(&level).ok())
+        .unwrap_or(LevelFilter::INFO);
+    let (filter, handle) = reload::Layer::new(initial);
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer())
+        .init();
+    LogLevel { handle }
+}
+
+/// `level` is one of `off`, `error`, `warn`, `info`, `debug` or `trace`.
+#[tauri::command]
+pub async fn set_log_level(level: String, log_level: State<'_, LogLevel>) -> Result<(), HushError> {
+    log_level.set(&level).map_err(HushError::from)
+}
+
+#[tauri::command]
+pub async fn get_log_level(log_level: State<'_, LogLevel>) -> Result<Option<String>, HushError> {
+    Ok(log_level.current())
+}
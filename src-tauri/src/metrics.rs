@@ -0,0 +1,189 @@
+use std::fmt::Write;
+use std::sync::Arc;
+use tauri::State;
+
+use crate::error::HushError;
+use crate::quic_transport::QuicTransport;
+use crate::shared_state::SharedState;
+
+#[derive(Debug, Clone, Copy)]
+enum MetricType {
+    Counter,
+    Gauge,
+}
+
+impl MetricType {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Counter => "counter",
+            Self::Gauge => "gauge",
+        }
+    }
+}
+
+/// Builds a Prometheus text exposition (format 0.0.4), one unlabelled sample per metric.
+#[derive(Default)]
+struct Exposition {
+    out: String,
+}
+
+impl Exposition {
+    fn metric(&mut self, name: &str, kind: MetricType, help: &str, value: f64) {
+        let _ = writeln!(self.out, "# HELP {} {}", name, help);
+        let _ = writeln!(self.out, "# TYPE {} {}", name, kind.as_str());
+        let _ = writeln!(self.out, "{} {}", name, value);
+    }
+}
+
+/// Current transport counters and gauges in Prometheus text format. Session counters
+/// restart at zero on every new relay connection, which Prometheus treats as a reset.
+pub fn render(transport: &QuicTransport) -> String {
+    let mut exposition = Exposition::default();
+    let connection = transport.connection();
+    let stats = connection.as_ref().map(|c| c.stats());
+
+    exposition.metric(
+        "hush_connected",
+        MetricType::Gauge,
+        "Whether a relay connection is active.",
+        if connection.is_some() { 1.0 } else { 0.0 },
+    );
+    exposition.metric(
+        "hush_session_bytes_sent_total",
+        MetricType::Counter,
+        "UDP bytes sent on the current relay connection.",
+        stats.as_ref().map_or(0.0, |s| s.udp_tx.bytes as f64),
+    );
+    exposition.metric(
+        "hush_session_bytes_received_total",
+        MetricType::Counter,
+        "UDP bytes received on the current relay connection.",
+        stats.as_ref().map_or(0.0, |s| s.udp_rx.bytes as f64),
+    );
+    exposition.metric(
+        "hush_session_messages_sent_total",
+        MetricType::Counter,
+        "Messages sent on the current relay connection.",
+        transport.session_messages_sent() as f64,
+    );
+    exposition.metric(
+        "hush_rtt_seconds",
+        MetricType::Gauge,
+        "Smoothed round-trip time to the relay.",
+        connection.as_ref().map_or(0.0, |c| c.rtt().as_secs_f64()),
+    );
+    exposition.metric(
+        "hush_packet_loss_ratio",
+        MetricType::Gauge,
+        "Share of packets lost on the current relay connection.",
+        stats.as_ref().map_or(0.0, |s| {
+            if s.path.sent_packets == 0 {
+                0.0
+            } else {
+                s.path.lost_packets as f64 / s.path.sent_packets as f64
+            }
+        }),
+    );
+    exposition.metric(
+        "hush_reconnects_total",
+        MetricType::Counter,
+        "Relay connections established after the first since startup.",
+        transport.reconnects() as f64,
+    );
+    exposition.metric(
+        "hush_send_queue_depth",
+        MetricType::Gauge,
+        "Messages waiting in the send queue.",
+        transport.pending_send_count() as f64,
+    );
+    exposition.metric(
+        "hush_open_streams",
+        MetricType::Gauge,
+        "Streams left open for later writes.",
+        transport.kept_stream_count() as f64,
+    );
+
+    exposition.out
+}
+
+#[tauri::command]
+pub async fn metrics_prometheus(
+    state: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<String, HushError> {
+    Ok(render(&*state.read().await?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quic_transport::FinishMode;
+    use crate::send_queue::Priority;
+    use crate::test_relay::TestRelay;
+    use std::collections::BTreeMap;
+
+    fn is_metric_name(name: &str) -> bool {
+        let mut chars = name.chars();
+        chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == ':')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':')
+    }
+
+    /// Checks `text` against the exposition format and returns each sample with its type.
+    fn parse(text: &str) -> BTreeMap<String, (String, f64)> {
+        let mut types = BTreeMap::new();
+        let mut samples = BTreeMap::new();
+        for line in text.lines() {
+            let mut fields = line.splitn(4, ' ');
+            match (fields.next(), fields.next(), fields.next(), fields.next()) {
+                (Some("#"), Some("HELP"), Some(name), Some(help)) => {
+                    assert!(is_metric_name(name) && !help.is_empty(), "bad HELP line: {}", line);
+                }
+                (Some("#"), Some("TYPE"), Some(name), Some(kind)) => {
+                    assert!(["counter", "gauge"].contains(&kind), "bad TYPE line: {}", line);
+                    assert!(types.insert(name.to_string(), kind.to_string()).is_none(), "TYPE repeated: {}", name);
+                }
+                (Some(name), Some(value), None, None) => {
+                    assert!(is_metric_name(name), "bad metric name: {}", line);
+                    let kind = types.get(name).unwrap_or_else(|| panic!("sample before its TYPE: {}", line));
+                    let value: f64 = value.parse().unwrap_or_else(|_| panic!("bad value: {}", line));
+                    assert!(samples.insert(name.to_string(), (kind.clone(), value)).is_none(), "sample repeated: {}", name);
+                }
+                _ => panic!("unparseable line: {:?}", line),
+            }
+        }
+        samples
+    }
+
+    #[tokio::test]
+    async fn exposition_parses_and_names_every_metric() {
+        let relay = TestRelay::start().unwrap();
+        let mut transport = QuicTransport::new();
+        relay.pin(&mut transport);
+        let disconnected = parse(&render(&transport));
+        assert_eq!(disconnected["hush_connected"], ("gauge".to_string(), 0.0));
+
+        transport.connect(relay.relay_info().unwrap()).await.unwrap();
+        transport.send(b"counted", FinishMode::Finish, Priority::Normal).await.unwrap();
+        let samples = parse(&render(&transport));
+
+        let expected = [
+            ("hush_connected", "gauge"),
+            ("hush_session_bytes_sent_total", "counter"),
+            ("hush_session_bytes_received_total", "counter"),
+            ("hush_session_messages_sent_total", "counter"),
+            ("hush_rtt_seconds", "gauge"),
+            ("hush_packet_loss_ratio", "gauge"),
+            ("hush_reconnects_total", "counter"),
+            ("hush_send_queue_depth", "gauge"),
+            ("hush_open_streams", "gauge"),
+        ];
+        assert_eq!(samples.len(), expected.len());
+        for (name, kind) in expected {
+            assert_eq!(samples[name].0, kind, "{}", name);
+        }
+        assert_eq!(samples["hush_connected"].1, 1.0);
+        assert_eq!(samples["hush_session_messages_sent_total"].1, 1.0);
+        assert!(samples["hush_session_bytes_sent_total"].1 > 0.0);
+        assert!(samples["hush_rtt_seconds"].1 > 0.0);
+        relay.stop();
+    }
+}
@@ -0,0 +1,141 @@
+//! Artificial latency, jitter and loss on outgoing UDP datagrams so QA can reproduce
+//! poor-network behaviour deterministically. Only built with the `network-sim` feature;
+//! release builds never contain the wrapping socket.
+
+use anyhow::Result;
+use quinn::udp::{RecvMeta, Transmit};
+use quinn::{AsyncUdpSocket, Runtime, TokioRuntime, UdpPoller};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::io::{self, IoSliceMut};
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct NetworkSimParams {
+    pub latency_ms: u64,
+    pub loss_pct: f32,
+    pub jitter_ms: u64,
+}
+
+impl NetworkSimParams {
+    pub fn is_active(&self) -> bool {
+        self.latency_ms > 0 || self.jitter_ms > 0 || self.loss_pct > 0.0
+    }
+}
+
+static PARAMS: Mutex<NetworkSimParams> = Mutex::new(NetworkSimParams {
+    latency_ms: 0,
+    loss_pct: 0.0,
+    jitter_ms: 0,
+});
+
+/// Applies to every endpoint, including ones created before the call.
+pub fn set(params: NetworkSimParams) -> Result<()> {
+    if !(0.0..=100.0).contains(&params.loss_pct) {
+        anyhow::bail!("Loss must be between 0 and 100 percent, got {}", params.loss_pct);
+    }
+
+    *PARAMS.lock().map_err(|_| anyhow::anyhow!("Network sim settings poisoned"))? = params;
+    tracing::warn!("Network simulation: {:?}", params);
+    Ok(())
+}
+
+pub fn current() -> NetworkSimParams {
+    PARAMS.lock().map(|p| *p).unwrap_or_default()
+}
+
+/// Wraps a bound UDP socket so its sends pass through the simulator.
+pub fn wrap(socket: std::net::UdpSocket) -> io::Result<Arc<dyn AsyncUdpSocket>> {
+    wrap_with(socket, &PARAMS)
+}
+
+/// Like [`wrap`], but following `params` instead of the process-wide settings.
+pub(crate) fn wrap_with(
+    socket: std::net::UdpSocket,
+    params: &'static Mutex<NetworkSimParams>,
+) -> io::Result<Arc<dyn AsyncUdpSocket>> {
+    let inner = TokioRuntime.wrap_udp_socket(socket)?;
+    Ok(Arc::new(SimSocket { inner, params }))
+}
+
+#[derive(Debug)]
+struct SimSocket {
+    inner: Arc<dyn AsyncUdpSocket>,
+    params: &'static Mutex<NetworkSimParams>,
+}
+
+impl AsyncUdpSocket for SimSocket {
+    fn create_io_poller(self: Arc<Self>) -> Pin<Box<dyn UdpPoller>> {
+        self.inner.clone().create_io_poller()
+    }
+
+    fn try_send(&self, transmit: &Transmit) -> io::Result<()> {
+        let params = self.params.lock().map(|p| *p).unwrap_or_default();
+        if !params.is_active() {
+            return self.inner.try_send(transmit);
+        }
+
+        let (dropped, delay_ms) = {
+            let mut rng = rand::thread_rng();
+            let jitter = if params.jitter_ms > 0 { rng.gen_range(0..=params.jitter_ms) } else { 0 };
+            (rng.gen::<f32>() * 100.0 < params.loss_pct, params.latency_ms + jitter)
+        };
+        // Lost datagrams report success, exactly as a lossy network would
+        if dropped {
+            return Ok(());
+        }
+        if delay_ms == 0 {
+            return self.inner.try_send(transmit);
+        }
+
+        let inner = self.inner.clone();
+        let destination = transmit.destination;
+        let ecn = transmit.ecn;
+        let segment_size = transmit.segment_size;
+        let src_ip = transmit.src_ip;
+        let contents = transmit.contents.to_vec();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+            let delayed = Transmit {
+                destination,
+                ecn,
+                contents: &contents,
+                segment_size,
+                src_ip,
+            };
+            if let Err(e) = inner.try_send(&delayed) {
+                tracing::trace!("Delayed datagram dropped: {}", e);
+            }
+        });
+        Ok(())
+    }
+
+    fn poll_recv(
+        &self,
+        cx: &mut Context,
+        bufs: &mut [IoSliceMut<'_>],
+        meta: &mut [RecvMeta],
+    ) -> Poll<io::Result<usize>> {
+        self.inner.poll_recv(cx, bufs, meta)
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    fn max_transmit_segments(&self) -> usize {
+        self.inner.max_transmit_segments()
+    }
+
+    fn max_receive_segments(&self) -> usize {
+        self.inner.max_receive_segments()
+    }
+
+    fn may_fragment(&self) -> bool {
+        self.inner.may_fragment()
+    }
+}
@@ -0,0 +1,181 @@
+use serde::Serialize;
+use std::sync::Arc;
+use tauri::State;
+use tokio::sync::RwLock;
+
+use crate::cover_traffic::CoverDestinationPolicy;
+use crate::error::HushError;
+use crate::quic_transport::QuicTransport;
+use crate::reconnect::{AutoReconnect, ReconnectStatus};
+use crate::relay_client::RelayDiscovery;
+use crate::shared_state::{SharedState, StatePoisoned};
+use crate::taior_bridge::TaiorState;
+
+/// Point-in-time view of the backend for dashboards and integration tests.
+#[derive(Debug, Clone, Serialize)]
+pub struct AppSnapshot {
+    pub taior_initialized: bool,
+    pub taior_address: Option<String>,
+    pub cover_traffic_enabled: bool,
+    pub cover_traffic_ratio: f32,
+    pub cover_destination: CoverDestinationPolicy,
+    pub cover_streams_active: usize,
+    pub connected: bool,
+    pub relay_address: Option<String>,
+    pub active_connections: usize,
+    pub queue_depth: usize,
+    pub open_streams: usize,
+    pub inbound_listening: bool,
+    pub known_relays: usize,
+    pub unhealthy_relays: usize,
+    pub reconnect: ReconnectStatus,
+}
+
+/// Builds a snapshot while holding all three state locks at once so no field can
+/// change between reads. Locks are always taken in the order Taior → transport →
+/// discovery; any other code that holds more than one of them must use the same order.
+/// The reconnect status is read last, while the three are still held.
+pub async fn snapshot(
+    taior: &SharedState<TaiorState>,
+    transport: &SharedState<QuicTransport>,
+    discovery: &RwLock<RelayDiscovery>,
+    reconnect: &AutoReconnect,
+) -> Result<AppSnapshot, StatePoisoned> {
+    let taior = taior.read().await?;
+    let transport = transport.read().await?;
+    let discovery = discovery.read().await;
+
+    let (cover_traffic_enabled, cover_traffic_ratio) = taior.cover_traffic();
+    let status = transport.status();
+
+    Ok(AppSnapshot {
+        taior_initialized: taior.is_initialized(),
+        taior_address: taior.address().ok(),
+        cover_traffic_enabled,
+        cover_traffic_ratio,
+        cover_destination: taior.cover_destination().clone(),
+        cover_streams_active: taior.cover_streams().active,
+        connected: status.connected,
+        relay_address: status.relay_address,
+        active_connections: transport.connection_count(),
+        queue_depth: transport.pending_send_count(),
+        open_streams: transport.kept_stream_count(),
+        inbound_listening: transport.inbound_listening(),
+        known_relays: discovery.len(),
+        unhealthy_relays: discovery.unhealthy_count(),
+        reconnect: reconnect.status(),
+    })
+}
+
+#[tauri::command]
+pub async fn observe(
+    taior: State<'_, Arc<SharedState<TaiorState>>>,
+    transport: State<'_, Arc<SharedState<QuicTransport>>>,
+    discovery: State<'_, Arc<RwLock<RelayDiscovery>>>,
+    reconnect: State<'_, AutoReconnect>,
+) -> Result<AppSnapshot, HushError> {
+    Ok(snapshot(&taior, &transport, &discovery, &reconnect).await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quic_transport::{FinishMode, RelayInfo};
+    use crate::relay_client::RelayNode;
+    use crate::send_queue::Priority;
+    use crate::taior_bridge::TaiorConfig;
+    use crate::test_relay::TestRelay;
+
+    fn pinned_as(relay: &TestRelay, id: &str, transport: &mut QuicTransport) -> RelayInfo {
+        transport.pins_mut().add(id, relay.fingerprint());
+        RelayInfo { id: Some(id.to_string()), ..relay.relay_info().unwrap() }
+    }
+
+    fn node(id: &str) -> RelayNode {
+        RelayNode {
+            id: id.to_string(),
+            address: "198.51.100.1".to_string(),
+            port: 4433,
+            public_key: String::new(),
+            latency_ms: None,
+            bandwidth_mbps: None,
+            connect_timeout_ms: None,
+            network: None,
+        }
+    }
+
+    /// Fields that describe the same thing from different sides must agree.
+    fn assert_consistent(snapshot: &AppSnapshot) {
+        assert_eq!(snapshot.connected, snapshot.relay_address.is_some(), "{:?}", snapshot);
+        assert!(snapshot.active_connections >= usize::from(snapshot.connected), "{:?}", snapshot);
+        assert!(snapshot.open_streams == 0 || snapshot.connected, "{:?}", snapshot);
+        assert_eq!(snapshot.taior_initialized, snapshot.taior_address.is_some(), "{:?}", snapshot);
+        assert!(snapshot.unhealthy_relays <= snapshot.known_relays, "{:?}", snapshot);
+    }
+
+    #[tokio::test]
+    async fn snapshot_stays_consistent_through_connects_and_disconnects() {
+        let (primary, pooled) = (TestRelay::start().unwrap(), TestRelay::start().unwrap());
+        let taior = Arc::new(SharedState::new(TaiorState::new()));
+        let mut transport = QuicTransport::new();
+        let primary_info = pinned_as(&primary, "primary", &mut transport);
+        let pooled_info = pinned_as(&pooled, "pooled", &mut transport);
+        let transport = Arc::new(SharedState::new(transport));
+        let discovery = Arc::new(RwLock::new(RelayDiscovery::new()));
+        let reconnect = AutoReconnect::new();
+
+        let address = taior.write().await.unwrap().init(TaiorConfig { bootstrap_nodes: Vec::new() }).unwrap();
+        transport.write().await.unwrap().enqueue(b"queued".to_vec(), None, Priority::Normal).await.unwrap();
+        {
+            let mut discovery = discovery.write().await;
+            for known in discovery.get_available_relays(false) {
+                discovery.remove_relay(&known.id);
+            }
+            discovery.add_relay(node("primary"));
+            discovery.add_relay(node("pooled"));
+            discovery.mark_unhealthy("pooled");
+        }
+
+        let queued = snapshot(&taior, &transport, &discovery, &reconnect).await.unwrap();
+        assert_consistent(&queued);
+        assert_eq!(queued.taior_address, Some(address));
+        assert_eq!((queued.connected, queued.queue_depth), (false, 1));
+        assert_eq!((queued.known_relays, queued.unhealthy_relays), (2, 1));
+        assert!(!queued.reconnect.enabled);
+
+        {
+            let mut transport = transport.write().await.unwrap();
+            transport.connect(primary_info.clone()).await.unwrap();
+            transport.connect_pooled(pooled_info).await.unwrap();
+            transport.send(b"held", FinishMode::KeepOpen, Priority::Normal).await.unwrap();
+        }
+        let connected = snapshot(&taior, &transport, &discovery, &reconnect).await.unwrap();
+        assert_consistent(&connected);
+        assert_eq!(connected.relay_address, Some(primary_info.host_port()));
+        assert_eq!((connected.active_connections, connected.open_streams, connected.queue_depth), (2, 1, 0));
+
+        // Snapshots taken while another task keeps replacing the default connection
+        let churn = {
+            let transport = transport.clone();
+            tokio::spawn(async move {
+                for _ in 0..20 {
+                    let mut transport = transport.write().await.unwrap();
+                    transport.disconnect();
+                    transport.connect(primary_info.clone()).await.unwrap();
+                }
+            })
+        };
+        while !churn.is_finished() {
+            assert_consistent(&snapshot(&taior, &transport, &discovery, &reconnect).await.unwrap());
+            tokio::task::yield_now().await;
+        }
+        churn.await.unwrap();
+
+        transport.write().await.unwrap().disconnect();
+        let disconnected = snapshot(&taior, &transport, &discovery, &reconnect).await.unwrap();
+        assert_consistent(&disconnected);
+        assert_eq!((disconnected.connected, disconnected.active_connections, disconnected.open_streams), (false, 1, 0));
+        primary.stop();
+        pooled.stop();
+    }
+}
@@ -0,0 +1,162 @@
+use anyhow::{Context, Result};
+use std::sync::Arc;
+use tauri::State;
+
+use crate::circuits::CircuitManager;
+use crate::error::HushError;
+use crate::quic_transport::{FinishMode, QuicTransport, SendTiming};
+use crate::relay_client::{RelayCircuit, RelayNode};
+use crate::send_queue::Priority;
+use crate::shared_state::SharedState;
+
+/// Marks a layered packet: `[FRAME_ONION][u8 next_len][next hop "host:port"][inner]`.
+/// A hop strips its layer and forwards `inner` to the next hop; an empty next hop
+/// means this hop is the exit and `inner` is the payload itself.
+pub const FRAME_ONION: u8 = 0x50;
+
+/// `host:port` must fit the one-byte length prefix.
+const MAX_HOP_ADDR_LEN: usize = u8::MAX as usize;
+
+/// Wraps `payload` for `hops` in order, entry first, so the outermost layer is the one
+/// the entry hop reads. Each layer only names the hop after it.
+///
+/// Layers are framing only: hiding the rest of the route from each hop additionally
+/// needs per-hop encryption, which depends on relays publishing circuit keys. The
+/// payload itself is already end-to-end encrypted by the Taior layer.
+pub fn wrap(hops: &[RelayNode], payload: &[u8]) -> Result<Vec<u8>> {
+    if hops.is_empty() {
+        anyhow::bail!("Circuit has no hops");
+    }
+
+    // Innermost layer first: the exit hop learns no next hop
+    let mut packet = layer(None, payload)?;
+    for next in hops[1..].iter().rev() {
+        packet = layer(Some(next), &packet)?;
+    }
+    Ok(packet)
+}
+
+fn layer(next: Option<&RelayNode>, inner: &[u8]) -> Result<Vec<u8>> {
+    let next_addr = next
+        .map(|hop| format!("{}:{}", hop.address, hop.port))
+        .unwrap_or_default();
+    if next_addr.len() > MAX_HOP_ADDR_LEN {
+        anyhow::bail!("Hop address too long: {}", next_addr);
+    }
+
+    let mut frame = Vec::with_capacity(2 + next_addr.len() + inner.len());
+    frame.push(FRAME_ONION);
+    frame.push(next_addr.len() as u8);
+    frame.extend_from_slice(next_addr.as_bytes());
+    frame.extend_from_slice(inner);
+    Ok(frame)
+}
+
+/// Sends `payload` through `circuit`, connecting to the entry hop first unless it is
+/// already in the connection pool.
+pub async fn send(
+    transport: &mut QuicTransport,
+    circuit: &RelayCircuit,
+    payload: &[u8],
+    priority: Priority,
+) -> Result<SendTiming> {
+    let entry = circuit.get_hops().first().context("Circuit has no hops")?;
+    let packet = wrap(circuit.get_hops(), payload)?;
+
+    let entry_id = transport.connect_pooled(entry.to_relay_info())
+        .await
+        .with_context(|| format!("Failed to reach entry hop {}", entry.id))?;
+    let (timing, _) = transport
+        .send_to(Some(&entry_id), &packet, FinishMode::Finish, priority)
+        .await?;
+
+    tracing::debug!("Sent {} bytes over a {}-hop circuit", payload.len(), circuit.get_hops().len());
+    Ok(timing)
+}
+
+/// Sends over the circuit the [`CircuitManager`] currently holds.
+#[tauri::command]
+pub async fn send_via_circuit(
+    data: Vec<u8>,
+    priority: Option<Priority>,
+    circuits: State<'_, CircuitManager>,
+    state: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<SendTiming, HushError> {
+    let circuit = circuits.circuit()
+        .ok_or_else(|| HushError::Routing("No circuit built yet".to_string()))?;
+
+    let mut transport = state.write().await?;
+    send(&mut transport, &circuit, &data, priority.unwrap_or_default())
+        .await
+        .map_err(HushError::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_relay::TestRelay;
+    use quinn::Connection;
+    use std::time::Duration;
+    use tokio::sync::mpsc;
+
+    fn hop(id: &str, address: &str, port: u16) -> RelayNode {
+        RelayNode {
+            id: id.to_string(),
+            address: address.to_string(),
+            port,
+            public_key: String::new(),
+            latency_ms: None,
+            bandwidth_mbps: None,
+            connect_timeout_ms: None,
+            network: None,
+        }
+    }
+
+    /// Strips one layer, returning the next hop it names and the inner packet.
+    fn peel(packet: &[u8]) -> (String, &[u8]) {
+        assert_eq!(packet[0], FRAME_ONION);
+        let next_len = packet[1] as usize;
+        let next = String::from_utf8(packet[2..2 + next_len].to_vec()).unwrap();
+        (next, &packet[2 + next_len..])
+    }
+
+    #[tokio::test]
+    async fn layers_nest_in_hop_order() {
+        let (received_tx, mut received) = mpsc::unbounded_channel();
+        let entry = TestRelay::serve(Duration::ZERO, move |connection: Connection| {
+            let received_tx = received_tx.clone();
+            async move {
+                while let Ok(mut recv) = connection.accept_uni().await {
+                    if let Ok(packet) = recv.read_to_end(64 * 1024).await {
+                        let _ = received_tx.send(packet);
+                    }
+                }
+            }
+        })
+        .unwrap();
+        let published = entry.relay_info().unwrap();
+
+        let mut circuit = RelayCircuit::new(3);
+        circuit.add_hop(hop(&published.pin_key(), &published.address, published.port)).unwrap();
+        circuit.add_hop(hop("middle", "10.0.0.2", 4433)).unwrap();
+        circuit.add_hop(hop("exit", "relay-exit.example", 8443)).unwrap();
+
+        let mut transport = QuicTransport::new();
+        entry.pin(&mut transport);
+        send(&mut transport, &circuit, b"payload", Priority::Normal).await.unwrap();
+        let packet = tokio::time::timeout(Duration::from_secs(5), received.recv()).await.unwrap().unwrap();
+        assert_eq!(packet, wrap(circuit.get_hops(), b"payload").unwrap());
+
+        // The entry's layer names the middle hop, the middle's the exit, the exit's none
+        let mut inner = packet.as_slice();
+        for next in circuit.get_hops()[1..].iter().map(Some).chain([None]) {
+            let (named, rest) = peel(inner);
+            assert_eq!(named, next.map(|h| format!("{}:{}", h.address, h.port)).unwrap_or_default());
+            inner = rest;
+        }
+        assert_eq!(inner, b"payload");
+
+        assert!(wrap(&[], b"payload").is_err());
+        entry.stop();
+    }
+}
@@ -0,0 +1,162 @@
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, State};
+use tokio_util::sync::CancellationToken;
+
+use crate::error::HushError;
+use crate::quic_transport::QuicTransport;
+use crate::shared_state::SharedState;
+
+/// Shorter intervals churn connections without making ports meaningfully less linkable.
+pub const MIN_ROTATION_SECS: u64 = 30;
+
+/// Periodically moves the client endpoint to a new UDP source port so one port can't
+/// link a long stretch of traffic. Cloned handles control the same schedule.
+#[derive(Clone, Default)]
+pub struct PortRotation {
+    running: Arc<Mutex<Option<CancellationToken>>>,
+}
+
+impl PortRotation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts rotating every `interval`, replacing any earlier schedule.
+    pub fn start(&self, interval: Duration, transport: Arc<SharedState<QuicTransport>>, app: AppHandle) {
+        self.schedule(interval, transport, move |local_addr: SocketAddr| {
+            if let Err(e) = app.emit("source-port-rotated", local_addr.to_string()) {
+                tracing::debug!("Failed to emit source-port-rotated: {}", e);
+            }
+        });
+    }
+
+    /// [`Self::start`] reporting each new local address to `rotated`.
+    fn schedule<E>(&self, interval: Duration, transport: Arc<SharedState<QuicTransport>>, rotated: E)
+    where
+        E: Fn(SocketAddr) + Send + 'static,
+    {
+        let token = CancellationToken::new();
+        if let Ok(mut running) = self.running.lock() {
+            if let Some(old) = running.replace(token.clone()) {
+                old.cancel();
+            }
+        }
+        tokio::spawn(rotate_periodically(interval, transport, rotated, token));
+        tracing::info!("Source port rotation every {:?}", interval);
+    }
+
+    pub fn stop(&self) {
+        if let Ok(mut running) = self.running.lock() {
+            if let Some(token) = running.take() {
+                token.cancel();
+                tracing::info!("Source port rotation stopped");
+            }
+        }
+    }
+}
+
+async fn rotate_periodically<E: Fn(SocketAddr)>(
+    interval: Duration,
+    transport: Arc<SharedState<QuicTransport>>,
+    rotated: E,
+    shutdown: CancellationToken,
+) {
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => break,
+            _ = tokio::time::sleep(interval) => {}
+        }
+
+        let outcome = match transport.write().await {
+            Ok(mut transport) => transport.rotate_source_port().await,
+            Err(e) => Err(anyhow::anyhow!(e.to_string())),
+        };
+        match outcome {
+            Ok(Some(local_addr)) => {
+                tracing::info!("Rotated source port to {}", local_addr);
+                rotated(local_addr);
+            }
+            Ok(None) => {}
+            Err(e) => tracing::warn!("Source port rotation failed: {:#}", e),
+        }
+    }
+}
+
+/// `None` stops rotating.
+#[tauri::command]
+pub async fn set_port_rotation(
+    interval_secs: Option<u64>,
+    app: AppHandle,
+    rotation: State<'_, PortRotation>,
+    state: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<(), HushError> {
+    let Some(secs) = interval_secs else {
+        rotation.stop();
+        return Ok(());
+    };
+    if secs < MIN_ROTATION_SECS {
+        return Err(HushError::InvalidInput(format!(
+            "Rotation interval must be at least {}s",
+            MIN_ROTATION_SECS
+        )));
+    }
+    if let Some(port) = state.read().await?.source_port() {
+        return Err(HushError::InvalidInput(format!(
+            "Source port is fixed to {}; clear it before enabling rotation",
+            port
+        )));
+    }
+
+    rotation.start(Duration::from_secs(secs), state.inner().clone(), app);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::send_queue::Priority;
+    use crate::test_relay::TestRelay;
+
+    /// Round trip through the echoing relay. The request is framed like a response, so
+    /// the echo reads back as its body.
+    async fn still_connected(transport: &SharedState<QuicTransport>) {
+        let request = [&5u32.to_be_bytes()[..], b"hello"].concat();
+        let response = transport.read().await.unwrap().send_recv(&request, Priority::Normal).await.unwrap();
+        assert_eq!(response, b"hello");
+    }
+
+    #[tokio::test]
+    async fn port_changes_each_interval_and_traffic_continues() {
+        let relay = TestRelay::start().unwrap();
+        for per_connection in [false, true] {
+            let mut transport = QuicTransport::new();
+            relay.pin(&mut transport);
+            transport.set_per_connection_endpoint(per_connection);
+            transport.connect(relay.relay_info().unwrap()).await.unwrap();
+            let mut ports = vec![transport.local_endpoint_addr().unwrap().port()];
+            let transport = Arc::new(SharedState::new(transport));
+
+            let (rotated_tx, mut rotated) = tokio::sync::mpsc::unbounded_channel();
+            let rotation = PortRotation::new();
+            rotation.schedule(Duration::from_millis(300), transport.clone(), move |addr: SocketAddr| {
+                let _ = rotated_tx.send(addr);
+            });
+            for _ in 0..2 {
+                let addr = tokio::time::timeout(Duration::from_secs(5), rotated.recv()).await.unwrap().unwrap();
+                assert_eq!(transport.read().await.unwrap().local_endpoint_addr().unwrap(), addr);
+                assert!(!ports.contains(&addr.port()), "port {} reused: {:?}", addr.port(), ports);
+                ports.push(addr.port());
+                still_connected(&transport).await;
+            }
+
+            rotation.stop();
+            let last = transport.read().await.unwrap().local_endpoint_addr().unwrap();
+            let after_stop = tokio::time::timeout(Duration::from_millis(700), rotated.recv()).await;
+            assert!(!matches!(after_stop, Ok(Some(_))), "rotated after stop: {:?}", after_stop);
+            assert_eq!(transport.read().await.unwrap().local_endpoint_addr().unwrap(), last);
+        }
+        relay.stop();
+    }
+}
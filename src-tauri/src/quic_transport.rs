@@ -1,17 +1,153 @@
 use anyhow::{Context, Result};
-use quinn::{ClientConfig, Endpoint, Connection};
+use quinn::{ClientConfig, Endpoint, Connection, MtuDiscoveryConfig, SendStream, TransportConfig};
 use rustls::pki_types::CertificateDer;
 use serde::{Deserialize, Serialize};
-use std::net::SocketAddr;
-use std::sync::Arc;
-use tauri::State;
-use tokio::sync::RwLock;
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager, State};
+use tokio::sync::{oneshot, RwLock};
+
+use crate::ack::{self, AckStatus};
+use crate::blocklist::RelayBlocklist;
+use crate::cert_pins::{
+    self, CertVerificationHook, Fingerprint, PendingConfirmations, RelayPins, RelayTrust,
+    UnpinnedPolicy,
+};
+use crate::close_codes::AppCloseCode;
+use crate::cover_traffic::CoverStats;
+use crate::dedup::InboundDedup;
+use crate::error::HushError;
+use crate::directory_mirror::DirectoryMirror;
+use crate::inbound::InboundListener;
+use crate::keepalive::{self, AdaptiveKeepAlive, ConnectionTuning, NetworkKeepAlive};
+use crate::receipts::ReceiptTracker;
+use crate::relay_client::{self, ConnectivityMatrix, RelayDiscovery};
+use crate::resumable::{self, Checkpoint, TransferProgress};
+use crate::response;
+use crate::retry::{self, Backoff, RetryBudget, RetryMechanism, DEFAULT_RETRY_BUDGET, MAX_RETRY_DELAY};
+use crate::send_queue::{Priority, QueuedMessage, SendQueue};
+use crate::session_tickets::SessionTickets;
+use crate::shared_state::SharedState;
+use crate::store_forward::{self, DeliveryOutcome};
+use crate::taior_bridge::{self, TaiorState};
+use crate::throttle::BandwidthLimits;
+use crate::timeouts::TimeoutConfig;
+
+/// Asks a relay whether it can forward to the `host:port` that follows. The relay
+/// answers with a single status byte, `FORWARD_OK` when the next hop is reachable.
+const FRAME_FORWARD_PROBE: u8 = 0x10;
+const FORWARD_OK: u8 = 0x00;
+
+/// Largest stream `recv_via_quic` reads unless the caller sets its own cap.
+const DEFAULT_RECV_LIMIT: usize = 1024 * 1024;
+
+/// How long a migration target must stay connected before traffic moves to it.
+const MIGRATION_SETTLE: Duration = Duration::from_millis(500);
+
+/// Upper bound on the shutdown run when the app exits, so a relay that never answers
+/// the close can't keep the process alive.
+pub const EXIT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// How a send ends its stream, matching what the relay's protocol expects.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FinishMode {
+    /// Send FIN; the relay reads to EOF.
+    #[default]
+    Finish,
+    /// Leave the stream open for a relay that frames messages itself; close it later
+    /// with `finish_stream`.
+    KeepOpen,
+    /// Use a bidirectional stream, wait for the relay's one-byte ack, then reset the
+    /// send half instead of sending FIN.
+    ResetAfterAck,
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RelayInfo {
+    #[serde(default)]
+    pub id: Option<String>,
     pub address: String,
     pub port: u16,
     pub public_key: Option<String>,
+    /// Overrides the global connect timeout for this relay.
+    #[serde(default)]
+    pub connect_timeout_ms: Option<u64>,
+    /// TLS server name (SNI) sent in the handshake; see [`Self::server_name`].
+    #[serde(default)]
+    pub server_name: Option<String>,
+}
+
+impl RelayInfo {
+    /// Key used to look up certificate pins: the relay id, or `address:port` when
+    /// the relay was added without one.
+    pub fn pin_key(&self) -> String {
+        self.id.clone()
+            .unwrap_or_else(|| self.host_port())
+    }
+
+    /// `address:port`, as shown to the frontend. IPv6 literals are bracketed.
+    pub fn host_port(&self) -> String {
+        let host = self.host();
+        if host.contains(':') {
+            format!("[{}]:{}", host, self.port)
+        } else {
+            format!("{}:{}", host, self.port)
+        }
+    }
+
+    /// Server name for the TLS handshake, so a relay serving several certificates can
+    /// pick the right one: the configured `server_name`, else the address when it is a
+    /// hostname. IP-literal relays without a configured name send `localhost`.
+    /// Certificates are checked against pins and public keys, never against this name.
+    pub fn server_name(&self) -> &str {
+        if let Some(name) = self.server_name.as_deref().filter(|name| !name.trim().is_empty()) {
+            return name.trim();
+        }
+        if self.host().parse::<IpAddr>().is_ok() {
+            "localhost"
+        } else {
+            self.host()
+        }
+    }
+
+    /// The address without the brackets an IPv6 literal may have been given with.
+    fn host(&self) -> &str {
+        self.address.trim_start_matches('[').trim_end_matches(']')
+    }
+
+    /// Socket addresses for this relay: the literal IP, or every address the hostname
+    /// resolves to, in resolver order. Never empty.
+    pub async fn resolve(&self) -> Result<Vec<SocketAddr>> {
+        if let Ok(ip) = self.host().parse::<IpAddr>() {
+            return Ok(vec![SocketAddr::new(ip, self.port)]);
+        }
+
+        let addrs: Vec<SocketAddr> = tokio::net::lookup_host((self.host(), self.port))
+            .await
+            .map_err(|e| HushError::InvalidAddress(format!(
+                "Could not resolve relay address {}: {}",
+                self.host_port(),
+                e
+            )))?
+            .collect();
+        if addrs.is_empty() {
+            return Err(HushError::InvalidAddress(format!(
+                "Relay address {} resolved to no addresses",
+                self.host_port()
+            ))
+            .into());
+        }
+        Ok(addrs)
+    }
+
+    /// First address of [`Self::resolve`], for probes that try a single address.
+    pub async fn primary_addr(&self) -> Result<SocketAddr> {
+        Ok(self.resolve().await?[0])
+    }
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -19,12 +155,443 @@ pub struct RelayStatus {
     pub connected: bool,
     pub relay_address: Option<String>,
     pub latency_ms: Option<u64>,
+    pub relay_id: Option<String>,
+    /// Whether this is the relay sends go to when no relay id is given.
+    pub is_default: bool,
+}
+
+/// Traffic totals for one relay session, returned when the session ends.
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionSummary {
+    pub relay_address: Option<String>,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub duration_ms: u64,
+    pub messages_sent: u64,
+}
+
+/// Payload of the `relay-connected`, `relay-disconnected` and `relay-error` events:
+/// `{ relay_address: string, error: string | null }`. `error` is set for `relay-error`
+/// and for a `relay-disconnected` caused by the relay or the network rather than by us.
+#[derive(Debug, Clone, Serialize)]
+pub struct RelayLifecycleEvent {
+    pub relay_address: String,
+    pub error: Option<String>,
+}
+
+/// Stream a send wrote to, for matching it against relay-side logs.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct SentStream {
+    pub stream_id: u64,
+    /// Bytes written before the stream was finished; always the full payload, since
+    /// a partial write fails the send.
+    pub bytes_written: usize,
+}
+
+/// What `send_via_quic` reports back.
+#[derive(Debug, Clone, Serialize)]
+pub struct SendResult {
+    /// Id of the stream left open with [`FinishMode::KeepOpen`].
+    pub kept_open: Option<u64>,
+    /// The stream the data went out on; `None` when it was queued or sent as 0-RTT
+    /// early data.
+    pub stream: Option<SentStream>,
+    /// The relay's answer, when the send required an acknowledgement.
+    pub ack: Option<AckStatus>,
+    /// The data went out as 0-RTT early data and the relay accepted it.
+    pub zero_rtt: bool,
+    /// Id in the send queue when there was no connection and the data was buffered
+    /// for delivery after the next connect instead of being sent.
+    pub queued: Option<String>,
+}
+
+/// What `send_via_quic_retry` returns once a send went through.
+#[derive(Debug, Clone, Serialize)]
+pub struct RetrySendResult {
+    /// Attempts made, including the one that succeeded.
+    pub attempts: u32,
+}
+
+/// Most attempts `send_via_quic_retry` accepts for one send.
+const MAX_SEND_ATTEMPTS: u32 = 10;
+
+/// Outcome of [`QuicTransport::shutdown`] for the send queue.
+#[derive(Debug, Clone, Serialize)]
+pub struct ShutdownReport {
+    /// Queued messages sent before closing.
+    pub delivered: usize,
+    /// Messages left in the persisted queue for the next launch.
+    pub deferred: usize,
+    /// Relay connections closed, default and pooled.
+    pub closed: usize,
+}
+
+/// Per-stage durations (microseconds) emitted as a `send-timing` event. Routing is
+/// reported by `taior_send`, the stream stages by the QUIC send path; `total_us`
+/// covers the stages present in that event.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SendTiming {
+    pub routing_us: Option<u64>,
+    pub open_stream_us: Option<u64>,
+    pub write_us: Option<u64>,
+    pub finish_us: Option<u64>,
+    pub total_us: u64,
+}
+
+/// How concurrent per-recipient streams share the uplink in a fan-out send.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FanOutPolicy {
+    /// All recipient streams are written concurrently in weighted chunks, so a
+    /// recipient whose stream is flow-control blocked can't starve the others.
+    #[default]
+    WeightedFair,
+    /// Recipients are written one after another in the given order.
+    Sequential,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RecipientSend {
+    pub recipient: String,
+    pub data: Vec<u8>,
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RecipientResult {
+    pub recipient: String,
+    pub bytes_written: usize,
+    pub elapsed_ms: u64,
+    pub error: Option<String>,
+}
+
+/// Bytes a weight-1 stream writes per scheduling turn.
+const FAIR_QUANTUM: usize = 4 * 1024;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum PinAuditOutcome {
+    Match { fingerprint: String },
+    /// The relay served a certificate none of its pins cover: a rotation that wasn't
+    /// pinned yet, or a MITM.
+    Mismatch { served: String, pinned: Vec<String> },
+    Unreachable { error: String },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PinAuditEntry {
+    pub relay_id: String,
+    pub address: String,
+    pub outcome: PinAuditOutcome,
+}
+
+/// Result of a reachability probe. `latency_ms` is the handshake RTT and is `None`
+/// when the relay could not be reached.
+#[derive(Debug, Clone, Serialize)]
+pub struct RelayProbe {
+    pub relay_id: String,
+    pub latency_ms: Option<u64>,
+    pub error: Option<String>,
+}
+
+/// Payload of the `relay-fingerprint-pending` event: an unpinned relay's certificate
+/// waiting for the user to call `confirm_fingerprint`.
+#[derive(Debug, Clone, Serialize)]
+pub struct FingerprintPrompt {
+    pub relay_id: String,
+    pub fingerprint: String,
+    pub timeout_secs: u64,
+}
+
+/// A fingerprint shown to the user by [`QuicTransport::request_first_use`] and awaiting
+/// `confirm_fingerprint`.
+pub struct FirstUsePrompt {
+    served: Fingerprint,
+    prompt: FingerprintPrompt,
+    decision: oneshot::Receiver<()>,
+    confirmations: PendingConfirmations,
+}
+
+impl FirstUsePrompt {
+    /// Payload for the `relay-fingerprint-pending` event.
+    pub fn event(&self) -> &FingerprintPrompt {
+        &self.prompt
+    }
+
+    /// Waits for the user to confirm the fingerprint and returns it for pinning. Fails
+    /// if no confirmation arrives within the policy's timeout. Call it without holding
+    /// the transport lock; the wait can last as long as the timeout.
+    pub async fn confirmed(self) -> Result<Fingerprint> {
+        let relay_id = &self.prompt.relay_id;
+        let timeout_secs = self.prompt.timeout_secs;
+        match tokio::time::timeout(Duration::from_secs(timeout_secs), self.decision).await {
+            Ok(Ok(())) => Ok(self.served),
+            Ok(Err(_)) => anyhow::bail!("Fingerprint confirmation for relay {} was superseded", relay_id),
+            Err(_) => {
+                self.confirmations.cancel(relay_id);
+                anyhow::bail!(
+                    "Fingerprint for relay {} was not confirmed within {}s",
+                    relay_id,
+                    timeout_secs
+                )
+            }
+        }
+    }
+
+    /// Withdraws the prompt, e.g. when it could not be shown.
+    pub fn cancel(self) {
+        self.confirmations.cancel(&self.prompt.relay_id);
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct InboundStatus {
+    pub local_addr: String,
+    pub fingerprint: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectionParams {
+    pub current_mtu: u16,
+    pub min_mtu: u16,
+    pub max_mtu: u16,
+    pub pmtud_enabled: bool,
+    pub black_hole_detected: bool,
+    pub black_holes_detected: u64,
+}
+
+/// Live counters of the active relay connection, read from quinn on every call.
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectionStats {
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub datagrams_sent: u64,
+    pub datagrams_received: u64,
+    /// Congestion window in bytes.
+    pub congestion_window: u64,
+    pub lost_packets: u64,
+    pub sent_packets: u64,
+    pub rtt_ms: f64,
+}
+
+/// Whether a transport feature is compiled in and whether the current configuration
+/// turns it on, with the relevant setting when there is one.
+#[derive(Debug, Clone, Serialize)]
+pub struct Capability {
+    pub supported: bool,
+    pub enabled: bool,
+    pub config: Option<String>,
+}
+
+impl Capability {
+    fn new(supported: bool, enabled: bool, config: Option<String>) -> Self {
+        Self {
+            supported,
+            enabled,
+            config,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct QuicCapabilities {
+    pub datagrams: Capability,
+    pub zero_rtt: Capability,
+    pub pmtud: Capability,
+    pub migration: Capability,
+    pub bbr: Capability,
+    pub network_sim: Capability,
+    pub keep_alive: Capability,
+}
+
+/// Bounds for QUIC path MTU discovery. `min_mtu` is the floor quinn falls back to when
+/// a black hole is detected; `max_mtu` caps how far discovery probes upward.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct MtuConfig {
+    pub min_mtu: u16,
+    pub max_mtu: u16,
+}
+
+impl Default for MtuConfig {
+    fn default() -> Self {
+        Self {
+            min_mtu: 1200,
+            max_mtu: 1452,
+        }
+    }
+}
+
+impl MtuConfig {
+    fn validate(&self) -> Result<()> {
+        if self.min_mtu < 1200 {
+            anyhow::bail!("MTU floor must be at least 1200 bytes (QUIC minimum)");
+        }
+        if self.min_mtu > self.max_mtu {
+            anyhow::bail!("MTU floor {} exceeds ceiling {}", self.min_mtu, self.max_mtu);
+        }
+        Ok(())
+    }
+
+    /// Discovery only runs when there is room to probe above the floor.
+    pub fn discovery_enabled(&self) -> bool {
+        self.max_mtu > self.min_mtu
+    }
+
+    fn transport_config(&self) -> TransportConfig {
+        let mut discovery = MtuDiscoveryConfig::default();
+        discovery.upper_bound(self.max_mtu);
+
+        let mut transport = TransportConfig::default();
+        transport
+            .initial_mtu(self.min_mtu)
+            .min_mtu(self.min_mtu)
+            .mtu_discovery_config(self.discovery_enabled().then_some(discovery))
+            .datagram_receive_buffer_size(Some(DATAGRAM_BUFFER_SIZE))
+            .datagram_send_buffer_size(DATAGRAM_BUFFER_SIZE);
+        transport
+    }
+}
+
+/// Datagrams buffered in each direction before the oldest are dropped.
+const DATAGRAM_BUFFER_SIZE: usize = 1024 * 1024;
+
+type ConnectOutcome = Result<(), HushError>;
+
+/// Coalesces concurrent `connect_to_relay` calls for the same relay onto a single
+/// handshake. Lives outside the transport lock so a second caller can register as a
+/// waiter while the first holds the lock for its handshake.
+#[derive(Debug, Clone, Default)]
+pub struct ConnectDedup {
+    in_flight: Arc<Mutex<HashMap<String, Vec<oneshot::Sender<ConnectOutcome>>>>>,
+}
+
+/// Held by the caller that performs the handshake. Dropping it without completing
+/// (e.g. the command was cancelled) releases waiters with an error.
+pub struct ConnectLease {
+    dedup: ConnectDedup,
+    relay_key: String,
+}
+
+impl ConnectDedup {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a lease if no connect to `relay_key` is in flight, otherwise a receiver
+    /// for the in-flight attempt's outcome.
+    pub fn join(&self, relay_key: &str) -> std::result::Result<ConnectLease, oneshot::Receiver<ConnectOutcome>> {
+        let mut in_flight = self.in_flight.lock().unwrap_or_else(|e| e.into_inner());
+        match in_flight.get_mut(relay_key) {
+            Some(waiters) => {
+                let (tx, rx) = oneshot::channel();
+                waiters.push(tx);
+                Err(rx)
+            }
+            None => {
+                in_flight.insert(relay_key.to_string(), Vec::new());
+                Ok(ConnectLease {
+                    dedup: self.clone(),
+                    relay_key: relay_key.to_string(),
+                })
+            }
+        }
+    }
+
+    /// Runs `connect` unless a connect to `relay_key` is already in flight, in which case
+    /// `connect` is dropped unrun and the in-flight attempt's outcome is returned.
+    pub async fn run<F>(&self, relay_key: &str, connect: F) -> ConnectOutcome
+    where
+        F: std::future::Future<Output = ConnectOutcome>,
+    {
+        match self.join(relay_key) {
+            Ok(lease) => {
+                let outcome = connect.await;
+                lease.complete(&outcome);
+                outcome
+            }
+            Err(in_flight) => in_flight
+                .await
+                .unwrap_or_else(|_| Err(HushError::QuicConnect("Connect attempt was abandoned".to_string()))),
+        }
+    }
+
+    fn release(&self, relay_key: &str) -> Vec<oneshot::Sender<ConnectOutcome>> {
+        self.in_flight.lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(relay_key)
+            .unwrap_or_default()
+    }
+}
+
+impl ConnectLease {
+    /// Hands the handshake outcome to every caller that joined while it was running.
+    pub fn complete(self, outcome: &ConnectOutcome) {
+        let waiters = self.dedup.release(&self.relay_key);
+        if !waiters.is_empty() {
+            tracing::debug!("Shared connect to {} with {} waiting callers", self.relay_key, waiters.len());
+        }
+        for waiter in waiters {
+            let _ = waiter.send(outcome.clone());
+        }
+    }
+}
+
+impl Drop for ConnectLease {
+    fn drop(&mut self) {
+        // No-op after complete(); otherwise drops the senders so waiters see an error
+        self.dedup.release(&self.relay_key);
+    }
 }
 
 pub struct QuicTransport {
+    /// The one client endpoint every connection shares, so they all use one UDP socket.
+    /// Dual-stack where the host allows it; created on first use.
     endpoint: Option<Endpoint>,
     active_connection: Option<Connection>,
     relay_info: Option<RelayInfo>,
+    /// Connections to relays other than the default one, keyed by relay id.
+    pool: HashMap<String, PooledRelay>,
+    send_queue: SendQueue,
+    mtu: MtuConfig,
+    pins: RelayPins,
+    connected_at: Option<Instant>,
+    messages_sent: AtomicU64,
+    sessions_started: u64,
+    per_connection_endpoint: bool,
+    dedicated_endpoint: Option<Endpoint>,
+    retry_budget: u32,
+    connected_fingerprint: Option<Fingerprint>,
+    kept_streams: Mutex<HashMap<u64, SendStream>>,
+    tickets: SessionTickets,
+    zero_rtt: bool,
+    inbound: Option<InboundListener>,
+    unpinned_policy: UnpinnedPolicy,
+    confirmations: PendingConfirmations,
+    directory_mirror: DirectoryMirror,
+    receipts: ReceiptTracker,
+    dedup: InboundDedup,
+    checkpoints: HashMap<String, Checkpoint>,
+    verification_hook: Option<Arc<dyn CertVerificationHook>>,
+    source_port: Option<u16>,
+    timeouts: TimeoutConfig,
+    keep_alive: AdaptiveKeepAlive,
+    blocklist: RelayBlocklist,
+    bandwidth: BandwidthLimits,
+    cover_stats: CoverStats,
+    /// Relays a failed send to the default relay moves on to, in order.
+    failover: Vec<RelayInfo>,
+    app: Option<AppHandle>,
+}
+
+impl Default for QuicTransport {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl QuicTransport {
@@ -33,204 +600,4392 @@ impl QuicTransport {
             endpoint: None,
             active_connection: None,
             relay_info: None,
+            pool: HashMap::new(),
+            send_queue: SendQueue::new(),
+            mtu: MtuConfig::default(),
+            pins: RelayPins::new(),
+            connected_at: None,
+            messages_sent: AtomicU64::new(0),
+            sessions_started: 0,
+            per_connection_endpoint: false,
+            dedicated_endpoint: None,
+            retry_budget: DEFAULT_RETRY_BUDGET,
+            connected_fingerprint: None,
+            kept_streams: Mutex::new(HashMap::new()),
+            tickets: SessionTickets::new(),
+            zero_rtt: false,
+            inbound: None,
+            unpinned_policy: UnpinnedPolicy::default(),
+            confirmations: PendingConfirmations::new(),
+            directory_mirror: DirectoryMirror::new(),
+            receipts: ReceiptTracker::new(),
+            dedup: InboundDedup::new(),
+            checkpoints: HashMap::new(),
+            verification_hook: None,
+            source_port: None,
+            timeouts: TimeoutConfig::default(),
+            keep_alive: AdaptiveKeepAlive::new(),
+            blocklist: RelayBlocklist::new(),
+            bandwidth: BandwidthLimits::new(),
+            cover_stats: CoverStats::new(),
+            failover: Vec::new(),
+            app: None,
         }
     }
 
-    async fn create_endpoint() -> Result<Endpoint> {
-        let client_config = configure_client()?;
-        
-        let mut endpoint = Endpoint::client("0.0.0.0:0".parse()?)?;
-        endpoint.set_default_client_config(client_config);
-        
-        Ok(endpoint)
+    /// Handle used to emit events to the frontend, such as fingerprint prompts.
+    pub fn attach_app(&mut self, app: AppHandle) {
+        self.app = Some(app);
     }
 
-    async fn connect_to_address(&mut self, addr: SocketAddr) -> Result<Connection> {
-        let endpoint = if let Some(ep) = &self.endpoint {
-            ep
-        } else {
-            let ep = Self::create_endpoint().await?;
-            self.endpoint = Some(ep);
-            self.endpoint.as_ref().unwrap()
+    /// Registry the `confirm_fingerprint` command resolves prompts through.
+    pub fn confirmations(&self) -> PendingConfirmations {
+        self.confirmations.clone()
+    }
+
+    /// Directory cache the inbound listener serves to peers when mirroring is enabled.
+    pub fn directory_mirror(&self) -> DirectoryMirror {
+        self.directory_mirror.clone()
+    }
+
+    /// Payloads the inbound listener already delivered, used to drop duplicates.
+    pub fn dedup(&self) -> InboundDedup {
+        self.dedup.clone()
+    }
+
+    /// Per-relay upload limits that sends and cover traffic wait on.
+    pub fn bandwidth(&self) -> BandwidthLimits {
+        self.bandwidth.clone()
+    }
+
+    /// Cover traffic counters; real sends are recorded here alongside cover packets.
+    pub fn cover_stats(&self) -> CoverStats {
+        self.cover_stats.clone()
+    }
+
+    /// Keep-alive intervals learned per network, loaded from disk at startup.
+    pub fn keep_alive(&self) -> AdaptiveKeepAlive {
+        self.keep_alive.clone()
+    }
+
+    /// Shares the user's blocklist; blocked relays are refused before dialing.
+    pub fn attach_blocklist(&mut self, blocklist: RelayBlocklist) {
+        self.blocklist = blocklist;
+    }
+
+    fn ensure_not_blocked(&self, relay: &RelayInfo) -> Result<()> {
+        let key = relay.pin_key();
+        if self.blocklist.contains(&key) {
+            return Err(HushError::RelayBlocked(key).into());
+        }
+        Ok(())
+    }
+
+    /// Sent messages awaiting receipts; receipts arriving on the inbound listener
+    /// update it directly.
+    pub fn receipts(&self) -> ReceiptTracker {
+        self.receipts.clone()
+    }
+
+    /// Installs (or with `None` removes) a hook that can veto relay certificates after
+    /// the pin check. Applies to connections opened after this call.
+    pub fn set_verification_hook(&mut self, hook: Option<Arc<dyn CertVerificationHook>>) {
+        tracing::info!("Certificate verification hook installed: {}", hook.is_some());
+        self.verification_hook = hook;
+    }
+
+    pub fn set_unpinned_policy(&mut self, policy: UnpinnedPolicy) {
+        tracing::info!("Unpinned relay policy: {:?}", policy);
+        self.unpinned_policy = policy;
+    }
+
+    /// Adopts the queue restored from disk at startup. Messages queued while it was
+    /// still loading are merged in behind the restored ones of the same priority.
+    pub fn restore_send_queue(&mut self, mut queue: SendQueue) {
+        tracing::info!("Restored {} queued messages", queue.len());
+        // Keep a capacity set while the persisted queue was still loading
+        if let Err(e) = queue.set_capacity(self.send_queue.capacity()) {
+            tracing::warn!("Kept the default send queue capacity: {:#}", e);
+        }
+        let queued_meanwhile = std::mem::take(&mut self.send_queue);
+        if let Err(e) = queue.merge(queued_meanwhile) {
+            tracing::warn!("Failed to persist the merged send queue: {:#}", e);
+        }
+        self.send_queue = queue;
+    }
+
+    /// Delivers queued messages over the active connection, highest priority first and
+    /// FIFO within a priority. Stops at the first failure and keeps the remaining
+    /// messages queued for the next attempt.
+    async fn flush_send_queue(&mut self) -> Result<usize> {
+        let mut written = Vec::new();
+        self.flush_send_queue_until(None, &mut written).await?;
+        Ok(written.len())
+    }
+
+    /// [`flush_send_queue`](Self::flush_send_queue) that also stops at `deadline`. Each
+    /// write is bounded by the time left, so nothing taken from the queue is lost when
+    /// the deadline passes mid-flush. The stream of every delivered message is added to
+    /// `written`, also when a later message fails.
+    async fn flush_send_queue_until(
+        &mut self,
+        deadline: Option<Instant>,
+        written: &mut Vec<SendStream>,
+    ) -> Result<()> {
+        let Some(connection) = self.active_connection.clone() else {
+            return Ok(());
         };
 
-        let connection = endpoint
-            .connect(addr, "localhost")?
+        let ready = self.send_queue.take_ready();
+        let mut delivered = 0;
+        for message in &ready {
+            let limit = match deadline {
+                Some(deadline) => self.timeouts.stream_io()
+                    .min(deadline.saturating_duration_since(Instant::now())),
+                None => self.timeouts.stream_io(),
+            };
+            let stream = tokio::time::timeout(
+                limit,
+                write_uni(&connection, &message.data, message.priority),
+            )
             .await
-            .context("Failed to establish QUIC connection")?;
+            .unwrap_or_else(|_| Err(anyhow::anyhow!("Timed out writing queued message")));
+            match stream {
+                Ok(stream) => written.push(stream),
+                Err(e) => {
+                    self.send_queue.requeue_front(ready[delivered..].to_vec())?;
+                    return Err(e);
+                }
+            }
+            delivered += 1;
+            self.messages_sent.fetch_add(1, Ordering::Relaxed);
+        }
 
-        tracing::info!("QUIC connection established to {}", addr);
-        Ok(connection)
+        self.send_queue.persist()?;
+        if delivered > 0 {
+            tracing::info!("Flushed {} queued messages", delivered);
+        }
+        Ok(())
     }
-}
 
-fn configure_client() -> Result<ClientConfig> {
-    // TODO: Load pinned relay certificate hashes from app configuration
-    let pinned_hashes: Vec<[u8; 32]> = Vec::new();
-    let crypto = rustls::ClientConfig::builder()
-        .dangerous()
-        .with_custom_certificate_verifier(Arc::new(PinnedCertVerifier::new(pinned_hashes)))
-        .with_no_client_auth();
-
-    Ok(ClientConfig::new(Arc::new(
-        quinn::crypto::rustls::QuicClientConfig::try_from(crypto)?
-    )))
-}
+    #[tracing::instrument(skip_all, fields(relay = %relay.host_port()))]
+    pub async fn connect(&mut self, relay: RelayInfo) -> Result<()> {
+        let dialed = match self.dial_relay(&relay).await {
+            Ok(dialed) => dialed,
+            Err(e) => {
+                self.emit_lifecycle("relay-error", relay.host_port(), Some(format!("{:#}", e)));
+                return Err(e);
+            }
+        };
+        self.adopt_connection(relay, dialed, AppCloseCode::Migration).await;
+        Ok(())
+    }
 
-/// Certificate pinning verifier: accepts only certificates whose SHA-256 fingerprint
-/// matches one of the pinned hashes. Prevents MITM attacks on relay connections.
-#[derive(Debug)]
-struct PinnedCertVerifier {
-    pinned_hashes: Vec<[u8; 32]>,
-}
+    /// Connects to `relay` as the default relay and sends `data`. With 0-RTT enabled and
+    /// a session ticket from an earlier connection to this pinned relay, `data` travels
+    /// in the first flight as early data. Early data can be replayed to the relay by
+    /// anyone on the path, so `data` must be safe to deliver twice. If the relay
+    /// rejects 0-RTT, `data` is sent again once the handshake completes.
+    pub async fn connect_early(
+        &mut self,
+        relay: RelayInfo,
+        data: &[u8],
+        priority: Priority,
+    ) -> Result<SendResult> {
+        if !self.zero_rtt || self.trust_for(&relay)?.is_empty() {
+            self.connect(relay).await?;
+            let (_, stream) = self.send(data, FinishMode::Finish, priority).await?;
+            return Ok(SendResult { kept_open: None, stream: Some(stream), ack: None, zero_rtt: false, queued: None });
+        }
 
-impl PinnedCertVerifier {
-    fn new(pinned_hashes: Vec<[u8; 32]>) -> Self {
-        Self { pinned_hashes }
+        let (dialed, zero_rtt) = match self.dial_early(&relay, data, priority).await {
+            Ok(dialed) => dialed,
+            Err(e) => {
+                self.emit_lifecycle("relay-error", relay.host_port(), Some(format!("{:#}", e)));
+                return Err(e);
+            }
+        };
+        self.adopt_connection(relay, dialed, AppCloseCode::Migration).await;
+        self.messages_sent.fetch_add(1, Ordering::Relaxed);
+        Ok(SendResult { kept_open: None, stream: None, ack: None, zero_rtt, queued: None })
     }
 
-    fn fingerprint(cert: &CertificateDer<'_>) -> [u8; 32] {
-        use sha2::{Sha256, Digest};
-        let mut hasher = Sha256::new();
-        hasher.update(cert.as_ref());
-        let result = hasher.finalize();
-        let mut hash = [0u8; 32];
-        hash.copy_from_slice(&result);
-        hash
+    /// Dials `relay` and writes `data` before the handshake completes when a ticket
+    /// allows it, else right after. Returns whether the relay accepted it as early data.
+    async fn dial_early(
+        &mut self,
+        relay: &RelayInfo,
+        data: &[u8],
+        priority: Priority,
+    ) -> Result<(Dialed, bool)> {
+        self.ensure_not_blocked(relay)?;
+        let addr = relay.primary_addr().await?;
+        let timeouts = self.timeouts.for_relay(relay.connect_timeout_ms);
+        let network = keepalive::network_key(addr);
+        let client_config = self.relay_client_config(addr, &network, self.trust_for(relay)?)?;
+        let endpoint = self.dial_endpoint(addr).await?;
+        let owned = self.per_connection_endpoint.then(|| endpoint.clone());
+        let dialed = async {
+            let connecting = endpoint.connect_with(client_config, addr, relay.server_name())?;
+            self.handshake_early(connecting, data, priority, timeouts).await
+        }
+        .await;
+        match dialed {
+            Ok((connection, early)) => {
+                self.keep_alive.watch(network, connection.clone());
+                tracing::info!("Connected to {} (0-RTT accepted: {})", addr, early);
+                Ok((Dialed { connection, endpoint: owned }, early))
+            }
+            Err(e) => {
+                if let Some(endpoint) = owned {
+                    AppCloseCode::Normal.close_endpoint(&endpoint);
+                }
+                Err(e)
+            }
+        }
     }
-}
 
-impl rustls::client::danger::ServerCertVerifier for PinnedCertVerifier {
-    fn verify_server_cert(
+    /// Completes `connecting`, writing `data` as early data when the ticket allows it.
+    async fn handshake_early(
         &self,
-        end_entity: &CertificateDer<'_>,
-        _intermediates: &[CertificateDer<'_>],
-        _server_name: &rustls::pki_types::ServerName<'_>,
-        _ocsp_response: &[u8],
-        _now: rustls::pki_types::UnixTime,
-    ) -> Result<rustls::client::danger::ServerCertVerified, rustls::Error> {
-        if self.pinned_hashes.is_empty() {
-            return Err(rustls::Error::General(
-                "No pinned certificates configured. Cannot verify relay identity.".into()
-            ));
+        connecting: quinn::Connecting,
+        data: &[u8],
+        priority: Priority,
+        timeouts: TimeoutConfig,
+    ) -> Result<(Connection, bool)> {
+        let (connection, early) = match connecting.into_0rtt() {
+            Ok((connection, accepted)) => {
+                let written = tokio::time::timeout(
+                    timeouts.stream_io(),
+                    write_uni(&connection, data, priority),
+                )
+                .await;
+                let accepted = tokio::time::timeout(timeouts.handshake(), accepted)
+                    .await
+                    .map_err(|_| HushError::QuicConnect("QUIC handshake timed out".to_string()))?;
+                if let Some(reason) = connection.close_reason() {
+                    return Err(HushError::QuicConnect(format!("QUIC connection failed: {}", reason)).into());
+                }
+                // A rejected 0-RTT stream fails with ZeroRttRejected and is resent below
+                (connection, accepted && matches!(written, Ok(Ok(_))))
+            }
+            // No ticket for this relay yet
+            Err(connecting) => {
+                let connection = tokio::time::timeout(timeouts.handshake(), connecting)
+                    .await
+                    .map_err(|_| HushError::QuicConnect("QUIC handshake timed out".to_string()))?
+                    .map_err(|e| HushError::QuicConnect(format!("QUIC connection failed: {}", e)))?;
+                (connection, false)
+            }
+        };
+
+        if !early {
+            tokio::time::timeout(self.timeouts.stream_io(), write_uni(&connection, data, priority))
+                .await
+                .context("Timed out writing to stream")??;
         }
+        Ok((connection, early))
+    }
 
-        let cert_hash = Self::fingerprint(end_entity);
-        if self.pinned_hashes.iter().any(|pin| pin == &cert_hash) {
-            Ok(rustls::client::danger::ServerCertVerified::assertion())
-        } else {
-            Err(rustls::Error::General(
-                "Certificate fingerprint does not match any pinned hash. Possible MITM.".into()
-            ))
+    fn emit_lifecycle(&self, event: &str, relay_address: String, error: Option<String>) {
+        if let Some(app) = &self.app {
+            emit_lifecycle(app, event, relay_address, error);
         }
     }
 
-    fn verify_tls12_signature(
-        &self,
-        _message: &[u8],
-        _cert: &CertificateDer<'_>,
-        _dss: &rustls::DigitallySignedStruct,
-    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
-        Ok(rustls::client::danger::HandshakeSignatureValid::assertion())
+    /// Adds a connection to `relay` alongside the ones already open and returns its
+    /// relay id. The first relay connected becomes the default; later ones are only
+    /// used by sends that name them.
+    #[tracing::instrument(skip_all, fields(relay = %relay.host_port()))]
+    pub async fn connect_pooled(&mut self, relay: RelayInfo) -> Result<String> {
+        let relay_id = relay.pin_key();
+        if self.active_connection.is_none() {
+            self.connect(relay).await?;
+            return Ok(relay_id);
+        }
+        if self.is_default_relay(&relay_id) || self.pool.contains_key(&relay_id) {
+            return Ok(relay_id);
+        }
+
+        let Dialed { connection, endpoint } = match self.dial_relay(&relay).await {
+            Ok(dialed) => dialed,
+            Err(e) => {
+                self.emit_lifecycle("relay-error", relay.host_port(), Some(format!("{:#}", e)));
+                return Err(e);
+            }
+        };
+
+        tracing::info!("Added relay {} to the connection pool", relay_id);
+        if let Some(app) = self.app.clone() {
+            emit_lifecycle(&app, "relay-connected", relay.host_port(), None);
+            tokio::spawn(report_remote_close(app, connection.clone(), relay.host_port()));
+        }
+        self.pool.insert(relay_id.clone(), PooledRelay {
+            relay,
+            connection,
+            endpoint,
+            connected_at: Instant::now(),
+            messages_sent: AtomicU64::new(0),
+        });
+        Ok(relay_id)
     }
 
-    fn verify_tls13_signature(
-        &self,
-        _message: &[u8],
-        _cert: &CertificateDer<'_>,
-        _dss: &rustls::DigitallySignedStruct,
-    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
-        Ok(rustls::client::danger::HandshakeSignatureValid::assertion())
+    /// `address:port` of the default relay, for log spans.
+    fn relay_label(&self) -> Option<String> {
+        self.relay_info.as_ref().map(RelayInfo::host_port)
     }
 
-    fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
-        vec![
-            rustls::SignatureScheme::RSA_PKCS1_SHA256,
-            rustls::SignatureScheme::ECDSA_NISTP256_SHA256,
-            rustls::SignatureScheme::ED25519,
-        ]
+    fn is_default_relay(&self, relay_id: &str) -> bool {
+        self.relay_info.as_ref().is_some_and(|r| r.pin_key() == relay_id)
     }
-}
 
-#[tauri::command]
-pub async fn connect_to_relay(
-    relay: RelayInfo,
-    state: State<'_, Arc<RwLock<QuicTransport>>>,
-) -> Result<String, String> {
-    let mut transport = state.write().await;
-    
-    let addr: SocketAddr = format!("{}:{}", relay.address, relay.port)
-        .parse()
-        .map_err(|e| format!("Invalid relay address: {}", e))?;
-    
-    let connection = transport
-        .connect_to_address(addr)
-        .await
-        .map_err(|e| format!("QUIC connection failed: {}", e))?;
-    
-    transport.active_connection = Some(connection);
-    transport.relay_info = Some(relay.clone());
-    
-    tracing::info!("Connected to relay: {}:{}", relay.address, relay.port);
-    Ok(format!("Connected to {}:{}", relay.address, relay.port))
-}
+    /// Id bandwidth limits are keyed by for `relay_id`, or for the default relay.
+    pub fn throttle_key(&self, relay_id: Option<&str>) -> Option<String> {
+        match relay_id {
+            Some(id) if !self.is_default_relay(id) => Some(id.to_string()),
+            _ => self.relay_info.as_ref().map(RelayInfo::pin_key),
+        }
+    }
 
-#[tauri::command]
-pub async fn disconnect_relay(
-    state: State<'_, Arc<RwLock<QuicTransport>>>,
-) -> Result<(), String> {
-    let mut transport = state.write().await;
-    
-    if let Some(conn) = transport.active_connection.take() {
-        conn.close(0u32.into(), b"Client disconnect");
-        tracing::info!("Disconnected from relay");
-    }
-    
-    transport.relay_info = None;
-    Ok(())
-}
+    /// Waits until the bandwidth limit of `relay_id`, or of the default relay, allows
+    /// `bytes` more.
+    async fn throttle(&self, relay_id: Option<&str>, bytes: usize) {
+        if let Some(key) = self.throttle_key(relay_id) {
+            self.bandwidth.acquire(&key, bytes).await;
+        }
+    }
+
+    /// Closes the connection to one relay. Naming the default relay, or no relay,
+    /// behaves like [`Self::disconnect`]; pooled relays stay connected either way.
+    pub fn disconnect_relay(&mut self, relay_id: Option<&str>) -> Option<SessionSummary> {
+        let pooled = match relay_id {
+            Some(id) if !self.is_default_relay(id) => self.pool.remove(id)?,
+            _ => return self.disconnect(),
+        };
+
+        let summary = pooled.summary();
+        AppCloseCode::Normal.close(&pooled.connection);
+        if let Some(endpoint) = &pooled.endpoint {
+            AppCloseCode::Normal.close_endpoint(endpoint);
+        }
+        tracing::info!("Removed relay {} from the connection pool", pooled.relay.pin_key());
+        self.emit_lifecycle("relay-disconnected", pooled.relay.host_port(), None);
+        Some(summary)
+    }
+
+    /// Returns the per-connection endpoints that were closed along with their connections.
+    fn close_pool(&mut self, code: AppCloseCode) -> Vec<Endpoint> {
+        let mut endpoints = Vec::new();
+        for (_, pooled) in self.pool.drain() {
+            code.close(&pooled.connection);
+            if let Some(endpoint) = pooled.endpoint {
+                code.close_endpoint(&endpoint);
+                endpoints.push(endpoint);
+            }
+        }
+        endpoints
+    }
+
+    /// Opens a connection to `relay` without making it active. An unpinned relay under
+    /// the confirm-first-use policy must have gone through [`Self::request_first_use`]
+    /// first. A hostname resolving to several addresses is tried address by address.
+    #[tracing::instrument(skip_all, fields(relay = %relay.host_port()))]
+    async fn dial_relay(&mut self, relay: &RelayInfo) -> Result<Dialed> {
+        self.ensure_not_blocked(relay)?;
+        let addrs = relay.resolve().await?;
+
+        let timeouts = self.timeouts.for_relay(relay.connect_timeout_ms);
+        let trust = self.trust_for(relay)?;
+        if trust.is_empty() && matches!(self.unpinned_policy, UnpinnedPolicy::ConfirmFirstUse { .. }) {
+            return Err(HushError::CertVerification(format!(
+                "Relay {} is unpinned; its fingerprint must be confirmed before connecting",
+                relay.pin_key()
+            ))
+            .into());
+        }
+
+        let mut failures = Vec::new();
+        let mut all_rejected = true;
+        for addr in addrs {
+            match self.connect_to_address(addr, relay.server_name(), trust.clone(), timeouts).await {
+                Ok(dialed) => return Ok(dialed),
+                Err(e) => {
+                    tracing::debug!("Connecting to {} at {} failed: {:#}", relay.host_port(), addr, e);
+                    all_rejected &= matches!(e.downcast_ref(), Some(HushError::CertVerification(_)));
+                    failures.push(format!("{} ({:#})", addr, e));
+                }
+            }
+        }
+        let message = match failures.as_slice() {
+            [only] => format!("QUIC connection failed: {}", only),
+            _ => format!(
+                "QUIC connection to {} failed on every address: {}",
+                relay.host_port(),
+                failures.join("; ")
+            ),
+        };
+        // Another address may still get through, unless every one served a bad certificate
+        if all_rejected {
+            return Err(HushError::CertVerification(message).into());
+        }
+        Err(HushError::QuicConnect(message).into())
+    }
+
+    /// Starts pin-on-first-use for `relay` when it has no pins and the policy asks for
+    /// confirmation: learns the certificate it serves over a throwaway endpoint and
+    /// registers it as awaiting `confirm_fingerprint`. Returns `None` when there is
+    /// nothing to confirm. Needs only `&self`, so the caller can release the transport
+    /// lock before waiting on the prompt and pin the result afterwards.
+    pub async fn request_first_use(&self, relay: &RelayInfo) -> Result<Option<FirstUsePrompt>> {
+        let UnpinnedPolicy::ConfirmFirstUse { timeout_secs } = self.unpinned_policy else {
+            return Ok(None);
+        };
+        if !self.trust_for(relay)?.is_empty() {
+            return Ok(None);
+        }
+        self.ensure_not_blocked(relay)?;
+        let addr = relay.primary_addr().await?;
+        let relay_id = relay.pin_key();
+        let timeouts = self.timeouts.for_relay(relay.connect_timeout_ms);
+
+        // With no pins the verifier rejects the handshake, but records what was served first
+        let observed: ObservedCert = Arc::new(Mutex::new(None));
+        let verifier = PinnedCertVerifier::observing(Vec::new(), observed.clone());
+        let client_config = client_config_with_verifier(&self.mtu, verifier)?;
+        if let Ok(dialed) = self.probe_connect(addr, relay.server_name(), client_config, timeouts).await {
+            dialed.close(AppCloseCode::Normal);
+        }
+        let served = observed.lock().ok()
+            .and_then(|slot| *slot)
+            .context("Relay did not present a certificate")?;
+
+        let decision = self.confirmations.register(&relay_id, served);
+        Ok(Some(FirstUsePrompt {
+            served,
+            prompt: FingerprintPrompt {
+                relay_id,
+                fingerprint: cert_pins::format_fingerprint(&served),
+                timeout_secs,
+            },
+            decision,
+            confirmations: self.confirmations.clone(),
+        }))
+    }
+
+    /// Makes `dialed` the active relay session, closing the one it replaces with
+    /// `close_old`, and flushes anything queued.
+    async fn adopt_connection(&mut self, relay: RelayInfo, dialed: Dialed, close_old: AppCloseCode) {
+        let (old, old_endpoint) = self.swap_connection(relay, dialed);
+        if let Some(old) = old {
+            close_old.close(&old);
+        }
+        if let Some(endpoint) = old_endpoint {
+            close_old.close_endpoint(&endpoint);
+        }
+        self.deliver_pending().await;
+    }
+
+    /// Moves the default relay session onto a fresh connection and closes the old one
+    /// with [`AppCloseCode::Rekey`], so the relay can't tie traffic under a new Taior
+    /// identity to the connection the old one used. Returns false if nothing was
+    /// connected; on a failed dial the old connection stays up.
+    pub async fn rekey(&mut self) -> Result<bool> {
+        let Some(relay) = self.relay_info.clone().filter(|_| self.active_connection.is_some()) else {
+            return Ok(false);
+        };
+        let dialed = self.dial_relay(&relay).await?;
+        self.adopt_connection(relay, dialed, AppCloseCode::Rekey).await;
+        Ok(true)
+    }
+
+    /// Installs `dialed` as the active connection and returns the connection it replaced
+    /// with the endpoint that connection owned.
+    fn swap_connection(&mut self, relay: RelayInfo, dialed: Dialed) -> (Option<Connection>, Option<Endpoint>) {
+        let Dialed { connection, endpoint } = dialed;
+        let old = self.active_connection.replace(connection.clone());
+        let old_endpoint = std::mem::replace(&mut self.dedicated_endpoint, endpoint);
+        self.connected_fingerprint = self.active_connection.as_ref().and_then(peer_fingerprint);
+        tracing::info!("Connected to relay: {}", relay.host_port());
+        if let Some(app) = self.app.clone() {
+            let relay_address = relay.host_port();
+            emit_lifecycle(&app, "relay-connected", relay_address.clone(), None);
+            tokio::spawn(report_remote_close(app, connection, relay_address));
+        }
+        self.relay_info = Some(relay);
+        self.connected_at = Some(Instant::now());
+        self.messages_sent.store(0, Ordering::Relaxed);
+        self.sessions_started += 1;
+        (old, old_endpoint)
+    }
+
+    /// Sends queued messages and continues interrupted transfers on the active connection.
+    async fn deliver_pending(&mut self) {
+        if let Err(e) = self.flush_send_queue().await {
+            tracing::warn!("Failed to flush send queue: {}", e);
+        }
+        for progress in self.resume_transfers().await {
+            tracing::info!(
+                "Resumed transfer {} from {} ({} of {} bytes)",
+                progress.transfer_id,
+                progress.resumed_from,
+                progress.acked_bytes,
+                progress.total_bytes
+            );
+        }
+    }
+
+    /// Dials every candidate at once and keeps whichever completes its handshake first.
+    /// Losing handshakes are closed as they finish. Returns the winning relay id.
+    pub async fn connect_fastest(&mut self, candidates: Vec<(String, RelayInfo)>) -> Result<String> {
+        if candidates.is_empty() {
+            anyhow::bail!("No relays to connect to");
+        }
+
+        let labels = candidates.iter()
+            .map(|(_, relay)| relay.host_port())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let mut dials = tokio::task::JoinSet::new();
+        for (id, relay) in candidates {
+            if self.blocklist.contains(&relay.pin_key()) {
+                tracing::warn!("Skipping blocked relay {}", id);
+                continue;
+            }
+            let addr = match relay.primary_addr().await {
+                Ok(addr) => addr,
+                Err(e) => {
+                    tracing::warn!("Skipping relay {} with invalid address: {:#}", id, e);
+                    continue;
+                }
+            };
+            let trust = match self.trust_for(&relay) {
+                Ok(trust) => trust,
+                Err(e) => {
+                    tracing::warn!("Skipping relay {} with invalid public key: {:#}", id, e);
+                    continue;
+                }
+            };
+            let network = keepalive::network_key(addr);
+            let client_config = self.relay_client_config(addr, &network, trust)?;
+            let endpoint = self.dial_endpoint(addr).await?;
+            let connecting = endpoint.connect_with(client_config, addr, relay.server_name())?;
+            let handshake = self.timeouts.for_relay(relay.connect_timeout_ms).handshake();
+            dials.spawn(async move {
+                let result = tokio::time::timeout(handshake, connecting)
+                    .await
+                    .unwrap_or(Err(quinn::ConnectionError::TimedOut));
+                (id, relay, endpoint, network, result)
+            });
+        }
+
+        let mut last_error = None;
+        while let Some(joined) = dials.join_next().await {
+            let (id, relay, endpoint, network, result) = joined.context("Dial task panicked")?;
+            match result {
+                Ok(connection) => {
+                    self.keep_alive.watch(network, connection.clone());
+                    let per_connection = self.per_connection_endpoint;
+                    tokio::spawn(async move {
+                        while let Some(joined) = dials.join_next().await {
+                            if let Ok((_, _, loser_endpoint, _, outcome)) = joined {
+                                if let Ok(loser) = outcome {
+                                    AppCloseCode::Normal.close(&loser);
+                                }
+                                if per_connection {
+                                    AppCloseCode::Normal.close_endpoint(&loser_endpoint);
+                                }
+                            }
+                        }
+                    });
+                    let endpoint = per_connection.then_some(endpoint);
+                    tracing::info!("Relay {} won the connection race", id);
+                    self.adopt_connection(relay, Dialed { connection, endpoint }, AppCloseCode::Migration).await;
+                    return Ok(id);
+                }
+                Err(e) => {
+                    tracing::debug!("Relay {} lost the race with error: {}", id, e);
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        let error = match last_error {
+            Some(e) => anyhow::Error::new(e).context("All relay connection attempts failed"),
+            None => anyhow::anyhow!("No relay had a valid address"),
+        };
+        self.emit_lifecycle("relay-error", labels, Some(format!("{:#}", error)));
+        Err(error)
+    }
+
+    /// Closes the active connection and returns the traffic totals for its session,
+    /// or `None` if nothing was connected.
+    pub fn disconnect(&mut self) -> Option<SessionSummary> {
+        self.close_session(AppCloseCode::Normal)
+    }
+
+    fn close_session(&mut self, code: AppCloseCode) -> Option<SessionSummary> {
+        let mut summary = None;
+        if let Some(conn) = self.active_connection.take() {
+            // Snapshot before closing so the counters cover the whole session
+            summary = Some(self.session_summary(&conn));
+
+            code.close(&conn);
+            tracing::info!("Disconnected from relay");
+            if let Some(relay) = &self.relay_info {
+                self.emit_lifecycle("relay-disconnected", relay.host_port(), None);
+            }
+        }
+        if let Some(endpoint) = self.dedicated_endpoint.take() {
+            code.close_endpoint(&endpoint);
+        }
+
+        if let Ok(kept) = self.kept_streams.get_mut() {
+            kept.clear();
+        }
+        self.relay_info = None;
+        self.connected_at = None;
+        self.connected_fingerprint = None;
+        summary
+    }
+
+    /// Closes everything before the app exits. With `flush`, queued messages are sent
+    /// until `timeout` runs out; whatever is left (or everything, without `flush`) is
+    /// persisted and sent after the next launch. Flushed messages get until the deadline
+    /// to be acknowledged, since closing discards stream data the relay hasn't received.
+    /// Every connection is then closed with the shutdown code, and the endpoints get
+    /// what remains of `timeout` to go idle so relays receive the close instead of
+    /// timing the connection out.
+    pub async fn shutdown(&mut self, flush: bool, timeout: Duration) -> ShutdownReport {
+        let deadline = Instant::now() + timeout;
+        let mut flushed = Vec::new();
+        if flush {
+            if let Err(e) = self.flush_send_queue_until(Some(deadline), &mut flushed).await {
+                tracing::warn!("Shutdown flush stopped early: {:#}", e);
+            }
+        }
+        let delivered = flushed.len();
+        let acknowledged = async {
+            for stream in &flushed {
+                let _ = stream.stopped().await;
+            }
+        };
+        if tokio::time::timeout_at(deadline.into(), acknowledged).await.is_err() {
+            tracing::warn!("Flushed messages still unacknowledged at the shutdown deadline");
+        }
+        if let Err(e) = self.send_queue.persist() {
+            tracing::warn!("Failed to persist send queue on shutdown: {:#}", e);
+        }
+        let deferred = self.send_queue.len();
+
+        if let Some(listener) = self.inbound.take() {
+            listener.stop();
+        }
+        let pooled = self.pool.len();
+        let mut closed = 0;
+        // Taken first so it can be waited on; close_session would only close it
+        let dedicated = self.dedicated_endpoint.take();
+        if self.close_session(AppCloseCode::Shutdown).is_some() {
+            closed += 1;
+        }
+        let mut endpoints = self.close_pool(AppCloseCode::Shutdown);
+        closed += pooled;
+        for endpoint in [dedicated, self.endpoint.take()].into_iter().flatten() {
+            AppCloseCode::Shutdown.close_endpoint(&endpoint);
+            endpoints.push(endpoint);
+        }
+
+        let idle = async {
+            for endpoint in &endpoints {
+                endpoint.wait_idle().await;
+            }
+        };
+        if tokio::time::timeout_at(deadline.into(), idle).await.is_err() {
+            tracing::warn!("Endpoints still draining at the shutdown deadline");
+        }
+
+        tracing::info!(
+            "Transport shut down: {} connections closed, {} delivered, {} deferred",
+            closed,
+            delivered,
+            deferred
+        );
+        ShutdownReport { delivered, deferred, closed }
+    }
+
+    fn session_summary(&self, connection: &Connection) -> SessionSummary {
+        let stats = connection.stats();
+        SessionSummary {
+            relay_address: self.relay_info.as_ref().map(RelayInfo::host_port),
+            bytes_sent: stats.udp_tx.bytes,
+            bytes_received: stats.udp_rx.bytes,
+            duration_ms: self.connected_at
+                .map(|t| t.elapsed().as_millis() as u64)
+                .unwrap_or(0),
+            messages_sent: self.messages_sent.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Moves all traffic to `relay` for planned maintenance. The new connection must
+    /// stay up for a settle period before it takes over; queued messages and interrupted
+    /// transfers then continue on it and every later send uses it. The old connection
+    /// is closed only after the drain timeout, so data already written to it is not cut
+    /// off. Returns the totals of the session that was migrated away from.
+    ///
+    /// Callers hold the write lock, so sends in progress under a read lock finish on the
+    /// old connection before the switch.
+    pub async fn migrate(&mut self, relay: RelayInfo) -> Result<Option<SessionSummary>> {
+        if self.active_connection.is_none() {
+            anyhow::bail!("Not connected to relay; nothing to migrate");
+        }
+
+        let dialed = self.dial_relay(&relay).await
+            .context("Migration target unreachable")?;
+        if tokio::time::timeout(MIGRATION_SETTLE, dialed.connection.closed()).await.is_ok() {
+            dialed.close(AppCloseCode::Normal);
+            anyhow::bail!("Migration target closed the connection before it became healthy");
+        }
+
+        let summary = self.active_connection.as_ref().map(|old| self.session_summary(old));
+        let kept: Vec<SendStream> = match self.kept_streams.get_mut() {
+            Ok(kept) => kept.drain().map(|(_, stream)| stream).collect(),
+            Err(_) => Vec::new(),
+        };
+        let target = relay.host_port();
+        if let (Some(old), old_endpoint) = self.swap_connection(relay, dialed) {
+            tokio::spawn(drain_and_close(old, old_endpoint, kept, self.timeouts.drain()));
+        }
+        self.deliver_pending().await;
+
+        tracing::info!("Migrated traffic to relay {}", target);
+        Ok(summary)
+    }
+
+    /// Brings the transport back to a known-good idle state after a panic interrupted
+    /// an update: connections, endpoints, the inbound listener and in-progress transfers
+    /// are dropped. Pins, settings, the send queue and shared handles are kept.
+    pub fn reset_after_panic(&mut self) {
+        self.close_session(AppCloseCode::Error);
+        self.close_pool(AppCloseCode::Error);
+        if let Some(listener) = self.inbound.take() {
+            listener.stop();
+        }
+        for endpoint in [self.dedicated_endpoint.take(), self.endpoint.take()].into_iter().flatten() {
+            AppCloseCode::Error.close_endpoint(&endpoint);
+        }
+        self.checkpoints.clear();
+        tracing::warn!("Transport state reset after panic");
+    }
+
+    /// Writes `data` on a new stream and ends it according to `finish_mode`. Returns the
+    /// stage timings and the stream written to; with [`FinishMode::KeepOpen`] that
+    /// stream stays open under its id.
+    #[tracing::instrument(skip_all, fields(relay = ?self.relay_label(), bytes = data.len()))]
+    pub async fn send(
+        &self,
+        data: &[u8],
+        finish_mode: FinishMode,
+        priority: Priority,
+    ) -> Result<(SendTiming, SentStream)> {
+        let connection = self.active_connection.as_ref()
+            .ok_or(HushError::NotConnected)?;
+
+        self.throttle(None, data.len()).await;
+        let sent = self.send_on(connection, data, finish_mode, priority).await?;
+        self.messages_sent.fetch_add(1, Ordering::Relaxed);
+        self.cover_stats.record_real(data.len());
+        Ok(sent)
+    }
+
+    /// Like [`Self::send`], but to the pooled relay `relay_id`; `None` or the default
+    /// relay's id sends to the default relay. Streams can only be kept open on the
+    /// default relay.
+    #[tracing::instrument(skip_all, fields(relay = ?relay_id, bytes = data.len()))]
+    pub async fn send_to(
+        &self,
+        relay_id: Option<&str>,
+        data: &[u8],
+        finish_mode: FinishMode,
+        priority: Priority,
+    ) -> Result<(SendTiming, SentStream)> {
+        let pooled = relay_id.is_some_and(|id| !self.is_default_relay(id));
+        if !pooled {
+            return self.send(data, finish_mode, priority).await;
+        }
+        if finish_mode == FinishMode::KeepOpen {
+            anyhow::bail!("Streams can only be kept open on the default relay");
+        }
+
+        let (connection, sent_counter) = self.target(relay_id)?;
+        self.throttle(relay_id, data.len()).await;
+        let sent = self.send_on(connection, data, finish_mode, priority).await?;
+        sent_counter.fetch_add(1, Ordering::Relaxed);
+        self.cover_stats.record_real(data.len());
+        Ok(sent)
+    }
+
+    /// Sends `data` on a bidirectional stream and waits for the relay's ack frame.
+    /// A rejection is reported in the returned [`AckStatus`]; no ack within the ack
+    /// timeout fails with [`HushError::AckTimeout`].
+    #[tracing::instrument(skip_all, fields(relay = ?relay_id, bytes = data.len()))]
+    pub async fn send_acked(
+        &self,
+        relay_id: Option<&str>,
+        data: &[u8],
+        priority: Priority,
+    ) -> Result<(SendTiming, AckStatus, SentStream)> {
+        let (connection, sent_counter) = self.target(relay_id)?;
+        self.throttle(relay_id, data.len()).await;
+
+        let started = Instant::now();
+        let (mut send_stream, mut recv_stream) = connection.open_bi().await
+            .context("Failed to open QUIC stream")?;
+        send_stream
+            .set_priority(priority.stream_priority())
+            .context("Failed to set stream priority")?;
+        let stream_id = u64::from(send_stream.id());
+        let opened = Instant::now();
+
+        tokio::time::timeout(self.timeouts.stream_io(), send_stream.write_all(data))
+            .await
+            .context("Timed out writing to stream")?
+            .context("Failed to send data")?;
+        send_stream.finish().context("Failed to finish stream")?;
+        let written = Instant::now();
+
+        let ack = ack::read(&mut recv_stream, self.timeouts.ack()).await?;
+        let finished = Instant::now();
+        sent_counter.fetch_add(1, Ordering::Relaxed);
+        self.cover_stats.record_real(data.len());
+        tracing::debug!("Sent {} bytes via QUIC, ack status {}", data.len(), ack.status);
+
+        let timing = SendTiming {
+            open_stream_us: Some((opened - started).as_micros() as u64),
+            write_us: Some((written - opened).as_micros() as u64),
+            finish_us: Some((finished - written).as_micros() as u64),
+            total_us: (finished - started).as_micros() as u64,
+            ..Default::default()
+        };
+        let stream = SentStream { stream_id, bytes_written: data.len() };
+        Ok((timing, ack, stream))
+    }
+
+    /// Connection and message counter for `relay_id`, or for the default relay.
+    fn target(&self, relay_id: Option<&str>) -> Result<(&Connection, &AtomicU64)> {
+        match relay_id {
+            Some(id) if !self.is_default_relay(id) => {
+                let pooled = self.pool.get(id)
+                    .ok_or(HushError::NotConnected)
+                    .with_context(|| format!("Relay {} is not in the connection pool", id))?;
+                Ok((&pooled.connection, &pooled.messages_sent))
+            }
+            _ => {
+                let connection = self.active_connection.as_ref()
+                    .ok_or(HushError::NotConnected)?;
+                Ok((connection, &self.messages_sent))
+            }
+        }
+    }
+
+    async fn send_on(
+        &self,
+        connection: &Connection,
+        data: &[u8],
+        finish_mode: FinishMode,
+        priority: Priority,
+    ) -> Result<(SendTiming, SentStream)> {
+        let started = Instant::now();
+        let (mut send_stream, recv_stream) = match finish_mode {
+            FinishMode::ResetAfterAck => {
+                let (send, recv) = connection.open_bi().await
+                    .context("Failed to open QUIC stream")?;
+                (send, Some(recv))
+            }
+            _ => {
+                let send = connection.open_uni().await
+                    .context("Failed to open QUIC stream")?;
+                (send, None)
+            }
+        };
+        send_stream
+            .set_priority(priority.stream_priority())
+            .context("Failed to set stream priority")?;
+        let stream_id = u64::from(send_stream.id());
+        let opened = Instant::now();
+
+        tokio::time::timeout(self.timeouts.stream_io(), send_stream.write_all(data))
+            .await
+            .context("Timed out writing to stream")?
+            .context("Failed to send data")?;
+        let written = Instant::now();
+
+        match (finish_mode, recv_stream) {
+            (FinishMode::ResetAfterAck, Some(mut recv)) => {
+                let mut ack = [0u8; 1];
+                tokio::time::timeout(self.timeouts.ack(), recv.read_exact(&mut ack))
+                    .await
+                    .context("Timed out waiting for relay stream ack")?
+                    .context("Failed to read relay stream ack")?;
+                send_stream
+                    .reset(AppCloseCode::Normal.code())
+                    .context("Failed to reset stream")?;
+            }
+            (FinishMode::KeepOpen, _) => {
+                self.kept_streams.lock()
+                    .map_err(|_| anyhow::anyhow!("Kept stream table poisoned"))?
+                    .insert(stream_id, send_stream);
+            }
+            _ => {
+                send_stream
+                    .finish()
+                    .context("Failed to finish stream")?;
+            }
+        }
+        let finished = Instant::now();
+
+        tracing::debug!("Sent {} bytes via QUIC ({:?})", data.len(), finish_mode);
+
+        let timing = SendTiming {
+            open_stream_us: Some((opened - started).as_micros() as u64),
+            write_us: Some((written - opened).as_micros() as u64),
+            finish_us: Some((finished - written).as_micros() as u64),
+            total_us: (finished - started).as_micros() as u64,
+            ..Default::default()
+        };
+        Ok((timing, SentStream { stream_id, bytes_written: data.len() }))
+    }
+
+    /// Hands `packet` to the relay, which delivers it live or, if the recipient is
+    /// offline, stores it for up to `ttl_secs`.
+    pub async fn send_store_forward(&self, packet: &[u8], ttl_secs: u64) -> Result<DeliveryOutcome> {
+        let connection = self.active_connection.as_ref()
+            .ok_or(HushError::NotConnected)?;
+
+        let outcome = store_forward::send(
+            connection,
+            packet,
+            ttl_secs,
+            Priority::Normal,
+            self.timeouts.stream_io(),
+            self.timeouts.ack(),
+        )
+        .await?;
+        self.messages_sent.fetch_add(1, Ordering::Relaxed);
+        tracing::debug!("Store-and-forward send of {} bytes: {:?}", packet.len(), outcome);
+        Ok(outcome)
+    }
+
+    /// Sends `data` as a request on a bidirectional stream and returns the relay's framed
+    /// response. A response cut short fails with
+    /// [`ResponseError::TruncatedResponse`](response::ResponseError::TruncatedResponse).
+    #[tracing::instrument(skip_all, fields(relay = ?self.relay_label(), bytes = data.len(), received = tracing::field::Empty))]
+    pub async fn send_recv(&self, data: &[u8], priority: Priority) -> Result<Vec<u8>> {
+        let connection = self.active_connection.as_ref()
+            .ok_or(HushError::NotConnected)?;
+
+        let (mut send, mut recv) = connection.open_bi().await
+            .context("Failed to open QUIC stream")?;
+        send.set_priority(priority.stream_priority())
+            .context("Failed to set stream priority")?;
+        tokio::time::timeout(self.timeouts.stream_io(), send.write_all(data))
+            .await
+            .context("Timed out writing to stream")?
+            .context("Failed to send data")?;
+        send.finish().context("Failed to finish stream")?;
+        self.messages_sent.fetch_add(1, Ordering::Relaxed);
+
+        let body = response::read_framed(&mut recv, self.timeouts.stream_io()).await?;
+        tracing::Span::current().record("received", body.len());
+        tracing::debug!("Sent {} bytes, received {} byte response", data.len(), body.len());
+        Ok(body)
+    }
+
+    /// Waits up to `timeout` for the relay to open a stream and reads it to the end,
+    /// failing if it exceeds `max_size`. The bytes must be a packet in the `taior_send`
+    /// format, whose length prefix is checked before it is returned.
+    #[tracing::instrument(skip_all, fields(relay = ?self.relay_label(), bytes = tracing::field::Empty))]
+    pub async fn recv(&self, max_size: usize, timeout: Duration) -> Result<Vec<u8>> {
+        let connection = self.active_connection.as_ref()
+            .ok_or(HushError::NotConnected)?;
+
+        let packet = tokio::time::timeout(timeout, async {
+            let mut recv = connection.accept_uni().await
+                .context("Failed to accept relay stream")?;
+            recv.read_to_end(max_size).await
+                .context("Failed to read relay stream")
+        })
+        .await
+        .context("Timed out waiting for a relay stream")??;
+
+        taior_bridge::split_packet(&packet)?;
+        tracing::Span::current().record("bytes", packet.len());
+        tracing::debug!("Received {} bytes via QUIC", packet.len());
+        Ok(packet)
+    }
+
+    /// Sends `data` as a single unreliable, unordered datagram to the default relay. Fails
+    /// with [`HushError::InvalidInput`] naming the limit if `data` doesn't fit in one
+    /// datagram on the current path. Waits for the relay's bandwidth limit first.
+    #[tracing::instrument(skip_all, fields(relay = ?self.relay_label(), bytes = data.len()))]
+    pub async fn send_datagram(&self, data: &[u8]) -> Result<()> {
+        let connection = self.active_connection.as_ref()
+            .ok_or(HushError::NotConnected)?;
+
+        let max_size = connection.max_datagram_size()
+            .context("Relay does not accept datagrams")?;
+        if data.len() > max_size {
+            return Err(HushError::InvalidInput(format!(
+                "Datagram of {} bytes exceeds the {} byte limit",
+                data.len(),
+                max_size
+            ))
+            .into());
+        }
+
+        self.throttle(None, data.len()).await;
+        connection.send_datagram(data.to_vec().into())
+            .context("Failed to send datagram")?;
+        self.messages_sent.fetch_add(1, Ordering::Relaxed);
+        self.cover_stats.record_real(data.len());
+        tracing::debug!("Sent {} byte datagram via QUIC", data.len());
+        Ok(())
+    }
+
+    /// Waits up to `timeout` for the next datagram from the default relay. Datagrams
+    /// arrive as sent, without the packet framing [`Self::recv`] checks.
+    #[tracing::instrument(skip_all, fields(relay = ?self.relay_label(), bytes = tracing::field::Empty))]
+    pub async fn recv_datagram(&self, timeout: Duration) -> Result<Vec<u8>> {
+        let connection = self.active_connection.as_ref()
+            .ok_or(HushError::NotConnected)?;
+
+        let datagram = tokio::time::timeout(timeout, connection.read_datagram())
+            .await
+            .context("Timed out waiting for a datagram")?
+            .context("Failed to read datagram")?;
+        tracing::Span::current().record("bytes", datagram.len());
+        tracing::debug!("Received {} byte datagram via QUIC", datagram.len());
+        Ok(datagram.to_vec())
+    }
+
+    /// Sends one stream per recipient over the active connection, scheduled by `policy`.
+    /// Results are returned in the same order as `sends`.
+    pub async fn send_multi(
+        &self,
+        sends: Vec<RecipientSend>,
+        policy: FanOutPolicy,
+    ) -> Result<Vec<RecipientResult>> {
+        let connection = self.active_connection.clone()
+            .ok_or(HushError::NotConnected)?;
+
+        let mut results = Vec::with_capacity(sends.len());
+        match policy {
+            FanOutPolicy::Sequential => {
+                for send in sends {
+                    results.push(write_recipient(connection.clone(), send, usize::MAX).await);
+                }
+            }
+            FanOutPolicy::WeightedFair => {
+                let mut tasks = tokio::task::JoinSet::new();
+                for (index, send) in sends.into_iter().enumerate() {
+                    let quantum = FAIR_QUANTUM.saturating_mul(send.weight.max(1) as usize);
+                    let connection = connection.clone();
+                    tasks.spawn(async move {
+                        (index, write_recipient(connection, send, quantum).await)
+                    });
+                }
+
+                let mut indexed = Vec::new();
+                while let Some(joined) = tasks.join_next().await {
+                    indexed.push(joined.context("Fan-out task panicked")?);
+                }
+                indexed.sort_by_key(|(index, _)| *index);
+                results.extend(indexed.into_iter().map(|(_, result)| result));
+            }
+        }
+
+        let delivered = results.iter().filter(|r| r.error.is_none()).count() as u64;
+        self.messages_sent.fetch_add(delivered, Ordering::Relaxed);
+        Ok(results)
+    }
+
+    /// Finishes a stream previously left open by [`FinishMode::KeepOpen`].
+    pub fn finish_kept_stream(&self, stream_id: u64) -> Result<()> {
+        let mut stream = self.kept_streams.lock()
+            .map_err(|_| anyhow::anyhow!("Kept stream table poisoned"))?
+            .remove(&stream_id)
+            .with_context(|| format!("No open stream with id {}", stream_id))?;
+        stream.finish().context("Failed to finish stream")?;
+        Ok(())
+    }
+
+    /// Makes `relay` the active connection unless it already is.
+    pub async fn ensure_connected(&mut self, relay: &RelayInfo) -> Result<()> {
+        let same_relay = self.relay_info.as_ref()
+            .is_some_and(|r| r.pin_key() == relay.pin_key());
+        if same_relay && self.active_connection.is_some() {
+            return Ok(());
+        }
+        self.connect(relay.clone()).await
+    }
+
+    /// Sends `data` through the relay discovery picks, falling back to the next relay
+    /// when a connect fails and reconnecting once when a stream fails on a live
+    /// connection. When the reconnect fails too, the send is tried on the other pooled
+    /// relays (multi-path) before falling back, and once discovery has no relay left the
+    /// failover list is tried. Every attempt draws from `budget`; returns the relay id
+    /// used, or every attempt made once the budget or the relays run out.
+    pub async fn send_via_discovery(
+        &mut self,
+        discovery: &mut RelayDiscovery,
+        data: &[u8],
+        budget: &mut RetryBudget,
+    ) -> Result<String> {
+        discovery.ensure_not_empty()?;
+
+        let unused = budget.remaining();
+        let mut mechanism = RetryMechanism::Initial;
+        let mut last_error = None;
+
+        while let Some(relay) = discovery.next_relay() {
+            if !budget.try_begin(mechanism, Some(&relay.id)) {
+                break;
+            }
+            mechanism = RetryMechanism::Fallback;
+
+            let info = relay.to_relay_info();
+            if let Err(e) = self.ensure_connected(&info).await {
+                discovery.mark_unhealthy(&relay.id);
+                discovery.record_connect(&relay.id, false);
+                budget.record_failure(&e);
+                last_error = Some(e);
+                continue;
+            }
+            discovery.record_connect(&relay.id, true);
+            match self.send(data, FinishMode::Finish, Priority::Normal).await {
+                Ok(_) => return Ok(relay.id),
+                Err(e) => budget.record_failure(&e),
+            }
+
+            if !budget.try_begin(RetryMechanism::Reconnect, Some(&relay.id)) {
+                break;
+            }
+            let retried = match self.connect(info).await {
+                Ok(()) => {
+                    discovery.record_connect(&relay.id, true);
+                    self.send(data, FinishMode::Finish, Priority::Normal).await
+                }
+                Err(e) => {
+                    discovery.record_connect(&relay.id, false);
+                    Err(e)
+                }
+            };
+            match retried {
+                Ok(_) => return Ok(relay.id),
+                Err(e) => {
+                    discovery.mark_unhealthy(&relay.id);
+                    budget.record_failure(&e);
+                    last_error = Some(e);
+                }
+            }
+
+            if let Some(relay_id) = self.send_over_pool(data, &relay.id, budget).await {
+                return Ok(relay_id);
+            }
+        }
+
+        if let Some(error) = last_error.filter(|_| budget.remaining() > 0 && !self.failover.is_empty()) {
+            if self.fail_over(data, FinishMode::Finish, Priority::Normal, error, budget).await.is_ok() {
+                return Ok(self.relay_info.as_ref().map(RelayInfo::pin_key).unwrap_or_default());
+            }
+        }
+
+        if budget.remaining() == unused {
+            anyhow::bail!("No healthy relays available");
+        }
+        Err(budget.clone().into_error().into())
+    }
+
+    /// Tries `data` on every pooled relay other than `failed`, one budget attempt each.
+    /// Returns the relay id that took it.
+    async fn send_over_pool(&self, data: &[u8], failed: &str, budget: &mut RetryBudget) -> Option<String> {
+        let others: Vec<String> = self.pool.keys()
+            .filter(|id| id.as_str() != failed)
+            .cloned()
+            .collect();
+        for relay_id in others {
+            if !budget.try_begin(RetryMechanism::MultiPath, Some(&relay_id)) {
+                break;
+            }
+            match self.send_to(Some(&relay_id), data, FinishMode::Finish, Priority::Normal).await {
+                Ok(_) => return Some(relay_id),
+                Err(e) => budget.record_failure(&e),
+            }
+        }
+        None
+    }
+
+    /// Fresh budget of the configured total, for one logical send to spend across every
+    /// retry mechanism.
+    pub fn new_retry_budget(&self) -> RetryBudget {
+        RetryBudget::new(self.retry_budget)
+    }
+
+    /// Starts a checkpointed transfer of `data`. If the connection drops part-way the
+    /// transfer stays registered and continues from the relay's acknowledged offset on
+    /// the next connect (or an explicit [`resume_transfers`](Self::resume_transfers)).
+    pub async fn send_resumable(&mut self, data: Vec<u8>) -> Result<TransferProgress> {
+        let mut checkpoint = Checkpoint::new(data);
+        let transfer_id = checkpoint.transfer_id();
+
+        let outcome = match self.active_connection.clone() {
+            Some(connection) => resumable::push(&connection, &mut checkpoint, self.timeouts.ack()).await,
+            None => Err(HushError::NotConnected.into()),
+        };
+        match outcome {
+            Ok(progress) if progress.complete => Ok(progress),
+            Ok(progress) => {
+                self.checkpoints.insert(transfer_id, checkpoint);
+                Ok(progress)
+            }
+            Err(e) => {
+                tracing::info!("Transfer {} interrupted at {} bytes", transfer_id, checkpoint.acked);
+                self.checkpoints.insert(transfer_id, checkpoint);
+                Err(e)
+            }
+        }
+    }
+
+    /// Continues every unfinished transfer over the active connection. Transfers that
+    /// fail again stay registered.
+    pub async fn resume_transfers(&mut self) -> Vec<TransferProgress> {
+        let Some(connection) = self.active_connection.clone() else {
+            return Vec::new();
+        };
+
+        let mut resumed = Vec::new();
+        let ids: Vec<String> = self.checkpoints.keys().cloned().collect();
+        for id in ids {
+            let Some(checkpoint) = self.checkpoints.get_mut(&id) else {
+                continue;
+            };
+            match resumable::push(&connection, checkpoint, self.timeouts.ack()).await {
+                Ok(progress) => {
+                    if progress.complete {
+                        self.checkpoints.remove(&id);
+                    }
+                    resumed.push(progress);
+                }
+                Err(e) => tracing::warn!("Failed to resume transfer {}: {:#}", id, e),
+            }
+        }
+        resumed
+    }
+
+    /// Drops an unfinished transfer. Returns false if it was unknown or already done.
+    pub fn cancel_transfer(&mut self, transfer_id: &str) -> bool {
+        self.checkpoints.remove(transfer_id).is_some()
+    }
+
+    /// Total attempts one send may make across reconnect, fallback, multi-path and
+    /// failover combined.
+    pub fn set_retry_budget(&mut self, total: u32) -> Result<()> {
+        if total == 0 {
+            anyhow::bail!("Retry budget must allow at least one attempt");
+        }
+        self.retry_budget = total;
+        Ok(())
+    }
+
+    /// Replaces the failover list; an empty list turns failover off.
+    pub fn set_failover(&mut self, relays: Vec<RelayInfo>) {
+        tracing::info!(
+            "Relay failover: {}",
+            relays.iter().map(RelayInfo::host_port).collect::<Vec<_>>().join(", ")
+        );
+        self.failover = relays;
+    }
+
+    pub fn failover(&self) -> &[RelayInfo] {
+        &self.failover
+    }
+
+    /// Called after `error` failed a send to the default relay: connects to the
+    /// failover relays in order, skipping the one that failed, and sends `data` on the
+    /// first that takes it. The relay connected to becomes the default and
+    /// `relay-failover` is emitted with its address and the error that caused the
+    /// switch. Attempts draw from `budget`, which already holds the failed attempt;
+    /// once the list or the budget is used up the last error is returned.
+    pub async fn fail_over(
+        &mut self,
+        data: &[u8],
+        finish_mode: FinishMode,
+        priority: Priority,
+        error: anyhow::Error,
+        budget: &mut RetryBudget,
+    ) -> Result<(SendTiming, SentStream)> {
+        let failed = self.relay_info.as_ref().map(RelayInfo::pin_key);
+
+        let mut last_error = error;
+        for relay in self.failover.clone() {
+            let relay_id = relay.pin_key();
+            if failed.as_deref() == Some(relay_id.as_str()) {
+                continue;
+            }
+            if !budget.try_begin(RetryMechanism::Failover, Some(&relay_id)) {
+                break;
+            }
+
+            let attempt = match self.connect(relay.clone()).await {
+                Ok(()) => {
+                    tracing::warn!("Failed over to relay {}: {:#}", relay.host_port(), last_error);
+                    self.emit_lifecycle("relay-failover", relay.host_port(), Some(format!("{:#}", last_error)));
+                    self.send(data, finish_mode, priority).await
+                }
+                Err(e) => Err(e),
+            };
+            match attempt {
+                Ok(sent) => return Ok(sent),
+                Err(e) => {
+                    budget.record_failure(&e);
+                    last_error = e;
+                }
+            }
+        }
+        Err(last_error)
+    }
+
+    /// Queues `data` for delivery and tries to flush immediately if connected.
+    /// Returns the queued message id.
+    pub async fn enqueue(
+        &mut self,
+        data: Vec<u8>,
+        ttl_secs: Option<u64>,
+        priority: Priority,
+    ) -> Result<String> {
+        let message = QueuedMessage::new(data, ttl_secs, priority);
+        let id = message.id.clone();
+        self.send_queue.push(message).context("Failed to queue message")?;
+
+        if let Err(e) = self.flush_send_queue().await {
+            tracing::warn!("Queued message {} will be retried: {}", id, e);
+        }
+        Ok(id)
+    }
+
+    /// Messages sent since the current relay connection was established.
+    pub fn session_messages_sent(&self) -> u64 {
+        self.messages_sent.load(Ordering::Relaxed)
+    }
+
+    /// Relay connections established after the first one since startup.
+    pub fn reconnects(&self) -> u64 {
+        self.sessions_started.saturating_sub(1)
+    }
+
+    /// Messages waiting in the send queue for a relay connection.
+    pub fn pending_send_count(&self) -> usize {
+        self.send_queue.len()
+    }
+
+    pub fn set_queue_capacity(&mut self, capacity: usize) -> Result<()> {
+        self.send_queue.set_capacity(capacity)?;
+        tracing::info!("Send queue capacity: {}", capacity);
+        Ok(())
+    }
+
+    /// Streams left open by [`FinishMode::KeepOpen`] and not yet finished.
+    pub fn kept_stream_count(&self) -> usize {
+        self.kept_streams.lock().map(|k| k.len()).unwrap_or(0)
+    }
+
+    pub fn inbound_listening(&self) -> bool {
+        self.inbound.is_some()
+    }
+
+    /// Handle to the active relay connection, for background tasks that write their
+    /// own streams.
+    pub fn connection(&self) -> Option<Connection> {
+        self.active_connection.clone()
+    }
+
+    pub fn connected_relay(&self) -> Option<RelayInfo> {
+        self.relay_info.clone()
+    }
+
+    /// Open connection to `relay_id`, whether it is the default relay or pooled.
+    pub fn connection_to(&self, relay_id: &str) -> Option<Connection> {
+        if self.is_default_relay(relay_id) {
+            return self.active_connection.clone();
+        }
+        self.pool.get(relay_id).map(|pooled| pooled.connection.clone())
+    }
+
+    /// Status of the default relay.
+    pub fn status(&self) -> RelayStatus {
+        RelayStatus {
+            connected: self.active_connection.is_some(),
+            relay_address: self.relay_info.as_ref().map(RelayInfo::host_port),
+            latency_ms: self.active_connection.as_ref().map(|c| rtt_ms(c.rtt())),
+            relay_id: self.relay_info.as_ref().map(RelayInfo::pin_key),
+            is_default: true,
+        }
+    }
+
+    /// Status of every open relay connection, the default relay first.
+    pub fn pool_status(&self) -> Vec<RelayStatus> {
+        let mut statuses = Vec::with_capacity(1 + self.pool.len());
+        if self.active_connection.is_some() {
+            statuses.push(self.status());
+        }
+        let mut pooled: Vec<_> = self.pool.iter().collect();
+        pooled.sort_by(|a, b| a.0.cmp(b.0));
+        statuses.extend(pooled.into_iter().map(|(id, pooled)| RelayStatus {
+            connected: pooled.connection.close_reason().is_none(),
+            relay_address: Some(pooled.relay.host_port()),
+            latency_ms: Some(rtt_ms(pooled.connection.rtt())),
+            relay_id: Some(id.clone()),
+            is_default: false,
+        }));
+        statuses
+    }
+
+    /// Open relay connections, counting the default one.
+    pub fn connection_count(&self) -> usize {
+        usize::from(self.active_connection.is_some()) + self.pool.len()
+    }
+
+    /// Replaces every transport timeout at once after checking their ordering.
+    /// Applies to operations started after this call.
+    pub fn set_timeouts(&mut self, timeouts: TimeoutConfig) -> Result<()> {
+        timeouts.validate()?;
+        self.timeouts = timeouts;
+        tracing::info!("Timeouts: {:?}", timeouts);
+        Ok(())
+    }
+
+    pub fn timeouts(&self) -> TimeoutConfig {
+        self.timeouts
+    }
+
+    /// Changes only the connect timeout. The handshake keeps its margin below it and
+    /// the probe timeout is capped at it, as [`TimeoutConfig::validate`] requires.
+    pub fn set_connect_timeout(&mut self, connect_ms: u64) -> Result<()> {
+        if connect_ms == 0 {
+            let message = "Connect timeout must be greater than zero".to_string();
+            return Err(HushError::InvalidInput(message).into());
+        }
+        let scaled = self.timeouts.for_relay(Some(connect_ms));
+        self.set_timeouts(TimeoutConfig {
+            probe_ms: scaled.probe_ms.min(connect_ms),
+            ..scaled
+        })
+    }
+
+    /// Transport features this build supports and what the client config enables, so
+    /// the UI can hide options that would have no effect. Derived from the stored
+    /// settings [`MtuConfig::transport_config`] and [`AdaptiveKeepAlive::apply_default`]
+    /// build the endpoint's client config from. Datagrams also need the relay's consent,
+    /// so they only count as enabled on a connection that negotiated them.
+    pub fn quic_capabilities(&self) -> QuicCapabilities {
+        let (keep_alive, idle_timeout) = self.keep_alive.default_settings();
+        let tuning = self.keep_alive.tuning();
+        let max_datagram = self.connection().and_then(|connection| connection.max_datagram_size());
+
+        #[cfg(feature = "network-sim")]
+        let network_sim = {
+            let simulated = crate::network_sim::current();
+            Capability::new(
+                true,
+                simulated.is_active(),
+                Some(format!(
+                    "latency_ms={}, jitter_ms={}, loss_pct={}",
+                    simulated.latency_ms, simulated.jitter_ms, simulated.loss_pct
+                )),
+            )
+        };
+        #[cfg(not(feature = "network-sim"))]
+        let network_sim = Capability::new(false, false, None);
+
+        QuicCapabilities {
+            datagrams: Capability::new(
+                true,
+                max_datagram.is_some(),
+                Some(match max_datagram {
+                    Some(size) => format!("buffer_bytes={}, max_size={}", DATAGRAM_BUFFER_SIZE, size),
+                    None => format!("buffer_bytes={}", DATAGRAM_BUFFER_SIZE),
+                }),
+            ),
+            zero_rtt: Capability::new(true, self.zero_rtt, None),
+            pmtud: Capability::new(
+                true,
+                self.mtu.discovery_enabled(),
+                Some(format!("min_mtu={}, max_mtu={}", self.mtu.min_mtu, self.mtu.max_mtu)),
+            ),
+            // Rebinding the shared endpoint moves live connections along; per-connection
+            // endpoints are never rebound
+            migration: Capability::new(
+                true,
+                !self.per_connection_endpoint,
+                Some(format!("per_connection_endpoint={}", self.per_connection_endpoint)),
+            ),
+            // The transport config never replaces quinn's default controller
+            bbr: Capability::new(true, false, Some("congestion_controller=cubic".to_string())),
+            network_sim,
+            keep_alive: Capability::new(
+                true,
+                true,
+                Some(format!(
+                    "interval_ms={}, idle_timeout_ms={}, adaptive={}",
+                    keep_alive.as_millis(),
+                    idle_timeout.as_millis(),
+                    tuning.keep_alive_ms.is_none()
+                )),
+            ),
+        }
+    }
+
+    /// Applies to connections opened after this call; live connections keep their bounds.
+    /// Every connection is dialed with its own pinned client config built from these
+    /// bounds, so the endpoint's default config is left alone.
+    pub fn set_mtu_bounds(&mut self, mtu: MtuConfig) -> Result<()> {
+        mtu.validate()?;
+        self.mtu = mtu;
+
+        tracing::info!("MTU bounds set: min={}, max={}", mtu.min_mtu, mtu.max_mtu);
+        Ok(())
+    }
+
+    pub fn connection_params(&self) -> Result<ConnectionParams> {
+        let connection = self.active_connection.as_ref()
+            .ok_or(HushError::NotConnected)?;
+
+        let path = connection.stats().path;
+        Ok(ConnectionParams {
+            current_mtu: path.current_mtu,
+            min_mtu: self.mtu.min_mtu,
+            max_mtu: self.mtu.max_mtu,
+            pmtud_enabled: self.mtu.discovery_enabled(),
+            black_hole_detected: path.black_holes_detected > 0,
+            black_holes_detected: path.black_holes_detected,
+        })
+    }
+
+    /// `None` when no relay is connected. Byte and datagram counts are UDP-level and
+    /// include QUIC overhead.
+    pub fn connection_stats(&self) -> Option<ConnectionStats> {
+        let stats = self.active_connection.as_ref()?.stats();
+        Some(ConnectionStats {
+            bytes_sent: stats.udp_tx.bytes,
+            bytes_received: stats.udp_rx.bytes,
+            datagrams_sent: stats.udp_tx.datagrams,
+            datagrams_received: stats.udp_rx.datagrams,
+            congestion_window: stats.path.cwnd,
+            lost_packets: stats.path.lost_packets,
+            sent_packets: stats.path.sent_packets,
+            rtt_ms: stats.path.rtt.as_secs_f64() * 1000.0,
+        })
+    }
+
+    /// SHA-256 fingerprint of the certificate the connected relay presented.
+    pub fn connected_fingerprint(&self) -> Option<String> {
+        self.connected_fingerprint.as_ref().map(cert_pins::format_fingerprint)
+    }
+
+    /// Connects to every relay that has pins configured and compares the certificate it
+    /// serves with those pins. Each audit connection is made from a throwaway endpoint
+    /// and closed right after the handshake.
+    pub async fn audit_pins(&self, relays: Vec<RelayInfo>) -> Vec<PinAuditEntry> {
+        let mut report = Vec::new();
+
+        for relay in relays {
+            let relay_id = relay.pin_key();
+            let pins = self.pins.get(&relay_id);
+            if pins.is_empty() {
+                continue;
+            }
+            let address = relay.host_port();
+
+            let observed: ObservedCert = Arc::new(Mutex::new(None));
+            let attempt: Result<Dialed> = async {
+                let addr = relay.primary_addr().await?;
+                let verifier = PinnedCertVerifier::observing(pins.clone(), observed.clone())
+                    .with_hook(self.verification_hook.clone());
+                let client_config = client_config_with_verifier(&self.mtu, verifier)?;
+                let timeouts = self.timeouts.for_relay(relay.connect_timeout_ms);
+                self.probe_connect(addr, relay.server_name(), client_config, timeouts).await
+            }
+            .await;
+
+            let served = observed.lock().ok().and_then(|slot| *slot);
+            let outcome = match (attempt, served) {
+                (Ok(dialed), served) => {
+                    dialed.close(AppCloseCode::Normal);
+                    PinAuditOutcome::Match {
+                        fingerprint: served.as_ref().map(cert_pins::format_fingerprint).unwrap_or_default(),
+                    }
+                }
+                (Err(_), Some(served)) if !pins.contains(&served) => PinAuditOutcome::Mismatch {
+                    served: cert_pins::format_fingerprint(&served),
+                    pinned: pins.iter().map(cert_pins::format_fingerprint).collect(),
+                },
+                (Err(e), _) => PinAuditOutcome::Unreachable {
+                    error: format!("{:#}", e),
+                },
+            };
+
+            report.push(PinAuditEntry {
+                relay_id,
+                address,
+                outcome,
+            });
+        }
+
+        report
+    }
+
+    /// Starts or stops the server-side endpoint for direct peer messages. Off by
+    /// default: listening exposes a port, so it's opt-in. The node certificate is kept
+    /// in `key_dir` so peers' pins stay valid across restarts.
+    pub fn set_inbound(
+        &mut self,
+        enabled: bool,
+        port: u16,
+        key_dir: &Path,
+        app: AppHandle,
+        taior: Arc<SharedState<TaiorState>>,
+    ) -> Result<Option<InboundStatus>> {
+        if let Some(listener) = self.inbound.take() {
+            listener.stop();
+        }
+        if !enabled {
+            return Ok(None);
+        }
+        self.ensure_port_free(port)?;
+
+        let listener = InboundListener::start(
+            SocketAddr::from(([0, 0, 0, 0], port)),
+            key_dir,
+            app,
+            taior,
+            self.directory_mirror.clone(),
+            self.receipts.clone(),
+            self.dedup.clone(),
+        )?;
+        let status = InboundStatus {
+            local_addr: listener.local_addr()?.to_string(),
+            fingerprint: listener.fingerprint(),
+        };
+        self.inbound = Some(listener);
+        Ok(Some(status))
+    }
+
+    pub fn pins(&self) -> &RelayPins {
+        &self.pins
+    }
+
+    /// Fingerprint pins for `relay` plus the public key it was published with. Fails if
+    /// the public key is set but can't be decoded.
+    fn trust_for(&self, relay: &RelayInfo) -> Result<RelayTrust> {
+        let public_key = relay.public_key.as_deref()
+            .filter(|key| !key.trim().is_empty())
+            .map(cert_pins::parse_public_key)
+            .transpose()
+            .map_err(|e| HushError::InvalidInput(format!("{:#}", e)))?;
+        Ok(RelayTrust {
+            fingerprints: self.pins.get(&relay.pin_key()),
+            public_key,
+        })
+    }
+
+    /// Also forgets session tickets: a resumed handshake would skip the changed pins.
+    pub fn pins_mut(&mut self) -> &mut RelayPins {
+        self.tickets.clear();
+        &mut self.pins
+    }
+
+    /// Lets [`Self::connect_early`] send its payload as 0-RTT early data. Takes effect
+    /// for connections opened after this call.
+    pub fn set_zero_rtt(&mut self, enabled: bool) {
+        self.zero_rtt = enabled;
+        tracing::info!("0-RTT early data: {}", enabled);
+    }
+
+    /// When enabled, every relay connection binds its own ephemeral UDP endpoint instead
+    /// of sharing one socket. Costs a socket per connect but avoids source-port linkability.
+    pub fn set_per_connection_endpoint(&mut self, enabled: bool) {
+        self.per_connection_endpoint = enabled;
+        tracing::info!("Per-connection endpoint: {}", enabled);
+    }
+
+    /// UDP port the shared endpoint is pinned to, if any.
+    pub fn source_port(&self) -> Option<u16> {
+        self.source_port
+    }
+
+    /// Moves to a fresh ephemeral source port. The shared endpoint is rebound in place,
+    /// which migrates the live connection; with per-connection endpoints the relay is
+    /// reconnected on a new socket instead. Returns the new local address, or `None` when
+    /// there was nothing bound to rotate.
+    pub async fn rotate_source_port(&mut self) -> Result<Option<SocketAddr>> {
+        if let Some(port) = self.source_port {
+            anyhow::bail!("Source port is fixed to {}; clear it before rotating", port);
+        }
+
+        if self.per_connection_endpoint {
+            let Some(relay) = self.relay_info.clone() else {
+                return Ok(None);
+            };
+            self.connect(relay).await.context("Reconnect on a new port failed")?;
+            let local_addr = self.dedicated_endpoint.as_ref()
+                .map(|e| e.local_addr())
+                .transpose()?;
+            return Ok(local_addr);
+        }
+
+        if self.endpoint.is_none() {
+            return Ok(None);
+        }
+        self.set_source_port(None).await.map(Some)
+    }
+
+    /// Local address the client endpoint is bound to: the endpoint of the current
+    /// connection in per-connection mode, otherwise the shared one. Endpoints are bound
+    /// lazily on the first connect (or by [`Self::set_source_port`]), so before that
+    /// this fails with [`HushError::NotConnected`].
+    pub fn local_endpoint_addr(&self) -> Result<SocketAddr> {
+        let endpoint = if self.per_connection_endpoint {
+            self.dedicated_endpoint.as_ref()
+        } else {
+            self.endpoint.as_ref()
+        };
+        let endpoint = endpoint.ok_or(HushError::NotConnected)
+            .context("No client endpoint is bound yet; it is created on the first connect")?;
+        Ok(endpoint.local_addr()?)
+    }
+
+    /// Binds the shared endpoint to a fixed UDP source port, or back to an ephemeral one
+    /// with `None`, for manual port forwarding or predictable hole punching. An existing
+    /// endpoint is rebound in place so the live connection migrates to the new port.
+    /// Per-connection endpoints always use ephemeral ports.
+    pub async fn set_source_port(&mut self, port: Option<u16>) -> Result<SocketAddr> {
+        if let Some(port) = port {
+            let held = self.endpoint.as_ref()
+                .and_then(|e| e.local_addr().ok())
+                .is_some_and(|addr| addr.port() == port);
+            if held {
+                // Already bound there; rebinding would fail against our own socket
+                self.source_port = Some(port);
+                return Ok(self.endpoint.as_ref().expect("checked above").local_addr()?);
+            }
+            self.ensure_port_free(port)?;
+        }
+        let socket = bind_shared_udp(port.unwrap_or(0))?;
+
+        let local_addr = match &self.endpoint {
+            Some(endpoint) => {
+                #[cfg(feature = "network-sim")]
+                endpoint.rebind_abstract(crate::network_sim::wrap(socket)?)
+                    .context("Failed to rebind endpoint")?;
+                #[cfg(not(feature = "network-sim"))]
+                endpoint.rebind(socket).context("Failed to rebind endpoint")?;
+                endpoint.local_addr()?
+            }
+            None => {
+                let endpoint = Self::create_endpoint(&self.mtu, &self.keep_alive, socket).await?;
+                let local_addr = endpoint.local_addr()?;
+                self.endpoint = Some(endpoint);
+                local_addr
+            }
+        };
+
+        self.source_port = port;
+        tracing::info!("Source port: {:?} (bound {})", port, local_addr);
+        Ok(local_addr)
+    }
+
+    /// Handshakes with each of `relays` and closes again right away, measuring the RTT.
+    /// Each attempt is bounded by the probe timeout rather than the connect timeout so
+    /// a dead relay doesn't stall the whole sweep. Probes run on their own endpoints,
+    /// so the relay connections in use are not disturbed.
+    pub async fn probe_relays(&self, relays: &[(String, RelayInfo)]) -> Vec<RelayProbe> {
+        let mut results = Vec::with_capacity(relays.len());
+
+        for (relay_id, relay) in relays {
+            let attempt: Result<Dialed> = async {
+                let addr = relay.primary_addr().await?;
+                let scaled = self.timeouts.for_relay(relay.connect_timeout_ms);
+                let timeouts = TimeoutConfig {
+                    connect_ms: scaled.probe_ms,
+                    handshake_ms: scaled.handshake_ms.min(scaled.probe_ms),
+                    ..scaled
+                };
+                let network = keepalive::network_key(addr);
+                let client_config = self.relay_client_config(addr, &network, self.trust_for(relay)?)?;
+                self.probe_connect(addr, relay.server_name(), client_config, timeouts).await
+            }
+            .await;
+
+            let probe = match attempt {
+                Ok(dialed) => {
+                    let latency = rtt_ms(dialed.connection.rtt());
+                    dialed.close(AppCloseCode::Normal);
+                    RelayProbe {
+                        relay_id: relay_id.clone(),
+                        latency_ms: Some(latency),
+                        error: None,
+                    }
+                }
+                Err(e) => {
+                    tracing::debug!("Relay {} failed its probe: {:#}", relay_id, e);
+                    RelayProbe {
+                        relay_id: relay_id.clone(),
+                        latency_ms: None,
+                        error: Some(format!("{:#}", e)),
+                    }
+                }
+            };
+            results.push(probe);
+        }
+
+        results
+    }
+
+    /// Probes every ordered pair of `relays`. A relay that can't be reached at all
+    /// gets an all-false row. Like [`Self::probe_relays`], each relay is dialed from a
+    /// throwaway endpoint.
+    pub async fn connectivity_matrix(
+        &self,
+        relays: &[(String, RelayInfo)],
+    ) -> ConnectivityMatrix {
+        let mut reachable = vec![vec![false; relays.len()]; relays.len()];
+
+        for (i, (from_id, from)) in relays.iter().enumerate() {
+            let addr = match from.primary_addr().await {
+                Ok(addr) => addr,
+                Err(e) => {
+                    tracing::warn!("Skipping relay {} with invalid address: {:#}", from_id, e);
+                    continue;
+                }
+            };
+            let network = keepalive::network_key(addr);
+            let client_config = match self.trust_for(from)
+                .and_then(|trust| self.relay_client_config(addr, &network, trust))
+            {
+                Ok(client_config) => client_config,
+                Err(e) => {
+                    tracing::warn!("Skipping relay {} with invalid public key: {:#}", from_id, e);
+                    continue;
+                }
+            };
+            let timeouts = self.timeouts.for_relay(from.connect_timeout_ms);
+            let dialed = match self.probe_connect(addr, from.server_name(), client_config, timeouts).await {
+                Ok(dialed) => dialed,
+                Err(e) => {
+                    tracing::warn!("Relay {} unreachable for probing: {}", from_id, e);
+                    continue;
+                }
+            };
+
+            for (j, (to_id, to)) in relays.iter().enumerate() {
+                if i == j {
+                    continue;
+                }
+                reachable[i][j] = match probe_forwarding(&dialed.connection, to, self.timeouts.probe()).await {
+                    Ok(ok) => ok,
+                    Err(e) => {
+                        tracing::debug!("Probe {} -> {} failed: {}", from_id, to_id, e);
+                        false
+                    }
+                };
+            }
+
+            dialed.close(AppCloseCode::Normal);
+        }
+
+        ConnectivityMatrix {
+            relay_ids: relays.iter().map(|(id, _)| id.clone()).collect(),
+            reachable,
+        }
+    }
+
+    /// Fails with a clear error if one of this app's own sockets already holds `port`.
+    /// The OS would otherwise report it the same way as a foreign process.
+    fn ensure_port_free(&self, port: u16) -> Result<()> {
+        if port == 0 {
+            return Ok(());
+        }
+
+        let owners = [
+            (PortUser::ClientEndpoint, self.endpoint.as_ref().and_then(|e| e.local_addr().ok())),
+            (PortUser::ClientEndpoint, self.dedicated_endpoint.as_ref().and_then(|e| e.local_addr().ok())),
+            (PortUser::Inbound, self.inbound.as_ref().and_then(|l| l.local_addr().ok())),
+        ];
+        for (user, addr) in owners {
+            if addr.is_some_and(|a| a.port() == port) {
+                anyhow::bail!("UDP port {} is already bound by this app ({})", port, user.label());
+            }
+        }
+        Ok(())
+    }
+
+    async fn create_endpoint(
+        mtu: &MtuConfig,
+        keep_alive: &AdaptiveKeepAlive,
+        socket: std::net::UdpSocket,
+    ) -> Result<Endpoint> {
+        let mut client_config = configure_client(mtu, RelayTrust::default(), None)?;
+        let mut transport = mtu.transport_config();
+        keep_alive.apply_default(&mut transport);
+        client_config.transport_config(Arc::new(transport));
+        
+        #[cfg(feature = "network-sim")]
+        let mut endpoint = Endpoint::new_with_abstract_socket(
+            quinn::EndpointConfig::default(),
+            None,
+            crate::network_sim::wrap(socket)?,
+            Arc::new(quinn::TokioRuntime),
+        )?;
+        #[cfg(not(feature = "network-sim"))]
+        let mut endpoint = Endpoint::new(
+            quinn::EndpointConfig::default(),
+            None,
+            socket,
+            Arc::new(quinn::TokioRuntime),
+        )?;
+        endpoint.set_default_client_config(client_config);
+        
+        Ok(endpoint)
+    }
+
+    async fn connect_to_address(
+        &mut self,
+        addr: SocketAddr,
+        server_name: &str,
+        trust: RelayTrust,
+        timeouts: TimeoutConfig,
+    ) -> Result<Dialed> {
+        let network = keepalive::network_key(addr);
+        let client_config = self.relay_client_config(addr, &network, trust)?;
+        let dialed = tokio::time::timeout(
+            timeouts.connect(),
+            self.connect_with_config(addr, server_name, client_config, timeouts.handshake()),
+        )
+        .await
+        .map_err(|_| HushError::QuicConnect(format!(
+            "Connecting to {} timed out after {}ms",
+            addr, timeouts.connect_ms
+        )))??;
+
+        self.keep_alive.watch(network, dialed.connection.clone());
+        Ok(dialed)
+    }
+
+    /// Pinned client config carrying the keep-alive interval learned for `network`. It
+    /// resumes with the session tickets of `addr` and, when enabled, offers 0-RTT.
+    fn relay_client_config(
+        &self,
+        addr: SocketAddr,
+        network: &str,
+        trust: RelayTrust,
+    ) -> Result<ClientConfig> {
+        let verifier = PinnedCertVerifier::trusting(trust).with_hook(self.verification_hook.clone());
+        let mut crypto = crypto_config(verifier);
+        crypto.resumption = self.tickets.resumption(&addr.to_string());
+        crypto.enable_early_data = self.zero_rtt;
+
+        let mut client_config = quic_client_config(&self.mtu, crypto)?;
+        let mut transport = self.mtu.transport_config();
+        self.keep_alive.apply(network, &mut transport);
+        client_config.transport_config(Arc::new(transport));
+        Ok(client_config)
+    }
+
+    /// Dials `addr` from a throwaway endpoint that is never stored, for handshakes that
+    /// only inspect a relay. The endpoint is closed with the returned connection by
+    /// [`Dialed::close`], or right away if the dial fails.
+    async fn probe_connect(
+        &self,
+        addr: SocketAddr,
+        server_name: &str,
+        client_config: ClientConfig,
+        timeouts: TimeoutConfig,
+    ) -> Result<Dialed> {
+        let endpoint = Self::create_endpoint(&self.mtu, &self.keep_alive, bind_udp_for(addr, 0)?).await?;
+        let handshake = async {
+            let connecting = endpoint.connect_with(client_config, addr, server_name)?;
+            tokio::time::timeout(timeouts.handshake(), connecting)
+                .await
+                .context("QUIC handshake timed out")?
+                .map_err(handshake_error)
+        };
+        let connected = match tokio::time::timeout(timeouts.connect(), handshake).await {
+            Ok(connected) => connected,
+            Err(_) => Err(HushError::QuicConnect(format!(
+                "Connecting to {} timed out after {}ms",
+                addr, timeouts.connect_ms
+            ))
+            .into()),
+        };
+
+        match connected {
+            Ok(connection) => Ok(Dialed { connection, endpoint: Some(endpoint) }),
+            Err(e) => {
+                AppCloseCode::Normal.close_endpoint(&endpoint);
+                Err(e)
+            }
+        }
+    }
+
+    /// Endpoint the next outgoing connection should use: the shared client endpoint,
+    /// or a fresh one when per-connection endpoints are enabled. A fresh endpoint
+    /// belongs to the connection dialed on it and is stored and closed with it.
+    async fn dial_endpoint(&mut self, remote: SocketAddr) -> Result<Endpoint> {
+        if self.per_connection_endpoint {
+            // Fresh UDP socket so this connection can't be linked to earlier ones by source port
+            return Self::create_endpoint(&self.mtu, &self.keep_alive, bind_udp_for(remote, 0)?).await;
+        }
+
+        let endpoint = self.shared_endpoint().await?;
+        if remote.is_ipv6() && endpoint.local_addr()?.is_ipv4() {
+            return Err(HushError::InvalidAddress(format!(
+                "Cannot reach IPv6 relay {}: IPv6 is unavailable on this host",
+                remote
+            ))
+            .into());
+        }
+        Ok(endpoint)
+    }
+
+    /// The shared client endpoint, bound on first use. Every connection outside
+    /// per-connection mode goes through it, whatever the relay's address family.
+    async fn shared_endpoint(&mut self) -> Result<Endpoint> {
+        if let Some(endpoint) = &self.endpoint {
+            return Ok(endpoint.clone());
+        }
+        let socket = bind_shared_udp(self.source_port.unwrap_or(0))?;
+        let endpoint = Self::create_endpoint(&self.mtu, &self.keep_alive, socket).await?;
+        tracing::debug!("Shared client endpoint bound to {}", endpoint.local_addr()?);
+        self.endpoint = Some(endpoint.clone());
+        Ok(endpoint)
+    }
+
+    async fn connect_with_config(
+        &mut self,
+        addr: SocketAddr,
+        server_name: &str,
+        client_config: ClientConfig,
+        handshake: Duration,
+    ) -> Result<Dialed> {
+        let endpoint = self.dial_endpoint(addr).await?;
+        let owned = self.per_connection_endpoint.then(|| endpoint.clone());
+        let connected = async {
+            let connecting = endpoint.connect_with(client_config, addr, server_name)?;
+            tokio::time::timeout(handshake, connecting)
+                .await
+                .context("QUIC handshake timed out")?
+                .map_err(handshake_error)
+        }
+        .await;
+
+        match connected {
+            Ok(connection) => {
+                tracing::info!("QUIC connection established to {}", addr);
+                Ok(Dialed { connection, endpoint: owned })
+            }
+            Err(e) => {
+                if let Some(endpoint) = owned {
+                    AppCloseCode::Normal.close_endpoint(&endpoint);
+                }
+                Err(e)
+            }
+        }
+    }
+}
+
+/// Context for a failed handshake. A TLS alert raised by our own side means the
+/// verifier rejected the relay's certificate or its proof of the key, which becomes
+/// [`HushError::CertVerification`] so it isn't retried.
+fn handshake_error(error: quinn::ConnectionError) -> anyhow::Error {
+    if let quinn::ConnectionError::TransportError(e) = &error {
+        if (0x100..0x200).contains(&u64::from(e.code)) {
+            return HushError::CertVerification(format!(
+                "Relay certificate rejected: {}",
+                e.reason
+            ))
+            .into();
+        }
+    }
+    anyhow::Error::new(error).context("Failed to establish QUIC connection")
+}
+
+/// Finishes streams still open on a connection being migrated away from, waits up to
+/// `drain` for the relay to receive what was written, then closes it.
+async fn drain_and_close(
+    connection: Connection,
+    endpoint: Option<Endpoint>,
+    kept: Vec<SendStream>,
+    drain: Duration,
+) {
+    for mut stream in kept {
+        stream.finish().ok();
+    }
+    // Closing immediately would discard stream data still waiting to be sent
+    let _ = tokio::time::timeout(drain, connection.closed()).await;
+
+    AppCloseCode::Migration.close(&connection);
+    if let Some(endpoint) = endpoint {
+        AppCloseCode::Migration.close_endpoint(&endpoint);
+    }
+    tracing::info!("Closed connection drained after migration");
+}
+
+fn emit_lifecycle(app: &AppHandle, event: &str, relay_address: String, error: Option<String>) {
+    if let Err(e) = app.emit(event, RelayLifecycleEvent { relay_address, error }) {
+        tracing::debug!("Failed to emit {}: {}", event, e);
+    }
+}
+
+/// Emits `relay-disconnected` with the reason if the relay or the network ends the
+/// connection. Local closes report themselves through [`QuicTransport::disconnect`].
+async fn report_remote_close(app: AppHandle, connection: Connection, relay_address: String) {
+    let reason = connection.closed().await;
+    if matches!(reason, quinn::ConnectionError::LocallyClosed) {
+        return;
+    }
+    tracing::warn!("Relay {} closed the connection: {}", relay_address, reason);
+    emit_lifecycle(&app, "relay-disconnected", relay_address, Some(reason.to_string()));
+}
+
+/// A freshly dialed relay connection.
+struct Dialed {
+    connection: Connection,
+    /// Its own socket in per-connection endpoint mode, closed along with it.
+    endpoint: Option<Endpoint>,
+}
+
+impl Dialed {
+    fn close(&self, code: AppCloseCode) {
+        code.close(&self.connection);
+        if let Some(endpoint) = &self.endpoint {
+            code.close_endpoint(endpoint);
+        }
+    }
+}
+
+/// A relay connection held next to the default one.
+struct PooledRelay {
+    relay: RelayInfo,
+    connection: Connection,
+    /// Its own socket in per-connection endpoint mode.
+    endpoint: Option<Endpoint>,
+    connected_at: Instant,
+    messages_sent: AtomicU64,
+}
+
+impl PooledRelay {
+    fn summary(&self) -> SessionSummary {
+        let stats = self.connection.stats();
+        SessionSummary {
+            relay_address: Some(self.relay.host_port()),
+            bytes_sent: stats.udp_tx.bytes,
+            bytes_received: stats.udp_rx.bytes,
+            duration_ms: self.connected_at.elapsed().as_millis() as u64,
+            messages_sent: self.messages_sent.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Which of this app's sockets holds a local port.
+#[derive(Debug, Clone, Copy)]
+enum PortUser {
+    ClientEndpoint,
+    Inbound,
+}
+
+impl PortUser {
+    fn label(self) -> &'static str {
+        match self {
+            Self::ClientEndpoint => "client endpoint",
+            Self::Inbound => "inbound listener",
+        }
+    }
+}
+
+/// Smoothed RTT rounded to the nearest millisecond.
+fn rtt_ms(rtt: Duration) -> u64 {
+    (rtt.as_secs_f64() * 1000.0).round() as u64
+}
+
+/// Binds `[::]:port` with IPv4-mapped addresses enabled, so one socket reaches relays
+/// of both families. Falls back to an IPv4-only socket where the host has no IPv6.
+fn bind_shared_udp(port: u16) -> Result<std::net::UdpSocket> {
+    use socket2::{Domain, Protocol, Socket, Type};
+
+    let dual_stack = Socket::new(Domain::IPV6, Type::DGRAM, Some(Protocol::UDP))
+        .and_then(|socket| {
+            socket.set_only_v6(false)?;
+            socket.bind(&SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), port).into())?;
+            Ok(socket)
+        });
+    match dual_stack {
+        Ok(socket) => Ok(socket.into()),
+        Err(e) if e.kind() == std::io::ErrorKind::AddrInUse => {
+            Err(anyhow::anyhow!("UDP port {} is in use by another process", port))
+        }
+        Err(e) => {
+            tracing::debug!("No dual-stack socket ({}), binding IPv4 only", e);
+            bind_udp_on(Ipv4Addr::UNSPECIFIED.into(), port)
+        }
+    }
+}
+
+/// Binds the unspecified address of `remote`'s family, so the socket can reach it.
+fn bind_udp_for(remote: SocketAddr, port: u16) -> Result<std::net::UdpSocket> {
+    let ip: IpAddr = if remote.is_ipv6() {
+        Ipv6Addr::UNSPECIFIED.into()
+    } else {
+        Ipv4Addr::UNSPECIFIED.into()
+    };
+    bind_udp_on(ip, port)
+}
+
+fn bind_udp_on(ip: IpAddr, port: u16) -> Result<std::net::UdpSocket> {
+    std::net::UdpSocket::bind(SocketAddr::new(ip, port)).map_err(|e| {
+        if e.kind() == std::io::ErrorKind::AddrInUse {
+            anyhow::anyhow!("UDP port {} is in use by another process", port)
+        } else {
+            anyhow::Error::new(e).context(format!("Failed to bind UDP port {}", port))
+        }
+    })
+}
+
+/// Writes one recipient's stream in `quantum`-sized chunks, yielding between chunks so
+/// concurrently scheduled streams take turns.
+async fn write_recipient(connection: Connection, send: RecipientSend, quantum: usize) -> RecipientResult {
+    let started = Instant::now();
+    let mut written = 0;
+
+    let outcome: Result<()> = async {
+        let mut stream = connection.open_uni().await.context("Failed to open QUIC stream")?;
+        for chunk in send.data.chunks(quantum.max(1)) {
+            stream.write_all(chunk).await.context("Failed to send data")?;
+            written += chunk.len();
+            tokio::task::yield_now().await;
+        }
+        stream.finish().context("Failed to finish stream")?;
+        Ok(())
+    }
+    .await;
+
+    RecipientResult {
+        recipient: send.recipient,
+        bytes_written: written,
+        elapsed_ms: started.elapsed().as_millis() as u64,
+        error: outcome.err().map(|e| format!("{:#}", e)),
+    }
+}
+
+/// Fingerprint of the end-entity certificate that passed verification during the
+/// handshake, taken from the peer identity rustls hands to quinn.
+fn peer_fingerprint(connection: &Connection) -> Option<Fingerprint> {
+    let identity = connection.peer_identity()?;
+    let certs = identity.downcast::<Vec<CertificateDer<'static>>>().ok()?;
+    certs.first().map(PinnedCertVerifier::fingerprint)
+}
+
+async fn probe_forwarding(
+    connection: &Connection,
+    next_hop: &RelayInfo,
+    probe_timeout: Duration,
+) -> Result<bool> {
+    let target = next_hop.host_port();
+    let mut frame = Vec::with_capacity(3 + target.len());
+    frame.push(FRAME_FORWARD_PROBE);
+    frame.extend_from_slice(&(target.len() as u16).to_be_bytes());
+    frame.extend_from_slice(target.as_bytes());
+
+    let (mut send, mut recv) = connection.open_bi().await
+        .context("Failed to open probe stream")?;
+    send.write_all(&frame).await.context("Failed to send probe")?;
+    send.finish().context("Failed to finish probe stream")?;
+
+    let mut status = [0u8; 1];
+    tokio::time::timeout(probe_timeout, recv.read_exact(&mut status))
+        .await
+        .context("Forwarding probe timed out")?
+        .context("Failed to read probe response")?;
+
+    Ok(status[0] == FORWARD_OK)
+}
+
+async fn write_uni(connection: &Connection, data: &[u8], priority: Priority) -> Result<SendStream> {
+    let mut send_stream = connection
+        .open_uni()
+        .await
+        .context("Failed to open QUIC stream")?;
+    send_stream
+        .set_priority(priority.stream_priority())
+        .context("Failed to set stream priority")?;
+
+    send_stream
+        .write_all(data)
+        .await
+        .context("Failed to send data")?;
+
+    send_stream.finish().context("Failed to finish stream")?;
+    Ok(send_stream)
+}
+
+/// Pins come from [`RelayPins`], which is seeded from the pin file in the app config
+/// directory at startup and extended at runtime. Without fingerprint pins the verifier
+/// falls back to the relay's published public key, if `trust` has one.
+fn configure_client(
+    mtu: &MtuConfig,
+    trust: RelayTrust,
+    hook: Option<Arc<dyn CertVerificationHook>>,
+) -> Result<ClientConfig> {
+    client_config_with_verifier(mtu, PinnedCertVerifier::trusting(trust).with_hook(hook))
+}
+
+/// Signature algorithms of the process-wide rustls provider, used to check handshake
+/// signatures against the relay's certificate.
+fn signature_algorithms() -> Result<rustls::crypto::WebPkiSupportedAlgorithms, rustls::Error> {
+    rustls::crypto::CryptoProvider::get_default()
+        .map(|provider| provider.signature_verification_algorithms)
+        .ok_or_else(|| rustls::Error::General("No rustls crypto provider installed".into()))
+}
+
+/// Never resumes: audits and first-use confirmations need the certificate served in a
+/// full handshake.
+fn client_config_with_verifier(mtu: &MtuConfig, verifier: PinnedCertVerifier) -> Result<ClientConfig> {
+    let mut crypto = crypto_config(verifier);
+    crypto.resumption = rustls::client::Resumption::disabled();
+    quic_client_config(mtu, crypto)
+}
+
+fn crypto_config(verifier: PinnedCertVerifier) -> rustls::ClientConfig {
+    rustls::ClientConfig::builder()
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(verifier))
+        .with_no_client_auth()
+}
+
+fn quic_client_config(mtu: &MtuConfig, crypto: rustls::ClientConfig) -> Result<ClientConfig> {
+    let mut client_config = ClientConfig::new(Arc::new(
+        quinn::crypto::rustls::QuicClientConfig::try_from(crypto)?
+    ));
+    client_config.transport_config(Arc::new(mtu.transport_config()));
+
+    Ok(client_config)
+}
+
+/// Slot the verifier fills with the fingerprint it was shown, whether or not it matched.
+type ObservedCert = Arc<Mutex<Option<Fingerprint>>>;
+
+/// Certificate pinning verifier: accepts only certificates whose SHA-256 fingerprint
+/// matches one of the pinned hashes. Prevents MITM attacks on relay connections.
+/// A relay may have several pins while its certificate rotates; any match is accepted.
+#[derive(Debug)]
+struct PinnedCertVerifier {
+    pinned_hashes: Vec<Fingerprint>,
+    /// Checked against the certificate's SPKI when no fingerprint is pinned.
+    pinned_key: Option<Vec<u8>>,
+    observed: Option<ObservedCert>,
+    hook: Option<Arc<dyn CertVerificationHook>>,
+}
+
+impl PinnedCertVerifier {
+    /// Fingerprint pinning when `trust` has fingerprints, key pinning otherwise.
+    fn trusting(trust: RelayTrust) -> Self {
+        Self {
+            pinned_hashes: trust.fingerprints,
+            pinned_key: trust.public_key,
+            observed: None,
+            hook: None,
+        }
+    }
+
+    /// Same checks, but also records the served fingerprint so an audit can report
+    /// what a mismatching relay presented.
+    fn observing(pinned_hashes: Vec<Fingerprint>, observed: ObservedCert) -> Self {
+        Self {
+            pinned_hashes,
+            pinned_key: None,
+            observed: Some(observed),
+            hook: None,
+        }
+    }
+
+    /// Runs `hook` after a successful pin match; it can still reject the certificate.
+    fn with_hook(mut self, hook: Option<Arc<dyn CertVerificationHook>>) -> Self {
+        self.hook = hook;
+        self
+    }
+
+    fn fingerprint(cert: &CertificateDer<'_>) -> Fingerprint {
+        use sha2::{Sha256, Digest};
+        let mut hasher = Sha256::new();
+        hasher.update(cert.as_ref());
+        let result = hasher.finalize();
+        let mut hash = [0u8; 32];
+        hash.copy_from_slice(&result);
+        hash
+    }
+}
+
+impl rustls::client::danger::ServerCertVerifier for PinnedCertVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &rustls::pki_types::ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: rustls::pki_types::UnixTime,
+    ) -> Result<rustls::client::danger::ServerCertVerified, rustls::Error> {
+        let cert_hash = Self::fingerprint(end_entity);
+        if let Some(slot) = &self.observed {
+            if let Ok(mut slot) = slot.lock() {
+                *slot = Some(cert_hash);
+            }
+        }
+
+        match (&self.pinned_hashes[..], &self.pinned_key) {
+            ([], None) => {
+                return Err(rustls::Error::General(
+                    "No pinned certificates configured. Cannot verify relay identity.".into()
+                ));
+            }
+            ([], Some(key)) => {
+                let matches = cert_pins::spki_matches(end_entity, key)
+                    .map_err(|e| rustls::Error::General(format!("{:#}", e)))?;
+                if !matches {
+                    return Err(rustls::Error::General(
+                        "Certificate key does not match the relay's public key. Possible MITM.".into()
+                    ));
+                }
+            }
+            (pins, _) => {
+                if !pins.iter().any(|pin| pin == &cert_hash) {
+                    return Err(rustls::Error::General(
+                        "Certificate fingerprint does not match any pinned hash. Possible MITM.".into()
+                    ));
+                }
+            }
+        }
+
+        if let Some(hook) = &self.hook {
+            hook.verify(server_name, end_entity, intermediates).map_err(|e| {
+                rustls::Error::General(format!("Certificate rejected by verification hook: {:#}", e))
+            })?;
+        }
+        Ok(rustls::client::danger::ServerCertVerified::assertion())
+    }
+
+    // The handshake signature proves the relay holds the pinned certificate's key;
+    // without it a key pin could be passed with someone else's public certificate.
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &rustls::DigitallySignedStruct,
+    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(message, cert, dss, &signature_algorithms()?)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &rustls::DigitallySignedStruct,
+    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(message, cert, dss, &signature_algorithms()?)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
+        vec![
+            rustls::SignatureScheme::RSA_PKCS1_SHA256,
+            rustls::SignatureScheme::ECDSA_NISTP256_SHA256,
+            rustls::SignatureScheme::ED25519,
+        ]
+    }
+}
+
+/// Adds `relay` to the connection pool; the first relay connected becomes the default.
+/// `make_default` replaces the current default relay instead. Concurrent calls for the
+/// same relay share one handshake and all receive its result.
+#[tauri::command]
+pub async fn connect_to_relay(
+    relay: RelayInfo,
+    make_default: Option<bool>,
+    app: AppHandle,
+    dedup: State<'_, ConnectDedup>,
+    state: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<String, HushError> {
+    let label = relay.host_port();
+
+    let relay_key = relay.pin_key();
+    dedup.run(&relay_key, async {
+        confirm_unpinned(&app, &state, &relay).await?;
+        let mut transport = state.write().await?;
+        if make_default.unwrap_or(false) {
+            transport.connect(relay).await
+        } else {
+            transport.connect_pooled(relay).await.map(|_| ())
+        }
+        .map_err(HushError::from)
+    })
+    .await?;
+
+    Ok(format!("Connected to {}", label))
+}
+
+/// Runs pin-on-first-use for `relay` if it needs it: shows the served fingerprint with
+/// `relay-fingerprint-pending`, waits for `confirm_fingerprint` with the transport lock
+/// released so other commands keep working, then pins the confirmed fingerprint.
+async fn confirm_unpinned(
+    app: &AppHandle,
+    state: &SharedState<QuicTransport>,
+    relay: &RelayInfo,
+) -> Result<(), HushError> {
+    let prompt = state.read().await?.request_first_use(relay).await.map_err(HushError::from)?;
+    let Some(prompt) = prompt else {
+        return Ok(());
+    };
+    if let Err(e) = app.emit("relay-fingerprint-pending", prompt.event().clone()) {
+        prompt.cancel();
+        return Err(HushError::Other(format!("Failed to ask for fingerprint confirmation: {}", e)));
+    }
+
+    let relay_id = relay.pin_key();
+    let confirmed = prompt.confirmed().await.map_err(HushError::from)?;
+    state.write().await?.pins_mut().add(&relay_id, confirmed);
+    tracing::info!("Pinned confirmed fingerprint for relay {}", relay_id);
+    Ok(())
+}
+
+/// Connects to `relay` and sends `data`, as 0-RTT early data when possible. Only pass
+/// data that is safe for the relay to receive twice.
+#[tauri::command]
+pub async fn connect_early(
+    relay: RelayInfo,
+    data: Vec<u8>,
+    priority: Option<Priority>,
+    app: AppHandle,
+    state: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<SendResult, HushError> {
+    confirm_unpinned(&app, &state, &relay).await?;
+    state.write().await?
+        .connect_early(relay, &data, priority.unwrap_or_default())
+        .await
+        .map_err(HushError::from)
+}
+
+#[tauri::command]
+pub async fn set_zero_rtt(
+    enabled: bool,
+    state: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<(), HushError> {
+    state.write().await?.set_zero_rtt(enabled);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn connect_fastest(
+    relay_ids: Vec<String>,
+    discovery: State<'_, Arc<RwLock<RelayDiscovery>>>,
+    state: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<String, HushError> {
+    let candidates = {
+        let discovery = discovery.read().await;
+        discovery.ensure_not_empty().map_err(HushError::from)?;
+        relay_ids.iter()
+            .map(|id| discovery.get_relay(id)
+                .map(|r| (id.clone(), r.to_relay_info()))
+                .ok_or_else(|| HushError::InvalidInput(format!("Unknown relay: {}", id))))
+            .collect::<Result<Vec<_>, _>>()?
+    };
+
+    state.write().await?
+        .connect_fastest(candidates)
+        .await
+        .map_err(HushError::from)
+}
+
+/// `relay_id` picks a pooled relay; without it the default relay is disconnected.
+#[tauri::command]
+pub async fn disconnect_relay(
+    relay_id: Option<String>,
+    state: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<Option<SessionSummary>, HushError> {
+    Ok(state.write().await?.disconnect_relay(relay_id.as_deref()))
+}
+
+/// `timeout_ms` bounds the flush and defaults to the configured drain timeout.
+#[tauri::command]
+pub async fn shutdown(
+    flush: bool,
+    timeout_ms: Option<u64>,
+    state: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<ShutdownReport, HushError> {
+    let mut transport = state.write().await?;
+
+    let timeout = timeout_ms
+        .map(Duration::from_millis)
+        .unwrap_or_else(|| transport.timeouts().drain());
+    Ok(transport.shutdown(flush, timeout).await)
+}
+
+/// Planned switch to another relay; unlike failover, nothing in flight is dropped.
+#[tauri::command]
+pub async fn migrate_to_relay(
+    new_relay_id: String,
+    app: AppHandle,
+    discovery: State<'_, Arc<RwLock<RelayDiscovery>>>,
+    state: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<Option<SessionSummary>, HushError> {
+    let relay = discovery.read().await
+        .get_relay(&new_relay_id)
+        .map(|r| r.to_relay_info())
+        .ok_or_else(|| HushError::InvalidInput(format!("Unknown relay: {}", new_relay_id)))?;
+    confirm_unpinned(&app, &state, &relay).await?;
+
+    let result = state.write().await?.migrate(relay).await;
+    discovery.write().await.record_connect(&new_relay_id, result.is_ok());
+    result.map_err(HushError::from)
+}
+
+/// Like [`migrate_to_relay`] for a relay that isn't in the directory. The Taior
+/// identity lives above the transport and is kept; if `relay` can't be reached the old
+/// connection stays in use and the error is returned.
+#[tauri::command]
+pub async fn switch_relay(
+    relay: RelayInfo,
+    app: AppHandle,
+    state: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<Option<SessionSummary>, HushError> {
+    confirm_unpinned(&app, &state, &relay).await?;
+    state.write().await?
+        .migrate(relay)
+        .await
+        .map_err(HushError::from)
+}
+
+/// Sends to the pooled relay `relay_id`, or to the default relay without one. With
+/// `require_ack` the relay must acknowledge the message on a bidirectional stream;
+/// this only combines with the default finish mode. A plain send to the default relay
+/// while disconnected is buffered in the send queue and flushed in order once a
+/// connection is back; a full queue fails with `queue_full`. When a send to the default
+/// relay fails because the connection broke, the relays set with `set_relay_failover`
+/// are tried in turn.
+#[tauri::command]
+pub async fn send_via_quic(
+    data: Vec<u8>,
+    relay_id: Option<String>,
+    finish_mode: Option<FinishMode>,
+    priority: Option<Priority>,
+    require_ack: Option<bool>,
+    app: AppHandle,
+    state: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<SendResult, HushError> {
+    let finish_mode = finish_mode.unwrap_or_default();
+    let priority = priority.unwrap_or_default();
+
+    let bufferable = relay_id.is_none() && finish_mode == FinishMode::Finish && !require_ack.unwrap_or(false);
+    if bufferable && state.read().await?.connection().is_none() {
+        let id = state.write().await?.enqueue(data, None, priority).await?;
+        return Ok(SendResult { kept_open: None, stream: None, ack: None, zero_rtt: false, queued: Some(id) });
+    }
+
+    let transport = state.read().await?;
+    let (timing, result) = if require_ack.unwrap_or(false) {
+        if finish_mode != FinishMode::Finish {
+            return Err(HushError::InvalidInput(
+                "require_ack needs the default finish mode".to_string(),
+            ));
+        }
+        let (timing, ack, stream) = transport.send_acked(relay_id.as_deref(), &data, priority).await?;
+        (timing, SendResult { kept_open: None, stream: Some(stream), ack: Some(ack), zero_rtt: false, queued: None })
+    } else {
+        let sent = transport
+            .send_to(relay_id.as_deref(), &data, finish_mode, priority)
+            .await;
+        let (timing, stream) = match sent {
+            Ok(sent) => sent,
+            Err(e) if relay_id.is_none() && !transport.failover().is_empty() && retry::is_transient(&e) => {
+                let mut budget = transport.new_retry_budget();
+                let failed = transport.connected_relay().map(|relay| relay.pin_key());
+                budget.try_begin(RetryMechanism::Initial, failed.as_deref());
+                budget.record_failure(&e);
+                drop(transport);
+                state.write().await?
+                    .fail_over(&data, finish_mode, priority, e, &mut budget)
+                    .await?
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let kept_open = (finish_mode == FinishMode::KeepOpen).then_some(stream.stream_id);
+        (timing, SendResult { kept_open, stream: Some(stream), ack: None, zero_rtt: false, queued: None })
+    };
+    if let Err(e) = app.emit("send-timing", timing) {
+        tracing::debug!("Failed to emit send-timing: {}", e);
+    }
+    Ok(result)
+}
+
+/// Like [`send_via_quic`] without buffering, acks or kept-open streams, but retried on
+/// transient failures (connection lost, stream reset, timeouts) up to `max_attempts`
+/// times, waiting `base_delay_ms` before the first retry and doubling the wait after
+/// each one. Permanent failures such as invalid input or a blocked relay are returned
+/// at once. The transport lock is released between attempts so a reconnect can run.
+/// When every attempt fails the error lists them all.
+#[tauri::command]
+pub async fn send_via_quic_retry(
+    data: Vec<u8>,
+    max_attempts: u32,
+    base_delay_ms: u64,
+    relay_id: Option<String>,
+    priority: Option<Priority>,
+    app: AppHandle,
+    state: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<RetrySendResult, HushError> {
+    if max_attempts == 0 || max_attempts > MAX_SEND_ATTEMPTS {
+        return Err(HushError::InvalidInput(format!(
+            "max_attempts must be between 1 and {}, got {}",
+            MAX_SEND_ATTEMPTS, max_attempts
+        )));
+    }
+    let priority = priority.unwrap_or_default();
+    let relay_id = relay_id.as_deref();
+    let (timing, attempts) = retry::with_retries(
+        max_attempts,
+        Duration::from_millis(base_delay_ms),
+        relay_id,
+        || async {
+            let transport = state.read().await?;
+            let (timing, _) = transport.send_to(relay_id, &data, FinishMode::Finish, priority).await?;
+            Ok(timing)
+        },
+    )
+    .await
+    .map_err(HushError::from)?;
+
+    if let Err(e) = app.emit("send-timing", timing) {
+        tracing::debug!("Failed to emit send-timing: {}", e);
+    }
+    Ok(RetrySendResult { attempts })
+}
+
+#[tauri::command]
+pub async fn send_recv_via_quic(
+    data: Vec<u8>,
+    priority: Option<Priority>,
+    state: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<Vec<u8>, HushError> {
+    state.read().await?
+        .send_recv(&data, priority.unwrap_or_default())
+        .await
+        .map_err(HushError::from)
+}
+
+/// `max_size` defaults to 1 MiB and `timeout_ms` to the stream I/O timeout.
+///
+/// Not for use alongside the app's receive loop (`receiver::start`), which accepts
+/// every relay stream itself; while it runs, inbound messages arrive as
+/// `message-received` events and this only gets streams it happens to win.
+#[tauri::command]
+pub async fn recv_via_quic(
+    max_size: Option<usize>,
+    timeout_ms: Option<u64>,
+    state: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<Vec<u8>, HushError> {
+    let transport = state.read().await?;
+
+    let timeout = timeout_ms
+        .map(Duration::from_millis)
+        .unwrap_or_else(|| transport.timeouts().stream_io());
+    transport
+        .recv(max_size.unwrap_or(DEFAULT_RECV_LIMIT), timeout)
+        .await
+        .map_err(HushError::from)
+}
+
+#[tauri::command]
+pub async fn send_datagram_via_quic(
+    data: Vec<u8>,
+    state: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<(), HushError> {
+    state.read().await?
+        .send_datagram(&data)
+        .await
+        .map_err(HushError::from)
+}
+
+/// `timeout_ms` defaults to the stream I/O timeout. Like [`recv_via_quic`], this
+/// competes with the app's receive loop for every datagram.
+#[tauri::command]
+pub async fn recv_datagram_via_quic(
+    timeout_ms: Option<u64>,
+    state: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<Vec<u8>, HushError> {
+    let transport = state.read().await?;
+
+    let timeout = timeout_ms
+        .map(Duration::from_millis)
+        .unwrap_or_else(|| transport.timeouts().stream_io());
+    transport
+        .recv_datagram(timeout)
+        .await
+        .map_err(HushError::from)
+}
+
+#[tauri::command]
+pub async fn send_multi_via_quic(
+    sends: Vec<RecipientSend>,
+    policy: Option<FanOutPolicy>,
+    state: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<Vec<RecipientResult>, HushError> {
+    state.read().await?
+        .send_multi(sends, policy.unwrap_or_default())
+        .await
+        .map_err(HushError::from)
+}
+
+#[tauri::command]
+pub async fn finish_stream(
+    stream_id: u64,
+    state: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<(), HushError> {
+    state.read().await?
+        .finish_kept_stream(stream_id)
+        .map_err(HushError::from)
+}
+
+/// Sends through the relay chosen by discovery, rotating when rotation is enabled and
+/// falling back across relays within the retry budget. Returns the relay id used.
+#[tauri::command]
+pub async fn send_via_rotation(
+    data: Vec<u8>,
+    app: AppHandle,
+    discovery: State<'_, Arc<RwLock<RelayDiscovery>>>,
+    state: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<String, HushError> {
+    let mut transport = state.write().await?;
+    let mut discovery = discovery.write().await;
+
+    let mut budget = transport.new_retry_budget();
+    let result = transport.send_via_discovery(&mut discovery, &data, &mut budget).await;
+    relay_client::notify_if_empty(&app, &discovery);
+    result.map_err(HushError::from)
+}
+
+#[tauri::command]
+pub async fn send_resumable(
+    data: Vec<u8>,
+    state: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<TransferProgress, HushError> {
+    state.write().await?
+        .send_resumable(data)
+        .await
+        .map_err(HushError::from)
+}
+
+#[tauri::command]
+pub async fn resume_transfers(
+    state: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<Vec<TransferProgress>, HushError> {
+    Ok(state.write().await?.resume_transfers().await)
+}
+
+#[tauri::command]
+pub async fn cancel_transfer(
+    transfer_id: String,
+    state: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<bool, HushError> {
+    Ok(state.write().await?.cancel_transfer(&transfer_id))
+}
+
+/// Relays a failed send to the default relay moves on to, tried in order; the one in
+/// use becomes the default and `relay-failover` is emitted. An empty list turns
+/// failover off.
+#[tauri::command]
+pub async fn set_relay_failover(
+    relays: Vec<RelayInfo>,
+    state: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<(), HushError> {
+    state.write().await?.set_failover(relays);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn set_retry_budget(
+    total: u32,
+    state: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<(), HushError> {
+    state.write().await?
+        .set_retry_budget(total)
+        .map_err(HushError::from)
+}
+
+#[tauri::command]
+pub async fn queue_send(
+    data: Vec<u8>,
+    ttl_secs: Option<u64>,
+    priority: Option<Priority>,
+    state: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<String, HushError> {
+    state.write().await?
+        .enqueue(data, ttl_secs, priority.unwrap_or_default())
+        .await
+        .map_err(HushError::from)
+}
+
+#[tauri::command]
+pub async fn pending_send_count(
+    state: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<usize, HushError> {
+    Ok(state.read().await?.pending_send_count())
+}
+
+#[tauri::command]
+pub async fn set_queue_capacity(
+    capacity: usize,
+    state: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<(), HushError> {
+    state.write().await?
+        .set_queue_capacity(capacity)
+        .map_err(HushError::from)
+}
+
+#[tauri::command]
+pub async fn get_relay_status(
+    state: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<Vec<RelayStatus>, HushError> {
+    Ok(state.read().await?.pool_status())
+}
+
+#[tauri::command]
+pub async fn set_mtu_bounds(
+    min_mtu: u16,
+    max_mtu: u16,
+    state: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<(), HushError> {
+    state.write().await?
+        .set_mtu_bounds(MtuConfig { min_mtu, max_mtu })
+        .map_err(HushError::from)
+}
+
+#[tauri::command]
+pub async fn get_connection_params(
+    state: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<ConnectionParams, HushError> {
+    state.read().await?
+        .connection_params()
+        .map_err(HushError::from)
+}
+
+#[tauri::command]
+pub async fn get_connection_stats(
+    state: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<Option<ConnectionStats>, HushError> {
+    Ok(state.read().await?.connection_stats())
+}
+
+#[tauri::command]
+pub async fn set_connect_timeout(
+    ms: u64,
+    state: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<(), HushError> {
+    state.write().await?
+        .set_connect_timeout(ms)
+        .map_err(HushError::from)
+}
+
+#[tauri::command]
+pub async fn set_timeouts(
+    timeouts: TimeoutConfig,
+    state: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<(), HushError> {
+    state.write().await?
+        .set_timeouts(timeouts)
+        .map_err(HushError::from)
+}
+
+/// Unset values fall back to the adaptive keep-alive interval and the default idle
+/// timeout. Applies to connections opened afterwards.
+#[tauri::command]
+pub async fn set_connection_tuning(
+    keep_alive_ms: Option<u64>,
+    idle_timeout_ms: Option<u64>,
+    state: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<(), HushError> {
+    let tuning = ConnectionTuning { keep_alive_ms, idle_timeout_ms };
+    state.read().await?
+        .keep_alive()
+        .set_tuning(tuning)
+        .map_err(|e| HushError::InvalidInput(format!("{:#}", e)))
+}
+
+#[tauri::command]
+pub async fn get_connection_tuning(
+    state: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<ConnectionTuning, HushError> {
+    Ok(state.read().await?.keep_alive().tuning())
+}
+
+#[tauri::command]
+pub async fn keep_alive_profiles(
+    state: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<Vec<NetworkKeepAlive>, HushError> {
+    Ok(state.read().await?.keep_alive().profiles())
+}
+
+#[tauri::command]
+pub async fn get_timeouts(
+    state: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<TimeoutConfig, HushError> {
+    Ok(state.read().await?.timeouts())
+}
 
 #[tauri::command]
-pub async fn send_via_quic(
-    data: Vec<u8>,
-    state: State<'_, Arc<RwLock<QuicTransport>>>,
-) -> Result<(), String> {
-    let transport = state.read().await;
-    
-    let connection = transport.active_connection.as_ref()
-        .ok_or_else(|| "Not connected to relay".to_string())?;
-    
-    let mut send_stream = connection
-        .open_uni()
-        .await
-        .map_err(|e| format!("Failed to open QUIC stream: {}", e))?;
-    
-    send_stream
-        .write_all(&data)
+pub async fn quic_capabilities(
+    state: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<QuicCapabilities, HushError> {
+    Ok(state.read().await?.quic_capabilities())
+}
+
+#[tauri::command]
+pub async fn get_connected_fingerprint(
+    state: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<Option<String>, HushError> {
+    Ok(state.read().await?.connected_fingerprint())
+}
+
+#[tauri::command]
+pub async fn set_inbound_listener(
+    enabled: bool,
+    port: Option<u16>,
+    app: AppHandle,
+    taior: State<'_, Arc<SharedState<TaiorState>>>,
+    state: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<Option<InboundStatus>, HushError> {
+    let key_dir = app.path().app_data_dir()
+        .map_err(|e| HushError::Other(format!("No data directory: {}", e)))?;
+    state.write().await?
+        .set_inbound(enabled, port.unwrap_or(0), &key_dir, app, taior.inner().clone())
+        .map_err(HushError::from)
+}
+
+#[tauri::command]
+pub async fn audit_pins(
+    discovery: State<'_, Arc<RwLock<RelayDiscovery>>>,
+    state: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<Vec<PinAuditEntry>, HushError> {
+    let relays: Vec<RelayInfo> = discovery.read().await
+        .get_available_relays(false)
+        .iter()
+        .map(|r| r.to_relay_info())
+        .collect();
+
+    Ok(state.read().await?.audit_pins(relays).await)
+}
+
+#[tauri::command]
+pub async fn add_relay_pin(
+    relay_id: String,
+    fingerprint: String,
+    state: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<bool, HushError> {
+    let pin = cert_pins::parse_fingerprint(&fingerprint).map_err(HushError::from)?;
+    let added = state.write().await?.pins_mut().add(&relay_id, pin);
+
+    tracing::info!("Pin {} for relay {} (new: {})", fingerprint, relay_id, added);
+    Ok(added)
+}
+
+#[tauri::command]
+pub async fn prune_relay_pin(
+    relay_id: String,
+    fingerprint: String,
+    state: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<bool, HushError> {
+    let pin = cert_pins::parse_fingerprint(&fingerprint).map_err(HushError::from)?;
+    let removed = state.write().await?.pins_mut().prune(&relay_id, &pin);
+
+    tracing::info!("Pruned pin {} for relay {} (removed: {})", fingerprint, relay_id, removed);
+    Ok(removed)
+}
+
+#[tauri::command]
+pub async fn list_relay_pins(
+    relay_id: String,
+    state: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<Vec<String>, HushError> {
+    Ok(state.read().await?.pins()
+        .get(&relay_id)
+        .iter()
+        .map(cert_pins::format_fingerprint)
+        .collect())
+}
+
+#[tauri::command]
+pub async fn set_unpinned_policy(
+    policy: UnpinnedPolicy,
+    state: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<(), HushError> {
+    state.write().await?.set_unpinned_policy(policy);
+    Ok(())
+}
+
+/// Accepts the fingerprint shown in a `relay-fingerprint-pending` event. Doesn't take
+/// the transport lock; the connect waiting on this answer pins it.
+#[tauri::command]
+pub async fn confirm_fingerprint(
+    relay_id: String,
+    fingerprint: String,
+    confirmations: State<'_, PendingConfirmations>,
+) -> Result<(), HushError> {
+    let pin = cert_pins::parse_fingerprint(&fingerprint).map_err(HushError::from)?;
+    confirmations.confirm(&relay_id, &pin).map_err(HushError::from)?;
+
+    tracing::info!("Fingerprint {} confirmed for relay {}", fingerprint, relay_id);
+    Ok(())
+}
+
+/// Injects latency, jitter and loss into outgoing datagrams. Only available in builds
+/// with the `network-sim` feature.
+#[tauri::command]
+pub async fn set_network_sim(
+    latency_ms: u64,
+    loss_pct: f32,
+    jitter_ms: u64,
+) -> Result<(), HushError> {
+    #[cfg(feature = "network-sim")]
+    {
+        crate::network_sim::set(crate::network_sim::NetworkSimParams {
+            latency_ms,
+            loss_pct,
+            jitter_ms,
+        })
+        .map_err(HushError::from)
+    }
+
+    #[cfg(not(feature = "network-sim"))]
+    {
+        let _ = (latency_ms, loss_pct, jitter_ms);
+        Err("Network simulation is not available in this build".into())
+    }
+}
+
+#[tauri::command]
+pub async fn set_source_port(
+    port: Option<u16>,
+    state: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<String, HushError> {
+    state.write().await?
+        .set_source_port(port)
         .await
-        .map_err(|e| format!("Failed to send data: {}", e))?;
-    
-    send_stream
-        .finish()
-        .map_err(|e| format!("Failed to finish stream: {}", e))?;
-    
-    tracing::debug!("Sent {} bytes via QUIC", data.len());
+        .map(|addr| addr.to_string())
+        .map_err(HushError::from)
+}
+
+/// Address the client endpoint is bound to, for debugging NAT and firewall issues.
+/// Fails until the first connect binds an endpoint.
+#[tauri::command]
+pub async fn get_local_endpoint_addr(
+    state: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<String, HushError> {
+    state.read().await?
+        .local_endpoint_addr()
+        .map(|addr| addr.to_string())
+        .map_err(HushError::from)
+}
+
+#[tauri::command]
+pub async fn per_connection_endpoint(
+    enabled: bool,
+    state: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<(), HushError> {
+    state.write().await?.set_per_connection_endpoint(enabled);
     Ok(())
 }
 
+/// Probes every ordered pair of `relay_ids` and stores the result in discovery so the
+/// circuit builder can skip hops that cannot forward to each other.
 #[tauri::command]
-pub async fn get_relay_status(
-    state: State<'_, Arc<RwLock<QuicTransport>>>,
-) -> Result<RelayStatus, String> {
-    let transport = state.read().await;
-    
-    let connected = transport.active_connection.is_some();
-    let relay_address = transport.relay_info.as_ref()
-        .map(|r| format!("{}:{}", r.address, r.port));
-    
-    Ok(RelayStatus {
-        connected,
-        relay_address,
-        latency_ms: None,
-    })
+pub async fn connectivity_matrix(
+    relay_ids: Vec<String>,
+    discovery: State<'_, Arc<RwLock<RelayDiscovery>>>,
+    state: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<ConnectivityMatrix, HushError> {
+    let relays = {
+        let discovery = discovery.read().await;
+        discovery.ensure_not_empty().map_err(HushError::from)?;
+        relay_ids.iter()
+            .map(|id| discovery.get_relay(id)
+                .map(|r| (id.clone(), r.to_relay_info()))
+                .ok_or_else(|| HushError::InvalidInput(format!("Unknown relay: {}", id))))
+            .collect::<Result<Vec<_>, _>>()?
+    };
+
+    let matrix = state.read().await?.connectivity_matrix(&relays).await;
+    discovery.write().await.set_connectivity(matrix.clone());
+    Ok(matrix)
+}
+
+/// Probes `relay_ids`, or every known relay, and records the measured latency and
+/// reachability in discovery so `list_relays` can show a live list.
+#[tauri::command]
+pub async fn probe_relays(
+    relay_ids: Option<Vec<String>>,
+    discovery: State<'_, Arc<RwLock<RelayDiscovery>>>,
+    state: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<Vec<RelayProbe>, HushError> {
+    let relays = {
+        let discovery = discovery.read().await;
+        discovery.ensure_not_empty().map_err(HushError::from)?;
+        match relay_ids {
+            Some(ids) => ids.iter()
+                .map(|id| discovery.get_relay(id)
+                    .map(|r| (id.clone(), r.to_relay_info()))
+                    .ok_or_else(|| HushError::InvalidInput(format!("Unknown relay: {}", id))))
+                .collect::<Result<Vec<_>, _>>()?,
+            None => discovery.get_available_relays(false).iter()
+                .map(|r| (r.id.clone(), r.to_relay_info()))
+                .collect(),
+        }
+    };
+
+    let probes = state.read().await?.probe_relays(&relays).await;
+
+    let mut discovery = discovery.write().await;
+    for probe in &probes {
+        discovery.record_probe(&probe.relay_id, probe.latency_ms);
+    }
+    tracing::info!(
+        "Probed {} relays, {} reachable",
+        probes.len(),
+        probes.iter().filter(|p| p.latency_ms.is_some()).count()
+    );
+    Ok(probes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::relay_client::RelayNode;
+    use crate::retry::RetryExhausted;
+    use crate::test_relay::TestRelay;
+
+    /// `relay`'s info under `id`, pinned in `transport`, so several test relays can be
+    /// pooled side by side.
+    fn pinned_as(relay: &TestRelay, id: &str, transport: &mut QuicTransport) -> RelayInfo {
+        transport.pins_mut().add(id, relay.fingerprint());
+        RelayInfo { id: Some(id.to_string()), ..relay.relay_info().unwrap() }
+    }
+
+    #[tokio::test]
+    async fn per_connection_endpoints_are_owned_by_their_connections() {
+        let first = TestRelay::start().unwrap();
+        let second = TestRelay::start().unwrap();
+        let mut transport = QuicTransport::new();
+        transport.set_per_connection_endpoint(true);
+        let first_info = pinned_as(&first, "first", &mut transport);
+        let second_info = pinned_as(&second, "second", &mut transport);
+
+        transport.connect(first_info.clone()).await.unwrap();
+        let default_port = transport.local_endpoint_addr().unwrap().port();
+        transport.connect_pooled(second_info).await.unwrap();
+
+        // Dialing the pooled relay must leave the default connection's socket alone
+        assert_eq!(transport.local_endpoint_addr().unwrap().port(), default_port);
+        assert!(transport.connection().unwrap().close_reason().is_none());
+        let pooled = &transport.pool["second"];
+        let pooled_port = pooled.endpoint.as_ref().unwrap().local_addr().unwrap().port();
+        assert_ne!(pooled_port, default_port);
+
+        let old = transport.connection().unwrap();
+        transport.connect(first_info).await.unwrap();
+        assert_ne!(transport.local_endpoint_addr().unwrap().port(), default_port);
+        assert!(old.close_reason().is_some());
+        assert!(transport.pool["second"].connection.close_reason().is_none());
+
+        transport.disconnect_relay(Some("second"));
+        transport.disconnect();
+        assert!(transport.local_endpoint_addr().is_err());
+        first.stop();
+        second.stop();
+    }
+
+    /// Info for a loopback port nothing listens on any more.
+    fn dead_relay(transport: &mut QuicTransport, id: &str) -> RelayInfo {
+        let relay = TestRelay::start().unwrap();
+        let info = pinned_as(&relay, id, transport);
+        relay.stop();
+        info
+    }
+
+    fn quick_timeouts() -> TimeoutConfig {
+        TimeoutConfig { connect_ms: 500, handshake_ms: 400, probe_ms: 300, ..TimeoutConfig::default() }
+    }
+
+    #[tokio::test]
+    async fn probes_measure_live_relays_and_leave_connections_alone() {
+        let live = TestRelay::start().unwrap();
+        let mut transport = QuicTransport::new();
+        transport.set_timeouts(quick_timeouts()).unwrap();
+        let live_info = pinned_as(&live, "live", &mut transport);
+        let dead_info = dead_relay(&mut transport, "dead");
+        transport.connect(live_info.clone()).await.unwrap();
+        let local = transport.local_endpoint_addr().unwrap();
+
+        let probes = transport
+            .probe_relays(&[("live".to_string(), live_info), ("dead".to_string(), dead_info)])
+            .await;
+
+        assert_eq!(probes[0].relay_id, "live");
+        assert!(probes[0].latency_ms.is_some());
+        assert!(probes[0].error.is_none());
+        assert_eq!(probes[1].relay_id, "dead");
+        assert!(probes[1].latency_ms.is_none());
+        assert!(probes[1].error.is_some());
+        assert_eq!(transport.local_endpoint_addr().unwrap(), local);
+        assert!(transport.connection().unwrap().close_reason().is_none());
+        live.stop();
+    }
+
+    /// Relay answering forwarding probes: `FORWARD_OK` for ports in `peers`, a refusal
+    /// for anything else.
+    fn forwarding_relay(peers: Arc<Mutex<Vec<u16>>>) -> TestRelay {
+        TestRelay::serve(Duration::ZERO, move |connection| {
+            let peers = peers.clone();
+            async move {
+                while let Ok((mut send, mut recv)) = connection.accept_bi().await {
+                    let Ok(frame) = recv.read_to_end(512).await else { break };
+                    assert_eq!(frame[0], FRAME_FORWARD_PROBE);
+                    let target = std::str::from_utf8(&frame[3..]).unwrap();
+                    let port: u16 = target.rsplit(':').next().unwrap().parse().unwrap();
+                    let forwards = peers.lock().unwrap().contains(&port);
+                    let _ = send.write_all(&[if forwards { FORWARD_OK } else { 0x01 }]).await;
+                    let _ = send.finish();
+                }
+            }
+        })
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn ipv6_relays_are_bracketed_in_summaries_and_probes() {
+        let targets: Arc<Mutex<Vec<String>>> = Arc::default();
+        let relay = {
+            let targets = targets.clone();
+            TestRelay::serve(Duration::ZERO, move |connection| {
+                let targets = targets.clone();
+                async move {
+                    while let Ok((mut send, mut recv)) = connection.accept_bi().await {
+                        let Ok(frame) = recv.read_to_end(512).await else { break };
+                        targets.lock().unwrap().push(String::from_utf8(frame[3..].to_vec()).unwrap());
+                        let _ = send.write_all(&[FORWARD_OK]).await;
+                        let _ = send.finish();
+                    }
+                }
+            })
+            .unwrap()
+        };
+        let mut transport = QuicTransport::new();
+        relay.pin(&mut transport);
+        // The test relay listens on IPv4; the shared socket reaches it through its
+        // IPv4-mapped IPv6 address
+        let port = relay.local_addr().unwrap().port();
+        transport.connect(RelayInfo { address: "::ffff:127.0.0.1".to_string(), ..relay.relay_info().unwrap() })
+            .await
+            .unwrap();
+        let expected = format!("[::ffff:127.0.0.1]:{}", port);
+        assert_eq!(transport.status().relay_address.as_deref(), Some(expected.as_str()));
+
+        let next_hop = RelayInfo { address: "2001:db8::1".to_string(), port: 4433, ..relay.relay_info().unwrap() };
+        let connection = transport.connection().unwrap();
+        assert!(probe_forwarding(&connection, &next_hop, Duration::from_secs(5)).await.unwrap());
+        assert_eq!(*targets.lock().unwrap(), ["[2001:db8::1]:4433"]);
+
+        let summary = transport.disconnect().unwrap();
+        assert_eq!(summary.relay_address, Some(expected));
+        relay.stop();
+    }
+
+    #[tokio::test]
+    async fn connectivity_matrix_reflects_the_topology() {
+        // a -> b, b -> a and b -> c forward; c forwards nowhere; d is down
+        let peers: Vec<Arc<Mutex<Vec<u16>>>> = (0..3).map(|_| Arc::default()).collect();
+        let relays: Vec<TestRelay> = peers.iter().map(|p| forwarding_relay(p.clone())).collect();
+        let port = |i: usize| relays[i].local_addr().unwrap().port();
+        peers[0].lock().unwrap().push(port(1));
+        peers[1].lock().unwrap().extend([port(0), port(2)]);
+
+        let mut transport = QuicTransport::new();
+        transport.set_timeouts(quick_timeouts()).unwrap();
+        let mut infos: Vec<(String, RelayInfo)> = ["a", "b", "c"].iter().zip(&relays)
+            .map(|(id, relay)| (id.to_string(), pinned_as(relay, id, &mut transport)))
+            .collect();
+        infos.push(("d".to_string(), dead_relay(&mut transport, "d")));
+
+        let matrix = transport.connectivity_matrix(&infos).await;
+
+        assert_eq!(matrix.relay_ids, ["a", "b", "c", "d"]);
+        assert_eq!(matrix.reachable, vec![
+            vec![false, true, false, false],
+            vec![true, false, true, false],
+            vec![false, false, false, false],
+            vec![false, false, false, false],
+        ]);
+        assert!(transport.connection().is_none());
+        for relay in relays {
+            relay.stop();
+        }
+    }
+
+    #[tokio::test]
+    async fn pin_audit_sorts_relays_by_outcome() {
+        let matching = TestRelay::start().unwrap();
+        let rotated = TestRelay::start().unwrap();
+        let mut transport = QuicTransport::new();
+        transport.set_timeouts(quick_timeouts()).unwrap();
+        let matching_info = pinned_as(&matching, "matching", &mut transport);
+        let rotated_info = RelayInfo { id: Some("rotated".to_string()), ..rotated.relay_info().unwrap() };
+        transport.pins_mut().add("rotated", [0xab; 32]);
+        let dead_info = dead_relay(&mut transport, "dead");
+        let unpinned_info = RelayInfo { id: Some("unpinned".to_string()), ..matching.relay_info().unwrap() };
+
+        let report = transport.audit_pins(vec![matching_info, rotated_info, dead_info, unpinned_info]).await;
+
+        let ids: Vec<&str> = report.iter().map(|entry| entry.relay_id.as_str()).collect();
+        assert_eq!(ids, ["matching", "rotated", "dead"]);
+        match &report[0].outcome {
+            PinAuditOutcome::Match { fingerprint } => assert_eq!(*fingerprint, matching.fingerprint_hex()),
+            other => panic!("expected a match, got {:?}", other),
+        }
+        match &report[1].outcome {
+            PinAuditOutcome::Mismatch { served, pinned } => {
+                assert_eq!(*served, rotated.fingerprint_hex());
+                assert_eq!(*pinned, vec![cert_pins::format_fingerprint(&[0xab; 32])]);
+            }
+            other => panic!("expected a mismatch, got {:?}", other),
+        }
+        assert!(matches!(report[2].outcome, PinAuditOutcome::Unreachable { .. }));
+        matching.stop();
+        rotated.stop();
+    }
+
+    fn confirm_first_use(timeout_secs: u64) -> QuicTransport {
+        let mut transport = QuicTransport::new();
+        transport.set_timeouts(quick_timeouts()).unwrap();
+        transport.set_unpinned_policy(UnpinnedPolicy::ConfirmFirstUse { timeout_secs });
+        transport
+    }
+
+    #[tokio::test]
+    async fn confirmed_fingerprint_allows_the_connection() {
+        let relay = TestRelay::start().unwrap();
+        let transport = SharedState::new(confirm_first_use(5));
+        let info = relay.relay_info().unwrap();
+
+        let prompt = transport.read().await.unwrap().request_first_use(&info).await.unwrap().unwrap();
+        assert_eq!(prompt.event().relay_id, info.pin_key());
+        assert_eq!(prompt.event().fingerprint, relay.fingerprint_hex());
+
+        // The transport stays usable while the user decides
+        let confirmations = transport.write().await.unwrap().confirmations();
+        confirmations.confirm(&info.pin_key(), &relay.fingerprint()).unwrap();
+        let confirmed = prompt.confirmed().await.unwrap();
+
+        let mut transport = transport.write().await.unwrap();
+        transport.pins_mut().add(&info.pin_key(), confirmed);
+        transport.connect(info.clone()).await.unwrap();
+        assert_eq!(transport.connected_fingerprint(), Some(relay.fingerprint_hex()));
+        assert!(transport.request_first_use(&info).await.unwrap().is_none());
+        relay.stop();
+    }
+
+    #[tokio::test]
+    async fn unconfirmed_fingerprint_times_out_and_is_rejected() {
+        let relay = TestRelay::start().unwrap();
+        let mut transport = confirm_first_use(1);
+        let info = relay.relay_info().unwrap();
+
+        let prompt = transport.request_first_use(&info).await.unwrap().unwrap();
+        let error = prompt.confirmed().await.unwrap_err();
+        assert!(error.to_string().contains("not confirmed"), "{:#}", error);
+        assert!(transport.confirmations().confirm(&info.pin_key(), &relay.fingerprint()).is_err());
+
+        let error = transport.connect(info).await.unwrap_err();
+        assert!(format!("{:#}", error).contains("unpinned"), "{:#}", error);
+        assert!(matches!(error.downcast_ref(), Some(HushError::CertVerification(_))), "{:#}", error);
+        assert!(transport.connection().is_none());
+        relay.stop();
+    }
+
+    #[tokio::test]
+    async fn shared_endpoint_serves_every_connection() {
+        let first = TestRelay::start().unwrap();
+        let second = TestRelay::start().unwrap();
+        let mut transport = QuicTransport::new();
+        let first_info = pinned_as(&first, "first", &mut transport);
+        let second_info = pinned_as(&second, "second", &mut transport);
+
+        transport.connect(first_info).await.unwrap();
+        transport.connect_pooled(second_info).await.unwrap();
+        assert!(transport.pool["second"].endpoint.is_none());
+        assert!(transport.dedicated_endpoint.is_none());
+        first.stop();
+        second.stop();
+    }
+
+    #[tokio::test]
+    async fn connections_to_two_addresses_share_one_socket() {
+        let (peers_tx, mut peers) = tokio::sync::mpsc::unbounded_channel();
+        let observing = || {
+            let peers_tx = peers_tx.clone();
+            TestRelay::serve(Duration::ZERO, move |connection: Connection| {
+                let _ = peers_tx.send(connection.remote_address());
+                async move { connection.closed().await; }
+            })
+            .unwrap()
+        };
+        let (first, second) = (observing(), observing());
+        let mut transport = QuicTransport::new();
+        let first_info = pinned_as(&first, "first", &mut transport);
+        let second_info = pinned_as(&second, "second", &mut transport);
+        assert_ne!(first.local_addr().unwrap(), second.local_addr().unwrap());
+
+        transport.connect(first_info).await.unwrap();
+        let local = transport.local_endpoint_addr().unwrap();
+        transport.connect_pooled(second_info).await.unwrap();
+        assert_eq!(transport.local_endpoint_addr().unwrap(), local);
+
+        let seen_by_first = peers.recv().await.unwrap();
+        let seen_by_second = peers.recv().await.unwrap();
+        assert_eq!(seen_by_first, seen_by_second);
+        assert_eq!(seen_by_first.port(), local.port());
+        first.stop();
+        second.stop();
+    }
+
+    /// Relay that completes the handshake but never reads a stream, so a send larger
+    /// than the stream window stalls until the write times out.
+    fn stalling_relay() -> TestRelay {
+        TestRelay::serve(Duration::ZERO, |connection: Connection| async move {
+            connection.closed().await;
+        })
+        .unwrap()
+    }
+
+    fn node_for(info: &RelayInfo) -> RelayNode {
+        RelayNode {
+            id: info.pin_key(),
+            address: info.address.clone(),
+            port: info.port,
+            public_key: String::new(),
+            latency_ms: None,
+            bandwidth_mbps: None,
+            connect_timeout_ms: None,
+            network: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn one_budget_spans_reconnect_multi_path_and_failover() {
+        let (primary, pooled) = (stalling_relay(), stalling_relay());
+        let mut transport = QuicTransport::new();
+        transport.set_timeouts(TimeoutConfig { stream_io_ms: 300, ..quick_timeouts() }).unwrap();
+        transport.set_retry_budget(5).unwrap();
+        let primary_info = pinned_as(&primary, "primary", &mut transport);
+        let pooled_info = pinned_as(&pooled, "pooled", &mut transport);
+        transport.connect(primary_info.clone()).await.unwrap();
+        transport.connect_pooled(pooled_info).await.unwrap();
+        let failover: Vec<RelayInfo> = ["backup-1", "backup-2", "backup-3"].iter()
+            .map(|id| dead_relay(&mut transport, id))
+            .collect();
+        transport.set_failover(failover);
+
+        let mut discovery = RelayDiscovery::new();
+        for known in discovery.get_available_relays(false) {
+            discovery.remove_relay(&known.id);
+        }
+        discovery.add_relay(node_for(&primary_info));
+        let mut budget = transport.new_retry_budget();
+        let data = vec![0u8; 8 * 1024 * 1024];
+        let error = transport.send_via_discovery(&mut discovery, &data, &mut budget).await.unwrap_err();
+
+        let exhausted = error.downcast_ref::<RetryExhausted>().expect("every attempt reported");
+        assert_eq!(exhausted.total, 5);
+        let made: Vec<(RetryMechanism, Option<&str>)> = exhausted.attempts.iter()
+            .map(|attempt| (attempt.mechanism, attempt.relay.as_deref()))
+            .collect();
+        assert_eq!(made, [
+            (RetryMechanism::Initial, Some("primary")),
+            (RetryMechanism::Reconnect, Some("primary")),
+            (RetryMechanism::MultiPath, Some("pooled")),
+            (RetryMechanism::Failover, Some("backup-1")),
+            (RetryMechanism::Failover, Some("backup-2")),
+        ]);
+        assert!(exhausted.attempts.iter().all(|attempt| attempt.error.is_some()));
+        assert_eq!(budget.remaining(), 0);
+
+        primary.stop();
+        pooled.stop();
+    }
+
+    #[test]
+    fn rtt_rounds_to_the_nearest_millisecond() {
+        assert_eq!(rtt_ms(Duration::ZERO), 0);
+        assert_eq!(rtt_ms(Duration::from_micros(1_400)), 1);
+        assert_eq!(rtt_ms(Duration::from_micros(1_500)), 2);
+        assert_eq!(rtt_ms(Duration::from_micros(499)), 0);
+        assert_eq!(rtt_ms(Duration::from_secs(2)), 2_000);
+    }
+
+    #[tokio::test]
+    async fn relay_status_reports_latency_only_while_connected() {
+        let relay = TestRelay::start().unwrap();
+        let mut transport = QuicTransport::new();
+        relay.pin(&mut transport);
+        assert_eq!(transport.status().latency_ms, None);
+
+        transport.connect(relay.relay_info().unwrap()).await.unwrap();
+        let statuses = transport.pool_status();
+        assert_eq!(statuses.len(), 1);
+        assert!(statuses[0].connected);
+        let rtt = transport.connection().unwrap().rtt();
+        let latency = statuses[0].latency_ms.expect("latency while connected");
+        assert!(latency.abs_diff(rtt_ms(rtt)) <= 1, "{} ms against an RTT of {:?}", latency, rtt);
+
+        transport.disconnect();
+        assert!(transport.pool_status().is_empty());
+        let status = transport.status();
+        assert!(!status.connected);
+        assert_eq!(status.latency_ms, None);
+        relay.stop();
+    }
+
+    #[tokio::test]
+    async fn capabilities_follow_the_endpoint_settings() {
+        let relay = TestRelay::start().unwrap();
+        let mut transport = QuicTransport::new();
+        relay.pin(&mut transport);
+        transport.set_mtu_bounds(MtuConfig { min_mtu: 1250, max_mtu: 1350 }).unwrap();
+        transport.keep_alive()
+            .set_tuning(ConnectionTuning { keep_alive_ms: Some(5_000), idle_timeout_ms: Some(20_000) })
+            .unwrap();
+        transport.connect(relay.relay_info().unwrap()).await.unwrap();
+
+        let capabilities = transport.quic_capabilities();
+        let connection = transport.connection().unwrap();
+        assert_eq!(capabilities.datagrams.enabled, connection.max_datagram_size().is_some());
+        assert!(capabilities.pmtud.enabled);
+        assert_eq!(capabilities.pmtud.config.as_deref(), Some("min_mtu=1250, max_mtu=1350"));
+        let mtu = connection.stats().path.current_mtu;
+        assert!((1250..=1350).contains(&mtu), "path MTU {} outside the configured bounds", mtu);
+        assert_eq!(
+            capabilities.keep_alive.config.as_deref(),
+            Some("interval_ms=5000, idle_timeout_ms=20000, adaptive=false")
+        );
+
+        assert!(capabilities.migration.enabled);
+        assert_eq!(capabilities.network_sim.supported, cfg!(feature = "network-sim"));
+
+        // A floor equal to the ceiling leaves nothing to discover
+        transport.set_mtu_bounds(MtuConfig { min_mtu: 1300, max_mtu: 1300 }).unwrap();
+        assert!(!transport.quic_capabilities().pmtud.enabled);
+
+        // Without a connection nothing has negotiated datagrams, and per-connection
+        // endpoints can't be migrated
+        transport.disconnect();
+        transport.set_per_connection_endpoint(true);
+        let capabilities = transport.quic_capabilities();
+        assert!(!capabilities.datagrams.enabled);
+        assert!(!capabilities.migration.enabled);
+        relay.stop();
+    }
+
+    #[tokio::test]
+    async fn path_mtu_stays_within_the_configured_bounds() {
+        let relay = TestRelay::start().unwrap();
+        let mut transport = QuicTransport::new();
+        relay.pin(&mut transport);
+        transport.connect(relay.relay_info().unwrap()).await.unwrap();
+        // Set while connected, so the next connect must still be pinned
+        transport.set_mtu_bounds(MtuConfig { min_mtu: 1250, max_mtu: 1350 }).unwrap();
+        transport.connect(relay.relay_info().unwrap()).await.unwrap();
+
+        let mut packet = 200_000u32.to_be_bytes().to_vec();
+        packet.extend(std::iter::repeat_n(7u8, 200_000 + 32));
+        transport.send(&packet, FinishMode::Finish, Priority::default()).await.unwrap();
+        let echoed = transport.recv(1024 * 1024, Duration::from_secs(5)).await.unwrap();
+        assert_eq!(echoed.len(), packet.len());
+
+        let params = transport.connection_params().unwrap();
+        assert!(params.pmtud_enabled);
+        assert_eq!((params.min_mtu, params.max_mtu), (1250, 1350));
+        assert!((1250..=1350).contains(&params.current_mtu), "path MTU {}", params.current_mtu);
+
+        transport.set_mtu_bounds(MtuConfig { min_mtu: 1280, max_mtu: 1280 }).unwrap();
+        transport.connect(relay.relay_info().unwrap()).await.unwrap();
+        let params = transport.connection_params().unwrap();
+        assert!(!params.pmtud_enabled);
+        assert_eq!(params.current_mtu, 1280);
+        relay.stop();
+    }
+
+    /// Packet in the framing `recv` checks: length prefix, payload, 32 byte IKM.
+    fn framed(payload_len: usize) -> Vec<u8> {
+        let mut packet = (payload_len as u32).to_be_bytes().to_vec();
+        packet.extend(std::iter::repeat_n(7u8, payload_len + 32));
+        packet
+    }
+
+    #[tokio::test]
+    async fn disconnect_summary_matches_the_session_traffic() {
+        let relay = TestRelay::start().unwrap();
+        let mut transport = QuicTransport::new();
+        relay.pin(&mut transport);
+        let info = relay.relay_info().unwrap();
+        transport.connect(info.clone()).await.unwrap();
+
+        let sizes = [1_000, 20_000, 50_000];
+        for size in sizes {
+            let packet = framed(size);
+            transport.send(&packet, FinishMode::Finish, Priority::default()).await.unwrap();
+            let echoed = transport.recv(1024 * 1024, Duration::from_secs(5)).await.unwrap();
+            assert_eq!(echoed.len(), packet.len());
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        let summary = transport.disconnect().unwrap();
+        let payload: u64 = sizes.iter().map(|size| framed(*size).len() as u64).sum();
+        assert_eq!(summary.messages_sent, sizes.len() as u64);
+        assert_eq!(summary.relay_address, Some(info.host_port()));
+        // UDP-level counts: the payload plus QUIC framing and handshake overhead
+        for (direction, bytes) in [("sent", summary.bytes_sent), ("received", summary.bytes_received)] {
+            assert!(bytes >= payload, "{} {} bytes, less than the {} byte payload", direction, bytes, payload);
+            assert!(bytes < payload + payload / 5 + 20_000, "{} {} bytes for {} of payload", direction, bytes, payload);
+        }
+        assert!(summary.duration_ms >= 20);
+        relay.stop();
+    }
+
+    #[tokio::test]
+    async fn rotation_spreads_sends_across_the_top_healthy_relays() {
+        let relays: Vec<TestRelay> = (0..5).map(|_| TestRelay::start().unwrap()).collect();
+        let mut transport = QuicTransport::new();
+        let mut discovery = RelayDiscovery::new();
+        for known in discovery.get_available_relays(false) {
+            discovery.remove_relay(&known.id);
+        }
+        // Lowest latency first: one unhealthy, three in the rotation, one outside it
+        for (relay, (id, latency)) in relays.iter().zip([("down", 5), ("a", 10), ("b", 20), ("c", 30), ("d", 40)]) {
+            let info = pinned_as(relay, id, &mut transport);
+            discovery.add_relay(RelayNode { latency_ms: Some(latency), ..node_for(&info) });
+        }
+        discovery.mark_unhealthy("down");
+        discovery.set_rotation(Some(3));
+
+        let mut used = Vec::new();
+        for _ in 0..7 {
+            let mut budget = transport.new_retry_budget();
+            used.push(transport.send_via_discovery(&mut discovery, b"rotate", &mut budget).await.unwrap());
+        }
+        assert_eq!(used, ["a", "b", "c", "a", "b", "c", "a"]);
+
+        discovery.set_rotation(None);
+        let mut budget = transport.new_retry_budget();
+        assert_eq!(transport.send_via_discovery(&mut discovery, b"best", &mut budget).await.unwrap(), "a");
+        for relay in relays {
+            relay.stop();
+        }
+    }
+
+    #[tokio::test]
+    async fn send_timing_stages_add_up_to_the_total() {
+        let relay = TestRelay::start().unwrap();
+        let mut transport = QuicTransport::new();
+        relay.pin(&mut transport);
+        transport.connect(relay.relay_info().unwrap()).await.unwrap();
+
+        for finish_mode in [FinishMode::Finish, FinishMode::KeepOpen] {
+            let (timing, stream) = transport.send(&framed(200_000), finish_mode, Priority::default()).await.unwrap();
+            let stages = [timing.open_stream_us, timing.write_us, timing.finish_us];
+            assert!(stages.iter().all(Option::is_some), "{:?}: {:?}", finish_mode, timing);
+            assert_eq!(timing.routing_us, None);
+
+            // Each stage is truncated to whole microseconds on its own
+            let sum: u64 = stages.iter().flatten().sum();
+            assert!(sum <= timing.total_us && timing.total_us - sum <= 2, "{:?}: {:?}", finish_mode, timing);
+            assert!(timing.total_us > 0);
+            if finish_mode == FinishMode::KeepOpen {
+                transport.finish_kept_stream(stream.stream_id).unwrap();
+            }
+        }
+        relay.stop();
+    }
+
+    #[tokio::test]
+    async fn connected_fingerprint_is_the_served_certificate() {
+        let (first, second) = (TestRelay::start().unwrap(), TestRelay::start().unwrap());
+        let mut transport = QuicTransport::new();
+        first.pin(&mut transport);
+        let second_info = pinned_as(&second, "second", &mut transport);
+        assert_eq!(transport.connected_fingerprint(), None);
+
+        transport.connect(first.relay_info().unwrap()).await.unwrap();
+        assert_eq!(transport.connected_fingerprint(), Some(first.fingerprint_hex()));
+        transport.connect(second_info).await.unwrap();
+        assert_eq!(transport.connected_fingerprint(), Some(second.fingerprint_hex()));
+
+        transport.disconnect();
+        assert_eq!(transport.connected_fingerprint(), None);
+        first.stop();
+        second.stop();
+    }
+
+    #[tokio::test]
+    async fn no_relays_fails_with_the_guided_error() {
+        let mut transport = QuicTransport::new();
+        let mut discovery = RelayDiscovery::new();
+        for known in discovery.get_available_relays(false) {
+            discovery.remove_relay(&known.id);
+        }
+        assert!(discovery.is_empty());
+
+        let mut budget = transport.new_retry_budget();
+        let error = transport.send_via_discovery(&mut discovery, b"nowhere", &mut budget).await.unwrap_err();
+        assert_eq!(error.to_string(), relay_client::NO_RELAYS_MESSAGE);
+        assert_eq!(budget.remaining(), transport.new_retry_budget().remaining());
+
+        let error = HushError::from(discovery.ensure_not_empty().unwrap_err());
+        assert_eq!(error.to_string(), relay_client::NO_RELAYS_MESSAGE);
+        assert!(discovery.next_relay().is_none());
+    }
+
+    /// How a loopback receiver saw a stream end.
+    #[derive(Debug, PartialEq, Eq)]
+    enum StreamEnd {
+        Finished(usize),
+        /// Nothing more arrived for a while, but the stream was not ended either.
+        StillOpen(usize),
+        Reset(usize, u64),
+    }
+
+    const OBSERVED_LEN: usize = 4096;
+
+    /// Reads a stream and reports how it ended. Bidirectional streams get a one-byte
+    /// ack once the whole payload is in, as `ResetAfterAck` expects.
+    async fn observe(
+        mut recv: quinn::RecvStream,
+        mut ack: Option<quinn::SendStream>,
+        ends: tokio::sync::mpsc::UnboundedSender<StreamEnd>,
+    ) {
+        let (mut received, mut idle_reported) = (0, false);
+        let mut buf = [0u8; 1024];
+        loop {
+            match tokio::time::timeout(Duration::from_millis(200), recv.read(&mut buf)).await {
+                Err(_) if !idle_reported => {
+                    idle_reported = true;
+                    let _ = ends.send(StreamEnd::StillOpen(received));
+                }
+                Err(_) => {}
+                Ok(Ok(Some(read))) => {
+                    received += read;
+                    if received == OBSERVED_LEN {
+                        if let Some(ack) = ack.as_mut() {
+                            let _ = ack.write_all(&[1]).await;
+                        }
+                    }
+                }
+                Ok(Ok(None)) => {
+                    let _ = ends.send(StreamEnd::Finished(received));
+                    return;
+                }
+                Ok(Err(quinn::ReadError::Reset(code))) => {
+                    let _ = ends.send(StreamEnd::Reset(received, code.into_inner()));
+                    return;
+                }
+                Ok(Err(_)) => return,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn finish_modes_end_the_stream_as_the_receiver_sees_it() {
+        let (ends, mut seen) = tokio::sync::mpsc::unbounded_channel();
+        let relay = TestRelay::serve(Duration::ZERO, move |connection: Connection| {
+            let ends = ends.clone();
+            async move {
+                loop {
+                    tokio::select! {
+                        Ok(recv) = connection.accept_uni() => {
+                            tokio::spawn(observe(recv, None, ends.clone()));
+                        }
+                        Ok((send, recv)) = connection.accept_bi() => {
+                            tokio::spawn(observe(recv, Some(send), ends.clone()));
+                        }
+                        else => return,
+                    }
+                }
+            }
+        })
+        .unwrap();
+        let mut transport = QuicTransport::new();
+        relay.pin(&mut transport);
+        transport.connect(relay.relay_info().unwrap()).await.unwrap();
+        let data = vec![9u8; OBSERVED_LEN];
+
+        transport.send(&data, FinishMode::Finish, Priority::default()).await.unwrap();
+        assert_eq!(seen.recv().await, Some(StreamEnd::Finished(OBSERVED_LEN)));
+
+        transport.send(&data, FinishMode::ResetAfterAck, Priority::default()).await.unwrap();
+        assert_eq!(seen.recv().await, Some(StreamEnd::Reset(OBSERVED_LEN, AppCloseCode::Normal.code().into_inner())));
+
+        let (_, kept) = transport.send(&data, FinishMode::KeepOpen, Priority::default()).await.unwrap();
+        assert_eq!(seen.recv().await, Some(StreamEnd::StillOpen(OBSERVED_LEN)));
+        transport.finish_kept_stream(kept.stream_id).unwrap();
+        assert_eq!(seen.recv().await, Some(StreamEnd::Finished(OBSERVED_LEN)));
+        relay.stop();
+    }
+
+    #[tokio::test]
+    async fn fair_fan_out_does_not_let_a_slow_recipient_starve_the_others() {
+        const SLOW_MARK: u8 = 0xff;
+        const SLOW_READ_DELAY: Duration = Duration::from_millis(600);
+        // Drains every stream and reports when each one completed, except that the one
+        // marked slow is left unread for a while so it blocks on flow control
+        let (done, mut completed) = tokio::sync::mpsc::unbounded_channel();
+        let relay = TestRelay::serve(Duration::ZERO, move |connection: Connection| {
+            let done = done.clone();
+            async move {
+                while let Ok(mut recv) = connection.accept_uni().await {
+                    let done = done.clone();
+                    tokio::spawn(async move {
+                        let mut first = [0u8; 1];
+                        if recv.read_exact(&mut first).await.is_err() {
+                            return;
+                        }
+                        if first[0] == SLOW_MARK {
+                            tokio::time::sleep(SLOW_READ_DELAY).await;
+                        }
+                        if recv.read_to_end(usize::MAX).await.is_ok() {
+                            let _ = done.send((first[0], Instant::now()));
+                        }
+                    });
+                }
+            }
+        })
+        .unwrap();
+        let mut transport = QuicTransport::new();
+        relay.pin(&mut transport);
+        transport.connect(relay.relay_info().unwrap()).await.unwrap();
+
+        let mut sends = vec![RecipientSend {
+            recipient: "slow".to_string(),
+            data: vec![SLOW_MARK; 8 * 1024 * 1024],
+            weight: 1,
+        }];
+        sends.extend((0..3).map(|i| RecipientSend {
+            recipient: format!("fast-{}", i),
+            data: vec![i; 64 * 1024],
+            weight: 1,
+        }));
+        let started = Instant::now();
+        let results = transport.send_multi(sends, FanOutPolicy::WeightedFair).await.unwrap();
+        assert!(results.iter().all(|r| r.error.is_none()), "{:?}", results);
+        assert_eq!(results.iter().map(|r| r.bytes_written).sum::<usize>(), 8 * 1024 * 1024 + 3 * 64 * 1024);
+
+        let mut order = Vec::new();
+        while order.len() < 4 {
+            let (mark, at) = tokio::time::timeout(Duration::from_secs(5), completed.recv()).await.unwrap().unwrap();
+            order.push((mark, at - started));
+        }
+        assert_eq!(order.last().unwrap().0, SLOW_MARK, "{:?}", order);
+        assert!(order.last().unwrap().1 >= SLOW_READ_DELAY);
+        for (_, fast) in &order[..3] {
+            assert!(*fast < SLOW_READ_DELAY / 2, "{:?}", order);
+        }
+        relay.stop();
+    }
+
+    #[tokio::test]
+    async fn fastest_handshake_wins_and_the_losers_are_closed() {
+        let (closed, mut closes) = tokio::sync::mpsc::unbounded_channel();
+        let race = [("medium", 250), ("fast", 10), ("slow", 500)];
+        let relays: Vec<TestRelay> = race.into_iter()
+            .map(|(id, delay_ms)| {
+                let closed = closed.clone();
+                TestRelay::serve(Duration::from_millis(delay_ms), move |connection: Connection| {
+                    let closed = closed.clone();
+                    async move {
+                        let reason = connection.closed().await;
+                        let _ = closed.send((id.to_string(), reason));
+                    }
+                })
+                .unwrap()
+            })
+            .collect();
+        let mut transport = QuicTransport::new();
+        let candidates: Vec<(String, RelayInfo)> = relays.iter()
+            .zip(race)
+            .map(|(relay, (id, _))| (id.to_string(), pinned_as(relay, id, &mut transport)))
+            .collect();
+
+        assert_eq!(transport.connect_fastest(candidates).await.unwrap(), "fast");
+        assert_eq!(transport.connected_relay().unwrap().pin_key(), "fast");
+
+        let mut losers = Vec::new();
+        for _ in 0..2 {
+            let (id, reason) = tokio::time::timeout(Duration::from_secs(3), closes.recv()).await.unwrap().unwrap();
+            let quinn::ConnectionError::ApplicationClosed(close) = reason else {
+                panic!("{} was not closed by the client: {:?}", id, reason);
+            };
+            assert_eq!(close.error_code, AppCloseCode::Normal.code());
+            losers.push(id);
+        }
+        losers.sort();
+        assert_eq!(losers, ["medium", "slow"]);
+        assert!(transport.connection().unwrap().close_reason().is_none());
+        for relay in relays {
+            relay.stop();
+        }
+    }
+
+    #[tokio::test]
+    async fn concurrent_connects_to_one_relay_share_a_handshake() {
+        let handshakes = Arc::new(AtomicU64::new(0));
+        let relay = {
+            let handshakes = handshakes.clone();
+            TestRelay::serve(Duration::from_millis(200), move |connection: Connection| {
+                handshakes.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    connection.closed().await;
+                }
+            })
+            .unwrap()
+        };
+        let mut transport = QuicTransport::new();
+        relay.pin(&mut transport);
+        let transport = Arc::new(SharedState::new(transport));
+        let dedup = ConnectDedup::new();
+        let info = relay.relay_info().unwrap();
+
+        // Both callers replace the default relay, which always dials when run
+        let callers: Vec<_> = (0..2)
+            .map(|_| {
+                let (dedup, transport, info) = (dedup.clone(), transport.clone(), info.clone());
+                tokio::spawn(async move {
+                    dedup.run(&info.pin_key(), async {
+                        transport.write().await?.connect(info.clone()).await.map_err(HushError::from)
+                    })
+                    .await
+                })
+            })
+            .collect();
+        for caller in callers {
+            caller.await.unwrap().unwrap();
+        }
+
+        // The relay finishes its side of a handshake just after the client does
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(handshakes.load(Ordering::SeqCst), 1);
+        assert_eq!(transport.read().await.unwrap().connection_count(), 1);
+
+        // Once the attempt has finished, the next connect dials again
+        dedup.run(&info.pin_key(), async {
+            transport.write().await?.connect(info.clone()).await.map_err(HushError::from)
+        })
+        .await
+        .unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(handshakes.load(Ordering::SeqCst), 2);
+        relay.stop();
+    }
+
+    #[cfg(feature = "network-sim")]
+    #[tokio::test]
+    async fn simulated_latency_adds_to_the_round_trip() {
+        use crate::network_sim::{self, NetworkSimParams};
+
+        // Settings of this endpoint only, so tests running alongside aren't slowed
+        static SIM: Mutex<NetworkSimParams> = Mutex::new(NetworkSimParams {
+            latency_ms: 0,
+            loss_pct: 0.0,
+            jitter_ms: 0,
+        });
+        const LATENCY: Duration = Duration::from_millis(80);
+
+        let relay = TestRelay::start().unwrap();
+        let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let mut endpoint = Endpoint::new_with_abstract_socket(
+            quinn::EndpointConfig::default(),
+            None,
+            network_sim::wrap_with(socket, &SIM).unwrap(),
+            Arc::new(quinn::TokioRuntime),
+        )
+        .unwrap();
+        let trust = RelayTrust { fingerprints: vec![relay.fingerprint()], public_key: None };
+        endpoint.set_default_client_config(configure_client(&MtuConfig::default(), trust, None).unwrap());
+        let connection = endpoint.connect(relay.local_addr().unwrap(), "localhost").unwrap().await.unwrap();
+
+        // Fastest of a few echo round trips, so scheduling noise doesn't count
+        let round_trip = async || {
+            let mut fastest = Duration::MAX;
+            for _ in 0..5 {
+                let started = Instant::now();
+                let mut send = connection.open_uni().await.unwrap();
+                send.write_all(b"ping").await.unwrap();
+                send.finish().unwrap();
+                let mut echo = connection.accept_uni().await.unwrap();
+                assert_eq!(echo.read_to_end(16).await.unwrap(), b"ping");
+                fastest = fastest.min(started.elapsed());
+            }
+            fastest
+        };
+
+        let baseline = round_trip().await;
+        *SIM.lock().unwrap() = NetworkSimParams { latency_ms: LATENCY.as_millis() as u64, ..Default::default() };
+        let simulated = round_trip().await;
+
+        // Only outgoing datagrams are delayed, so each round trip gains the latency once
+        let added = simulated.saturating_sub(baseline);
+        assert!(added >= LATENCY.mul_f32(0.9), "baseline {:?}, simulated {:?}", baseline, simulated);
+        assert!(added < LATENCY * 2, "baseline {:?}, simulated {:?}", baseline, simulated);
+        assert!(connection.rtt() > baseline);
+
+        AppCloseCode::Normal.close(&connection);
+        relay.stop();
+    }
+
+    #[tokio::test]
+    async fn high_priority_message_is_dispatched_before_queued_low_ones() {
+        // Relay that records each stream's payload in the order the streams were opened
+        let (received, mut dispatched) = tokio::sync::mpsc::unbounded_channel();
+        let relay = TestRelay::serve(Duration::ZERO, move |connection: Connection| {
+            let received = received.clone();
+            async move {
+                while let Ok(mut recv) = connection.accept_uni().await {
+                    let id = recv.id().index();
+                    if let Ok(data) = recv.read_to_end(1024).await {
+                        let _ = received.send((id, data));
+                    }
+                }
+            }
+        })
+        .unwrap();
+        let mut transport = QuicTransport::new();
+        relay.pin(&mut transport);
+
+        for message in [&b"typing 1"[..], b"typing 2", b"typing 3"] {
+            transport.enqueue(message.to_vec(), None, Priority::Low).await.unwrap();
+        }
+        transport.enqueue(b"normal".to_vec(), None, Priority::Normal).await.unwrap();
+        transport.enqueue(b"sent message".to_vec(), None, Priority::High).await.unwrap();
+        assert_eq!(transport.pending_send_count(), 5);
+
+        transport.connect(relay.relay_info().unwrap()).await.unwrap();
+        assert_eq!(transport.pending_send_count(), 0);
+        let mut streams = Vec::new();
+        while streams.len() < 5 {
+            streams.push(tokio::time::timeout(Duration::from_secs(5), dispatched.recv()).await.unwrap().unwrap());
+        }
+        streams.sort_by_key(|(id, _)| *id);
+        let order: Vec<&[u8]> = streams.iter().map(|(_, data)| data.as_slice()).collect();
+        assert_eq!(order, [&b"sent message"[..], b"normal", b"typing 1", b"typing 2", b"typing 3"]);
+        relay.stop();
+    }
+
+    #[tokio::test]
+    async fn fixed_source_port_is_the_one_the_relay_sees() {
+        let (seen_tx, mut seen) = tokio::sync::mpsc::unbounded_channel();
+        let relay = TestRelay::serve(Duration::ZERO, move |connection: Connection| {
+            let _ = seen_tx.send(connection.remote_address());
+            async move { let _ = connection.closed().await; }
+        })
+        .unwrap();
+        let port = std::net::UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let mut transport = QuicTransport::new();
+        relay.pin(&mut transport);
+
+        assert_eq!(transport.set_source_port(Some(port)).await.unwrap().port(), port);
+        assert_eq!(transport.source_port(), Some(port));
+        transport.connect(relay.relay_info().unwrap()).await.unwrap();
+        assert_eq!(transport.local_endpoint_addr().unwrap().port(), port);
+        let remote = tokio::time::timeout(Duration::from_secs(5), seen.recv()).await.unwrap().unwrap();
+        assert_eq!(remote.port(), port);
+
+        // A port someone else holds fails clearly and leaves the endpoint where it was
+        let occupied = std::net::UdpSocket::bind("0.0.0.0:0").unwrap();
+        let taken = occupied.local_addr().unwrap().port();
+        let error = transport.set_source_port(Some(taken)).await.unwrap_err();
+        assert!(format!("{:#}", error).contains("in use"), "{:#}", error);
+        assert_eq!(transport.source_port(), Some(port));
+        assert_eq!(transport.local_endpoint_addr().unwrap().port(), port);
+        relay.stop();
+    }
+
+    /// Relay reporting every complete stream it reads as `(name, payload)`, each read
+    /// only after `read_delay` so the data is still unread when the sender moves on.
+    fn recording_relay(
+        name: &'static str,
+        read_delay: Duration,
+        received: tokio::sync::mpsc::UnboundedSender<(&'static str, Vec<u8>)>,
+    ) -> TestRelay {
+        TestRelay::serve(Duration::ZERO, move |connection: Connection| {
+            let received = received.clone();
+            async move {
+                while let Ok(mut recv) = connection.accept_uni().await {
+                    let received = received.clone();
+                    tokio::spawn(async move {
+                        tokio::time::sleep(read_delay).await;
+                        if let Ok(data) = recv.read_to_end(1024 * 1024).await {
+                            let _ = received.send((name, data));
+                        }
+                    });
+                }
+            }
+        })
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn migration_completes_in_flight_sends_and_moves_new_ones() {
+        let (received_tx, mut received) = tokio::sync::mpsc::unbounded_channel();
+        // The old relay only reads after the migration has settled
+        let old = recording_relay("old", MIGRATION_SETTLE * 2, received_tx.clone());
+        let new = recording_relay("new", Duration::ZERO, received_tx);
+        let mut transport = QuicTransport::new();
+        let old_info = pinned_as(&old, "old", &mut transport);
+        let new_info = pinned_as(&new, "new", &mut transport);
+        transport.connect(old_info).await.unwrap();
+        let old_connection = transport.connection().unwrap();
+
+        // Still unread when the migration starts: a large finished stream, and a
+        // kept-open stream only migration will finish
+        let large = vec![0x5a; 512 * 1024];
+        transport.send(&large, FinishMode::Finish, Priority::Normal).await.unwrap();
+        transport.send(b"kept open", FinishMode::KeepOpen, Priority::Normal).await.unwrap();
+
+        transport.migrate(new_info).await.unwrap().unwrap();
+        transport.send(b"after migration", FinishMode::Finish, Priority::Normal).await.unwrap();
+
+        let mut delivered = Vec::new();
+        while delivered.len() < 3 {
+            delivered.push(tokio::time::timeout(Duration::from_secs(10), received.recv()).await.unwrap().unwrap());
+        }
+        delivered.sort();
+        assert_eq!(delivered[0], ("new", b"after migration".to_vec()));
+        assert!(delivered[1] == ("old", large), "large send cut short: {} bytes", delivered[1].1.len());
+        assert_eq!(delivered[2], ("old", b"kept open".to_vec()));
+
+        // The old connection is closed once drained, as a migration
+        let reason = tokio::time::timeout(Duration::from_secs(10), old_connection.closed()).await.unwrap();
+        assert!(matches!(reason, quinn::ConnectionError::LocallyClosed), "{}", reason);
+        assert!(transport.connection().unwrap().close_reason().is_none());
+        old.stop();
+        new.stop();
+    }
+
+    /// A persisted queue holding `messages`, adopted after the transport connected as
+    /// when loading it from disk finishes late at startup.
+    fn restore_late(transport: &mut QuicTransport, dir: &std::path::Path, messages: &[&[u8]]) {
+        let mut queue = SendQueue::load(dir).unwrap();
+        for message in messages {
+            queue.push(QueuedMessage::new(message.to_vec(), None, Priority::Normal)).unwrap();
+        }
+        transport.restore_send_queue(queue);
+        assert_eq!(transport.pending_send_count(), messages.len());
+    }
+
+    #[tokio::test]
+    async fn shutdown_flushes_or_persists_the_queue() {
+        let (received_tx, mut received) = tokio::sync::mpsc::unbounded_channel();
+        let relay = recording_relay("relay", Duration::ZERO, received_tx);
+        let dir = std::env::temp_dir().join(format!("hush-shutdown-{}", uuid::Uuid::new_v4()));
+
+        let mut transport = QuicTransport::new();
+        relay.pin(&mut transport);
+        transport.connect(relay.relay_info().unwrap()).await.unwrap();
+        restore_late(&mut transport, &dir, &[b"one", b"two", b"three"]);
+        let report = transport.shutdown(true, Duration::from_secs(5)).await;
+        assert_eq!((report.delivered, report.deferred, report.closed), (3, 0, 1));
+        let mut delivered = Vec::new();
+        while delivered.len() < 3 {
+            let (_, data) = tokio::time::timeout(Duration::from_secs(5), received.recv()).await.unwrap().unwrap();
+            delivered.push(data);
+        }
+        delivered.sort();
+        assert_eq!(delivered, [&b"one"[..], b"three", b"two"]);
+        assert!(SendQueue::load(&dir).unwrap().is_empty());
+
+        let mut transport = QuicTransport::new();
+        relay.pin(&mut transport);
+        transport.connect(relay.relay_info().unwrap()).await.unwrap();
+        restore_late(&mut transport, &dir, &[b"later", b"much later"]);
+        let report = transport.shutdown(false, Duration::from_secs(5)).await;
+        assert_eq!((report.delivered, report.deferred, report.closed), (0, 2, 1));
+        assert!(transport.connection().is_none());
+        let next_launch = SendQueue::load(&dir).unwrap().take_ready();
+        let persisted: Vec<&[u8]> = next_launch.iter().map(|m| m.data.as_slice()).collect();
+        assert_eq!(persisted, [&b"later"[..], b"much later"]);
+        assert!(tokio::time::timeout(Duration::from_millis(200), received.recv()).await.is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        relay.stop();
+    }
+
+    #[tokio::test]
+    async fn port_conflicts_name_who_holds_the_port() {
+        let relay = TestRelay::start().unwrap();
+        let mut transport = QuicTransport::new();
+        relay.pin(&mut transport);
+
+        // Asking again for the port the shared endpoint already holds is not a conflict
+        let held = transport.set_source_port(None).await.unwrap().port();
+        assert_eq!(transport.set_source_port(Some(held)).await.unwrap().port(), held);
+        assert_eq!(transport.set_source_port(Some(held)).await.unwrap().port(), held);
+
+        // A port our own per-connection endpoint holds
+        transport.set_per_connection_endpoint(true);
+        transport.connect(relay.relay_info().unwrap()).await.unwrap();
+        let dedicated = transport.local_endpoint_addr().unwrap().port();
+        let error = transport.set_source_port(Some(dedicated)).await.unwrap_err().to_string();
+        assert_eq!(error, format!("UDP port {} is already bound by this app (client endpoint)", dedicated));
+
+        let foreign = std::net::UdpSocket::bind("0.0.0.0:0").unwrap();
+        let occupied = foreign.local_addr().unwrap().port();
+        let error = transport.set_source_port(Some(occupied)).await.unwrap_err().to_string();
+        assert_eq!(error, format!("UDP port {} is in use by another process", occupied));
+
+        assert_eq!(transport.source_port(), Some(held));
+        relay.stop();
+    }
+
+    #[tokio::test]
+    async fn connect_to_an_unroutable_address_times_out_promptly() {
+        let relay = TestRelay::start().unwrap();
+        let mut transport = QuicTransport::new();
+        transport.set_connect_timeout(600).unwrap();
+        // TEST-NET-1 never answers, so only the timeout can end the connect
+        let unroutable = RelayInfo {
+            address: "192.0.2.1".to_string(),
+            port: 4433,
+            ..pinned_as(&relay, "unroutable", &mut transport)
+        };
+        relay.stop();
+
+        let started = Instant::now();
+        let error = transport.connect(unroutable).await.unwrap_err();
+        let elapsed = started.elapsed();
+        assert!(elapsed >= transport.timeouts().handshake(), "gave up after {:?}", elapsed);
+        assert!(elapsed < Duration::from_millis(1_200), "gave up only after {:?}", elapsed);
+        assert!(
+            matches!(error.downcast_ref(), Some(HushError::QuicConnect(message)) if message.contains("timed out")),
+            "{:#}",
+            error
+        );
+        assert!(transport.connection().is_none());
+    }
 }
@@ -0,0 +1,316 @@
+use anyhow::{anyhow, Context, Result};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, Emitter, State};
+
+use crate::error::HushError;
+use crate::send_queue::unix_now;
+
+/// First byte of a receipt frame: `[magic][16 bytes receipt tag][12 bytes nonce][ciphertext]`.
+pub const RECEIPT_MAGIC: u8 = 0xC8;
+const TAG_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const RECEIPT_HEADER_LEN: usize = 1 + TAG_LEN + NONCE_LEN;
+const KEY_LEN: usize = 32;
+
+/// Sent messages whose receipts are still awaited. Bounded so untracked ids can't grow
+/// the table forever; the oldest entry is dropped first.
+const MAX_TRACKED: usize = 1024;
+
+type ReceiptTag = [u8; TAG_LEN];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryState {
+    Sent,
+    Delivered,
+    Read,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReceiptKind {
+    Delivered,
+    Read,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ReceiptBody {
+    message_id: String,
+    kind: ReceiptKind,
+    issued_at: u64,
+}
+
+/// Payload of the `message-receipt` event.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReceiptUpdate {
+    pub message_id: String,
+    pub state: DeliveryState,
+    pub issued_at: u64,
+}
+
+struct TrackedSend {
+    message_id: String,
+    key: Key,
+    state: DeliveryState,
+    tracked_at: u64,
+}
+
+/// Receipts are encrypted and authenticated with a random key the sender puts inside
+/// the (already end-to-end encrypted) message, so relays only ever see an opaque blob
+/// and neither side needs to reveal a long-term identity to prove the receipt. They
+/// are not signatures: anyone holding the key, sender included, could have made one,
+/// so a receipt convinces the sender and nobody else. The clear-text tag is a hash of
+/// that key, letting the sender find it without exposing the message id.
+#[derive(Clone, Default)]
+pub struct ReceiptTracker {
+    tracked: Arc<Mutex<HashMap<ReceiptTag, TrackedSend>>>,
+}
+
+impl ReceiptTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts awaiting receipts for `message_id` and returns the hex receipt key to
+    /// embed in the outgoing message.
+    pub fn track(&self, message_id: &str) -> Result<String> {
+        let key = ChaCha20Poly1305::generate_key(&mut OsRng);
+        let mut tracked = self.tracked.lock().map_err(|_| anyhow!("Receipt table poisoned"))?;
+
+        if tracked.len() >= MAX_TRACKED {
+            if let Some(oldest) = tracked.iter().min_by_key(|(_, t)| t.tracked_at).map(|(tag, _)| *tag) {
+                tracked.remove(&oldest);
+            }
+        }
+        tracked.insert(receipt_tag(&key), TrackedSend {
+            message_id: message_id.to_string(),
+            key,
+            state: DeliveryState::Sent,
+            tracked_at: unix_now(),
+        });
+
+        Ok(encode_key(&key))
+    }
+
+    pub fn state(&self, message_id: &str) -> Option<DeliveryState> {
+        let tracked = self.tracked.lock().ok()?;
+        tracked.values().find(|t| t.message_id == message_id).map(|t| t.state)
+    }
+
+    /// Decrypts a receipt frame and advances the message's state. States only move
+    /// forward, so a late Delivered never downgrades Read. Returns `None` for frames
+    /// that aren't receipts for a tracked message.
+    pub fn process(&self, frame: &[u8]) -> Result<Option<ReceiptUpdate>> {
+        if !is_receipt(frame) {
+            return Ok(None);
+        }
+
+        let mut tag = [0u8; TAG_LEN];
+        tag.copy_from_slice(&frame[1..1 + TAG_LEN]);
+        let mut tracked = self.tracked.lock().map_err(|_| anyhow!("Receipt table poisoned"))?;
+        let Some(entry) = tracked.get_mut(&tag) else {
+            return Ok(None);
+        };
+
+        let nonce = Nonce::from_slice(&frame[1 + TAG_LEN..RECEIPT_HEADER_LEN]);
+        let plaintext = ChaCha20Poly1305::new(&entry.key)
+            .decrypt(nonce, &frame[RECEIPT_HEADER_LEN..])
+            .map_err(|_| anyhow!("Receipt failed authentication"))?;
+        let body: ReceiptBody = serde_json::from_slice(&plaintext).context("Malformed receipt")?;
+        if body.message_id != entry.message_id {
+            anyhow::bail!("Receipt is for a different message");
+        }
+
+        let state = match body.kind {
+            ReceiptKind::Delivered => DeliveryState::Delivered,
+            ReceiptKind::Read => DeliveryState::Read,
+        };
+        entry.state = entry.state.max(state);
+
+        Ok(Some(ReceiptUpdate {
+            message_id: entry.message_id.clone(),
+            state: entry.state,
+            issued_at: body.issued_at,
+        }))
+    }
+}
+
+/// Builds the receipt frame a recipient routes back for `message_id`, encrypted under
+/// the receipt key the sender embedded in the message.
+pub fn create_receipt(message_id: &str, receipt_key: &str, kind: ReceiptKind) -> Result<Vec<u8>> {
+    let key = decode_key(receipt_key)?;
+
+    let body = serde_json::to_vec(&ReceiptBody {
+        message_id: message_id.to_string(),
+        kind,
+        issued_at: unix_now(),
+    })?;
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = ChaCha20Poly1305::new(&key)
+        .encrypt(&nonce, body.as_ref())
+        .map_err(|_| anyhow!("Failed to encrypt receipt"))?;
+
+    let mut frame = Vec::with_capacity(RECEIPT_HEADER_LEN + ciphertext.len());
+    frame.push(RECEIPT_MAGIC);
+    frame.extend_from_slice(&receipt_tag(&key));
+    frame.extend_from_slice(&nonce);
+    frame.extend_from_slice(&ciphertext);
+    Ok(frame)
+}
+
+pub fn is_receipt(frame: &[u8]) -> bool {
+    frame.len() > RECEIPT_HEADER_LEN && frame[0] == RECEIPT_MAGIC
+}
+
+/// Receipt keys travel inside messages as 64 lowercase hex digits.
+fn encode_key(key: &Key) -> String {
+    key.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Inverse of [`encode_key`]. Receipt keys are machine-made, so anything but exactly
+/// 64 hex digits is rejected rather than trimmed.
+fn decode_key(hex: &str) -> Result<Key> {
+    if hex.len() != KEY_LEN * 2 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        anyhow::bail!("Invalid receipt key: expected {} hex digits", KEY_LEN * 2);
+    }
+    let mut key = Key::default();
+    for (byte, pair) in key.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        let pair = std::str::from_utf8(pair).expect("checked ASCII above");
+        *byte = u8::from_str_radix(pair, 16).expect("checked hex above");
+    }
+    Ok(key)
+}
+
+fn receipt_tag(key: &Key) -> ReceiptTag {
+    use sha2::{Digest, Sha256};
+    let digest = Sha256::digest(key);
+    let mut tag = [0u8; TAG_LEN];
+    tag.copy_from_slice(&digest[..TAG_LEN]);
+    tag
+}
+
+/// Processes a receipt frame and emits `message-receipt` when it advanced a message.
+pub fn handle_receipt(app: &AppHandle, tracker: &ReceiptTracker, frame: &[u8]) -> Result<Option<ReceiptUpdate>> {
+    let update = tracker.process(frame)?;
+    if let Some(update) = &update {
+        if let Err(e) = app.emit("message-receipt", update.clone()) {
+            tracing::debug!("Failed to emit message-receipt: {}", e);
+        }
+    }
+    Ok(update)
+}
+
+#[tauri::command]
+pub async fn track_message(
+    message_id: String,
+    tracker: State<'_, ReceiptTracker>,
+) -> Result<String, HushError> {
+    tracker.track(&message_id).map_err(HushError::from)
+}
+
+/// Builds an authenticated receipt for a received message; see [`ReceiptTracker`] for
+/// what that does and doesn't prove.
+#[tauri::command]
+pub async fn make_receipt(
+    message_id: String,
+    receipt_key: String,
+    kind: ReceiptKind,
+) -> Result<Vec<u8>, HushError> {
+    create_receipt(&message_id, &receipt_key, kind).map_err(HushError::from)
+}
+
+/// For receipts that reached the frontend by a path other than the inbound listener.
+#[tauri::command]
+pub async fn process_receipt(
+    frame: Vec<u8>,
+    app: AppHandle,
+    tracker: State<'_, ReceiptTracker>,
+) -> Result<Option<ReceiptUpdate>, HushError> {
+    handle_receipt(&app, &tracker, &frame).map_err(HushError::from)
+}
+
+#[tauri::command]
+pub async fn message_state(
+    message_id: String,
+    tracker: State<'_, ReceiptTracker>,
+) -> Result<Option<DeliveryState>, HushError> {
+    Ok(tracker.state(&message_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quic_transport::{FinishMode, QuicTransport};
+    use crate::send_queue::Priority;
+    use crate::taior_bridge::{TaiorConfig, TaiorState};
+    use crate::test_relay::TestRelay;
+    use std::time::Duration;
+
+    /// Routes `frame` to ourselves through Taior and an echoing relay, the way a
+    /// recipient's receipt comes back.
+    async fn loop_back(taior: &mut TaiorState, transport: &mut QuicTransport, frame: &[u8]) -> Vec<u8> {
+        let address = taior.address().unwrap();
+        let (packet, _) = taior.send(frame, "fast", &address).unwrap();
+        transport.send(&packet, FinishMode::Finish, Priority::Normal).await.unwrap();
+        let echoed = transport.recv(64 * 1024, Duration::from_secs(5)).await.unwrap();
+        taior.receive(&echoed).unwrap().0
+    }
+
+    #[tokio::test]
+    async fn routed_receipts_advance_the_message_state() {
+        let relay = TestRelay::start().unwrap();
+        let mut transport = QuicTransport::new();
+        relay.pin(&mut transport);
+        transport.connect(relay.relay_info().unwrap()).await.unwrap();
+        let mut taior = TaiorState::new();
+        taior.init(TaiorConfig { bootstrap_nodes: Vec::new() }).unwrap();
+
+        let tracker = transport.receipts();
+        let receipt_key = tracker.track("msg-1").unwrap();
+        tracker.track("msg-2").unwrap();
+        assert_eq!(tracker.state("msg-1"), Some(DeliveryState::Sent));
+
+        let delivered = create_receipt("msg-1", &receipt_key, ReceiptKind::Delivered).unwrap();
+        let frame = loop_back(&mut taior, &mut transport, &delivered).await;
+        let update = tracker.process(&frame).unwrap().unwrap();
+        assert_eq!((update.message_id.as_str(), update.state), ("msg-1", DeliveryState::Delivered));
+        assert_eq!(tracker.state("msg-1"), Some(DeliveryState::Delivered));
+        assert_eq!(tracker.state("msg-2"), Some(DeliveryState::Sent));
+
+        let read = create_receipt("msg-1", &receipt_key, ReceiptKind::Read).unwrap();
+        let frame = loop_back(&mut taior, &mut transport, &read).await;
+        assert_eq!(tracker.process(&frame).unwrap().unwrap().state, DeliveryState::Read);
+        // A Delivered arriving late doesn't downgrade Read
+        assert_eq!(tracker.process(&delivered).unwrap().unwrap().state, DeliveryState::Read);
+
+        let mut tampered = read.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(tracker.process(&tampered).is_err());
+        assert!(ReceiptTracker::new().process(&read).unwrap().is_none());
+        relay.stop();
+    }
+
+    #[test]
+    fn receipt_keys_round_trip_and_reject_anything_else() {
+        let key = ChaCha20Poly1305::generate_key(&mut OsRng);
+        let encoded = encode_key(&key);
+        assert_eq!(decode_key(&encoded).unwrap(), key);
+        assert_eq!(decode_key(&encoded.to_uppercase()).unwrap(), key);
+
+        for invalid in [
+            format!(" {}", &encoded[1..]),
+            format!("+{}", &encoded[1..]),
+            format!("é{}", &encoded[2..]),
+            encoded[..62].to_string(),
+            format!("{}00", encoded),
+            "g".repeat(64),
+        ] {
+            assert!(decode_key(&invalid).is_err(), "{:?} was accepted", invalid);
+            assert!(create_receipt("msg-1", &invalid, ReceiptKind::Read).is_err());
+        }
+    }
+}
@@ -0,0 +1,412 @@
+use quinn::{Connection, RecvStream};
+use serde::Serialize;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+use crate::chunking::{self, Reassembler};
+use crate::dedup::InboundDedup;
+use crate::error::HushError;
+use crate::quic_transport::QuicTransport;
+use crate::shared_state::SharedState;
+use crate::taior_bridge::TaiorState;
+
+/// Largest packet accepted on a single relay stream.
+const MAX_INBOUND_PACKET: usize = 1024 * 1024;
+
+/// How often the loop looks for a live relay connection while there is none.
+const IDLE_POLL: Duration = Duration::from_secs(1);
+
+/// Streams read to the end but not yet decrypted.
+const STREAM_BACKLOG: usize = 64;
+
+/// Incomplete chunked messages held at once, and how long each may wait for its
+/// missing chunks.
+const MAX_PENDING_REASSEMBLY: usize = 64;
+const REASSEMBLY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Payload of the `message-received` event: a decrypted message routed to this
+/// identity through the relay.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReceivedMessage {
+    pub sender: String,
+    pub payload: Vec<u8>,
+}
+
+/// Payload of the `message-error` event for a packet that arrived but could not be
+/// turned into a message.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReceiveFailure {
+    /// `stream` or `datagram` from the relay, or `direct` from a peer.
+    pub source: &'static str,
+    pub packet_size: usize,
+    pub error: HushError,
+}
+
+/// What one inbound packet turned into, emitted under [`Self::event`].
+#[derive(Debug)]
+enum Inbound {
+    Message(ReceivedMessage),
+    Failure(ReceiveFailure),
+}
+
+impl Inbound {
+    fn event(&self) -> &'static str {
+        match self {
+            Inbound::Message(_) => "message-received",
+            Inbound::Failure(_) => "message-error",
+        }
+    }
+
+    fn emit(self, app: &AppHandle) {
+        let event = self.event();
+        let result = match self {
+            Inbound::Message(message) => app.emit(event, message),
+            Inbound::Failure(failure) => app.emit(event, failure),
+        };
+        if let Err(e) = result {
+            tracing::debug!("Failed to emit {}: {}", event, e);
+        }
+    }
+}
+
+/// Decrypts inbound packets for one Taior identity, reassembles chunked messages and
+/// drops duplicates. Shared by the relay receive loop and the direct peer listener.
+pub(crate) struct Dispatch {
+    taior: Arc<SharedState<TaiorState>>,
+    /// Token of the identity this dispatch decrypts for.
+    identity: CancellationToken,
+    dedup: InboundDedup,
+    reassembler: Reassembler,
+}
+
+/// Starts the loop that reads every stream and datagram the relay sends on the active
+/// connection, decrypts it with Taior and emits `message-received`. Packets that fail
+/// to decrypt or reassemble emit `message-error` and the loop carries on. When the
+/// connection closes the loop waits for the next one; it ends when `shutdown` is
+/// cancelled.
+///
+/// The loop accepts every relay stream, so `recv_via_quic` and `recv_datagram_via_quic`
+/// only see what arrives while it is stopped. Each stream is read on its own task, so
+/// a relay trickling one stream doesn't hold up the others.
+///
+/// The loop is bound to the current Taior identity: rotating or resetting it stops
+/// the loop before the next identity exists, and a new loop is spawned under the
+/// next identity's token, so no packet is handled by a loop of a stale identity.
+pub fn start(
+    app: AppHandle,
+    taior: Arc<SharedState<TaiorState>>,
+    transport: Arc<SharedState<QuicTransport>>,
+    dedup: InboundDedup,
+    shutdown: CancellationToken,
+) {
+    let emit = move |inbound: Inbound| inbound.emit(&app);
+    tokio::spawn(supervise(Arc::new(emit), taior, transport, dedup, shutdown));
+}
+
+/// Runs one receive loop per identity until `shutdown` is cancelled, each on its own
+/// task under that identity's token. Messages still being reassembled when the
+/// identity changes are dropped with the loop.
+async fn supervise<E>(
+    emit: Arc<E>,
+    taior: Arc<SharedState<TaiorState>>,
+    transport: Arc<SharedState<QuicTransport>>,
+    dedup: InboundDedup,
+    shutdown: CancellationToken,
+) where
+    E: Fn(Inbound) + Send + Sync + 'static,
+{
+    while !shutdown.is_cancelled() {
+        let identity = match taior.read().await {
+            Ok(taior) => taior.identity_token(),
+            Err(_) => {
+                tokio::select! {
+                    _ = shutdown.cancelled() => break,
+                    _ = tokio::time::sleep(IDLE_POLL) => continue,
+                }
+            }
+        };
+
+        let dispatch = Dispatch::new(taior.clone(), identity.clone(), dedup.clone());
+        let task = tokio::spawn(run(emit.clone(), dispatch, transport.clone(), identity.clone()));
+        tokio::select! {
+            _ = shutdown.cancelled() => identity.cancel(),
+            _ = identity.cancelled() => tracing::debug!("Identity changed, restarting receive loop"),
+        }
+        // The next loop only starts once the stale one has stopped
+        let _ = task.await;
+    }
+    tracing::debug!("Receive loop stopped");
+}
+
+async fn run<E>(
+    emit: Arc<E>,
+    mut dispatch: Dispatch,
+    transport: Arc<SharedState<QuicTransport>>,
+    shutdown: CancellationToken,
+) where
+    E: Fn(Inbound) + Send + Sync + 'static,
+{
+    while !shutdown.is_cancelled() {
+        // A closed connection stays in the transport until it reconnects; waiting on it
+        // would return at once and spin
+        let connection = match transport.read().await {
+            Ok(transport) => transport.connection()
+                .filter(|connection| connection.close_reason().is_none()),
+            Err(_) => None,
+        };
+        match connection {
+            Some(connection) => serve_connection(&connection, &*emit, &mut dispatch, &shutdown).await,
+            None => tokio::select! {
+                _ = shutdown.cancelled() => break,
+                _ = tokio::time::sleep(IDLE_POLL) => {}
+            },
+        }
+    }
+}
+
+async fn serve_connection(
+    connection: &Connection,
+    emit: &impl Fn(Inbound),
+    dispatch: &mut Dispatch,
+    shutdown: &CancellationToken,
+) {
+    let relay = connection.remote_address();
+    tracing::debug!("Receiving from relay {}", relay);
+    let (read_tx, mut read) = mpsc::channel::<Vec<u8>>(STREAM_BACKLOG);
+
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => return,
+            Some(packet) = read.recv() => dispatch.deliver(emit, &packet, "stream").await,
+            stream = connection.accept_uni() => match stream {
+                Ok(recv) => {
+                    tokio::spawn(read_stream(recv, relay, read_tx.clone()));
+                }
+                Err(e) => {
+                    tracing::debug!("Stopped receiving from relay {}: {}", relay, e);
+                    return;
+                }
+            },
+            datagram = connection.read_datagram() => match datagram {
+                Ok(datagram) => dispatch.deliver(emit, &datagram, "datagram").await,
+                Err(e) => {
+                    tracing::debug!("Stopped receiving from relay {}: {}", relay, e);
+                    return;
+                }
+            },
+        }
+    }
+}
+
+async fn read_stream(mut recv: RecvStream, relay: SocketAddr, read: mpsc::Sender<Vec<u8>>) {
+    match recv.read_to_end(MAX_INBOUND_PACKET).await {
+        // Only fails once the connection is being served no longer
+        Ok(packet) => {
+            let _ = read.send(packet).await;
+        }
+        Err(e) => tracing::warn!("Failed to read stream from relay {}: {}", relay, e),
+    }
+}
+
+impl Dispatch {
+    pub(crate) fn new(taior: Arc<SharedState<TaiorState>>, identity: CancellationToken, dedup: InboundDedup) -> Self {
+        Self {
+            taior,
+            identity,
+            dedup,
+            reassembler: Reassembler::new(MAX_PENDING_REASSEMBLY, REASSEMBLY_TIMEOUT),
+        }
+    }
+
+    /// Dispatch for whichever identity is current.
+    pub(crate) async fn current(taior: Arc<SharedState<TaiorState>>, dedup: InboundDedup) -> anyhow::Result<Self> {
+        let identity = taior.read().await?.identity_token();
+        Ok(Self::new(taior, identity, dedup))
+    }
+
+    /// Whether the identity this dispatch decrypts for has been rotated or reset.
+    pub(crate) fn is_stale(&self) -> bool {
+        self.identity.is_cancelled()
+    }
+
+    async fn deliver(&mut self, emit: &impl Fn(Inbound), packet: &[u8], source: &'static str) {
+        if let Some(inbound) = self.handle(packet, source).await {
+            emit(inbound);
+        }
+    }
+
+    async fn handle(&mut self, packet: &[u8], source: &'static str) -> Option<Inbound> {
+        match self.open(packet).await {
+            Ok(message) => message.map(Inbound::Message),
+            Err(e) => {
+                tracing::warn!("Dropped {} byte {} from relay: {:#}", packet.len(), source, e);
+                Some(Inbound::Failure(ReceiveFailure {
+                    source,
+                    packet_size: packet.len(),
+                    error: HushError::from(e),
+                }))
+            }
+        }
+    }
+
+    /// Decrypts `packet` and returns the message it completes: `None` for a chunk that
+    /// leaves its message incomplete, for a replay of a packet this sender already got
+    /// through, or once the identity this dispatch belongs to has been replaced.
+    pub(crate) async fn open(&mut self, packet: &[u8]) -> anyhow::Result<Option<ReceivedMessage>> {
+        let (payload, sender) = {
+            let mut taior = self.taior.write().await?;
+            // The identity may have rotated while this loop waited for the lock
+            if self.identity.is_cancelled() {
+                tracing::debug!("Dropped {} byte packet for a replaced identity", packet.len());
+                return Ok(None);
+            }
+            taior.receive(packet)?
+        };
+        // Keyed on the ciphertext: a relay replaying a packet resends it byte for byte,
+        // while the same text sent twice is padded differently each time
+        if !self.dedup.admit(&sender, packet) {
+            return Ok(None);
+        }
+
+        let payload = if chunking::is_chunk(&payload) {
+            match self.reassembler.accept(&payload)? {
+                Some(payload) => payload,
+                None => return Ok(None),
+            }
+        } else {
+            payload
+        };
+        Ok(Some(ReceivedMessage { sender, payload }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quic_transport::FinishMode;
+    use crate::send_queue::Priority;
+    use crate::taior_bridge::TaiorConfig;
+    use crate::test_relay::TestRelay;
+
+    async fn dispatch() -> Dispatch {
+        let mut taior = TaiorState::new();
+        taior.init(TaiorConfig { bootstrap_nodes: Vec::new() }).unwrap();
+        let identity = taior.identity_token();
+        Dispatch::new(Arc::new(SharedState::new(taior)), identity, InboundDedup::new())
+    }
+
+    #[tokio::test]
+    async fn undecryptable_packet_becomes_message_error() {
+        let mut dispatch = dispatch().await;
+        let mut packet = 4u32.to_be_bytes().to_vec();
+        packet.extend_from_slice(b"junk");
+        packet.extend_from_slice(&[0; 3]);
+
+        let inbound = dispatch.handle(&packet, "stream").await.expect("an event");
+        assert_eq!(inbound.event(), "message-error");
+        match inbound {
+            Inbound::Failure(failure) => {
+                assert_eq!(failure.source, "stream");
+                assert_eq!(failure.packet_size, packet.len());
+                assert_eq!(failure.error.kind(), "routing");
+            }
+            Inbound::Message(message) => panic!("decrypted junk into {:?}", message),
+        }
+    }
+
+    #[tokio::test]
+    async fn truncated_packet_becomes_message_error() {
+        let mut dispatch = dispatch().await;
+        let inbound = dispatch.handle(&[0, 0], "datagram").await.expect("an event");
+        assert_eq!(inbound.event(), "message-error");
+    }
+
+    async fn packet_to_self(taior: &SharedState<TaiorState>, payload: &[u8]) -> Vec<u8> {
+        let mut taior = taior.write().await.unwrap();
+        let address = taior.address().unwrap();
+        taior.send(payload, "fast", &address).unwrap().0
+    }
+
+    #[tokio::test]
+    async fn only_replayed_packets_are_dropped_as_duplicates() {
+        let mut dispatch = dispatch().await;
+        let packet = packet_to_self(&dispatch.taior, b"same text").await;
+        let message = dispatch.open(&packet).await.unwrap().expect("first copy");
+        assert_eq!(message.payload, b"same text");
+        assert!(dispatch.open(&packet).await.unwrap().is_none(), "replay delivered");
+
+        // Sending the same text again is a new packet and a new message
+        let again = packet_to_self(&dispatch.taior, b"same text").await;
+        assert_ne!(again, packet);
+        assert_eq!(dispatch.open(&again).await.unwrap().expect("second send").payload, b"same text");
+        assert_eq!(dispatch.dedup.stats().suppressed_total, 1);
+    }
+
+    #[tokio::test]
+    async fn dispatch_of_a_replaced_identity_drops_packets() {
+        let mut dispatch = dispatch().await;
+        let packet = packet_to_self(&dispatch.taior, b"before rotation").await;
+        dispatch.taior.write().await.unwrap().rotate_identity().unwrap();
+
+        assert!(dispatch.identity.is_cancelled());
+        assert!(dispatch.handle(&packet, "stream").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn rotation_restarts_the_loop_without_duplicate_messages() {
+        let relay = TestRelay::start().unwrap();
+        let mut transport = QuicTransport::new();
+        relay.pin(&mut transport);
+        transport.connect(relay.relay_info().unwrap()).await.unwrap();
+        let transport = Arc::new(SharedState::new(transport));
+
+        let mut taior = TaiorState::new();
+        taior.init(TaiorConfig { bootstrap_nodes: Vec::new() }).unwrap();
+        let first_identity = taior.identity_token();
+        let taior = Arc::new(SharedState::new(taior));
+
+        let (events_tx, mut events) = mpsc::unbounded_channel();
+        let emit = move |inbound: Inbound| {
+            let _ = events_tx.send(inbound);
+        };
+        let shutdown = CancellationToken::new();
+        tokio::spawn(supervise(
+            Arc::new(emit),
+            taior.clone(),
+            transport.clone(),
+            InboundDedup::new(),
+            shutdown.clone(),
+        ));
+
+        // The echo relay sends every packet straight back to the receive loop
+        for payload in [&b"before rotation"[..], b"after rotation"] {
+            let packet = packet_to_self(&taior, payload).await;
+            transport.write().await.unwrap()
+                .send(&packet, FinishMode::Finish, Priority::default()).await.unwrap();
+
+            let inbound = tokio::time::timeout(Duration::from_secs(5), events.recv())
+                .await
+                .expect("the message to arrive")
+                .unwrap();
+            match inbound {
+                Inbound::Message(message) => assert_eq!(message.payload, payload),
+                Inbound::Failure(failure) => panic!("receive failed: {:?}", failure),
+            }
+
+            if payload == b"before rotation" {
+                taior.write().await.unwrap().rotate_identity().unwrap();
+                assert!(first_identity.is_cancelled());
+            }
+        }
+
+        let extra = tokio::time::timeout(Duration::from_millis(300), events.recv()).await;
+        assert!(extra.is_err(), "duplicate event {:?}", extra);
+
+        shutdown.cancel();
+        relay.stop();
+    }
+}
@@ -0,0 +1,299 @@
+use quinn::ConnectionError;
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, State};
+use tokio_util::sync::CancellationToken;
+
+use crate::error::HushError;
+use crate::quic_transport::QuicTransport;
+use crate::retry::Backoff;
+use crate::shared_state::SharedState;
+
+const INITIAL_BACKOFF: Duration = Duration::from_millis(250);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// How often the supervisor looks for a connection while none is active.
+const IDLE_POLL: Duration = Duration::from_secs(1);
+
+/// Payload of the `relay-reconnect-attempt` event.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReconnectAttempt {
+    pub relay_address: String,
+    pub attempt: u32,
+    pub max_retries: u32,
+    pub delay_ms: u64,
+    /// Set once the attempt has run: `None` while waiting, then whether it succeeded.
+    pub succeeded: Option<bool>,
+    pub error: Option<String>,
+}
+
+/// Whether auto-reconnect is on and the last attempt it reported, if any.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ReconnectStatus {
+    pub enabled: bool,
+    pub last_attempt: Option<ReconnectAttempt>,
+}
+
+/// Reconnects to the last relay, with exponential backoff, when its connection drops
+/// for any reason other than a local close. Cloned handles control the same supervisor.
+#[derive(Clone, Default)]
+pub struct AutoReconnect {
+    running: Arc<Mutex<Option<CancellationToken>>>,
+    last_attempt: Arc<Mutex<Option<ReconnectAttempt>>>,
+}
+
+impl AutoReconnect {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn start(&self, max_retries: u32, transport: Arc<SharedState<QuicTransport>>, app: AppHandle) {
+        let token = CancellationToken::new();
+        if let Ok(mut running) = self.running.lock() {
+            if let Some(old) = running.replace(token.clone()) {
+                old.cancel();
+            }
+        }
+        let last_attempt = self.last_attempt.clone();
+        let emit = move |attempt: &ReconnectAttempt| {
+            if let Ok(mut last) = last_attempt.lock() {
+                *last = Some(attempt.clone());
+            }
+            emit_attempt(&app, attempt);
+        };
+        tokio::spawn(supervise(max_retries, transport, emit, token));
+        tracing::info!("Auto-reconnect enabled, up to {} retries", max_retries);
+    }
+
+    pub fn stop(&self) {
+        if let Ok(mut running) = self.running.lock() {
+            if let Some(token) = running.take() {
+                token.cancel();
+                tracing::info!("Auto-reconnect disabled");
+            }
+        }
+    }
+
+    pub fn status(&self) -> ReconnectStatus {
+        ReconnectStatus {
+            enabled: self.running.lock().map(|r| r.is_some()).unwrap_or(false),
+            last_attempt: self.last_attempt.lock().ok().and_then(|last| last.clone()),
+        }
+    }
+}
+
+/// Watches the default relay's connection and reconnects it when it drops. A
+/// connection it has given up on stays closed in the transport, so it is skipped like
+/// a missing one until something else connects.
+async fn supervise(
+    max_retries: u32,
+    transport: Arc<SharedState<QuicTransport>>,
+    emit: impl Fn(&ReconnectAttempt),
+    shutdown: CancellationToken,
+) {
+    let mut backoff = Backoff::new(INITIAL_BACKOFF, MAX_BACKOFF);
+
+    loop {
+        let connection = match transport.read().await {
+            Ok(transport) => transport.connection()
+                .filter(|connection| connection.close_reason().is_none()),
+            Err(_) => None,
+        };
+        let Some(connection) = connection else {
+            tokio::select! {
+                _ = shutdown.cancelled() => return,
+                _ = tokio::time::sleep(IDLE_POLL) => continue,
+            }
+        };
+
+        let reason = tokio::select! {
+            _ = shutdown.cancelled() => return,
+            reason = connection.closed() => reason,
+        };
+        if matches!(reason, ConnectionError::LocallyClosed) {
+            // Disconnect, migration or shutdown: nothing to recover
+            continue;
+        }
+
+        // The relay may already have been replaced by a connect while we waited
+        let relay = match transport.read().await {
+            Ok(transport) => {
+                let current = transport.connection().map(|c| c.stable_id());
+                if current != Some(connection.stable_id()) {
+                    continue;
+                }
+                transport.connected_relay()
+            }
+            Err(_) => None,
+        };
+        let Some(relay) = relay else {
+            continue;
+        };
+        tracing::warn!("Relay connection lost: {}", reason);
+
+        let relay_address = relay.host_port();
+        backoff.reset();
+        let mut reconnected = false;
+        while !reconnected && backoff.attempts() < max_retries {
+            let delay = backoff.next_delay();
+            let mut attempt = ReconnectAttempt {
+                relay_address: relay_address.clone(),
+                attempt: backoff.attempts(),
+                max_retries,
+                delay_ms: delay.as_millis() as u64,
+                succeeded: None,
+                error: None,
+            };
+            emit(&attempt);
+
+            tokio::select! {
+                _ = shutdown.cancelled() => return,
+                _ = tokio::time::sleep(delay) => {}
+            }
+            let result = match transport.write().await {
+                Ok(mut transport) => transport.connect(relay.clone()).await,
+                Err(e) => Err(anyhow::anyhow!(e.to_string())),
+            };
+
+            attempt.succeeded = Some(result.is_ok());
+            attempt.error = result.as_ref().err().map(|e| format!("{:#}", e));
+            emit(&attempt);
+            reconnected = result.is_ok();
+            if reconnected {
+                tracing::info!("Reconnected to {} after {} attempts", relay_address, attempt.attempt);
+            }
+        }
+        if !reconnected {
+            tracing::warn!("Giving up on {} after {} reconnect attempts", relay_address, max_retries);
+        }
+        backoff.reset();
+    }
+}
+
+fn emit_attempt(app: &AppHandle, attempt: &ReconnectAttempt) {
+    if let Err(e) = app.emit("relay-reconnect-attempt", attempt.clone()) {
+        tracing::debug!("Failed to emit relay-reconnect-attempt: {}", e);
+    }
+}
+
+#[tauri::command]
+pub async fn set_auto_reconnect(
+    enabled: bool,
+    max_retries: u32,
+    app: AppHandle,
+    reconnect: State<'_, AutoReconnect>,
+    state: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<(), HushError> {
+    if !enabled {
+        reconnect.stop();
+        return Ok(());
+    }
+    if max_retries == 0 {
+        return Err(HushError::InvalidInput("Auto-reconnect needs at least one retry".to_string()));
+    }
+
+    reconnect.start(max_retries, state.inner().clone(), app);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_relay::TestRelay;
+    use crate::timeouts::TimeoutConfig;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use tokio::sync::mpsc;
+
+    const QUICK: TimeoutConfig = TimeoutConfig {
+        connect_ms: 400,
+        handshake_ms: 300,
+        stream_io_ms: 400,
+        ack_ms: 400,
+        probe_ms: 300,
+        drain_ms: 100,
+    };
+
+    async fn connected(relay: &TestRelay) -> Arc<SharedState<QuicTransport>> {
+        let mut transport = QuicTransport::new();
+        transport.set_timeouts(QUICK).unwrap();
+        relay.pin(&mut transport);
+        transport.connect(relay.relay_info().unwrap()).await.unwrap();
+        Arc::new(SharedState::new(transport))
+    }
+
+    fn watch(
+        max_retries: u32,
+        transport: &Arc<SharedState<QuicTransport>>,
+    ) -> (mpsc::UnboundedReceiver<ReconnectAttempt>, CancellationToken) {
+        let (attempts_tx, attempts) = mpsc::unbounded_channel();
+        let emit = move |attempt: &ReconnectAttempt| {
+            let _ = attempts_tx.send(attempt.clone());
+        };
+        let shutdown = CancellationToken::new();
+        tokio::spawn(supervise(max_retries, transport.clone(), emit, shutdown.clone()));
+        (attempts, shutdown)
+    }
+
+    async fn finished(attempts: &mut mpsc::UnboundedReceiver<ReconnectAttempt>) -> ReconnectAttempt {
+        loop {
+            let attempt = tokio::time::timeout(Duration::from_secs(5), attempts.recv())
+                .await
+                .expect("a reconnect attempt")
+                .unwrap();
+            if attempt.succeeded.is_some() {
+                return attempt;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_retries_and_stays_quiet() {
+        let relay = TestRelay::start().unwrap();
+        let transport = connected(&relay).await;
+        let (mut attempts, shutdown) = watch(2, &transport);
+        relay.stop();
+
+        for expected in 1..=2 {
+            let attempt = finished(&mut attempts).await;
+            assert_eq!(attempt.attempt, expected);
+            assert_eq!(attempt.succeeded, Some(false));
+            assert!(attempt.error.is_some());
+        }
+
+        // The dead connection must not be retried again
+        tokio::time::sleep(IDLE_POLL * 2).await;
+        assert!(attempts.try_recv().is_err());
+        shutdown.cancel();
+    }
+
+    #[tokio::test]
+    async fn reconnects_when_the_relay_drops_the_connection() {
+        let accepted = Arc::new(AtomicU32::new(0));
+        let counter = accepted.clone();
+        let relay = TestRelay::serve(Duration::ZERO, move |connection| {
+            let first = counter.fetch_add(1, Ordering::SeqCst) == 0;
+            async move {
+                if first {
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    connection.close(1u32.into(), b"restart");
+                }
+                connection.closed().await;
+            }
+        })
+        .unwrap();
+        let transport = connected(&relay).await;
+        let dropped = transport.read().await.unwrap().connection().unwrap().stable_id();
+        let (mut attempts, shutdown) = watch(3, &transport);
+
+        let attempt = finished(&mut attempts).await;
+        assert_eq!(attempt.attempt, 1);
+        assert_eq!(attempt.succeeded, Some(true));
+        let connection = transport.read().await.unwrap().connection().unwrap();
+        assert_ne!(connection.stable_id(), dropped);
+        assert!(connection.close_reason().is_none());
+
+        shutdown.cancel();
+        relay.stop();
+    }
+}
@@ -1,6 +1,16 @@
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::net::IpAddr;
+use std::sync::Arc;
+use tauri::{AppHandle, Emitter, State};
+use tokio::sync::RwLock;
+
+use crate::blocklist::RelayBlocklist;
+use crate::directory_mirror::DirectoryMirror;
+use crate::discovery_backend::{parse_authority_key, AuthorityKey, DiscoveryBackend, HttpsBackend, StaticBackend};
+use crate::error::HushError;
+use crate::quic_transport::RelayInfo;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RelayNode {
@@ -10,58 +20,556 @@ pub struct RelayNode {
     pub public_key: String,
     pub latency_ms: Option<u64>,
     pub bandwidth_mbps: Option<u32>,
+    /// Connect timeout for this relay in place of the global one, e.g. for a distant
+    /// relay whose handshake legitimately takes longer.
+    #[serde(default)]
+    pub connect_timeout_ms: Option<u64>,
+    /// Network operator the relay runs in, e.g. `AS64500`. Relays without one are
+    /// grouped by address subnet where the address is an IP literal.
+    #[serde(default)]
+    pub network: Option<String>,
+}
+
+/// Connection outcomes remembered per relay for [`RelayDiscovery::success_rate`]; older
+/// outcomes are forgotten first.
+const HISTORY_LEN: usize = 20;
+
+/// Latency and bandwidth at which [`RelayNode::quality_score`] gives half marks.
+const REFERENCE_LATENCY_MS: f64 = 100.0;
+const REFERENCE_BANDWIDTH_MBPS: f64 = 100.0;
+
+/// Component score used in place of a measurement the relay doesn't have yet.
+const UNMEASURED_SCORE: f64 = 0.5;
+
+/// Relative weights of the [`RelayNode::quality_score`] components. Only the ratios
+/// matter; the score is divided by their sum.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct QualityWeights {
+    pub latency: f64,
+    pub bandwidth: f64,
+    pub reliability: f64,
+}
+
+impl Default for QualityWeights {
+    fn default() -> Self {
+        Self {
+            latency: 0.4,
+            bandwidth: 0.2,
+            reliability: 0.4,
+        }
+    }
+}
+
+impl QualityWeights {
+    pub fn validate(&self) -> Result<()> {
+        let weights = [self.latency, self.bandwidth, self.reliability];
+        if weights.iter().any(|w| !w.is_finite() || *w < 0.0) {
+            return Err(HushError::InvalidInput("Quality weights must be finite and non-negative".to_string()).into());
+        }
+        if weights.iter().sum::<f64>() <= 0.0 {
+            return Err(HushError::InvalidInput("At least one quality weight must be above zero".to_string()).into());
+        }
+        Ok(())
+    }
+}
+
+/// Returned instead of an opaque connect failure when discovery has no relays at all.
+pub const NO_RELAYS_MESSAGE: &str =
+    "No relays configured. Refresh the relay directory or add a relay manually.";
+
+impl RelayNode {
+    pub fn to_relay_info(&self) -> RelayInfo {
+        RelayInfo {
+            id: Some(self.id.clone()),
+            address: self.address.clone(),
+            port: self.port,
+            public_key: Some(self.public_key.clone()).filter(|k| !k.is_empty()),
+            connect_timeout_ms: self.connect_timeout_ms,
+            server_name: None,
+        }
+    }
+
+    /// Quality in `[0, 1]`, higher is better: the weighted mean of
+    ///
+    /// - latency: `R / (R + latency_ms)` with `R` = 100 ms, so 100 ms scores 0.5
+    /// - bandwidth: `bandwidth_mbps / (bandwidth_mbps + B)` with `B` = 100 Mbps
+    /// - reliability: `success_rate`, the share of recent connects that succeeded
+    ///
+    /// A component without a measurement scores 0.5.
+    pub fn quality_score(&self, success_rate: Option<f64>, weights: &QualityWeights) -> f64 {
+        let latency = self.latency_ms
+            .map(|ms| REFERENCE_LATENCY_MS / (REFERENCE_LATENCY_MS + ms as f64))
+            .unwrap_or(UNMEASURED_SCORE);
+        let bandwidth = self.bandwidth_mbps
+            .map(|mbps| f64::from(mbps) / (f64::from(mbps) + REFERENCE_BANDWIDTH_MBPS))
+            .unwrap_or(UNMEASURED_SCORE);
+        let reliability = success_rate.unwrap_or(UNMEASURED_SCORE);
+
+        let total = weights.latency + weights.bandwidth + weights.reliability;
+        if total <= 0.0 {
+            return UNMEASURED_SCORE;
+        }
+        (weights.latency * latency + weights.bandwidth * bandwidth + weights.reliability * reliability) / total
+    }
+
+    /// The relay's declared network, or its /24 (IPv4) or /48 (IPv6) subnet. `None`
+    /// for a hostname without a declared network.
+    pub fn network_key(&self) -> Option<String> {
+        if let Some(network) = &self.network {
+            return Some(network.clone());
+        }
+        match self.address.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>().ok()? {
+            IpAddr::V4(ip) => {
+                let [a, b, c, _] = ip.octets();
+                Some(format!("{}.{}.{}.0/24", a, b, c))
+            }
+            IpAddr::V6(ip) => {
+                let segments = ip.segments();
+                Some(format!("{:x}:{:x}:{:x}::/48", segments[0], segments[1], segments[2]))
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct RelayDiscovery {
     known_relays: HashMap<String, RelayNode>,
+    manual: HashMap<String, RelayNode>,
+    backends: Vec<Arc<dyn DiscoveryBackend>>,
+    /// Used when no backend could be reached.
+    fallback: Vec<RelayNode>,
+    unhealthy: HashSet<String>,
+    rotation: Option<RoundRobin>,
+    connectivity: Option<ConnectivityMatrix>,
+    blocklist: RelayBlocklist,
+    /// Recent connection outcomes per relay, newest last; `true` for a success.
+    history: HashMap<String, VecDeque<bool>>,
+    quality_weights: QualityWeights,
+}
+
+/// Which ordered relay pairs can forward to each other. `reachable[i][j]` is true when
+/// `relay_ids[i]` accepted a forwarding probe towards `relay_ids[j]`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ConnectivityMatrix {
+    pub relay_ids: Vec<String>,
+    pub reachable: Vec<Vec<bool>>,
+}
+
+impl ConnectivityMatrix {
+    pub fn can_forward(&self, from: &str, to: &str) -> Option<bool> {
+        let i = self.relay_ids.iter().position(|id| id == from)?;
+        let j = self.relay_ids.iter().position(|id| id == to)?;
+        Some(self.reachable[i][j])
+    }
+}
+
+/// Relays picked for a circuit or transfer. `floor_relaxed` is set when no relay met
+/// the requested bandwidth floor and the selection ignored it.
+#[derive(Debug, Clone, Serialize)]
+pub struct RelaySelection {
+    pub relays: Vec<RelayNode>,
+    pub floor_relaxed: bool,
+}
+
+/// Rotates sends across the `top_n` lowest-latency healthy relays.
+#[derive(Debug, Clone)]
+pub struct RoundRobin {
+    top_n: usize,
+    cursor: usize,
+}
+
+impl Default for RelayDiscovery {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl RelayDiscovery {
+    /// Discovery seeded with the built-in relay list, which stays the fallback until
+    /// a backend is registered and reachable.
     pub fn new() -> Self {
-        let mut known_relays = HashMap::new();
-        
-        // TODO: Replace public_key with real SHA-256 certificate fingerprints
-        // These must match the PinnedCertVerifier hashes in quic_transport.rs
-        known_relays.insert(
-            "relay1".to_string(),
-            RelayNode {
-                id: "relay1".to_string(),
-                address: "relay1.taior.net".to_string(),
-                port: 4433,
-                public_key: String::new(),
-                latency_ms: None,
-                bandwidth_mbps: None,
-            },
-        );
-        
-        known_relays.insert(
-            "relay2".to_string(),
-            RelayNode {
-                id: "relay2".to_string(),
-                address: "relay2.taior.net".to_string(),
-                port: 4433,
-                public_key: String::new(),
-                latency_ms: None,
-                bandwidth_mbps: None,
-            },
-        );
+        let defaults = StaticBackend::defaults().relays();
+        let known_relays = defaults.iter()
+            .map(|r| (r.id.clone(), r.clone()))
+            .collect();
 
-        Self { known_relays }
+        Self {
+            known_relays,
+            manual: HashMap::new(),
+            backends: Vec::new(),
+            fallback: defaults,
+            unhealthy: HashSet::new(),
+            rotation: None,
+            connectivity: None,
+            blocklist: RelayBlocklist::new(),
+            history: HashMap::new(),
+            quality_weights: QualityWeights::default(),
+        }
     }
 
-    pub fn get_available_relays(&self) -> Vec<RelayNode> {
-        self.known_relays.values().cloned().collect()
+    /// Shares the user's blocklist; blocked relays are left out of every listing,
+    /// selection and circuit.
+    pub fn attach_blocklist(&mut self, blocklist: RelayBlocklist) {
+        self.blocklist = blocklist;
+    }
+
+    /// Discovery fed by the HTTPS directories at `urls`, each of which must be signed
+    /// by `authority`. If none of them can be fetched and verified the built-in relay
+    /// list is used until the next refresh.
+    pub async fn from_bootstrap(urls: &[String], authority: AuthorityKey) -> Result<Self> {
+        let mut discovery = Self::new();
+        discovery.set_bootstrap(urls, authority)?;
+        discovery.refresh().await?;
+        Ok(discovery)
+    }
+
+    /// Replaces the registered HTTPS directories with `urls`, signed by `authority`.
+    pub fn set_bootstrap(&mut self, urls: &[String], authority: AuthorityKey) -> Result<()> {
+        if urls.is_empty() {
+            anyhow::bail!("No bootstrap URLs given");
+        }
+        let backends = urls.iter()
+            .map(|url| HttpsBackend::new(url, authority).map(|b| Arc::new(b) as Arc<dyn DiscoveryBackend>))
+            .collect::<Result<Vec<_>>>()?;
+
+        self.backends.retain(|b| !b.is_bootstrap());
+        for backend in backends {
+            self.add_backend(backend);
+        }
+        Ok(())
+    }
+
+    /// Registers another relay source; its relays appear after the next refresh.
+    pub fn add_backend(&mut self, backend: Arc<dyn DiscoveryBackend>) {
+        tracing::info!("Added discovery backend: {}", backend.name());
+        self.backends.push(backend);
+    }
+
+    /// Rebuilds the relay list from every backend plus manually added relays. Relays
+    /// are deduplicated by id: manual entries win, then backends in registration order.
+    /// A failing backend is skipped; if none succeeds the built-in list stands in.
+    /// Returns the number of known relays afterwards.
+    pub async fn refresh(&mut self) -> Result<usize> {
+        let mut merged: HashMap<String, RelayNode> = self.manual.clone();
+        let mut failures = 0;
+
+        for backend in &self.backends {
+            match backend.fetch().await {
+                Ok(relays) => {
+                    for relay in relays {
+                        merged.entry(relay.id.clone()).or_insert(relay);
+                    }
+                }
+                Err(e) => {
+                    failures += 1;
+                    tracing::warn!("Discovery backend {} failed: {:#}", backend.name(), e);
+                }
+            }
+        }
+        if failures == self.backends.len() {
+            if self.fallback.is_empty() && failures > 0 {
+                anyhow::bail!("All {} discovery backends failed", failures);
+            }
+            if failures > 0 {
+                tracing::warn!("No discovery backend reachable; using the built-in relay list");
+            }
+            for relay in &self.fallback {
+                merged.entry(relay.id.clone()).or_insert_with(|| relay.clone());
+            }
+        }
+
+        // Keep locally measured latency for relays that survived the refresh
+        for (id, relay) in merged.iter_mut() {
+            if let Some(known) = self.known_relays.get(id) {
+                relay.latency_ms = relay.latency_ms.or(known.latency_ms);
+            }
+        }
+        self.unhealthy.retain(|id| merged.contains_key(id));
+        self.known_relays = merged;
+
+        tracing::info!("Relay directory refreshed: {} relays", self.known_relays.len());
+        Ok(self.known_relays.len())
+    }
+
+    /// Every known relay that isn't blocked, or with `reachable_only` just those not
+    /// marked unhealthy by a failed probe or connect.
+    pub fn get_available_relays(&self, reachable_only: bool) -> Vec<RelayNode> {
+        self.known_relays.values()
+            .filter(|r| !self.blocklist.contains(&r.id))
+            .filter(|r| !reachable_only || !self.unhealthy.contains(&r.id))
+            .cloned()
+            .collect()
     }
 
     pub fn get_relay(&self, id: &str) -> Option<&RelayNode> {
         self.known_relays.get(id)
     }
+
+    pub fn is_blocked(&self, id: &str) -> bool {
+        self.blocklist.contains(id)
+    }
+
+    /// Adds a relay by hand. Manual relays survive refreshes.
+    pub fn add_relay(&mut self, relay: RelayNode) {
+        self.unhealthy.remove(&relay.id);
+        self.manual.insert(relay.id.clone(), relay.clone());
+        self.known_relays.insert(relay.id.clone(), relay);
+    }
+
+    pub fn remove_relay(&mut self, id: &str) -> bool {
+        self.unhealthy.remove(id);
+        self.manual.remove(id);
+        self.known_relays.remove(id).is_some()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.known_relays.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.known_relays.len()
+    }
+
+    pub fn unhealthy_count(&self) -> usize {
+        self.unhealthy.len()
+    }
+
+    /// SHA-256 over a canonical encoding of the directory so users can compare what
+    /// they see out-of-band. Locally measured fields (latency) are excluded; everything
+    /// the directory publishes is included, in id order.
+    pub fn directory_fingerprint(&self) -> String {
+        use sha2::{Digest, Sha256};
+
+        let mut relays: Vec<&RelayNode> = self.known_relays.values().collect();
+        relays.sort_by(|a, b| a.id.cmp(&b.id));
+
+        let mut hasher = Sha256::new();
+        for relay in relays {
+            for field in [
+                relay.id.as_str(),
+                relay.address.as_str(),
+                &relay.port.to_string(),
+                relay.public_key.as_str(),
+                &relay.bandwidth_mbps.map(|b| b.to_string()).unwrap_or_default(),
+                &relay.connect_timeout_ms.map(|t| t.to_string()).unwrap_or_default(),
+                relay.network.as_deref().unwrap_or_default(),
+            ] {
+                hasher.update((field.len() as u32).to_be_bytes());
+                hasher.update(field.as_bytes());
+            }
+        }
+        crate::cert_pins::format_fingerprint(&hasher.finalize().into())
+    }
+
+    /// Fails with [`NO_RELAYS_MESSAGE`] when there is nothing to connect to.
+    pub fn ensure_not_empty(&self) -> Result<()> {
+        if self.is_empty() {
+            anyhow::bail!(NO_RELAYS_MESSAGE);
+        }
+        Ok(())
+    }
+
+    pub fn mark_unhealthy(&mut self, id: &str) {
+        self.unhealthy.insert(id.to_string());
+    }
+
+    pub fn mark_healthy(&mut self, id: &str) {
+        self.unhealthy.remove(id);
+    }
+
+    /// Remembers whether a connection to the relay succeeded, for its quality score.
+    pub fn record_connect(&mut self, id: &str, succeeded: bool) {
+        let history = self.history.entry(id.to_string()).or_default();
+        if history.len() == HISTORY_LEN {
+            history.pop_front();
+        }
+        history.push_back(succeeded);
+    }
+
+    /// Share of the relay's recent connects that succeeded; `None` before the first.
+    pub fn success_rate(&self, id: &str) -> Option<f64> {
+        let history = self.history.get(id).filter(|h| !h.is_empty())?;
+        let successes = history.iter().filter(|&&ok| ok).count();
+        Some(successes as f64 / history.len() as f64)
+    }
+
+    pub fn quality_score(&self, relay: &RelayNode) -> f64 {
+        relay.quality_score(self.success_rate(&relay.id), &self.quality_weights)
+    }
+
+    pub fn set_quality_weights(&mut self, weights: QualityWeights) -> Result<()> {
+        weights.validate()?;
+        tracing::info!("Relay quality weights: {:?}", weights);
+        self.quality_weights = weights;
+        Ok(())
+    }
+
+    pub fn quality_weights(&self) -> QualityWeights {
+        self.quality_weights
+    }
+
+    pub fn is_reachable(&self, id: &str) -> bool {
+        self.known_relays.contains_key(id) && !self.unhealthy.contains(id) && !self.blocklist.contains(id)
+    }
+
+    /// Applies a probe result: a measured RTT updates the relay's latency and marks it
+    /// healthy, no answer marks it unhealthy and keeps the last known latency.
+    pub fn record_probe(&mut self, id: &str, latency_ms: Option<u64>) {
+        let Some(relay) = self.known_relays.get_mut(id) else {
+            return;
+        };
+        match latency_ms {
+            Some(latency) => {
+                relay.latency_ms = Some(latency);
+                self.unhealthy.remove(id);
+            }
+            None => {
+                self.unhealthy.insert(id.to_string());
+            }
+        }
+        self.record_connect(id, latency_ms.is_some());
+    }
+
+    /// Healthy, unblocked relays ordered by latency (unmeasured last), ties broken by id
+    /// so the order is stable between calls.
+    pub fn healthy_relays(&self) -> Vec<RelayNode> {
+        let mut relays: Vec<RelayNode> = self.known_relays.values()
+            .filter(|r| !self.unhealthy.contains(&r.id) && !self.blocklist.contains(&r.id))
+            .cloned()
+            .collect();
+        relays.sort_by(|a, b| {
+            a.latency_ms.unwrap_or(u64::MAX)
+                .cmp(&b.latency_ms.unwrap_or(u64::MAX))
+                .then_with(|| a.id.cmp(&b.id))
+        });
+        relays
+    }
+
+    /// Up to `count` healthy relays in latency order. With `min_bandwidth_mbps`, relays
+    /// below the floor or with unknown bandwidth are excluded; if that leaves nothing,
+    /// the floor is dropped with a warning rather than failing the selection.
+    pub fn select_relays(&self, count: usize, min_bandwidth_mbps: Option<u32>) -> RelaySelection {
+        let candidates = self.healthy_relays();
+
+        let Some(floor) = min_bandwidth_mbps else {
+            return RelaySelection {
+                relays: candidates.into_iter().take(count).collect(),
+                floor_relaxed: false,
+            };
+        };
+
+        let qualifying: Vec<RelayNode> = candidates.iter()
+            .filter(|r| r.bandwidth_mbps.is_some_and(|bw| bw >= floor))
+            .take(count)
+            .cloned()
+            .collect();
+        if !qualifying.is_empty() || candidates.is_empty() {
+            return RelaySelection {
+                relays: qualifying,
+                floor_relaxed: false,
+            };
+        }
+
+        tracing::warn!("No healthy relay offers {} Mbps; ignoring the bandwidth floor", floor);
+        RelaySelection {
+            relays: candidates.into_iter().take(count).collect(),
+            floor_relaxed: true,
+        }
+    }
+
+    /// Builds a circuit of `hops` distinct healthy relays in random order, where each
+    /// hop is known (or assumed) to forward to the next.
+    pub fn build_circuit(&self, hops: usize) -> Result<RelayCircuit> {
+        RelayCircuit::build_from(self, CircuitStrategy::Random { seed: None }, &[], hops)
+    }
+
+    /// Mean latency over healthy relays that have been measured.
+    pub fn mean_latency_ms(&self) -> Option<u64> {
+        let measured: Vec<u64> = self.healthy_relays().iter()
+            .filter_map(|r| r.latency_ms)
+            .collect();
+        if measured.is_empty() {
+            return None;
+        }
+        Some(measured.iter().sum::<u64>() / measured.len() as u64)
+    }
+
+    pub fn set_connectivity(&mut self, matrix: ConnectivityMatrix) {
+        self.connectivity = Some(matrix);
+    }
+
+    /// Whether `from` is known to forward to `to`. Pairs that were never probed are
+    /// assumed reachable so circuits can still be built before a matrix exists.
+    pub fn can_forward(&self, from: &str, to: &str) -> bool {
+        self.connectivity.as_ref()
+            .and_then(|m| m.can_forward(from, to))
+            .unwrap_or(true)
+    }
+
+    pub fn set_rotation(&mut self, top_n: Option<usize>) {
+        self.rotation = top_n
+            .filter(|n| *n > 0)
+            .map(|top_n| RoundRobin { top_n, cursor: 0 });
+    }
+
+    /// Picks the relay for the next send: the next entry of the rotation when enabled,
+    /// otherwise the single best healthy relay.
+    pub fn next_relay(&mut self) -> Option<RelayNode> {
+        let candidates = self.healthy_relays();
+        if candidates.is_empty() {
+            return None;
+        }
+
+        match &mut self.rotation {
+            Some(rr) => {
+                let pool = rr.top_n.min(candidates.len());
+                let relay = candidates[rr.cursor % pool].clone();
+                rr.cursor = rr.cursor.wrapping_add(1);
+                Some(relay)
+            }
+            None => candidates.into_iter().next(),
+        }
+    }
+}
+
+/// Extra rules [`RelayCircuit::build_from`] applies when choosing hops.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitConstraint {
+    /// No two hops share a network per [`RelayNode::network_key`], so a single operator
+    /// or datacenter can't see more than one hop. Relays whose network is unknown are
+    /// treated as distinct from every other relay.
+    DistinctNetwork,
+}
+
+/// How [`RelayCircuit::build_from`] orders candidate hops. Strategies ranking by a
+/// measurement skip relays that lack it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CircuitStrategy {
+    LowestLatency,
+    HighestBandwidth,
+    /// Highest [`RelayNode::quality_score`] first, under the discovery's weights.
+    BestQuality,
+    /// Uniformly shuffled; a fixed `seed` makes the choice reproducible.
+    Random { seed: Option<u64> },
 }
 
+/// A [`RelayCircuit`] as saved to disk: hop ids only, so relay details are taken from
+/// discovery when the circuit is loaded again.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedCircuit {
+    pub hops: Vec<String>,
+    pub max_hops: usize,
+    #[serde(default)]
+    pub latency_budget_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
 pub struct RelayCircuit {
     hops: Vec<RelayNode>,
     max_hops: usize,
+    latency_budget_ms: Option<u64>,
 }
 
 impl RelayCircuit {
@@ -69,9 +577,115 @@ impl RelayCircuit {
         Self {
             hops: Vec::new(),
             max_hops,
+            latency_budget_ms: None,
+        }
+    }
+
+    /// Caps end-to-end latency; [`Self::trim_to_budget`] enforces it.
+    pub fn with_latency_budget(mut self, max_ms: u64) -> Self {
+        self.latency_budget_ms = Some(max_ms);
+        self
+    }
+
+    /// Picks `max_hops` distinct healthy relays from `discovery` in the order
+    /// `strategy` prefers, skipping any relay the previous hop can't forward to or
+    /// that would break one of `constraints`. Fails if fewer than `max_hops` relays
+    /// qualify.
+    pub fn build_from(
+        discovery: &RelayDiscovery,
+        strategy: CircuitStrategy,
+        constraints: &[CircuitConstraint],
+        max_hops: usize,
+    ) -> Result<Self> {
+        use rand::seq::SliceRandom;
+        use rand::SeedableRng;
+
+        let mut candidates = discovery.healthy_relays();
+        match strategy {
+            // healthy_relays is already in latency order
+            CircuitStrategy::LowestLatency => candidates.retain(|r| r.latency_ms.is_some()),
+            CircuitStrategy::HighestBandwidth => {
+                candidates.retain(|r| r.bandwidth_mbps.is_some());
+                candidates.sort_by(|a, b| {
+                    b.bandwidth_mbps.cmp(&a.bandwidth_mbps).then_with(|| a.id.cmp(&b.id))
+                });
+            }
+            CircuitStrategy::BestQuality => {
+                candidates.sort_by(|a, b| {
+                    discovery.quality_score(b)
+                        .total_cmp(&discovery.quality_score(a))
+                        .then_with(|| a.id.cmp(&b.id))
+                });
+            }
+            CircuitStrategy::Random { seed: Some(seed) } => {
+                candidates.shuffle(&mut rand::rngs::StdRng::seed_from_u64(seed));
+            }
+            CircuitStrategy::Random { seed: None } => candidates.shuffle(&mut rand::thread_rng()),
+        }
+        if candidates.len() < max_hops {
+            anyhow::bail!("Need {} healthy relays for a circuit, have {}", max_hops, candidates.len());
+        }
+
+        let distinct_network = constraints.contains(&CircuitConstraint::DistinctNetwork);
+        let mut used_networks = HashSet::new();
+        let mut circuit = Self::new(max_hops);
+        while circuit.hops.len() < max_hops {
+            let previous = circuit.hops.last().map(|r| r.id.clone());
+            let forwards = |r: &RelayNode| match &previous {
+                Some(p) => discovery.can_forward(p, &r.id),
+                None => true,
+            };
+            let new_network = |r: &RelayNode| match r.network_key() {
+                Some(network) if distinct_network => !used_networks.contains(&network),
+                _ => true,
+            };
+            let Some(position) = candidates.iter().position(|r| forwards(r) && new_network(r)) else {
+                if distinct_network && candidates.iter().any(forwards) {
+                    anyhow::bail!(
+                        "Only {} of {} hops found in distinct networks; the remaining relays \
+                         share a network with a chosen hop",
+                        circuit.hops.len(),
+                        max_hops
+                    );
+                }
+                anyhow::bail!("No relay combination forwards end to end");
+            };
+            let relay = candidates.remove(position);
+            if let Some(network) = relay.network_key() {
+                used_networks.insert(network);
+            }
+            circuit.add_hop(relay)?;
+        }
+        Ok(circuit)
+    }
+
+    pub fn to_saved(&self) -> SavedCircuit {
+        SavedCircuit {
+            hops: self.hop_ids(),
+            max_hops: self.max_hops,
+            latency_budget_ms: self.latency_budget_ms,
         }
     }
 
+    /// Rebuilds a saved circuit from the relays `discovery` knows now, in the saved
+    /// order. Hops that are unknown or blocked are skipped and returned by id. Fails
+    /// if none of the hops can be resolved.
+    pub fn from_saved(saved: &SavedCircuit, discovery: &RelayDiscovery) -> Result<(Self, Vec<String>)> {
+        let mut circuit = Self::new(saved.max_hops.max(saved.hops.len()));
+        circuit.latency_budget_ms = saved.latency_budget_ms;
+        let mut missing = Vec::new();
+        for id in &saved.hops {
+            match discovery.get_relay(id).filter(|_| !discovery.is_blocked(id)) {
+                Some(relay) => circuit.add_hop(relay.clone())?,
+                None => missing.push(id.clone()),
+            }
+        }
+        if circuit.hops.is_empty() {
+            anyhow::bail!("None of the saved hops ({}) are known relays", saved.hops.join(", "));
+        }
+        Ok((circuit, missing))
+    }
+
     pub fn add_hop(&mut self, relay: RelayNode) -> Result<()> {
         if self.hops.len() >= self.max_hops {
             anyhow::bail!("Circuit already has maximum hops");
@@ -85,9 +699,423 @@ impl RelayCircuit {
         &self.hops
     }
 
+    pub fn hop_ids(&self) -> Vec<String> {
+        self.hops.iter().map(|h| h.id.clone()).collect()
+    }
+
     pub fn total_latency(&self) -> u64 {
         self.hops.iter()
             .filter_map(|h| h.latency_ms)
             .sum()
     }
+
+    /// Drops the slowest hops until [`Self::total_latency`] fits the latency budget,
+    /// keeping the remaining hops in order and at least one of them. Returns the
+    /// dropped hops. Fails if a single hop still exceeds the budget. Hops without a
+    /// latency measurement count as zero, like in `total_latency`.
+    pub fn trim_to_budget(&mut self) -> Result<Vec<RelayNode>> {
+        let Some(budget) = self.latency_budget_ms else {
+            return Ok(Vec::new());
+        };
+
+        let mut dropped = Vec::new();
+        while self.total_latency() > budget && self.hops.len() > 1 {
+            let slowest = self.hops.iter()
+                .enumerate()
+                .max_by_key(|(_, hop)| hop.latency_ms.unwrap_or(0))
+                .map(|(i, _)| i)
+                .expect("circuit has hops");
+            dropped.push(self.hops.remove(slowest));
+        }
+
+        if self.total_latency() > budget {
+            anyhow::bail!(
+                "Circuit latency {}ms exceeds the {}ms budget even with a single hop",
+                self.total_latency(),
+                budget
+            );
+        }
+        if !dropped.is_empty() {
+            tracing::debug!("Trimmed {} hops to fit the {}ms latency budget", dropped.len(), budget);
+        }
+        Ok(dropped)
+    }
+}
+
+#[tauri::command]
+pub async fn set_relay_rotation(
+    top_n: Option<usize>,
+    state: State<'_, Arc<RwLock<RelayDiscovery>>>,
+) -> Result<(), HushError> {
+    state.write().await.set_rotation(top_n);
+
+    tracing::info!("Relay rotation: {:?}", top_n);
+    Ok(())
+}
+
+/// Emits `relays-empty` so the UI can prompt for a directory refresh or a manual relay.
+pub fn notify_if_empty(app: &AppHandle, discovery: &RelayDiscovery) {
+    if discovery.is_empty() {
+        if let Err(e) = app.emit("relays-empty", NO_RELAYS_MESSAGE) {
+            tracing::debug!("Failed to emit relays-empty: {}", e);
+        }
+    }
+}
+
+#[tauri::command]
+pub async fn directory_fingerprint(
+    state: State<'_, Arc<RwLock<RelayDiscovery>>>,
+) -> Result<String, HushError> {
+    Ok(state.read().await.directory_fingerprint())
+}
+
+#[tauri::command]
+pub async fn select_relays(
+    count: usize,
+    min_bandwidth_mbps: Option<u32>,
+    state: State<'_, Arc<RwLock<RelayDiscovery>>>,
+) -> Result<RelaySelection, HushError> {
+    let discovery = state.read().await;
+    discovery.ensure_not_empty().map_err(HushError::from)?;
+    Ok(discovery.select_relays(count, min_bandwidth_mbps))
+}
+
+/// `bootstrap_urls` replaces the HTTPS directories fetched from before refreshing;
+/// `authority_key` (hex or base64 Ed25519) is the key their lists must be signed with
+/// and is required alongside them.
+#[tauri::command]
+pub async fn refresh_relays(
+    bootstrap_urls: Option<Vec<String>>,
+    authority_key: Option<String>,
+    app: AppHandle,
+    state: State<'_, Arc<RwLock<RelayDiscovery>>>,
+) -> Result<usize, HushError> {
+    let mut discovery = state.write().await;
+
+    if let Some(urls) = bootstrap_urls {
+        let authority = authority_key.as_deref()
+            .ok_or_else(|| HushError::InvalidInput("Bootstrap URLs need a directory authority key".to_string()))
+            .and_then(|key| parse_authority_key(key).map_err(|e| HushError::InvalidInput(format!("{:#}", e))))?;
+        discovery.set_bootstrap(&urls, authority).map_err(HushError::from)?;
+    }
+    let result = discovery.refresh().await.map_err(HushError::from);
+    notify_if_empty(&app, &discovery);
+    result
+}
+
+/// Weights of the `best_quality` circuit strategy's score; see
+/// [`RelayNode::quality_score`] for the formula.
+#[tauri::command]
+pub async fn set_quality_weights(
+    weights: QualityWeights,
+    state: State<'_, Arc<RwLock<RelayDiscovery>>>,
+) -> Result<(), HushError> {
+    state.write().await.set_quality_weights(weights).map_err(HushError::from)
+}
+
+#[tauri::command]
+pub async fn get_quality_weights(
+    state: State<'_, Arc<RwLock<RelayDiscovery>>>,
+) -> Result<QualityWeights, HushError> {
+    Ok(state.read().await.quality_weights())
+}
+
+/// `reachable_only` hides relays whose last probe or connect failed.
+#[tauri::command]
+pub async fn list_relays(
+    reachable_only: Option<bool>,
+    state: State<'_, Arc<RwLock<RelayDiscovery>>>,
+) -> Result<Vec<RelayNode>, HushError> {
+    Ok(state.read().await.get_available_relays(reachable_only.unwrap_or(false)))
+}
+
+#[tauri::command]
+pub async fn add_relay(
+    relay: RelayNode,
+    state: State<'_, Arc<RwLock<RelayDiscovery>>>,
+) -> Result<(), HushError> {
+    tracing::info!("Adding relay {} ({})", relay.id, relay.to_relay_info().host_port());
+    state.write().await.add_relay(relay);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn remove_relay(
+    id: String,
+    app: AppHandle,
+    state: State<'_, Arc<RwLock<RelayDiscovery>>>,
+) -> Result<bool, HushError> {
+    let mut discovery = state.write().await;
+
+    let removed = discovery.remove_relay(&id);
+    notify_if_empty(&app, &discovery);
+    Ok(removed)
+}
+
+/// Serves the cached signed directory to peers over the inbound listener. Requires the
+/// inbound listener to be running for peers to reach it.
+#[tauri::command]
+pub async fn set_directory_mirroring(
+    enabled: bool,
+    mirror: State<'_, DirectoryMirror>,
+) -> Result<(), HushError> {
+    mirror.set_enabled(enabled);
+    Ok(())
+}
+
+/// Stores the signed directory exactly as fetched so it can be mirrored to peers.
+#[tauri::command]
+pub async fn cache_signed_directory(
+    blob: Vec<u8>,
+    mirror: State<'_, DirectoryMirror>,
+) -> Result<(), HushError> {
+    if blob.is_empty() {
+        return Err(HushError::InvalidInput("Signed directory is empty".to_string()));
+    }
+
+    tracing::info!("Cached signed directory ({} bytes)", blob.len());
+    mirror.cache(blob);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(id: &str, address: &str) -> RelayNode {
+        RelayNode {
+            id: id.to_string(),
+            address: address.to_string(),
+            port: 4433,
+            public_key: String::new(),
+            latency_ms: None,
+            bandwidth_mbps: None,
+            connect_timeout_ms: None,
+            network: None,
+        }
+    }
+
+    #[test]
+    fn probes_decide_which_relays_are_offered_as_reachable() {
+        let mut discovery = RelayDiscovery::new();
+        discovery.add_relay(node("up", "198.51.100.1"));
+        discovery.add_relay(node("down", "198.51.100.2"));
+
+        discovery.record_probe("up", Some(42));
+        discovery.record_probe("down", None);
+
+        let reachable: Vec<String> = discovery.get_available_relays(true).into_iter().map(|r| r.id).collect();
+        assert!(reachable.contains(&"up".to_string()));
+        assert!(!reachable.contains(&"down".to_string()));
+        assert!(discovery.get_available_relays(false).iter().any(|r| r.id == "down"));
+        assert_eq!(discovery.get_relay("up").unwrap().latency_ms, Some(42));
+        assert!(!discovery.is_reachable("down"));
+
+        discovery.record_probe("down", Some(80));
+        assert!(discovery.is_reachable("down"));
+    }
+
+    fn directory(nodes: impl IntoIterator<Item = RelayNode>) -> RelayDiscovery {
+        let mut discovery = RelayDiscovery::new();
+        for known in discovery.get_available_relays(false) {
+            discovery.remove_relay(&known.id);
+        }
+        for node in nodes {
+            discovery.add_relay(node);
+        }
+        discovery
+    }
+
+    #[test]
+    fn directory_fingerprint_changes_with_any_published_entry() {
+        let nodes = [node("a", "198.51.100.1"), node("b", "198.51.100.2"), node("c", "203.0.113.7")];
+        let ours = directory(nodes.clone());
+        let mut theirs = directory(nodes.iter().rev().cloned());
+        assert_eq!(ours.directory_fingerprint(), theirs.directory_fingerprint());
+
+        // Latency is measured locally, so it differs between users without any tampering
+        theirs.record_probe("b", Some(250));
+        assert_eq!(ours.directory_fingerprint(), theirs.directory_fingerprint());
+
+        let edits: [fn(&mut RelayNode); 6] = [
+            |n| n.address = "192.0.2.66".to_string(),
+            |n| n.port = 443,
+            |n| n.public_key = "ab".repeat(32),
+            |n| n.bandwidth_mbps = Some(10),
+            |n| n.connect_timeout_ms = Some(30_000),
+            |n| n.network = Some("AS64511".to_string()),
+        ];
+        for edit in edits {
+            let mut changed = nodes.clone();
+            edit(&mut changed[1]);
+            assert_ne!(ours.directory_fingerprint(), directory(changed).directory_fingerprint());
+        }
+        let mut extra = nodes.to_vec();
+        extra.push(node("d", "203.0.113.8"));
+        assert_ne!(ours.directory_fingerprint(), directory(extra).directory_fingerprint());
+    }
+
+    #[test]
+    fn bandwidth_floor_is_honored_and_relaxed_when_unsatisfiable() {
+        let measured = |id: &str, latency_ms: u64, bandwidth_mbps: Option<u32>| RelayNode {
+            latency_ms: Some(latency_ms),
+            bandwidth_mbps,
+            ..node(id, "198.51.100.1")
+        };
+        let discovery = directory([
+            measured("thin", 10, Some(5)),
+            measured("unknown", 20, None),
+            measured("wide", 30, Some(100)),
+            measured("widest", 40, Some(1000)),
+        ]);
+        let ids = |selection: &RelaySelection| selection.relays.iter().map(|r| r.id.clone()).collect::<Vec<_>>();
+
+        let selection = discovery.select_relays(3, Some(50));
+        assert_eq!(ids(&selection), ["wide", "widest"]);
+        assert!(!selection.floor_relaxed);
+        assert_eq!(ids(&discovery.select_relays(1, Some(50))), ["wide"]);
+
+        let selection = discovery.select_relays(3, Some(10_000));
+        assert_eq!(ids(&selection), ["thin", "unknown", "wide"]);
+        assert!(selection.floor_relaxed);
+
+        let selection = discovery.select_relays(3, None);
+        assert_eq!(ids(&selection), ["thin", "unknown", "wide"]);
+        assert!(!selection.floor_relaxed);
+    }
+
+    #[test]
+    fn latency_budget_drops_the_slowest_hops_in_order() {
+        let timed = |id: &str, latency_ms: u64| RelayNode { latency_ms: Some(latency_ms), ..node(id, "198.51.100.1") };
+        let circuit = |budget_ms: u64, latencies: &[(&str, u64)]| {
+            let mut circuit = RelayCircuit::new(latencies.len()).with_latency_budget(budget_ms);
+            for (id, latency_ms) in latencies {
+                circuit.add_hop(timed(id, *latency_ms)).unwrap();
+            }
+            circuit
+        };
+
+        let mut trimmed = circuit(100, &[("a", 30), ("b", 60), ("c", 20), ("d", 40)]);
+        let dropped = trimmed.trim_to_budget().unwrap();
+        assert_eq!(dropped.iter().map(|h| h.id.as_str()).collect::<Vec<_>>(), ["b"]);
+        assert_eq!(trimmed.hop_ids(), ["a", "c", "d"]);
+        assert_eq!(trimmed.total_latency(), 90);
+
+        // Trimming stops at one hop, which may use up the whole budget
+        let mut single = circuit(50, &[("a", 50), ("b", 45), ("c", 40)]);
+        assert_eq!(single.trim_to_budget().unwrap().len(), 2);
+        assert_eq!(single.hop_ids(), ["c"]);
+
+        let mut over = circuit(50, &[("a", 80), ("b", 70)]);
+        assert!(over.trim_to_budget().is_err());
+        assert_eq!(over.hop_ids(), ["b"]);
+
+        let mut unbounded = RelayCircuit::new(1);
+        unbounded.add_hop(timed("a", 5_000)).unwrap();
+        assert!(unbounded.trim_to_budget().unwrap().is_empty());
+    }
+
+    #[test]
+    fn distinct_network_circuits_take_one_hop_per_subnet() {
+        let timed = |id: &str, address: &str, latency_ms: u64| RelayNode { latency_ms: Some(latency_ms), ..node(id, address) };
+        let discovery = directory([
+            timed("a", "198.51.100.1", 10),
+            timed("b", "198.51.100.2", 20),
+            timed("c", "203.0.113.1", 30),
+            timed("d", "203.0.113.2", 40),
+        ]);
+        let build = |constraints: &[CircuitConstraint], hops| {
+            RelayCircuit::build_from(&discovery, CircuitStrategy::LowestLatency, constraints, hops)
+        };
+
+        assert_eq!(build(&[], 2).unwrap().hop_ids(), ["a", "b"]);
+        let circuit = build(&[CircuitConstraint::DistinctNetwork], 2).unwrap();
+        assert_eq!(circuit.hop_ids(), ["a", "c"]);
+        let networks: HashSet<_> = circuit.get_hops().iter().filter_map(|h| h.network_key()).collect();
+        assert_eq!(networks.len(), 2);
+
+        // Two subnets can't hold three distinct hops, though enough relays exist
+        let error = build(&[CircuitConstraint::DistinctNetwork], 3).unwrap_err();
+        assert!(error.to_string().contains("distinct networks"), "{:#}", error);
+        assert_eq!(build(&[], 3).unwrap().hop_ids(), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn build_from_orders_hops_by_strategy_without_repeats() {
+        let measured = |id: &str, latency_ms: Option<u64>, bandwidth_mbps: Option<u32>| RelayNode {
+            latency_ms,
+            bandwidth_mbps,
+            ..node(id, "198.51.100.1")
+        };
+        let discovery = directory([
+            measured("a", Some(30), Some(10)),
+            measured("b", Some(10), Some(100)),
+            measured("c", Some(20), Some(50)),
+            measured("d", None, None),
+            measured("e", Some(40), Some(100)),
+        ]);
+        let build = |strategy, hops| RelayCircuit::build_from(&discovery, strategy, &[], hops);
+
+        // Ties on bandwidth fall back to the id; a relay without bandwidth is skipped
+        let fastest = build(CircuitStrategy::HighestBandwidth, 4).unwrap();
+        assert_eq!(fastest.hop_ids(), ["b", "e", "c", "a"]);
+        let error = build(CircuitStrategy::HighestBandwidth, 5).unwrap_err();
+        assert!(error.to_string().contains("Need 5 healthy relays"), "{:#}", error);
+        assert!(build(CircuitStrategy::Random { seed: None }, 6).is_err());
+
+        // A seed fixes the order; different seeds explore different ones
+        let seeded = |seed| build(CircuitStrategy::Random { seed: Some(seed) }, 5).unwrap().hop_ids();
+        assert_eq!(seeded(7), seeded(7));
+        let orders: HashSet<Vec<String>> = (0..16).map(seeded).collect();
+        assert!(orders.len() > 1, "every seed gave {:?}", orders);
+
+        for strategy in [
+            CircuitStrategy::LowestLatency,
+            CircuitStrategy::HighestBandwidth,
+            CircuitStrategy::BestQuality,
+            CircuitStrategy::Random { seed: Some(3) },
+        ] {
+            let hops = build(strategy, 4).unwrap().hop_ids();
+            let distinct: HashSet<&String> = hops.iter().collect();
+            assert_eq!(distinct.len(), 4, "{:?} repeated a hop: {:?}", strategy, hops);
+        }
+    }
+
+    #[test]
+    fn best_quality_ranks_by_the_weighted_score() {
+        let latency_only = QualityWeights { latency: 1.0, bandwidth: 0.0, reliability: 0.0 };
+        let timed = |ms| RelayNode { latency_ms: Some(ms), ..node("x", "198.51.100.1") };
+        assert_eq!(timed(100).quality_score(None, &latency_only), 0.5);
+        assert!(timed(20).quality_score(None, &latency_only) > timed(200).quality_score(None, &latency_only));
+        assert_eq!(node("x", "198.51.100.1").quality_score(None, &QualityWeights::default()), 0.5);
+
+        let measured = |id: &str, latency_ms: u64, bandwidth_mbps: u32| RelayNode {
+            latency_ms: Some(latency_ms),
+            bandwidth_mbps: Some(bandwidth_mbps),
+            ..node(id, "198.51.100.1")
+        };
+        let mut discovery = directory([
+            measured("quick", 20, 50),
+            measured("wide", 80, 400),
+            measured("slow", 300, 10),
+        ]);
+        let best = |discovery: &RelayDiscovery| {
+            RelayCircuit::build_from(discovery, CircuitStrategy::BestQuality, &[], 3).unwrap().hop_ids()
+        };
+        assert_eq!(best(&discovery), ["quick", "wide", "slow"]);
+
+        // Failed connects sink a relay below ones that keep succeeding
+        for _ in 0..5 {
+            discovery.record_connect("quick", false);
+            discovery.record_connect("wide", true);
+            discovery.record_connect("slow", true);
+        }
+        assert_eq!(discovery.success_rate("quick"), Some(0.0));
+        assert_eq!(best(&discovery), ["wide", "slow", "quick"]);
+
+        // With only bandwidth weighted the history no longer matters
+        discovery.set_quality_weights(QualityWeights { latency: 0.0, bandwidth: 1.0, reliability: 0.0 }).unwrap();
+        assert_eq!(best(&discovery), ["wide", "quick", "slow"]);
+        assert!(discovery.set_quality_weights(QualityWeights { latency: 0.0, bandwidth: 0.0, reliability: 0.0 }).is_err());
+    }
 }
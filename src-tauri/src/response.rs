@@ -0,0 +1,129 @@
+use quinn::{ReadExactError, RecvStream};
+use std::fmt;
+use std::time::Duration;
+
+/// Relay responses are framed as `[u32 big-endian body length][body]`, so a response
+/// cut short by the relay closing its stream can be told apart from a complete one.
+pub const RESPONSE_HEADER_LEN: usize = 4;
+
+/// Largest response body accepted; a declared length above this is rejected before
+/// anything is allocated.
+pub const MAX_RESPONSE_LEN: usize = 1024 * 1024;
+
+#[derive(Debug)]
+pub enum ResponseError {
+    /// The stream finished before the declared length arrived.
+    TruncatedResponse { expected: usize, received: usize },
+    /// The header declared a body larger than [`MAX_RESPONSE_LEN`].
+    TooLarge { declared: usize },
+    TimedOut,
+    Read(quinn::ReadError),
+}
+
+impl fmt::Display for ResponseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TruncatedResponse { expected, received } => write!(
+                f,
+                "Truncated response: relay declared {} bytes but closed the stream after {}",
+                expected, received
+            ),
+            Self::TooLarge { declared } => write!(
+                f,
+                "Response of {} bytes exceeds the {} byte limit",
+                declared, MAX_RESPONSE_LEN
+            ),
+            Self::TimedOut => write!(f, "Timed out waiting for relay response"),
+            Self::Read(e) => write!(f, "Failed to read relay response: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ResponseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Read(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Reads one framed response. A stream that ends early, in the header or the body,
+/// yields [`ResponseError::TruncatedResponse`] with the byte counts, never a partial body.
+pub async fn read_framed(recv: &mut RecvStream, timeout: Duration) -> Result<Vec<u8>, ResponseError> {
+    tokio::time::timeout(timeout, read_framed_inner(recv))
+        .await
+        .map_err(|_| ResponseError::TimedOut)?
+}
+
+async fn read_framed_inner(recv: &mut RecvStream) -> Result<Vec<u8>, ResponseError> {
+    let mut header = [0u8; RESPONSE_HEADER_LEN];
+    recv.read_exact(&mut header).await.map_err(|e| match e {
+        ReadExactError::FinishedEarly(received) => ResponseError::TruncatedResponse {
+            expected: RESPONSE_HEADER_LEN,
+            received,
+        },
+        ReadExactError::ReadError(e) => ResponseError::Read(e),
+    })?;
+
+    let declared = u32::from_be_bytes(header) as usize;
+    if declared > MAX_RESPONSE_LEN {
+        return Err(ResponseError::TooLarge { declared });
+    }
+
+    let mut body = vec![0u8; declared];
+    recv.read_exact(&mut body).await.map_err(|e| match e {
+        ReadExactError::FinishedEarly(received) => ResponseError::TruncatedResponse {
+            expected: declared,
+            received,
+        },
+        ReadExactError::ReadError(e) => ResponseError::Read(e),
+    })?;
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quic_transport::QuicTransport;
+    use crate::send_queue::Priority;
+    use crate::test_relay::TestRelay;
+
+    /// Relay answering each request with the raw bytes its request names.
+    fn answering_relay() -> TestRelay {
+        TestRelay::serve(Duration::ZERO, |connection: quinn::Connection| async move {
+            while let Ok((mut send, mut recv)) = connection.accept_bi().await {
+                let Ok(request) = recv.read_to_end(64).await else { break };
+                let declared = 100u32.to_be_bytes();
+                let answer: Vec<u8> = match request.as_slice() {
+                    b"complete" => [&declared[..], &[7; 100]].concat(),
+                    b"short body" => [&declared[..], &[7; 40]].concat(),
+                    _ => declared[..2].to_vec(),
+                };
+                let _ = send.write_all(&answer).await;
+                let _ = send.finish();
+            }
+        })
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn response_cut_short_is_reported_as_truncated() {
+        let relay = answering_relay();
+        let mut transport = QuicTransport::new();
+        relay.pin(&mut transport);
+        transport.connect(relay.relay_info().unwrap()).await.unwrap();
+
+        assert_eq!(transport.send_recv(b"complete", Priority::Normal).await.unwrap(), [7; 100]);
+        for (request, expected, received) in [(&b"short body"[..], 100, 40), (b"short header", RESPONSE_HEADER_LEN, 2)] {
+            let error = transport.send_recv(request, Priority::Normal).await.unwrap_err();
+            match error.downcast_ref::<ResponseError>() {
+                Some(ResponseError::TruncatedResponse { expected: e, received: r }) => {
+                    assert_eq!((*e, *r), (expected, received));
+                }
+                _ => panic!("expected a truncated response, got {:#}", error),
+            }
+        }
+        relay.stop();
+    }
+}
@@ -0,0 +1,227 @@
+use anyhow::{Context, Result};
+use quinn::Connection;
+use serde::Serialize;
+use std::time::Duration;
+
+/// Asks the relay how many bytes of a transfer it already holds:
+/// `[FRAME_RESUME_QUERY][16 bytes transfer id]`, answered with a big-endian u64 offset.
+pub const FRAME_RESUME_QUERY: u8 = 0x31;
+
+/// Carries a slice of a transfer: `[FRAME_RESUMABLE_DATA][16 bytes transfer id]
+/// [u64 offset][u64 total][bytes from offset]`, answered with the relay's new u64 offset.
+pub const FRAME_RESUMABLE_DATA: u8 = 0x30;
+
+/// Bytes written between progress checks, so a large transfer notices a dead
+/// connection without waiting for the whole payload to be buffered.
+const CHECKPOINT_INTERVAL: usize = 64 * 1024;
+
+pub type TransferId = [u8; 16];
+
+/// A large send and how much of it the relay has acknowledged. Kept until the relay
+/// acknowledges every byte, so an interrupted transfer resumes from `acked` instead of
+/// restarting.
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    pub id: TransferId,
+    pub data: Vec<u8>,
+    pub acked: u64,
+    attempted: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TransferProgress {
+    pub transfer_id: String,
+    pub acked_bytes: u64,
+    pub total_bytes: u64,
+    pub resumed_from: u64,
+    pub complete: bool,
+}
+
+impl Checkpoint {
+    pub fn new(data: Vec<u8>) -> Self {
+        Self {
+            id: *uuid::Uuid::new_v4().as_bytes(),
+            data,
+            acked: 0,
+            attempted: false,
+        }
+    }
+
+    pub fn transfer_id(&self) -> String {
+        uuid::Uuid::from_bytes(self.id).to_string()
+    }
+
+    pub fn total(&self) -> u64 {
+        self.data.len() as u64
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.acked >= self.total()
+    }
+
+    fn progress(&self, resumed_from: u64) -> TransferProgress {
+        TransferProgress {
+            transfer_id: self.transfer_id(),
+            acked_bytes: self.acked,
+            total_bytes: self.total(),
+            resumed_from,
+            complete: self.is_complete(),
+        }
+    }
+}
+
+/// Sends whatever the relay is missing of `checkpoint` and advances `acked` to the
+/// relay's answer. For a transfer that was interrupted before, the relay is asked for
+/// its offset first, since it may have stored bytes whose ack never reached us.
+pub async fn push(
+    connection: &Connection,
+    checkpoint: &mut Checkpoint,
+    ack_timeout: Duration,
+) -> Result<TransferProgress> {
+    if checkpoint.attempted {
+        checkpoint.acked = query_offset(connection, &checkpoint.id, ack_timeout).await?
+            .min(checkpoint.total());
+    }
+    let resumed_from = checkpoint.acked;
+    checkpoint.attempted = true;
+
+    let (mut send, mut recv) = connection.open_bi().await
+        .context("Failed to open resumable stream")?;
+
+    let mut header = Vec::with_capacity(1 + 16 + 8 + 8);
+    header.push(FRAME_RESUMABLE_DATA);
+    header.extend_from_slice(&checkpoint.id);
+    header.extend_from_slice(&resumed_from.to_be_bytes());
+    header.extend_from_slice(&checkpoint.total().to_be_bytes());
+    send.write_all(&header).await.context("Failed to send transfer header")?;
+
+    for chunk in checkpoint.data[resumed_from as usize..].chunks(CHECKPOINT_INTERVAL) {
+        send.write_all(chunk).await.context("Transfer interrupted")?;
+    }
+    send.finish().context("Failed to finish resumable stream")?;
+
+    checkpoint.acked = read_offset(&mut recv, ack_timeout).await?.min(checkpoint.total());
+    Ok(checkpoint.progress(resumed_from))
+}
+
+async fn query_offset(connection: &Connection, id: &TransferId, ack_timeout: Duration) -> Result<u64> {
+    let (mut send, mut recv) = connection.open_bi().await
+        .context("Failed to open resume query stream")?;
+
+    let mut frame = Vec::with_capacity(1 + 16);
+    frame.push(FRAME_RESUME_QUERY);
+    frame.extend_from_slice(id);
+    send.write_all(&frame).await.context("Failed to send resume query")?;
+    send.finish().context("Failed to finish resume query")?;
+
+    read_offset(&mut recv, ack_timeout).await
+}
+
+async fn read_offset(recv: &mut quinn::RecvStream, ack_timeout: Duration) -> Result<u64> {
+    let mut offset = [0u8; 8];
+    tokio::time::timeout(ack_timeout, recv.read_exact(&mut offset))
+        .await
+        .context("Timed out waiting for transfer ack")?
+        .context("Failed to read transfer ack")?;
+    Ok(u64::from_be_bytes(offset))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::close_codes::AppCloseCode;
+    use crate::quic_transport::QuicTransport;
+    use crate::test_relay::TestRelay;
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    type Stored = Arc<Mutex<HashMap<TransferId, Vec<u8>>>>;
+
+    /// Relay speaking the resumable protocol that stores transfers in `stored` and
+    /// records the offset of every data frame in `offsets`. While `interrupt` is set it
+    /// drops the connection after `cut_at` bytes of the first transfer, without
+    /// acknowledging them.
+    async fn resumable_relay(
+        connection: Connection,
+        stored: Stored,
+        offsets: Arc<Mutex<Vec<usize>>>,
+        interrupt: Arc<AtomicBool>,
+        cut_at: usize,
+    ) {
+        while let Ok((mut send, mut recv)) = connection.accept_bi().await {
+            let mut kind = [0u8; 1];
+            let mut id = [0u8; 16];
+            if recv.read_exact(&mut kind).await.is_err() || recv.read_exact(&mut id).await.is_err() {
+                return;
+            }
+            if kind[0] == FRAME_RESUME_QUERY {
+                let held = stored.lock().unwrap().get(&id).map_or(0, Vec::len) as u64;
+                let _ = send.write_all(&held.to_be_bytes()).await;
+                let _ = send.finish();
+                continue;
+            }
+
+            let mut header = [0u8; 16];
+            if recv.read_exact(&mut header).await.is_err() {
+                return;
+            }
+            let offset = u64::from_be_bytes(header[..8].try_into().unwrap()) as usize;
+            offsets.lock().unwrap().push(offset);
+            if interrupt.swap(false, Ordering::SeqCst) {
+                let mut partial = vec![0u8; cut_at];
+                if recv.read_exact(&mut partial).await.is_ok() {
+                    stored.lock().unwrap().insert(id, partial);
+                }
+                AppCloseCode::Error.close(&connection);
+                return;
+            }
+
+            let Ok(rest) = recv.read_to_end(usize::MAX).await else {
+                return;
+            };
+            let held = {
+                let mut stored = stored.lock().unwrap();
+                let bytes = stored.entry(id).or_default();
+                bytes.truncate(offset);
+                bytes.extend_from_slice(&rest);
+                bytes.len() as u64
+            };
+            let _ = send.write_all(&held.to_be_bytes()).await;
+            let _ = send.finish();
+        }
+    }
+
+    #[tokio::test]
+    async fn interrupted_transfer_resumes_from_the_relay_offset_after_reconnect() {
+        const CUT_AT: usize = 100_000;
+        let stored: Stored = Arc::default();
+        let offsets: Arc<Mutex<Vec<usize>>> = Arc::default();
+        let interrupt = Arc::new(AtomicBool::new(true));
+        let relay = TestRelay::serve(Duration::ZERO, {
+            let (stored, offsets, interrupt) = (stored.clone(), offsets.clone(), interrupt.clone());
+            move |connection| {
+                resumable_relay(connection, stored.clone(), offsets.clone(), interrupt.clone(), CUT_AT)
+            }
+        })
+        .unwrap();
+
+        let mut transport = QuicTransport::new();
+        relay.pin(&mut transport);
+        transport.connect(relay.relay_info().unwrap()).await.unwrap();
+
+        let data: Vec<u8> = (0..300_000u32).map(|i| i as u8).collect();
+        assert!(transport.send_resumable(data.clone()).await.is_err());
+        assert_eq!(stored.lock().unwrap().values().next().map(Vec::len), Some(CUT_AT));
+
+        // Reconnecting resumes the transfer from the relay's offset
+        transport.connect(relay.relay_info().unwrap()).await.unwrap();
+        assert!(transport.resume_transfers().await.is_empty(), "transfer left unfinished");
+        assert_eq!(*offsets.lock().unwrap(), [0, CUT_AT]);
+
+        let stored = stored.lock().unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored.values().next().unwrap(), &data);
+        relay.stop();
+    }
+}
@@ -0,0 +1,384 @@
+use serde::Serialize;
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+
+use crate::error::HushError;
+
+pub const DEFAULT_RETRY_BUDGET: u32 = 6;
+
+/// Longest wait between two attempts of `send_via_quic_retry`, however many failed.
+pub const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Exponential backoff: `initial`, doubled after every failure up to `max`.
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    next: Duration,
+    attempts: u32,
+}
+
+impl Backoff {
+    pub fn new(initial: Duration, max: Duration) -> Self {
+        Self {
+            initial,
+            max,
+            next: initial,
+            attempts: 0,
+        }
+    }
+
+    /// Delay before the next attempt; advances the schedule.
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self.next;
+        self.next = (self.next * 2).min(self.max);
+        self.attempts += 1;
+        delay
+    }
+
+    /// Attempts handed out since the last reset.
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    pub fn reset(&mut self) {
+        self.next = self.initial;
+        self.attempts = 0;
+    }
+}
+
+/// Which layer spent an attempt from the budget.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RetryMechanism {
+    Initial,
+    Reconnect,
+    Fallback,
+    MultiPath,
+    Failover,
+    /// Same send repeated after a backoff delay.
+    Retry,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Attempt {
+    pub mechanism: RetryMechanism,
+    pub relay: Option<String>,
+    pub error: Option<String>,
+}
+
+/// Caps the total number of attempts one logical send may make across every retry
+/// mechanism (reconnect, fallback, multi-path, failover), so nested retry loops
+/// can't multiply into an attempt storm.
+#[derive(Debug, Clone)]
+pub struct RetryBudget {
+    total: u32,
+    attempts: Vec<Attempt>,
+}
+
+impl RetryBudget {
+    pub fn new(total: u32) -> Self {
+        Self {
+            total,
+            attempts: Vec::new(),
+        }
+    }
+
+    pub fn remaining(&self) -> u32 {
+        self.total.saturating_sub(self.attempts.len() as u32)
+    }
+
+    /// Records the start of an attempt. Returns false, without recording, when the
+    /// budget is spent.
+    pub fn try_begin(&mut self, mechanism: RetryMechanism, relay: Option<&str>) -> bool {
+        if self.remaining() == 0 {
+            return false;
+        }
+        self.attempts.push(Attempt {
+            mechanism,
+            relay: relay.map(str::to_string),
+            error: None,
+        });
+        true
+    }
+
+    /// Attaches an error to the most recent attempt.
+    pub fn record_failure(&mut self, error: &anyhow::Error) {
+        if let Some(last) = self.attempts.last_mut() {
+            last.error = Some(format!("{:#}", error));
+        }
+    }
+
+    pub fn into_error(self) -> RetryExhausted {
+        RetryExhausted {
+            total: self.total,
+            attempts: self.attempts,
+        }
+    }
+}
+
+/// Aggregated failure once a send has used up its retry budget.
+#[derive(Debug, Clone, Serialize)]
+pub struct RetryExhausted {
+    pub total: u32,
+    pub attempts: Vec<Attempt>,
+}
+
+impl fmt::Display for RetryExhausted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Send failed after {} of {} attempts", self.attempts.len(), self.total)?;
+        for attempt in &self.attempts {
+            write!(
+                f,
+                "; {:?} via {}: {}",
+                attempt.mechanism,
+                attempt.relay.as_deref().unwrap_or("-"),
+                attempt.error.as_deref().unwrap_or("no error recorded"),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for RetryExhausted {}
+
+/// Whether `error` is worth retrying: the connection or stream went away, or a step
+/// timed out. Errors the same input would hit again (invalid input or address, a
+/// blocked relay, a certificate that fails verification, a datagram too large, a
+/// protocol violation) and errors that are not recognised are permanent.
+pub fn is_transient(error: &anyhow::Error) -> bool {
+    for cause in error.chain() {
+        if let Some(e) = cause.downcast_ref::<HushError>() {
+            return matches!(
+                e,
+                HushError::NotConnected | HushError::QuicConnect(_) | HushError::AckTimeout
+            );
+        }
+        if let Some(e) = cause.downcast_ref::<quinn::ConnectionError>() {
+            return is_transient_connection_error(e);
+        }
+        if let Some(e) = cause.downcast_ref::<quinn::WriteError>() {
+            return match e {
+                quinn::WriteError::ConnectionLost(e) => is_transient_connection_error(e),
+                _ => true,
+            };
+        }
+        if let Some(e) = cause.downcast_ref::<quinn::SendDatagramError>() {
+            return match e {
+                quinn::SendDatagramError::ConnectionLost(e) => is_transient_connection_error(e),
+                _ => false,
+            };
+        }
+        if cause.is::<quinn::ClosedStream>()
+            || cause.is::<quinn::ReadError>()
+            || cause.is::<quinn::ReadToEndError>()
+            || cause.is::<quinn::ReadExactError>()
+            || cause.is::<tokio::time::error::Elapsed>()
+        {
+            return true;
+        }
+        if let Some(e) = cause.downcast_ref::<std::io::Error>() {
+            return is_transient_io_error(e);
+        }
+    }
+    false
+}
+
+/// Runs `attempt` until it succeeds, fails with an error [`is_transient`] rejects, or
+/// `max_attempts` are spent. Waits `base_delay` before the first retry and doubles the
+/// wait after each one, up to [`MAX_RETRY_DELAY`]. Returns the result with the number
+/// of attempts made; once every attempt failed, the error is a [`RetryExhausted`]
+/// listing them.
+pub async fn with_retries<T, F, Fut>(
+    max_attempts: u32,
+    base_delay: Duration,
+    relay: Option<&str>,
+    mut attempt: F,
+) -> anyhow::Result<(T, u32)>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = anyhow::Result<T>>,
+{
+    let mut backoff = Backoff::new(base_delay.min(MAX_RETRY_DELAY), MAX_RETRY_DELAY);
+    let mut budget = RetryBudget::new(max_attempts);
+    let mut mechanism = RetryMechanism::Initial;
+
+    loop {
+        if !budget.try_begin(mechanism, relay) {
+            return Err(budget.into_error().into());
+        }
+        let attempts = max_attempts - budget.remaining();
+
+        match attempt().await {
+            Ok(value) => return Ok((value, attempts)),
+            Err(e) if !is_transient(&e) => return Err(e),
+            Err(e) => {
+                budget.record_failure(&e);
+                if budget.remaining() == 0 {
+                    continue;
+                }
+                let delay = backoff.next_delay();
+                tracing::debug!("Attempt {} failed, retrying in {:?}: {:#}", attempts, delay, e);
+                tokio::time::sleep(delay).await;
+            }
+        }
+        mechanism = RetryMechanism::Retry;
+    }
+}
+
+fn is_transient_connection_error(error: &quinn::ConnectionError) -> bool {
+    use quinn::ConnectionError;
+
+    match error {
+        ConnectionError::Reset
+        | ConnectionError::TimedOut
+        | ConnectionError::ConnectionClosed(_)
+        | ConnectionError::ApplicationClosed(_) => true,
+        // Version mismatch, transport errors, our own close
+        _ => false,
+    }
+}
+
+/// Socket errors where the network or the peer dropped out. Anything else, such as a
+/// refused permission or an address already in use, fails the same way next time.
+fn is_transient_io_error(error: &std::io::Error) -> bool {
+    use std::io::ErrorKind;
+
+    matches!(
+        error.kind(),
+        ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::ConnectionRefused
+            | ErrorKind::NotConnected
+            | ErrorKind::BrokenPipe
+            | ErrorKind::TimedOut
+            | ErrorKind::Interrupted
+            | ErrorKind::WouldBlock
+            | ErrorKind::UnexpectedEof
+            | ErrorKind::NetworkUnreachable
+            | ErrorKind::HostUnreachable
+            | ErrorKind::NetworkDown
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quic_transport::{FinishMode, QuicTransport};
+    use crate::send_queue::Priority;
+    use crate::shared_state::SharedState;
+    use crate::test_relay::TestRelay;
+    use anyhow::Context;
+    use std::sync::Arc;
+    use std::time::Instant;
+
+    #[test]
+    fn errors_are_classified_transient_or_permanent() {
+        let transient: Vec<anyhow::Error> = vec![
+            HushError::NotConnected.into(),
+            HushError::QuicConnect("handshake timed out".to_string()).into(),
+            HushError::AckTimeout.into(),
+            quinn::ConnectionError::Reset.into(),
+            quinn::ConnectionError::TimedOut.into(),
+            quinn::WriteError::Stopped(quinn::VarInt::from_u32(0)).into(),
+            quinn::WriteError::ConnectionLost(quinn::ConnectionError::Reset).into(),
+            std::io::Error::from(std::io::ErrorKind::ConnectionReset).into(),
+            std::io::Error::from(std::io::ErrorKind::TimedOut).into(),
+            Err::<(), _>(quinn::ConnectionError::Reset).context("Failed to open QUIC stream").unwrap_err(),
+        ];
+        for error in &transient {
+            assert!(is_transient(error), "should retry: {:#}", error);
+        }
+
+        let permanent: Vec<anyhow::Error> = vec![
+            HushError::InvalidInput("payload too large".to_string()).into(),
+            HushError::InvalidAddress("nowhere".to_string()).into(),
+            HushError::RelayBlocked("relay-1".to_string()).into(),
+            HushError::CertVerification("fingerprint does not match".to_string()).into(),
+            quinn::ConnectionError::VersionMismatch.into(),
+            quinn::ConnectionError::LocallyClosed.into(),
+            quinn::SendDatagramError::TooLarge.into(),
+            quinn::WriteError::ConnectionLost(quinn::ConnectionError::VersionMismatch).into(),
+            std::io::Error::from(std::io::ErrorKind::PermissionDenied).into(),
+            std::io::Error::from(std::io::ErrorKind::AddrInUse).into(),
+            anyhow::anyhow!("something unrecognised"),
+        ];
+        for error in &permanent {
+            assert!(!is_transient(error), "should not retry: {:#}", error);
+        }
+    }
+
+    #[tokio::test]
+    async fn transient_failures_are_retried_and_permanent_ones_are_not() {
+        // Two resets, then through on the third attempt
+        let mut calls = 0;
+        let (value, attempts) = with_retries(5, Duration::from_millis(10), None, || {
+            calls += 1;
+            let call = calls;
+            async move {
+                if call < 3 {
+                    Err(quinn::ConnectionError::Reset.into())
+                } else {
+                    Ok(call)
+                }
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!((value, attempts, calls), (3, 3, 3));
+
+        // Resets every time: all attempts spent, each listed, with growing waits
+        let started = Instant::now();
+        let mut calls = 0;
+        let error = with_retries(4, Duration::from_millis(20), Some("relay-1"), || {
+            calls += 1;
+            async { Err::<(), _>(anyhow::Error::from(quinn::ConnectionError::Reset)) }
+        })
+        .await
+        .unwrap_err();
+        assert_eq!(calls, 4);
+        assert!(started.elapsed() >= Duration::from_millis(20 + 40 + 80));
+        let exhausted = error.downcast_ref::<RetryExhausted>().unwrap();
+        assert_eq!(exhausted.attempts.len(), 4);
+        assert_eq!(exhausted.attempts[0].mechanism, RetryMechanism::Initial);
+        assert!(exhausted.attempts[1..].iter().all(|a| a.mechanism == RetryMechanism::Retry));
+        assert!(exhausted.attempts.iter().all(|a| a.relay.as_deref() == Some("relay-1") && a.error.is_some()));
+
+        // Invalid input fails the same way every time, so it is returned after one try
+        let mut calls = 0;
+        let error = with_retries(5, Duration::from_millis(10), None, || {
+            calls += 1;
+            async { Err::<(), _>(HushError::InvalidInput("payload too large".to_string()).into()) }
+        })
+        .await
+        .unwrap_err();
+        assert_eq!(calls, 1);
+        assert!(matches!(error.downcast_ref(), Some(HushError::InvalidInput(_))), "{:#}", error);
+    }
+
+    #[tokio::test]
+    async fn retried_send_goes_through_once_the_relay_is_reached() {
+        let relay = TestRelay::start().unwrap();
+        let mut transport = QuicTransport::new();
+        relay.pin(&mut transport);
+        let transport = Arc::new(SharedState::new(transport));
+
+        // Retries release the lock, so a reconnect can land while the send waits
+        let connecting = transport.clone();
+        let info = relay.relay_info().unwrap();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(150)).await;
+            connecting.write().await.unwrap().connect(info).await.unwrap();
+        });
+
+        let (_, attempts) = with_retries(6, Duration::from_millis(100), None, || async {
+            let transport = transport.read().await?;
+            transport.send(b"retried", FinishMode::Finish, Priority::Normal).await
+        })
+        .await
+        .unwrap();
+        assert!((2..=4).contains(&attempts), "{} attempts", attempts);
+        relay.stop();
+    }
+}
@@ -0,0 +1,168 @@
+use serde::Serialize;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tauri::{AppHandle, Manager, State};
+use tokio::sync::RwLock;
+
+use crate::cert_pins;
+use crate::error::HushError;
+use crate::quic_transport::QuicTransport;
+use crate::relay_client::RelayDiscovery;
+use crate::shared_state::SharedState;
+use crate::taior_bridge::{TaiorConfig, TaiorState};
+
+/// How long one bootstrap node may take to resolve.
+const RESOLVE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SelfTestCheck {
+    pub name: String,
+    pub passed: bool,
+    pub message: String,
+}
+
+impl SelfTestCheck {
+    fn pass(name: impl Into<String>, message: impl Into<String>) -> Self {
+        Self { name: name.into(), passed: true, message: message.into() }
+    }
+
+    fn fail(name: impl Into<String>, message: impl Into<String>) -> Self {
+        Self { name: name.into(), passed: false, message: message.into() }
+    }
+}
+
+/// Result of [`run_self_test`]; `passed` only if every check did.
+#[derive(Debug, Clone, Serialize)]
+pub struct SelfTestReport {
+    pub passed: bool,
+    pub checks: Vec<SelfTestCheck>,
+}
+
+/// The pin file parses, and every relay that publishes a public key has one that decodes.
+fn check_pins(pin_file: &Path, discovery: &RelayDiscovery) -> Vec<SelfTestCheck> {
+    let mut checks = Vec::new();
+    if pin_file.exists() {
+        let parsed = std::fs::read_to_string(pin_file)
+            .map_err(anyhow::Error::from)
+            .and_then(|text| cert_pins::parse_pin_file(&text));
+        checks.push(match parsed {
+            Ok(entries) => SelfTestCheck::pass("pin_file", format!("{} pins", entries.len())),
+            Err(e) => SelfTestCheck::fail("pin_file", format!("{:#}", e)),
+        });
+    } else {
+        checks.push(SelfTestCheck::pass("pin_file", "No pin file; relays are verified by public key"));
+    }
+
+    for relay in discovery.get_available_relays(false) {
+        if relay.public_key.trim().is_empty() {
+            continue;
+        }
+        if let Err(e) = cert_pins::parse_public_key(&relay.public_key) {
+            checks.push(SelfTestCheck::fail(format!("relay_key:{}", relay.id), format!("{:#}", e)));
+        }
+    }
+    checks
+}
+
+/// Host and port to resolve for a bootstrap node in `host:port` or multiaddr form.
+fn bootstrap_target(node: &str) -> Option<(String, u16)> {
+    if let Some(multiaddr) = node.strip_prefix('/') {
+        let parts: Vec<&str> = multiaddr.split('/').collect();
+        let host = parts.get(1)?.to_string();
+        let port = parts.windows(2)
+            .find(|pair| pair[0] == "udp" || pair[0] == "tcp")
+            .and_then(|pair| pair[1].parse().ok())
+            .unwrap_or(0);
+        return Some((host, port));
+    }
+    let (host, port) = node.rsplit_once(':')?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    Some((host.to_string(), port.parse().ok()?))
+}
+
+async fn check_bootstrap(config: Option<TaiorConfig>) -> Vec<SelfTestCheck> {
+    let Some(config) = config else {
+        return vec![SelfTestCheck::fail("bootstrap", "Taior is not initialized, so no bootstrap nodes are configured")];
+    };
+    let config = match config.validated() {
+        Ok(config) => config,
+        Err(e) => return vec![SelfTestCheck::fail("bootstrap", format!("{:#}", e))],
+    };
+    if config.bootstrap_nodes.is_empty() {
+        return vec![SelfTestCheck::pass("bootstrap", "No bootstrap nodes; using the library defaults")];
+    }
+
+    let mut checks = Vec::new();
+    for node in &config.bootstrap_nodes {
+        let name = format!("bootstrap:{}", node);
+        let Some(target) = bootstrap_target(node) else {
+            checks.push(SelfTestCheck::fail(name, "Unrecognized address"));
+            continue;
+        };
+        checks.push(match tokio::time::timeout(RESOLVE_TIMEOUT, tokio::net::lookup_host(target)).await {
+            Ok(Ok(mut addrs)) => match addrs.next() {
+                Some(addr) => SelfTestCheck::pass(name, format!("Resolves to {}", addr.ip())),
+                None => SelfTestCheck::fail(name, "Resolved to no addresses"),
+            },
+            Ok(Err(e)) => SelfTestCheck::fail(name, format!("Failed to resolve: {}", e)),
+            Err(_) => SelfTestCheck::fail(name, "Timed out resolving"),
+        });
+    }
+    checks
+}
+
+fn check_address(taior: &TaiorState) -> SelfTestCheck {
+    match taior.address() {
+        Ok(address) => SelfTestCheck::pass("taior_address", address),
+        Err(e) => SelfTestCheck::fail("taior_address", format!("{:#}", e)),
+    }
+}
+
+/// Validates pins, bootstrap nodes, relay reachability and the Taior identity. Relays
+/// are probed with a handshake that is closed right away; no session is opened and
+/// nothing is sent.
+#[tauri::command]
+pub async fn run_self_test(
+    app: AppHandle,
+    taior: State<'_, Arc<SharedState<TaiorState>>>,
+    transport: State<'_, Arc<SharedState<QuicTransport>>>,
+    discovery: State<'_, Arc<RwLock<RelayDiscovery>>>,
+) -> Result<SelfTestReport, HushError> {
+    let mut checks = Vec::new();
+
+    // Resolution can be slow, so it runs after the lock is released
+    let config = {
+        let taior = taior.read().await?;
+        checks.push(check_address(&taior));
+        taior.config().cloned()
+    };
+    checks.extend(check_bootstrap(config).await);
+
+    let pin_file = app.path().app_config_dir()
+        .map_err(|e| HushError::Other(format!("No config directory: {}", e)))?
+        .join(cert_pins::PIN_FILE);
+    let relays = {
+        let discovery = discovery.read().await;
+        checks.extend(check_pins(&pin_file, &discovery));
+        discovery.get_available_relays(false).iter()
+            .map(|r| (r.id.clone(), r.to_relay_info()))
+            .collect::<Vec<_>>()
+    };
+
+    if relays.is_empty() {
+        checks.push(SelfTestCheck::fail("relays", "No relays configured"));
+    }
+    let probes = transport.write().await?.probe_relays(&relays).await;
+    for probe in probes {
+        let name = format!("relay:{}", probe.relay_id);
+        checks.push(match (probe.latency_ms, probe.error) {
+            (Some(latency_ms), _) => SelfTestCheck::pass(name, format!("Reachable in {}ms", latency_ms)),
+            (None, error) => SelfTestCheck::fail(name, error.unwrap_or_else(|| "Unreachable".to_string())),
+        });
+    }
+
+    let passed = checks.iter().all(|check| check.passed);
+    tracing::info!("Self-test finished: {}", if passed { "passed" } else { "failed" });
+    Ok(SelfTestReport { passed, checks })
+}
@@ -0,0 +1,332 @@
+use anyhow::{anyhow, Context, Result};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::HushError;
+
+const QUEUE_FILE: &str = "send_queue.bin";
+const KEY_FILE: &str = "send_queue.key";
+const NONCE_LEN: usize = 12;
+
+/// Messages the queue holds before refusing more.
+pub const DEFAULT_QUEUE_CAPACITY: usize = 1000;
+
+/// How urgent a message is. Higher priorities leave the queue first and get a higher
+/// QUIC stream priority, so a sent message overtakes queued typing indicators.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+impl Priority {
+    /// Priority for `SendStream::set_priority`. Cover traffic sits below all of these.
+    pub fn stream_priority(self) -> i32 {
+        match self {
+            Self::Low => -1,
+            Self::Normal => 0,
+            Self::High => 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueuedMessage {
+    pub id: String,
+    pub data: Vec<u8>,
+    pub queued_at: u64,
+    pub ttl_secs: Option<u64>,
+    #[serde(default)]
+    pub priority: Priority,
+}
+
+impl QueuedMessage {
+    pub fn new(data: Vec<u8>, ttl_secs: Option<u64>, priority: Priority) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            data,
+            queued_at: unix_now(),
+            ttl_secs,
+            priority,
+        }
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        match self.ttl_secs {
+            Some(ttl) => now >= self.queued_at.saturating_add(ttl),
+            None => false,
+        }
+    }
+}
+
+/// Outbound messages waiting for a relay connection. When storage is attached the
+/// queue is mirrored to disk, encrypted with a per-install key, so it survives restarts.
+///
+/// The key is kept in `send_queue.key` next to the queue file, readable only by the
+/// owner. It keeps queued packets out of backups or copies that take the queue file
+/// alone; anyone who can read the app data directory can decrypt the queue.
+pub struct SendQueue {
+    pending: VecDeque<QueuedMessage>,
+    storage: Option<QueueStorage>,
+    capacity: usize,
+}
+
+struct QueueStorage {
+    path: PathBuf,
+    key: Key,
+}
+
+impl Default for SendQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SendQueue {
+    pub fn new() -> Self {
+        Self {
+            pending: VecDeque::new(),
+            storage: None,
+            capacity: DEFAULT_QUEUE_CAPACITY,
+        }
+    }
+
+    /// Loads the persisted queue from `dir`, creating the encryption key on first use.
+    /// Messages whose TTL elapsed while the app was closed are discarded.
+    pub fn load(dir: &Path) -> Result<Self> {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create queue directory {}", dir.display()))?;
+
+        let storage = QueueStorage {
+            path: dir.join(QUEUE_FILE),
+            key: load_or_create_key(&dir.join(KEY_FILE))?,
+        };
+
+        let mut pending: VecDeque<QueuedMessage> = if storage.path.exists() {
+            let blob = std::fs::read(&storage.path).context("Failed to read send queue")?;
+            storage.decrypt(&blob)?
+        } else {
+            VecDeque::new()
+        };
+
+        let now = unix_now();
+        let before = pending.len();
+        pending.retain(|m| !m.is_expired(now));
+        if before != pending.len() {
+            tracing::info!("Dropped {} expired queued messages", before - pending.len());
+        }
+
+        let queue = Self {
+            pending,
+            storage: Some(storage),
+            capacity: DEFAULT_QUEUE_CAPACITY,
+        };
+        queue.persist()?;
+        Ok(queue)
+    }
+
+    /// Inserts behind every message of the same or higher priority, so order is FIFO
+    /// within a priority level. Fails with [`HushError::QueueFull`] at capacity.
+    pub fn push(&mut self, message: QueuedMessage) -> Result<()> {
+        if self.pending.len() >= self.capacity {
+            return Err(HushError::QueueFull(self.capacity).into());
+        }
+        let position = self.pending.iter()
+            .position(|queued| queued.priority < message.priority)
+            .unwrap_or(self.pending.len());
+        self.pending.insert(position, message);
+        self.persist()
+    }
+
+    /// Removes and returns every unexpired message in dispatch order.
+    pub fn take_ready(&mut self) -> Vec<QueuedMessage> {
+        let now = unix_now();
+        self.pending.drain(..).filter(|m| !m.is_expired(now)).collect()
+    }
+
+    /// Moves every message of `other` into this queue as if pushed after the messages
+    /// already here, so each keeps its place behind older messages of its priority.
+    /// Capacity is not enforced: the messages were accepted once already.
+    pub fn merge(&mut self, other: SendQueue) -> Result<()> {
+        for message in other.pending {
+            let position = self.pending.iter()
+                .position(|queued| queued.priority < message.priority)
+                .unwrap_or(self.pending.len());
+            self.pending.insert(position, message);
+        }
+        self.persist()
+    }
+
+    /// Puts undelivered messages back at the head of the queue, preserving their order.
+    pub fn requeue_front(&mut self, messages: Vec<QueuedMessage>) -> Result<()> {
+        for message in messages.into_iter().rev() {
+            self.pending.push_front(message);
+        }
+        self.persist()
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Messages already queued beyond a lowered capacity stay; only new pushes are refused.
+    pub fn set_capacity(&mut self, capacity: usize) -> Result<()> {
+        if capacity == 0 {
+            return Err(HushError::InvalidInput("Queue capacity must be at least 1".to_string()).into());
+        }
+        self.capacity = capacity;
+        Ok(())
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    pub fn persist(&self) -> Result<()> {
+        let Some(storage) = &self.storage else {
+            return Ok(());
+        };
+
+        let blob = storage.encrypt(&self.pending)?;
+        write_private(&storage.path, &blob).context("Failed to write send queue")
+    }
+}
+
+impl QueueStorage {
+    fn encrypt(&self, pending: &VecDeque<QueuedMessage>) -> Result<Vec<u8>> {
+        let plaintext = serde_json::to_vec(pending)?;
+        let cipher = ChaCha20Poly1305::new(&self.key);
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(&nonce, plaintext.as_ref())
+            .map_err(|_| anyhow!("Failed to encrypt send queue"))?;
+
+        let mut blob = Vec::with_capacity(NONCE_LEN + ciphertext.len());
+        blob.extend_from_slice(&nonce);
+        blob.extend_from_slice(&ciphertext);
+        Ok(blob)
+    }
+
+    fn decrypt(&self, blob: &[u8]) -> Result<VecDeque<QueuedMessage>> {
+        if blob.len() < NONCE_LEN {
+            anyhow::bail!("Send queue file is truncated");
+        }
+
+        let (nonce, ciphertext) = blob.split_at(NONCE_LEN);
+        let cipher = ChaCha20Poly1305::new(&self.key);
+        let plaintext = cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow!("Failed to decrypt send queue (wrong key or corrupted file)"))?;
+
+        Ok(serde_json::from_slice(&plaintext)?)
+    }
+}
+
+fn load_or_create_key(path: &Path) -> Result<Key> {
+    if path.exists() {
+        let bytes = std::fs::read(path).context("Failed to read send queue key")?;
+        if bytes.len() != 32 {
+            anyhow::bail!("Send queue key has invalid length {}", bytes.len());
+        }
+        return Ok(*Key::from_slice(&bytes));
+    }
+
+    let key = ChaCha20Poly1305::generate_key(&mut OsRng);
+    write_private(path, &key).context("Failed to write send queue key")?;
+    Ok(key)
+}
+
+/// Writes `bytes` to `path`, restricting permissions to the owner on Unix.
+pub(crate) fn write_private(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::io::Write;
+        use std::os::unix::fs::OpenOptionsExt;
+
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(path)?;
+        file.write_all(bytes)
+    }
+
+    #[cfg(not(unix))]
+    {
+        std::fs::write(path, bytes)
+    }
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("hush-send-queue-{}", uuid::Uuid::new_v4()))
+    }
+
+    fn ids(messages: &[QueuedMessage]) -> Vec<&[u8]> {
+        messages.iter().map(|m| m.data.as_slice()).collect()
+    }
+
+    #[test]
+    fn reload_keeps_pending_messages_and_drops_expired_ones() {
+        let dir = queue_dir();
+        let mut queue = SendQueue::load(&dir).unwrap();
+        queue.push(QueuedMessage::new(b"first".to_vec(), None, Priority::Normal)).unwrap();
+        let mut expired = QueuedMessage::new(b"expired".to_vec(), Some(60), Priority::High);
+        expired.queued_at -= 120;
+        queue.push(expired).unwrap();
+        queue.push(QueuedMessage::new(b"second".to_vec(), Some(3600), Priority::Normal)).unwrap();
+        drop(queue);
+
+        let mut restored = SendQueue::load(&dir).unwrap();
+        assert_eq!(restored.len(), 2);
+        let delivered = restored.take_ready();
+        assert_eq!(ids(&delivered), [&b"first"[..], b"second"]);
+
+        // Taken messages are gone once the queue is persisted again
+        restored.persist().unwrap();
+        assert!(SendQueue::load(&dir).unwrap().is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn merge_keeps_priority_then_fifo_order() {
+        let dir = queue_dir();
+        let mut loaded = SendQueue::load(&dir).unwrap();
+        loaded.push(QueuedMessage::new(b"old normal".to_vec(), None, Priority::Normal)).unwrap();
+        loaded.push(QueuedMessage::new(b"old low".to_vec(), None, Priority::Low)).unwrap();
+
+        let mut in_memory = SendQueue::new();
+        in_memory.push(QueuedMessage::new(b"new high".to_vec(), None, Priority::High)).unwrap();
+        in_memory.push(QueuedMessage::new(b"new normal".to_vec(), None, Priority::Normal)).unwrap();
+        in_memory.push(QueuedMessage::new(b"new low".to_vec(), None, Priority::Low)).unwrap();
+
+        loaded.merge(in_memory).unwrap();
+        let expected = [&b"new high"[..], b"old normal", b"new normal", b"old low", b"new low"];
+        let mut reloaded = SendQueue::load(&dir).unwrap();
+        assert_eq!(ids(&reloaded.take_ready()), expected);
+        assert_eq!(ids(&loaded.take_ready()), expected);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
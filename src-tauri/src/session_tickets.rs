@@ -0,0 +1,40 @@
+use rustls::client::{ClientSessionMemoryCache, ClientSessionStore, Resumption};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Session tickets kept per relay; relays issue a few at a time.
+const TICKETS_PER_RELAY: usize = 4;
+
+/// TLS session tickets, one cache per relay address. Every relay is dialled under the
+/// same server name, so a shared cache would offer one relay's ticket to another, and
+/// a resumed handshake is not shown a certificate to check against the pins.
+#[derive(Clone, Default)]
+pub struct SessionTickets {
+    stores: Arc<Mutex<HashMap<String, Arc<ClientSessionMemoryCache>>>>,
+}
+
+impl SessionTickets {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Resumption settings for a connection to `relay`, sharing its tickets with
+    /// earlier and later connections to the same relay.
+    pub fn resumption(&self, relay: &str) -> Resumption {
+        let Ok(mut stores) = self.stores.lock() else {
+            return Resumption::disabled();
+        };
+        let store = stores.entry(relay.to_string())
+            .or_insert_with(|| Arc::new(ClientSessionMemoryCache::new(TICKETS_PER_RELAY)))
+            .clone();
+        Resumption::store(store as Arc<dyn ClientSessionStore>)
+    }
+
+    /// Drops every ticket, so the next connection to each relay does a full,
+    /// pin-checked handshake.
+    pub fn clear(&self) {
+        if let Ok(mut stores) = self.stores.lock() {
+            stores.clear();
+        }
+    }
+}
@@ -0,0 +1,216 @@
+//! Managed state that survives a panicking command. Relies on panics unwinding, which
+//! is why the release profile sets `panic = "unwind"`; under `panic = "abort"` the
+//! process exits before a guard could mark anything poisoned.
+
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tauri::State;
+use tokio::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::error::HushError;
+use crate::quic_transport::QuicTransport;
+use crate::taior_bridge::TaiorState;
+
+/// Async lock for managed state that remembers whether a task panicked while holding
+/// it for writing. tokio's `RwLock` simply releases on unwind, so without this a
+/// half-applied update would silently serve every later command. Once poisoned, every
+/// access fails with [`StatePoisoned`] until [`recover`](Self::recover) runs.
+#[derive(Debug, Default)]
+pub struct SharedState<T> {
+    lock: RwLock<T>,
+    poisoned: AtomicBool,
+}
+
+/// Returned by [`SharedState`] after a writer panicked. Call `recover_state` to reset.
+#[derive(Debug, Clone, Copy)]
+pub struct StatePoisoned;
+
+impl fmt::Display for StatePoisoned {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Backend state is inconsistent after an internal error; call recover_state")
+    }
+}
+
+impl std::error::Error for StatePoisoned {}
+
+pub struct StateWriteGuard<'a, T> {
+    guard: RwLockWriteGuard<'a, T>,
+    poisoned: &'a AtomicBool,
+}
+
+impl<T> SharedState<T> {
+    pub fn new(value: T) -> Self {
+        Self {
+            lock: RwLock::new(value),
+            poisoned: AtomicBool::new(false),
+        }
+    }
+
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned.load(Ordering::Acquire)
+    }
+
+    pub async fn read(&self) -> Result<RwLockReadGuard<'_, T>, StatePoisoned> {
+        let guard = self.lock.read().await;
+        if self.is_poisoned() {
+            return Err(StatePoisoned);
+        }
+        Ok(guard)
+    }
+
+    pub async fn write(&self) -> Result<StateWriteGuard<'_, T>, StatePoisoned> {
+        let guard = self.lock.write().await;
+        if self.is_poisoned() {
+            return Err(StatePoisoned);
+        }
+        Ok(StateWriteGuard {
+            guard,
+            poisoned: &self.poisoned,
+        })
+    }
+
+    /// Like [`Self::write`] without waiting: `None` while another task holds the lock
+    /// or the state is poisoned. For setup code that runs before any task could.
+    pub fn try_write(&self) -> Option<StateWriteGuard<'_, T>> {
+        let guard = self.lock.try_write().ok()?;
+        if self.is_poisoned() {
+            return None;
+        }
+        Some(StateWriteGuard {
+            guard,
+            poisoned: &self.poisoned,
+        })
+    }
+
+    /// Repairs the state with `repair` and clears the poison flag. Works whether or not
+    /// the state is poisoned.
+    pub async fn recover(&self, repair: impl FnOnce(&mut T)) {
+        let mut guard = self.lock.write().await;
+        repair(&mut guard);
+        self.poisoned.store(false, Ordering::Release);
+    }
+}
+
+impl<T> Deref for StateWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T> DerefMut for StateWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<T> Drop for StateWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            self.poisoned.store(true, Ordering::Release);
+            tracing::error!("Task panicked while holding a state lock; state marked poisoned");
+        }
+    }
+}
+
+/// Resets both Taior and transport state after a panic left them poisoned. Pins, the
+/// persisted send queue and frontend attachments survive; connections and the Taior
+/// identity do not.
+pub async fn recover_all(taior: &SharedState<TaiorState>, transport: &SharedState<QuicTransport>) {
+    taior.recover(TaiorState::reset).await;
+    transport.recover(QuicTransport::reset_after_panic).await;
+
+    tracing::info!("Backend state recovered");
+}
+
+#[tauri::command]
+pub async fn recover_state(
+    taior: State<'_, Arc<SharedState<TaiorState>>>,
+    transport: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<(), HushError> {
+    recover_all(&taior, &transport).await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quic_transport::FinishMode;
+    use crate::send_queue::Priority;
+    use crate::taior_bridge::TaiorConfig;
+    use crate::test_relay::TestRelay;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn panic_under_the_lock_poisons_until_recovered() {
+        let relay = TestRelay::start().unwrap();
+        let taior = Arc::new(SharedState::new(TaiorState::new()));
+        let mut transport = QuicTransport::new();
+        relay.pin(&mut transport);
+        let transport = Arc::new(SharedState::new(transport));
+        let config = || TaiorConfig { bootstrap_nodes: Vec::new() };
+        taior.write().await.unwrap().init(config()).unwrap();
+        transport.write().await.unwrap().connect(relay.relay_info().unwrap()).await.unwrap();
+
+        // A reader panicking can't have changed anything, so it doesn't poison
+        let reader = transport.clone();
+        let panicked = tokio::spawn(async move {
+            let _transport = reader.read().await.unwrap();
+            panic!("reader panicked");
+        });
+        assert!(panicked.await.unwrap_err().is_panic());
+        assert!(!transport.is_poisoned());
+
+        let writer = transport.clone();
+        let panicked = tokio::spawn(async move {
+            let mut transport = writer.write().await.unwrap();
+            transport.disconnect();
+            panic!("writer panicked halfway through an update");
+        });
+        assert!(panicked.await.unwrap_err().is_panic());
+        assert!(transport.is_poisoned());
+        assert!(transport.read().await.is_err());
+        let error = HushError::from(transport.write().await.err().unwrap());
+        assert_eq!(error.kind(), "state_poisoned");
+        // Only the state that was being written is poisoned
+        assert!(taior.read().await.is_ok());
+
+        recover_all(&taior, &transport).await;
+        assert!(!transport.is_poisoned());
+        assert!(!taior.read().await.unwrap().is_initialized());
+
+        let address = taior.write().await.unwrap().init(config()).unwrap();
+        let (packet, _) = taior.write().await.unwrap().send(b"after recovery", "fast", &address).unwrap();
+        let mut transport = transport.write().await.unwrap();
+        assert!(transport.connection().is_none());
+        transport.connect(relay.relay_info().unwrap()).await.unwrap();
+        transport.send(&packet, FinishMode::Finish, Priority::Normal).await.unwrap();
+        let echoed = transport.recv(64 * 1024, Duration::from_secs(5)).await.unwrap();
+        assert_eq!(taior.write().await.unwrap().receive(&echoed).unwrap().0, b"after recovery");
+        relay.stop();
+    }
+
+    #[tokio::test]
+    async fn try_write_only_succeeds_on_a_free_healthy_lock() {
+        let state = Arc::new(SharedState::new(0u32));
+        *state.try_write().unwrap() += 1;
+
+        let reader = state.read().await.unwrap();
+        assert!(state.try_write().is_none());
+        drop(reader);
+
+        let writer = state.clone();
+        let panicked = tokio::spawn(async move {
+            let _state = writer.write().await.unwrap();
+            panic!("writer panicked");
+        });
+        assert!(panicked.await.unwrap_err().is_panic());
+        assert!(state.try_write().is_none());
+
+        state.recover(|value| *value = 0).await;
+        assert_eq!(*state.try_write().unwrap(), 0);
+    }
+}
@@ -0,0 +1,146 @@
+use anyhow::{Context, Result};
+use quinn::Connection;
+use serde::Serialize;
+use std::time::Duration;
+
+use crate::send_queue::Priority;
+
+/// Asks the relay to deliver a packet, or hold it for an offline recipient:
+/// `[FRAME_STORE_FORWARD][u32 ttl_secs][packet]`. The relay answers one status byte.
+pub const FRAME_STORE_FORWARD: u8 = 0x40;
+const STATUS_DELIVERED: u8 = 0x00;
+const STATUS_STORED: u8 = 0x01;
+const STATUS_REJECTED: u8 = 0x02;
+
+/// Relays hold stored messages for at most a week.
+pub const MAX_STORE_TTL_SECS: u64 = 7 * 24 * 60 * 60;
+
+/// How the relay handled a store-and-forward send.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum DeliveryOutcome {
+    /// The recipient was online and received it directly.
+    Live,
+    /// The recipient was offline; the relay keeps the (still end-to-end encrypted)
+    /// packet until the recipient fetches it or the TTL expires.
+    Stored { ttl_secs: u64 },
+}
+
+/// Payload of the `message-delivery` event.
+#[derive(Debug, Clone, Serialize)]
+pub struct DeliveryReport {
+    /// Id `taior_send` returned for the message this packet belongs to.
+    pub message_id: String,
+    pub packet_size: usize,
+    pub outcome: DeliveryOutcome,
+}
+
+/// Sends `packet` with a store-and-forward request and waits for the relay's verdict.
+pub async fn send(
+    connection: &Connection,
+    packet: &[u8],
+    ttl_secs: u64,
+    priority: Priority,
+    io_timeout: Duration,
+    ack_timeout: Duration,
+) -> Result<DeliveryOutcome> {
+    if ttl_secs == 0 || ttl_secs > MAX_STORE_TTL_SECS {
+        anyhow::bail!("Store TTL must be between 1 and {} seconds", MAX_STORE_TTL_SECS);
+    }
+
+    let (mut send, mut recv) = connection.open_bi().await
+        .context("Failed to open store-and-forward stream")?;
+    send.set_priority(priority.stream_priority())
+        .context("Failed to set stream priority")?;
+
+    let mut frame = Vec::with_capacity(1 + 4 + packet.len());
+    frame.push(FRAME_STORE_FORWARD);
+    frame.extend_from_slice(&(ttl_secs as u32).to_be_bytes());
+    frame.extend_from_slice(packet);
+    tokio::time::timeout(io_timeout, send.write_all(&frame))
+        .await
+        .context("Timed out writing store-and-forward request")?
+        .context("Failed to send store-and-forward request")?;
+    send.finish().context("Failed to finish store-and-forward stream")?;
+
+    let mut status = [0u8; 1];
+    tokio::time::timeout(ack_timeout, recv.read_exact(&mut status))
+        .await
+        .context("Timed out waiting for store-and-forward status")?
+        .context("Failed to read store-and-forward status")?;
+
+    match status[0] {
+        STATUS_DELIVERED => Ok(DeliveryOutcome::Live),
+        STATUS_STORED => Ok(DeliveryOutcome::Stored { ttl_secs }),
+        STATUS_REJECTED => anyhow::bail!("Relay refused to store the message"),
+        other => anyhow::bail!("Unexpected store-and-forward status 0x{:02x}", other),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quic_transport::QuicTransport;
+    use crate::test_relay::TestRelay;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    /// Messages the mock relay holds, as `(ttl_secs, packet)`.
+    type Mailbox = Arc<Mutex<Vec<(u32, Vec<u8>)>>>;
+
+    /// Relay that delivers live while the recipient is online, stores up to one
+    /// message while it is offline and refuses anything beyond that.
+    fn mailbox_relay(online: Arc<AtomicBool>, mailbox: Mailbox) -> TestRelay {
+        TestRelay::serve(Duration::ZERO, move |connection: Connection| {
+            let (online, mailbox) = (online.clone(), mailbox.clone());
+            async move {
+                while let Ok((mut send, mut recv)) = connection.accept_bi().await {
+                    let Ok(frame) = recv.read_to_end(64 * 1024).await else { break };
+                    assert_eq!(frame[0], FRAME_STORE_FORWARD);
+                    let ttl_secs = u32::from_be_bytes(frame[1..5].try_into().unwrap());
+                    let status = if online.load(Ordering::SeqCst) {
+                        STATUS_DELIVERED
+                    } else {
+                        let mut mailbox = mailbox.lock().unwrap();
+                        if mailbox.is_empty() {
+                            mailbox.push((ttl_secs, frame[5..].to_vec()));
+                            STATUS_STORED
+                        } else {
+                            STATUS_REJECTED
+                        }
+                    };
+                    let _ = send.write_all(&[status]).await;
+                    let _ = send.finish();
+                }
+            }
+        })
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn offline_recipient_gets_the_message_stored() {
+        let online = Arc::new(AtomicBool::new(false));
+        let mailbox = Mailbox::default();
+        let relay = mailbox_relay(online.clone(), mailbox.clone());
+        let mut transport = QuicTransport::new();
+        relay.pin(&mut transport);
+        transport.connect(relay.relay_info().unwrap()).await.unwrap();
+        let packet = b"end-to-end encrypted packet";
+
+        let outcome = transport.send_store_forward(packet, 3600).await.unwrap();
+        assert_eq!(outcome, DeliveryOutcome::Stored { ttl_secs: 3600 });
+        assert_eq!(*mailbox.lock().unwrap(), [(3600, packet.to_vec())]);
+
+        let error = transport.send_store_forward(packet, 60).await.unwrap_err();
+        assert!(error.to_string().contains("refused to store"), "{:#}", error);
+
+        online.store(true, Ordering::SeqCst);
+        assert_eq!(transport.send_store_forward(packet, 60).await.unwrap(), DeliveryOutcome::Live);
+        assert_eq!(mailbox.lock().unwrap().len(), 1);
+
+        for ttl_secs in [0, MAX_STORE_TTL_SECS + 1] {
+            assert!(transport.send_store_forward(packet, ttl_secs).await.is_err());
+        }
+        relay.stop();
+    }
+}
@@ -1,120 +1,1332 @@
+use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
-use tauri::State;
+use std::time::Instant;
+use tauri::{AppHandle, Emitter, State};
 use tokio::sync::RwLock;
+use tokio_util::sync::CancellationToken;
 use taior::{Taior, SendOptions, RoutingMode};
 
+use crate::chunking;
+use crate::cover_traffic::{
+    CoverDestinationPolicy, CoverScheduler, CoverStreamStatus, CoverStreams, CoverTrafficStats,
+};
+use crate::error::HushError;
+use crate::identity::IdentityStore;
+use crate::quic_transport::{QuicTransport, SendTiming};
+use crate::relay_client::{CircuitConstraint, CircuitStrategy, RelayCircuit, RelayDiscovery, RelayNode};
+use crate::shared_state::SharedState;
+use crate::store_forward::DeliveryReport;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TaiorConfig {
     pub bootstrap_nodes: Vec<String>,
 }
 
+impl TaiorConfig {
+    /// Checks every bootstrap node is `host:port` (IPv6 literals bracketed) or a taior
+    /// multiaddr such as `/ip4/203.0.113.5/udp/4433/quic`. Blank entries are dropped and
+    /// duplicates removed, keeping the first occurrence. Fails with
+    /// [`HushError::InvalidInput`] naming the first malformed entry.
+    pub fn validated(self) -> Result<Self> {
+        let mut bootstrap_nodes: Vec<String> = Vec::with_capacity(self.bootstrap_nodes.len());
+        for node in &self.bootstrap_nodes {
+            let node = node.trim();
+            if node.is_empty() {
+                continue;
+            }
+            let valid = if node.starts_with('/') {
+                is_multiaddr(node)
+            } else {
+                is_host_port(node)
+            };
+            if !valid {
+                return Err(HushError::InvalidInput(format!(
+                    "Bootstrap node must be host:port or a multiaddr, got {:?}",
+                    node
+                ))
+                .into());
+            }
+            if !bootstrap_nodes.iter().any(|seen| seen == node) {
+                bootstrap_nodes.push(node.to_string());
+            }
+        }
+        Ok(Self { bootstrap_nodes })
+    }
+}
+
+/// What `taior_send` returns: the message id that `message-delivery` events carry,
+/// and the packets in send order.
+#[derive(Debug, Clone, Serialize)]
+pub struct TaiorSendResult {
+    pub id: String,
+    /// Taior address the packets are routed to, as normalized by `taior_send`.
+    pub recipient: String,
+    /// One packet, or one per chunk when the payload exceeded the max payload.
+    pub packets: Vec<Vec<u8>>,
+}
+
+/// Current cover traffic setting, readable before Taior is initialized.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct CoverTrafficStatus {
+    pub enabled: bool,
+    pub ratio: f32,
+    /// Whether cover packets are being sent; the scheduler stops when the connection
+    /// it started on drops and restarts when cover traffic is enabled again.
+    pub scheduler_running: bool,
+}
+
+/// Estimated cost of sending one payload through a routing mode.
+#[derive(Debug, Clone, Serialize)]
+pub struct ModeBenchmark {
+    pub mode: String,
+    pub estimated_latency_ms: u64,
+    pub hop_count: u8,
+    /// Padded size of all packets the payload is routed as.
+    pub packet_size: usize,
+    pub overhead_bytes: usize,
+    /// Relays the planner picked for the mode, empty when discovery knows too few.
+    pub planned_hops: Vec<String>,
+}
+
+/// Quantitative preview of sending one payload through a mode on the current network.
+#[derive(Debug, Clone, Serialize)]
+pub struct ModeEstimate {
+    pub mode: String,
+    pub hop_count: u8,
+    pub estimated_latency_ms: u64,
+    pub packet_size: usize,
+    /// Bytes added by AORP framing and padding.
+    pub padding_overhead_bytes: usize,
+    /// Extra bytes of cover traffic sent per message at the current cover ratio.
+    pub cover_overhead_bytes: usize,
+    /// Relative anonymity from 0 (none) to 1 (strongest mode); only meaningful when
+    /// comparing modes.
+    pub anonymity_score: f32,
+}
+
+/// Every mode string `taior_send` accepts, with a description for the UI.
+pub const ROUTING_MODES: &[(&str, &str)] = &[
+    ("fast", "Fewest hops and no batching; lowest latency, weakest against timing analysis"),
+    ("mix", "Batches and delays messages at each hop to resist timing correlation"),
+    (
+        "reinforced",
+        "Routed like mix by the current library; reserved for a stronger mix profile",
+    ),
+    ("adaptive", "Mixes only when the network is busy enough to hide in"),
+];
+
+/// Sizes `taior_send` pads packets up to, so a packet's length only reveals its bucket.
+pub const DEFAULT_PADDING_BUCKETS: &[usize] = &[
+    256, 512, 1024, 2048, 4096, 8192, 16384, 32768, 65536,
+];
+
+/// Largest payload `taior_send` routes as a single packet; bigger payloads are split
+/// into chunk frames that are routed one by one.
+pub const DEFAULT_MAX_PAYLOAD: usize = 16 * 1024;
+
+/// Most chunks a payload planned by `taior_benchmark_modes` or `taior_estimate_mode`
+/// may span. Planning allocates the whole probe payload, so its length is capped at
+/// this many of the largest padding bucket.
+pub const MAX_PLANNED_CHUNKS: usize = 64;
+
+/// One entry of the `taior_routing_modes` list.
+#[derive(Debug, Clone, Serialize)]
+pub struct RoutingModeInfo {
+    pub mode: &'static str,
+    pub description: &'static str,
+}
+
+/// How the planner routes one mode: hop count, how hops are chosen, and the batching
+/// delay each hop adds on top of its round trip.
+struct ModeProfile {
+    mode: &'static str,
+    hops: u8,
+    strategy: CircuitStrategy,
+    constraints: &'static [CircuitConstraint],
+    mix_delay_ms: u64,
+    /// Relative anonymity: more hops and batching make timing correlation harder.
+    /// Adaptive sits between fast and mix because it mixes only under load.
+    anonymity: f32,
+}
+
+/// Matches the relay network's published targets (fast ~100ms, mix ~500ms).
+/// Reinforced adds a hop over mix.
+const MODE_PROFILES: &[ModeProfile] = &[
+    ModeProfile {
+        mode: "fast",
+        hops: 3,
+        strategy: CircuitStrategy::LowestLatency,
+        constraints: &[],
+        mix_delay_ms: 0,
+        anonymity: 0.35,
+    },
+    ModeProfile {
+        mode: "mix",
+        hops: 4,
+        strategy: CircuitStrategy::Random { seed: None },
+        constraints: &[CircuitConstraint::DistinctNetwork],
+        mix_delay_ms: 100,
+        anonymity: 0.8,
+    },
+    ModeProfile {
+        mode: "reinforced",
+        hops: 5,
+        strategy: CircuitStrategy::Random { seed: None },
+        constraints: &[CircuitConstraint::DistinctNetwork],
+        mix_delay_ms: 125,
+        anonymity: 1.0,
+    },
+    ModeProfile {
+        mode: "adaptive",
+        hops: 4,
+        strategy: CircuitStrategy::BestQuality,
+        constraints: &[],
+        mix_delay_ms: 30,
+        anonymity: 0.55,
+    },
+];
+
+/// Round trip assumed for a hop when neither the relay nor the live connection has a
+/// measurement.
+const DEFAULT_HOP_RTT_MS: u64 = 33;
+
+/// Bandwidth assumed for a hop that doesn't publish one.
+const DEFAULT_HOP_BANDWIDTH_MBPS: u32 = 10;
+
+fn mode_profile(mode: &str) -> Result<&'static ModeProfile> {
+    parse_routing_mode(mode)?;
+    MODE_PROFILES.iter()
+        .find(|profile| profile.mode == mode)
+        .with_context(|| format!("Invalid routing mode: {}", mode))
+}
+
+/// Where a mode estimate puts its hops and what they cost, from [`TaiorState::plan_mode`].
+struct ModePlan {
+    hop_count: u8,
+    hops: Vec<String>,
+    latency_ms: u64,
+    packet_size: usize,
+}
+
+/// Latency of `hop_count` hops that each add `mix_delay_ms` and carry `bytes`. Planned
+/// hops use their own measurements; the rest use `relay_rtt_ms`, the round trip
+/// measured on the live relay connection, or a default.
+fn path_latency_ms(
+    hops: &[RelayNode],
+    hop_count: u8,
+    mix_delay_ms: u64,
+    bytes: usize,
+    relay_rtt_ms: Option<u64>,
+) -> u64 {
+    let fallback_rtt = relay_rtt_ms.unwrap_or(DEFAULT_HOP_RTT_MS);
+    (0..hop_count as usize)
+        .map(|i| {
+            let hop = hops.get(i);
+            let rtt = hop.and_then(|h| h.latency_ms).unwrap_or(fallback_rtt);
+            let mbps = hop.and_then(|h| h.bandwidth_mbps).unwrap_or(DEFAULT_HOP_BANDWIDTH_MBPS).max(1);
+            // bits / (Mbit/s) is microseconds
+            let transfer_ms = (bytes as u64 * 8).div_ceil(u64::from(mbps) * 1000);
+            rtt + mix_delay_ms + transfer_ms
+        })
+        .sum()
+}
+
 pub struct TaiorState {
     instance: Option<Taior>,
+    config: Option<TaiorConfig>,
     cover_traffic_enabled: bool,
     cover_traffic_ratio: f32,
+    cover_destination: CoverDestinationPolicy,
+    cover_streams: CoverStreams,
+    cover_scheduler: CoverScheduler,
+    padding_buckets: Vec<usize>,
+    max_payload: usize,
+    identity_tasks: CancellationToken,
+    identity_store: Option<IdentityStore>,
+}
+
+impl Default for TaiorState {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl TaiorState {
     pub fn new() -> Self {
         Self {
             instance: None,
+            config: None,
             cover_traffic_enabled: false,
             cover_traffic_ratio: 0.0,
+            cover_destination: CoverDestinationPolicy::default(),
+            cover_streams: CoverStreams::new(),
+            cover_scheduler: CoverScheduler::new(),
+            padding_buckets: DEFAULT_PADDING_BUCKETS.to_vec(),
+            max_payload: DEFAULT_MAX_PAYLOAD,
+            identity_tasks: CancellationToken::new(),
+            identity_store: None,
+        }
+    }
+
+    /// Persists the identity across restarts: `init` restores it from `store` and
+    /// new identities are saved to it.
+    pub fn attach_identity_store(&mut self, store: IdentityStore) {
+        self.identity_store = Some(store);
+    }
+
+    /// Transport the cover streams and cover packets are sent over.
+    pub fn attach_transport(&mut self, transport: Arc<SharedState<QuicTransport>>) {
+        self.cover_streams.attach_transport(transport.clone());
+        self.cover_scheduler.attach_transport(transport);
+    }
+
+    /// Relays the cover destination policy chooses among besides the current one.
+    pub fn attach_discovery(&mut self, discovery: Arc<RwLock<RelayDiscovery>>) {
+        self.cover_scheduler.attach_discovery(discovery);
+    }
+
+    /// Token for background tasks bound to the current identity (receive loop,
+    /// sessions). It is cancelled before the identity is replaced or reset; tasks that
+    /// outlive an identity take a fresh token and restart under the next one.
+    pub fn identity_token(&self) -> CancellationToken {
+        self.identity_tasks.child_token()
+    }
+
+    /// Stops every task spawned under the current identity and arms a fresh token so
+    /// tasks for the next identity never overlap with stale ones.
+    fn cancel_identity_tasks(&mut self) {
+        self.identity_tasks.cancel();
+        self.identity_tasks = CancellationToken::new();
+    }
+
+    fn instance_mut(&mut self) -> Result<&mut Taior> {
+        self.instance.as_mut().ok_or_else(|| HushError::NotInitialized.into())
+    }
+
+    /// Starts Taior with the stored identity if there is one, otherwise with a new
+    /// identity that is stored for the next run.
+    pub fn init(&mut self, config: TaiorConfig) -> Result<String> {
+        let config = config.validated()?;
+        let stored = match &self.identity_store {
+            Some(store) => store.load()?,
+            None => None,
+        };
+
+        self.cancel_identity_tasks();
+        let taior = match &stored {
+            Some(identity) => restore_taior(&config, identity)?,
+            None => {
+                let taior = build_taior(&config);
+                self.persist_identity(&taior);
+                taior
+            }
+        };
+
+        let address = taior.address().to_string();
+        self.instance = Some(taior);
+        self.config = Some(config);
+
+        if stored.is_some() {
+            tracing::info!("Taior initialized with restored address: {}", address);
+        } else {
+            tracing::info!("Taior initialized with address: {}", address);
+        }
+        Ok(address)
+    }
+
+    /// Saves `taior`'s identity if a store is attached. A failure only costs the
+    /// identity on the next restart, so it is logged rather than failing the caller.
+    fn persist_identity(&self, taior: &Taior) {
+        if let Some(store) = &self.identity_store {
+            if let Err(e) = store.save(&taior.export_identity()) {
+                tracing::error!("Failed to persist Taior identity: {:#}", e);
+            }
+        }
+    }
+
+    /// Deletes the stored identity and resets, so the next `init` starts with a new
+    /// address. Returns whether a stored identity existed.
+    pub fn reset_identity(&mut self) -> Result<bool> {
+        let wiped = match &self.identity_store {
+            Some(store) => store.wipe()?,
+            None => false,
+        };
+        self.reset();
+
+        tracing::info!("Taior identity wiped");
+        Ok(wiped)
+    }
+
+    /// Routes `payload` through AORP and serializes the packet as
+    /// `[4 bytes payload_len] [encrypted_payload] [ikm]`, the same format as wasm.rs
+    /// `send()`, followed by random padding up to the smallest bucket that fits. The
+    /// IKM has a fixed length, so receivers ignore whatever follows it. Returns the
+    /// packet together with the routing time. `recipient` must already be validated
+    /// with [`parse_recipient`].
+    #[tracing::instrument(skip_all, fields(mode = %mode, bytes = payload.len()))]
+    pub fn send(&mut self, payload: &[u8], mode: &str, recipient: &str) -> Result<(Vec<u8>, SendTiming)> {
+        let options = send_options(parse_routing_mode(mode)?).with_recipient(recipient.to_string());
+        let taior = self.instance_mut()?;
+
+        let started = Instant::now();
+        let packet = taior.send(payload, options)
+            .map_err(|e| HushError::Routing(format!("AORP routing failed: {}", e)))?;
+        let routing_us = started.elapsed().as_micros() as u64;
+
+        tracing::debug!(
+            "Message routed via AORP - size: {} bytes",
+            packet.size()
+        );
+
+        let payload_len = packet.encrypted_payload.len() as u32;
+        let mut result = Vec::with_capacity(4 + packet.encrypted_payload.len() + packet.ikm.len());
+        result.extend_from_slice(&payload_len.to_be_bytes());
+        result.extend_from_slice(&packet.encrypted_payload);
+        result.extend_from_slice(&packet.ikm);
+        pad_to_bucket(&mut result, &self.padding_buckets)?;
+
+        let timing = SendTiming {
+            routing_us: Some(routing_us),
+            total_us: routing_us,
+            ..Default::default()
+        };
+        Ok((result, timing))
+    }
+
+    /// Routes `payload` under a fresh UUID v4 message id as one packet, or, when it
+    /// exceeds the max payload, as one packet per chunk frame in order. Chunks carry
+    /// the message id, and the receiver reassembles them with
+    /// [`chunking::Reassembler`]. Routing time is summed over all packets. Fails with
+    /// [`HushError::InvalidInput`] before routing anything if `recipient` is not a
+    /// Taior address.
+    #[tracing::instrument(skip_all, fields(mode = %mode, bytes = payload.len()))]
+    pub fn send_chunked(
+        &mut self,
+        payload: &[u8],
+        mode: &str,
+        recipient: &str,
+    ) -> Result<(TaiorSendResult, SendTiming)> {
+        let recipient = parse_recipient(recipient)?;
+        let id = uuid::Uuid::new_v4();
+        if payload.len() <= self.max_payload {
+            let (packet, timing) = self.send(payload, mode, &recipient)?;
+            let result = TaiorSendResult {
+                id: id.to_string(),
+                recipient,
+                packets: vec![packet],
+            };
+            return Ok((result, timing));
+        }
+
+        let frames = chunking::split(payload, self.max_payload, *id.as_bytes())?;
+        let mut packets = Vec::with_capacity(frames.len());
+        let mut routing_us = 0;
+        for frame in &frames {
+            let (packet, timing) = self.send(frame, mode, &recipient)?;
+            routing_us += timing.routing_us.unwrap_or(0);
+            packets.push(packet);
+        }
+        tracing::debug!("Split {} byte payload into {} chunks", payload.len(), packets.len());
+
+        let timing = SendTiming {
+            routing_us: Some(routing_us),
+            total_us: routing_us,
+            ..Default::default()
+        };
+        Ok((TaiorSendResult { id: id.to_string(), recipient, packets }, timing))
+    }
+
+    /// Decrypts a packet in the [`Self::send`] format addressed to this identity and
+    /// returns the plaintext with the sender's Taior address. Padding after the IKM is
+    /// passed along and ignored by the library.
+    pub fn receive(&mut self, packet: &[u8]) -> Result<(Vec<u8>, String)> {
+        let (encrypted_payload, ikm) = split_packet(packet)?;
+        let taior = self.instance_mut()?;
+        let message = taior.receive(encrypted_payload, ikm)
+            .map_err(|e| HushError::Routing(format!("AORP decryption failed: {}", e)))?;
+        Ok((message.payload, message.sender.to_string()))
+    }
+
+    pub fn rotate_identity(&mut self) -> Result<String> {
+        let config = self.config.clone().ok_or(HushError::NotInitialized)?;
+
+        // Old tasks must observe cancellation before the new instance exists, otherwise a
+        // stale receive loop could emit events for the previous identity.
+        self.cancel_identity_tasks();
+        self.cover_streams.stop();
+        self.cover_scheduler.stop();
+
+        let mut taior = build_taior(&config);
+        if self.cover_traffic_enabled {
+            taior.enable_cover_traffic(true, self.cover_traffic_ratio);
+        }
+        self.persist_identity(&taior);
+        let address = taior.address().to_string();
+        self.instance = Some(taior);
+        if self.cover_traffic_enabled {
+            self.cover_streams.start();
+            self.cover_scheduler.start(self.cover_traffic_ratio);
+        }
+
+        tracing::info!("Taior identity rotated, new address: {}", address);
+        Ok(address)
+    }
+
+    pub fn reset(&mut self) {
+        self.cancel_identity_tasks();
+        self.instance = None;
+        self.config = None;
+        self.cover_streams.stop();
+        self.cover_scheduler.stop();
+        self.cover_traffic_enabled = false;
+
+        tracing::info!("Taior state reset");
+    }
+
+    /// Stops the cover traffic tasks and everything bound to the current identity
+    /// before the app exits. The instance and settings are left as they are.
+    pub fn shutdown(&mut self) {
+        self.cancel_identity_tasks();
+        self.cover_streams.stop();
+        self.cover_scheduler.stop();
+    }
+
+    /// Configuration of the last successful `init`.
+    pub fn config(&self) -> Option<&TaiorConfig> {
+        self.config.as_ref()
+    }
+
+    pub fn is_initialized(&self) -> bool {
+        self.instance.is_some()
+    }
+
+    /// Current cover traffic setting as `(enabled, ratio)`.
+    pub fn cover_traffic(&self) -> (bool, f32) {
+        (self.cover_traffic_enabled, self.cover_traffic_ratio)
+    }
+
+    pub fn cover_scheduler_running(&self) -> bool {
+        self.cover_scheduler.is_running()
+    }
+
+    pub fn address(&self) -> Result<String> {
+        let taior = self.instance.as_ref().ok_or(HushError::NotInitialized)?;
+        Ok(taior.address().to_string())
+    }
+
+    /// `ratio` must be a finite number in `[0.0, 1.0]`. Disabling ignores the ratio
+    /// and records it as zero, so the stored setting never claims cover that isn't sent.
+    pub fn enable_cover_traffic(&mut self, enabled: bool, ratio: f32) -> Result<()> {
+        validate_cover_ratio(ratio)?;
+        let ratio = if enabled { ratio } else { 0.0 };
+
+        self.instance_mut()?.enable_cover_traffic(enabled, ratio);
+        self.cover_traffic_enabled = enabled;
+        self.cover_traffic_ratio = ratio;
+        if enabled {
+            self.cover_streams.start();
+            self.cover_scheduler.start(ratio);
+        } else {
+            self.cover_streams.stop();
+            self.cover_scheduler.stop();
         }
+
+        tracing::info!("Cover traffic: enabled={}, ratio={}", enabled, ratio);
+        Ok(())
+    }
+
+    pub fn set_cover_streams(&mut self, count: usize) -> Result<()> {
+        self.cover_streams.set_count(count)?;
+        tracing::info!("Cover streams: {}", count);
+        Ok(())
+    }
+
+    /// Configured stream count, and how many are running (zero while disabled).
+    pub fn cover_streams(&self) -> CoverStreamStatus {
+        self.cover_streams.status()
     }
+
+    pub fn set_cover_destination(&mut self, policy: CoverDestinationPolicy) -> Result<()> {
+        if let CoverDestinationPolicy::DecoySet(ids) = &policy {
+            if ids.is_empty() {
+                anyhow::bail!("Decoy set must contain at least one relay id");
+            }
+        }
+
+        tracing::info!("Cover traffic destination: {:?}", policy);
+        self.cover_scheduler.set_destination(policy.clone());
+        self.cover_destination = policy;
+        Ok(())
+    }
+
+    pub fn cover_destination(&self) -> &CoverDestinationPolicy {
+        &self.cover_destination
+    }
+
+    /// Replaces the padding bucket sizes. Packets larger than the biggest bucket are
+    /// rejected, so it bounds the payload size as well.
+    pub fn set_padding_buckets(&mut self, mut buckets: Vec<usize>) -> Result<()> {
+        buckets.sort_unstable();
+        buckets.dedup();
+        match buckets.first() {
+            None => {
+                return Err(HushError::InvalidInput("At least one padding bucket is required".to_string()).into());
+            }
+            Some(&smallest) if smallest <= 4 => {
+                return Err(HushError::InvalidInput(format!(
+                    "Padding buckets must be larger than the 4 byte length prefix, got {}",
+                    smallest
+                ))
+                .into());
+            }
+            Some(_) => {}
+        }
+
+        tracing::info!("Padding buckets: {:?}", buckets);
+        self.padding_buckets = buckets;
+        Ok(())
+    }
+
+    pub fn padding_buckets(&self) -> &[usize] {
+        &self.padding_buckets
+    }
+
+    /// Payloads above `max_payload` bytes are chunked by [`Self::send_chunked`]. Must
+    /// leave room for data after the chunk header.
+    pub fn set_max_payload(&mut self, max_payload: usize) -> Result<()> {
+        if max_payload <= chunking::CHUNK_HEADER_LEN {
+            return Err(HushError::InvalidInput(format!(
+                "Max payload must be larger than the {} byte chunk header, got {}",
+                chunking::CHUNK_HEADER_LEN,
+                max_payload
+            ))
+            .into());
+        }
+        tracing::info!("Max payload per packet: {} bytes", max_payload);
+        self.max_payload = max_payload;
+        Ok(())
+    }
+
+    pub fn max_payload(&self) -> usize {
+        self.max_payload
+    }
+
+    /// Largest payload length [`Self::benchmark_modes`] and [`Self::estimate_mode`]
+    /// accept: [`MAX_PLANNED_CHUNKS`] of the largest padding bucket.
+    pub fn max_planned_payload(&self) -> usize {
+        self.padding_buckets.last().copied().unwrap_or(0).saturating_mul(MAX_PLANNED_CHUNKS)
+    }
+
+    /// Plans a `payload_len` send through every mode on the relays `discovery` knows.
+    /// `relay_rtt_ms` is the round trip measured on the live relay connection.
+    pub fn benchmark_modes(
+        &mut self,
+        payload_len: usize,
+        discovery: &RelayDiscovery,
+        relay_rtt_ms: Option<u64>,
+    ) -> Result<Vec<ModeBenchmark>> {
+        let mut results = Vec::with_capacity(MODE_PROFILES.len());
+        for profile in MODE_PROFILES {
+            let plan = self.plan_mode(profile, payload_len, discovery, relay_rtt_ms)?;
+            results.push(ModeBenchmark {
+                mode: profile.mode.to_string(),
+                estimated_latency_ms: plan.latency_ms,
+                hop_count: plan.hop_count,
+                packet_size: plan.packet_size,
+                overhead_bytes: plan.packet_size.saturating_sub(payload_len),
+                planned_hops: plan.hops,
+            });
+        }
+        Ok(results)
+    }
+
+    /// Estimates `mode` for a `payload_len` payload, planned like
+    /// [`Self::benchmark_modes`].
+    pub fn estimate_mode(
+        &mut self,
+        mode: &str,
+        payload_len: usize,
+        discovery: &RelayDiscovery,
+        relay_rtt_ms: Option<u64>,
+    ) -> Result<ModeEstimate> {
+        let profile = mode_profile(mode)?;
+        let plan = self.plan_mode(profile, payload_len, discovery, relay_rtt_ms)?;
+        let (cover_enabled, cover_ratio) = self.cover_traffic();
+        let cover_overhead_bytes = if cover_enabled {
+            (plan.packet_size as f32 * cover_ratio.max(0.0)) as usize
+        } else {
+            0
+        };
+
+        Ok(ModeEstimate {
+            mode: mode.to_string(),
+            hop_count: plan.hop_count,
+            estimated_latency_ms: plan.latency_ms,
+            packet_size: plan.packet_size,
+            padding_overhead_bytes: plan.packet_size.saturating_sub(payload_len),
+            cover_overhead_bytes,
+            anonymity_score: profile.anonymity,
+        })
+    }
+
+    /// Routes a `payload_len` probe through the mode the way [`Self::send_chunked`]
+    /// would, one packet per chunk, and picks the mode's hops from `discovery`. The
+    /// packets are built locally and never handed to the transport, so planning puts
+    /// no traffic on the wire. When discovery can't fill the circuit, the hops are
+    /// costed at `relay_rtt_ms`. Fails with [`HushError::InvalidInput`] before
+    /// allocating anything when `payload_len` exceeds [`Self::max_planned_payload`].
+    fn plan_mode(
+        &mut self,
+        profile: &ModeProfile,
+        payload_len: usize,
+        discovery: &RelayDiscovery,
+        relay_rtt_ms: Option<u64>,
+    ) -> Result<ModePlan> {
+        let limit = self.max_planned_payload();
+        if payload_len > limit {
+            return Err(HushError::InvalidInput(format!(
+                "Payload length {} exceeds the {} bytes a mode can be planned for",
+                payload_len, limit
+            ))
+            .into());
+        }
+        let probe = vec![0u8; payload_len];
+        let frames = if payload_len <= self.max_payload {
+            vec![probe]
+        } else {
+            chunking::split(&probe, self.max_payload, *uuid::Uuid::nil().as_bytes())?
+        };
+
+        let buckets = self.padding_buckets.clone();
+        let taior = self.instance_mut()?;
+        let mut packet_size = 0;
+        for frame in &frames {
+            let packet = taior.send(frame, send_options(parse_routing_mode(profile.mode)?))
+                .map_err(|e| HushError::Routing(format!("AORP routing failed: {}", e)))?;
+            let unpadded = 4 + packet.encrypted_payload.len() + packet.ikm.len();
+            packet_size += bucket_for(unpadded, &buckets).unwrap_or(unpadded);
+        }
+
+        let hops = match RelayCircuit::build_from(discovery, profile.strategy, profile.constraints, profile.hops as usize) {
+            Ok(circuit) => circuit.get_hops().to_vec(),
+            Err(e) => {
+                tracing::debug!("Estimating {} without planned hops: {:#}", profile.mode, e);
+                Vec::new()
+            }
+        };
+        Ok(ModePlan {
+            hop_count: profile.hops,
+            latency_ms: path_latency_ms(&hops, profile.hops, profile.mix_delay_ms, packet_size, relay_rtt_ms),
+            hops: hops.into_iter().map(|hop| hop.id).collect(),
+            packet_size,
+        })
+    }
+}
+
+/// Smallest of `buckets` (sorted ascending) that holds `len` bytes.
+fn bucket_for(len: usize, buckets: &[usize]) -> Option<usize> {
+    buckets.iter().copied().find(|&bucket| bucket >= len)
+}
+
+/// Pads `packet` with random bytes to the smallest bucket that fits it. A packet larger
+/// than every bucket is rejected rather than sent with a revealing size.
+pub fn pad_to_bucket(packet: &mut Vec<u8>, buckets: &[usize]) -> Result<()> {
+    use rand::RngCore;
+
+    let Some(bucket) = bucket_for(packet.len(), buckets) else {
+        return Err(HushError::InvalidInput(format!(
+            "Packet of {} bytes exceeds the largest padding bucket of {} bytes",
+            packet.len(),
+            buckets.last().copied().unwrap_or(0)
+        ))
+        .into());
+    };
+
+    let unpadded = packet.len();
+    packet.resize(bucket, 0);
+    rand::thread_rng().fill_bytes(&mut packet[unpadded..]);
+    Ok(())
+}
+
+/// Splits a packet in the [`TaiorState::send`] format into its encrypted payload and
+/// IKM (plus any padding), rejecting packets whose length prefix doesn't fit the bytes
+/// received.
+pub fn split_packet(packet: &[u8]) -> Result<(&[u8], &[u8])> {
+    if packet.len() < 4 {
+        anyhow::bail!("Packet of {} bytes is too short for its length prefix", packet.len());
+    }
+    let payload_len = u32::from_be_bytes([packet[0], packet[1], packet[2], packet[3]]) as usize;
+    let rest = &packet[4..];
+    if payload_len > rest.len() {
+        anyhow::bail!(
+            "Packet declares a {} byte payload but only {} bytes follow",
+            payload_len,
+            rest.len()
+        );
+    }
+    Ok(rest.split_at(payload_len))
+}
+
+/// Parses a mode string from [`ROUTING_MODES`]. Unknown modes fail with the list of
+/// valid ones.
+pub fn parse_routing_mode(mode: &str) -> Result<RoutingMode> {
+    match mode {
+        "fast" => Ok(RoutingMode::Fast),
+        "mix" | "reinforced" => Ok(RoutingMode::Mix),
+        "adaptive" => Ok(RoutingMode::Adaptive),
+        _ => {
+            let valid: Vec<&str> = ROUTING_MODES.iter().map(|(name, _)| *name).collect();
+            Err(HushError::InvalidInput(format!(
+                "Invalid routing mode: {} (expected one of {})",
+                mode,
+                valid.join(", ")
+            ))
+            .into())
+        }
+    }
+}
+
+/// Longest address body accepted after the `taior://` scheme.
+const MAX_RECIPIENT_LEN: usize = 128;
+
+/// Checks `recipient` is a Taior address, `taior://` followed by an alphanumeric body,
+/// and returns it trimmed with the scheme lowercased. `taior://unknown`, the frontend's
+/// placeholder when no address is available, is rejected.
+pub fn parse_recipient(recipient: &str) -> Result<String> {
+    let recipient = recipient.trim();
+    let body = recipient.get(..8)
+        .filter(|scheme| scheme.eq_ignore_ascii_case("taior://"))
+        .map(|_| &recipient[8..]);
+    let valid = body.is_some_and(|body| {
+        !body.is_empty()
+            && body.len() <= MAX_RECIPIENT_LEN
+            && body != "unknown"
+            && body.chars().all(|c| c.is_ascii_alphanumeric())
+    });
+    match body {
+        Some(body) if valid => Ok(format!("taior://{}", body)),
+        _ => Err(HushError::InvalidInput(format!(
+            "Recipient must be a Taior address (taior://<address>), got {:?}",
+            recipient
+        ))
+        .into()),
+    }
+}
+
+fn send_options(mode: RoutingMode) -> SendOptions {
+    match mode {
+        RoutingMode::Fast => SendOptions::fast(),
+        RoutingMode::Mix => SendOptions::mix(),
+        RoutingMode::Adaptive => SendOptions::adaptive(),
+    }
+}
+
+fn validate_cover_ratio(ratio: f32) -> Result<()> {
+    if !ratio.is_finite() || !(0.0..=1.0).contains(&ratio) {
+        return Err(HushError::InvalidInput(format!(
+            "Cover traffic ratio must be between 0.0 and 1.0, got {}",
+            ratio
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+fn is_host_port(node: &str) -> bool {
+    let Some((host, port)) = node.rsplit_once(':') else {
+        return false;
+    };
+    if !port.parse::<u16>().is_ok_and(|port| port != 0) {
+        return false;
+    }
+    if let Some(ip) = host.strip_prefix('[').and_then(|h| h.strip_suffix(']')) {
+        return ip.parse::<std::net::Ipv6Addr>().is_ok();
+    }
+    is_hostname(host)
+}
+
+/// Also accepts IPv4 literals, which are valid hostnames character-wise.
+fn is_hostname(host: &str) -> bool {
+    !host.is_empty()
+        && host.len() <= 253
+        && host.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+/// `/<protocol>/<value>/...` starting with an address component whose value parses,
+/// followed by protocol/value pairs; a trailing protocol without value (`/quic`) is
+/// allowed.
+fn is_multiaddr(node: &str) -> bool {
+    let mut parts = node[1..].split('/');
+    let valid_address = match (parts.next(), parts.next()) {
+        (Some("ip4"), Some(ip)) => ip.parse::<std::net::Ipv4Addr>().is_ok(),
+        (Some("ip6"), Some(ip)) => ip.parse::<std::net::Ipv6Addr>().is_ok(),
+        (Some("dns" | "dns4" | "dns6"), Some(host)) => is_hostname(host),
+        _ => false,
+    };
+    if !valid_address {
+        return false;
+    }
+    let rest: Vec<&str> = parts.collect();
+    rest.chunks(2).all(|pair| match pair {
+        ["udp" | "tcp"] => false,
+        ["udp" | "tcp", port] => port.parse::<u16>().is_ok_and(|port| port != 0),
+        [protocol] | [protocol, _] => {
+            !protocol.is_empty() && protocol.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        }
+        _ => false,
+    })
+}
+
+fn build_taior(config: &TaiorConfig) -> Taior {
+    if config.bootstrap_nodes.is_empty() {
+        Taior::new()
+    } else {
+        Taior::with_bootstrap(config.bootstrap_nodes.clone())
+    }
+}
+
+fn restore_taior(config: &TaiorConfig, identity: &[u8]) -> Result<Taior> {
+    Taior::from_identity(identity, config.bootstrap_nodes.clone())
+        .map_err(|e| anyhow::anyhow!("Stored Taior identity is unusable: {}", e))
 }
 
 #[tauri::command]
 pub async fn taior_init(
     config: TaiorConfig,
-    state: State<'_, Arc<RwLock<TaiorState>>>,
-) -> Result<String, String> {
-    let mut taior_state = state.write().await;
-    
-    let taior = if config.bootstrap_nodes.is_empty() {
-        Taior::new()
-    } else {
-        Taior::with_bootstrap(config.bootstrap_nodes)
-    };
-    
-    let address = taior.address().to_string();
-    taior_state.instance = Some(taior);
-    
-    tracing::info!("Taior initialized with address: {}", address);
-    Ok(address)
+    state: State<'_, Arc<SharedState<TaiorState>>>,
+) -> Result<String, HushError> {
+    state.write().await?.init(config).map_err(HushError::from)
 }
 
+/// Deletes the persisted identity; the next `taior_init` creates a new address.
+#[tauri::command]
+pub async fn taior_reset_identity(
+    state: State<'_, Arc<SharedState<TaiorState>>>,
+) -> Result<bool, HushError> {
+    state.write().await?.reset_identity().map_err(HushError::from)
+}
+
+/// Routes `payload` to the Taior address `recipient` and returns its message id, the
+/// recipient and the packets: one, or one per chunk in order when the payload exceeds
+/// the max payload. With `store_ttl_secs`, the packets
+/// are also handed to the connected relay for store-and-forward (so the caller must not
+/// send them again) and a `message-delivery` event per packet, tagged with the message
+/// id, reports whether it was delivered live or stored.
 #[tauri::command]
 pub async fn taior_send(
     payload: Vec<u8>,
     mode: String,
-    state: State<'_, Arc<RwLock<TaiorState>>>,
-) -> Result<Vec<u8>, String> {
-    let mut taior_state = state.write().await;
-    
-    let taior = taior_state.instance.as_mut()
-        .ok_or_else(|| "Taior not initialized".to_string())?;
-    
-    let routing_mode = match mode.as_str() {
-        "fast" => RoutingMode::Fast,
-        "mix" | "reinforced" => RoutingMode::Mix,
-        "adaptive" => RoutingMode::Adaptive,
-        _ => return Err(format!("Invalid routing mode: {}", mode)),
-    };
-    
-    let options = match routing_mode {
-        RoutingMode::Fast => SendOptions::fast(),
-        RoutingMode::Mix => SendOptions::mix(),
-        RoutingMode::Adaptive => SendOptions::adaptive(),
-    };
-    
-    let packet = taior.send(&payload, options)
-        .map_err(|e| format!("AORP routing failed: {}", e))?;
-    
-    tracing::debug!(
-        "Message routed via AORP - size: {} bytes", 
-        packet.size()
-    );
-    
-    // Serialize: [4 bytes payload_len] [encrypted_payload] [ikm]
-    // Same format as wasm.rs send() for consistency
-    let payload_len = packet.encrypted_payload.len() as u32;
-    let mut result = Vec::with_capacity(4 + packet.encrypted_payload.len() + packet.ikm.len());
-    result.extend_from_slice(&payload_len.to_be_bytes());
-    result.extend_from_slice(&packet.encrypted_payload);
-    result.extend_from_slice(&packet.ikm);
-    
+    recipient: String,
+    store_ttl_secs: Option<u64>,
+    app: AppHandle,
+    state: State<'_, Arc<SharedState<TaiorState>>>,
+    transport: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<TaiorSendResult, HushError> {
+    let (result, timing) = state.write().await?
+        .send_chunked(&payload, &mode, &recipient)
+        .map_err(HushError::from)?;
+
+    if let Err(e) = app.emit("send-timing", timing) {
+        tracing::debug!("Failed to emit send-timing: {}", e);
+    }
+
+    if let Some(ttl_secs) = store_ttl_secs {
+        let transport = transport.read().await?;
+        for packet in &result.packets {
+            let outcome = transport.send_store_forward(packet, ttl_secs)
+                .await
+                .map_err(HushError::from)?;
+            let report = DeliveryReport {
+                message_id: result.id.clone(),
+                packet_size: packet.len(),
+                outcome,
+            };
+            if let Err(e) = app.emit("message-delivery", report) {
+                tracing::debug!("Failed to emit message-delivery: {}", e);
+            }
+        }
+    }
     Ok(result)
 }
 
+/// Rotates the identity, then moves the default relay onto a fresh connection so the
+/// new identity's traffic doesn't share one with the old.
+#[tauri::command]
+pub async fn taior_rotate_identity(
+    state: State<'_, Arc<SharedState<TaiorState>>>,
+    transport: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<String, HushError> {
+    let address = state.write().await?.rotate_identity().map_err(HushError::from)?;
+    if let Err(e) = transport.write().await?.rekey().await {
+        tracing::warn!("Relay connection kept across identity rotation: {:#}", e);
+    }
+    Ok(address)
+}
+
+#[tauri::command]
+pub async fn taior_reset(
+    state: State<'_, Arc<SharedState<TaiorState>>>,
+) -> Result<(), HushError> {
+    state.write().await?.reset();
+    Ok(())
+}
+
 #[tauri::command]
 pub async fn taior_address(
-    state: State<'_, Arc<RwLock<TaiorState>>>,
-) -> Result<String, String> {
-    let taior_state = state.read().await;
-    
-    let taior = taior_state.instance.as_ref()
-        .ok_or_else(|| "Taior not initialized".to_string())?;
-    
-    Ok(taior.address().to_string())
+    state: State<'_, Arc<SharedState<TaiorState>>>,
+) -> Result<String, HushError> {
+    state.read().await?.address().map_err(HushError::from)
 }
 
 #[tauri::command]
 pub async fn taior_enable_cover_traffic(
     enabled: bool,
     ratio: f32,
-    state: State<'_, Arc<RwLock<TaiorState>>>,
-) -> Result<(), String> {
-    let mut taior_state = state.write().await;
-    
-    let taior = taior_state.instance.as_mut()
-        .ok_or_else(|| "Taior not initialized".to_string())?;
-    
-    taior.enable_cover_traffic(enabled, ratio);
-    taior_state.cover_traffic_enabled = enabled;
-    taior_state.cover_traffic_ratio = ratio;
-    
-    tracing::info!("Cover traffic: enabled={}, ratio={}", enabled, ratio);
-    Ok(())
+    state: State<'_, Arc<SharedState<TaiorState>>>,
+) -> Result<(), HushError> {
+    state.write().await?
+        .enable_cover_traffic(enabled, ratio)
+        .map_err(HushError::from)
+}
+
+#[tauri::command]
+pub async fn taior_cover_traffic_status(
+    state: State<'_, Arc<SharedState<TaiorState>>>,
+) -> Result<CoverTrafficStatus, HushError> {
+    let taior = state.read().await?;
+    let (enabled, ratio) = taior.cover_traffic();
+    Ok(CoverTrafficStatus {
+        enabled,
+        ratio,
+        scheduler_running: taior.cover_scheduler_running(),
+    })
+}
+
+/// Cover packets and bytes sent so far, and the cover share of the last minute's
+/// sends next to the configured ratio.
+#[tauri::command]
+pub async fn cover_traffic_stats(
+    state: State<'_, Arc<SharedState<TaiorState>>>,
+    transport: State<'_, Arc<SharedState<QuicTransport>>>,
+) -> Result<CoverTrafficStats, HushError> {
+    let (_, configured_ratio) = state.read().await?.cover_traffic();
+    let stats = transport.read().await?.cover_stats();
+    Ok(stats.snapshot(configured_ratio))
+}
+
+#[tauri::command]
+pub async fn taior_set_padding_buckets(
+    buckets: Vec<usize>,
+    state: State<'_, Arc<SharedState<TaiorState>>>,
+) -> Result<(), HushError> {
+    state.write().await?
+        .set_padding_buckets(buckets)
+        .map_err(HushError::from)
+}
+
+#[tauri::command]
+pub async fn taior_padding_buckets(
+    state: State<'_, Arc<SharedState<TaiorState>>>,
+) -> Result<Vec<usize>, HushError> {
+    Ok(state.read().await?.padding_buckets().to_vec())
+}
+
+#[tauri::command]
+pub async fn taior_set_max_payload(
+    max_payload: usize,
+    state: State<'_, Arc<SharedState<TaiorState>>>,
+) -> Result<(), HushError> {
+    state.write().await?
+        .set_max_payload(max_payload)
+        .map_err(HushError::from)
+}
+
+#[tauri::command]
+pub async fn taior_max_payload(
+    state: State<'_, Arc<SharedState<TaiorState>>>,
+) -> Result<usize, HushError> {
+    Ok(state.read().await?.max_payload())
+}
+
+#[tauri::command]
+pub async fn taior_routing_modes() -> Result<Vec<RoutingModeInfo>, HushError> {
+    Ok(ROUTING_MODES.iter()
+        .map(|&(mode, description)| RoutingModeInfo { mode, description })
+        .collect())
+}
+
+/// Round trip measured on the live relay connection, if there is one.
+async fn relay_rtt_ms(transport: &SharedState<QuicTransport>) -> Result<Option<u64>, HushError> {
+    Ok(transport.read().await?
+        .connection_stats()
+        .map(|stats| stats.rtt_ms.round() as u64))
+}
+
+#[tauri::command]
+pub async fn benchmark_modes(
+    payload_len: usize,
+    state: State<'_, Arc<SharedState<TaiorState>>>,
+    transport: State<'_, Arc<SharedState<QuicTransport>>>,
+    discovery: State<'_, Arc<RwLock<RelayDiscovery>>>,
+) -> Result<Vec<ModeBenchmark>, HushError> {
+    let mut taior = state.write().await?;
+    let rtt_ms = relay_rtt_ms(&transport).await?;
+    let discovery = discovery.read().await;
+
+    taior.benchmark_modes(payload_len, &discovery, rtt_ms)
+        .map_err(HushError::from)
+}
+
+#[tauri::command]
+pub async fn estimate_mode(
+    mode: String,
+    payload_len: usize,
+    state: State<'_, Arc<SharedState<TaiorState>>>,
+    transport: State<'_, Arc<SharedState<QuicTransport>>>,
+    discovery: State<'_, Arc<RwLock<RelayDiscovery>>>,
+) -> Result<ModeEstimate, HushError> {
+    let mut taior = state.write().await?;
+    let rtt_ms = relay_rtt_ms(&transport).await?;
+    let discovery = discovery.read().await;
+
+    taior.estimate_mode(&mode, payload_len, &discovery, rtt_ms)
+        .map_err(HushError::from)
+}
+
+#[tauri::command]
+pub async fn taior_set_cover_destination(
+    policy: CoverDestinationPolicy,
+    state: State<'_, Arc<SharedState<TaiorState>>>,
+) -> Result<(), HushError> {
+    state.write().await?
+        .set_cover_destination(policy)
+        .map_err(HushError::from)
+}
+
+#[tauri::command]
+pub async fn taior_cover_destination(
+    state: State<'_, Arc<SharedState<TaiorState>>>,
+) -> Result<CoverDestinationPolicy, HushError> {
+    Ok(state.read().await?.cover_destination().clone())
+}
+
+#[tauri::command]
+pub async fn taior_set_cover_streams(
+    count: usize,
+    state: State<'_, Arc<SharedState<TaiorState>>>,
+) -> Result<(), HushError> {
+    state.write().await?
+        .set_cover_streams(count)
+        .map_err(HushError::from)
+}
+
+#[tauri::command]
+pub async fn taior_cover_streams(
+    state: State<'_, Arc<SharedState<TaiorState>>>,
+) -> Result<CoverStreamStatus, HushError> {
+    Ok(state.read().await?.cover_streams())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn taior() -> TaiorState {
+        let mut taior = TaiorState::new();
+        taior.init(TaiorConfig { bootstrap_nodes: Vec::new() }).unwrap();
+        taior
+    }
+
+    /// Discovery holding only `count` relays, each in its own network, with the same
+    /// measured latency.
+    fn network(count: usize, latency_ms: u64) -> RelayDiscovery {
+        let mut discovery = RelayDiscovery::new();
+        for known in discovery.get_available_relays(false) {
+            discovery.remove_relay(&known.id);
+        }
+        for i in 0..count {
+            discovery.add_relay(RelayNode {
+                id: format!("relay-{}", i),
+                address: format!("198.51.{}.1", i),
+                port: 4433,
+                public_key: String::new(),
+                latency_ms: Some(latency_ms),
+                bandwidth_mbps: Some(100),
+                connect_timeout_ms: None,
+                network: Some(format!("AS{}", 64500 + i)),
+            });
+        }
+        discovery
+    }
+
+    fn latency(results: &[ModeBenchmark], mode: &str) -> u64 {
+        results.iter().find(|r| r.mode == mode).unwrap().estimated_latency_ms
+    }
+
+    #[test]
+    fn benchmark_plans_every_mode_with_distinct_latencies() {
+        let mut taior = taior();
+        let discovery = network(6, 20);
+        let results = taior.benchmark_modes(512, &discovery, None).unwrap();
+
+        let modes: Vec<&str> = results.iter().map(|r| r.mode.as_str()).collect();
+        assert_eq!(modes, ["fast", "mix", "reinforced", "adaptive"]);
+        let mut latencies: Vec<u64> = results.iter().map(|r| r.estimated_latency_ms).collect();
+        latencies.sort_unstable();
+        latencies.dedup();
+        assert_eq!(latencies.len(), 4, "latencies not distinct: {:?}", results);
+        assert!(results.iter().all(|r| r.planned_hops.len() == r.hop_count as usize));
+    }
+
+    #[test]
+    fn benchmark_depends_on_payload_and_network() {
+        let mut taior = taior();
+        let fast_net = network(6, 20);
+        let small = taior.benchmark_modes(512, &fast_net, None).unwrap();
+        let large = taior.benchmark_modes(200_000, &fast_net, None).unwrap();
+        let slow = taior.benchmark_modes(512, &network(6, 80), None).unwrap();
+
+        for mode in ["fast", "mix", "reinforced", "adaptive"] {
+            assert!(latency(&large, mode) > latency(&small, mode), "{} ignores payload size", mode);
+            assert!(latency(&slow, mode) > latency(&small, mode), "{} ignores relay latency", mode);
+        }
+        assert!(large[0].packet_size > small[0].packet_size);
+
+        // Without enough relays to plan, hops are costed at the measured relay RTT
+        let unplanned = network(0, 0);
+        let near = taior.benchmark_modes(512, &unplanned, Some(10)).unwrap();
+        let far = taior.benchmark_modes(512, &unplanned, Some(200)).unwrap();
+        assert!(latency(&far, "fast") > latency(&near, "fast"));
+        assert!(near.iter().all(|r| r.planned_hops.is_empty()));
+    }
+
+    #[test]
+    fn mix_costs_more_hops_and_latency_than_fast() {
+        let mut taior = taior();
+        let discovery = network(6, 20);
+        let fast = taior.estimate_mode("fast", 1024, &discovery, Some(20)).unwrap();
+        let mix = taior.estimate_mode("mix", 1024, &discovery, Some(20)).unwrap();
+
+        assert!(mix.hop_count > fast.hop_count);
+        assert!(mix.estimated_latency_ms > fast.estimated_latency_ms);
+        assert!(mix.anonymity_score > fast.anonymity_score);
+        assert!(taior.estimate_mode("teleport", 1024, &discovery, None).is_err());
+    }
+
+    #[tokio::test]
+    async fn cover_ratio_must_be_finite_and_within_zero_to_one() {
+        let mut taior = taior();
+        for invalid in [f32::NAN, -0.5, 1.5, f32::INFINITY] {
+            let error = taior.enable_cover_traffic(true, invalid).unwrap_err();
+            assert!(matches!(error.downcast_ref(), Some(HushError::InvalidInput(_))), "{}: {:#}", invalid, error);
+        }
+        assert_eq!(taior.cover_traffic(), (false, 0.0));
+
+        for boundary in [0.0, 1.0] {
+            taior.enable_cover_traffic(true, boundary).unwrap();
+            assert_eq!(taior.cover_traffic(), (true, boundary));
+        }
+        // Disabling keeps no ratio around
+        taior.enable_cover_traffic(false, 0.7).unwrap();
+        assert_eq!(taior.cover_traffic(), (false, 0.0));
+    }
+
+    #[test]
+    fn lengths_in_one_bucket_pad_to_the_same_size() {
+        let mut short = vec![1u8; 300];
+        let mut long = vec![2u8; 500];
+        pad_to_bucket(&mut short, DEFAULT_PADDING_BUCKETS).unwrap();
+        pad_to_bucket(&mut long, DEFAULT_PADDING_BUCKETS).unwrap();
+        assert_eq!((short.len(), long.len()), (512, 512));
+        assert!(short[..300].iter().all(|&b| b == 1));
+
+        let mut exact = vec![0u8; 1024];
+        pad_to_bucket(&mut exact, DEFAULT_PADDING_BUCKETS).unwrap();
+        assert_eq!(exact.len(), 1024);
+
+        let mut oversized = vec![0u8; 65537];
+        let error = pad_to_bucket(&mut oversized, DEFAULT_PADDING_BUCKETS).unwrap_err();
+        assert!(matches!(error.downcast_ref(), Some(HushError::InvalidInput(_))), "{:#}", error);
+        assert_eq!(oversized.len(), 65537);
+    }
+
+    fn bootstrap(nodes: &[&str]) -> Result<Vec<String>> {
+        let config = TaiorConfig { bootstrap_nodes: nodes.iter().map(|n| n.to_string()).collect() };
+        Ok(config.validated()?.bootstrap_nodes)
+    }
+
+    #[test]
+    fn bootstrap_nodes_are_validated_and_deduplicated() {
+        let valid = bootstrap(&[
+            "relay.example.org:4433",
+            " 203.0.113.5:4433 ",
+            "",
+            "[2001:db8::1]:4433",
+            "/ip4/203.0.113.5/udp/4433/quic",
+            "/dns4/relay.example.org/udp/4433",
+        ])
+        .unwrap();
+        assert_eq!(
+            valid,
+            [
+                "relay.example.org:4433",
+                "203.0.113.5:4433",
+                "[2001:db8::1]:4433",
+                "/ip4/203.0.113.5/udp/4433/quic",
+                "/dns4/relay.example.org/udp/4433",
+            ]
+        );
+
+        for malformed in ["relay.example.org", "relay:0", "2001:db8::1:4433", "/ip4/999.0.0.1/udp/4433", "/udp/4433"] {
+            let error = bootstrap(&["relay.example.org:4433", malformed, "also bad"]).unwrap_err();
+            let Some(HushError::InvalidInput(message)) = error.downcast_ref() else {
+                panic!("{}: {:#}", malformed, error);
+            };
+            assert!(message.contains(&format!("{:?}", malformed)), "{} not named: {}", malformed, message);
+        }
+
+        let deduplicated = bootstrap(&["a.example:1", "b.example:2", "a.example:1", " b.example:2"]).unwrap();
+        assert_eq!(deduplicated, ["a.example:1", "b.example:2"]);
+    }
+
+    #[test]
+    fn send_targets_a_validated_recipient() {
+        let mut taior = taior();
+        for malformed in ["", "taior://", "taior://unknown", "http://abc123", "taior://abc 123", "abc123"] {
+            let error = taior.send_chunked(b"hello", "fast", malformed).unwrap_err();
+            assert!(matches!(error.downcast_ref(), Some(HushError::InvalidInput(_))), "{:?}: {:#}", malformed, error);
+        }
+
+        let (sent, _) = taior.send_chunked(b"hello", "fast", " TAIOR://Peer01 ").unwrap();
+        assert_eq!(sent.recipient, "taior://Peer01");
+        assert_eq!(sent.packets.len(), 1);
+
+        taior.set_max_payload(chunking::CHUNK_HEADER_LEN + 16).unwrap();
+        let (chunked, _) = taior.send_chunked(&[7u8; 100], "mix", "taior://peer02").unwrap();
+        assert_eq!(chunked.recipient, "taior://peer02");
+        assert!(chunked.packets.len() > 1);
+    }
+
+    #[test]
+    fn planning_rejects_payloads_past_the_chunk_limit() {
+        let mut taior = taior();
+        let discovery = network(6, 20);
+        let limit = taior.max_planned_payload();
+        assert_eq!(limit, 65536 * MAX_PLANNED_CHUNKS);
+
+        for payload_len in [limit + 1, usize::MAX] {
+            let error = taior.benchmark_modes(payload_len, &discovery, None).unwrap_err();
+            assert!(matches!(error.downcast_ref(), Some(HushError::InvalidInput(_))), "{:#}", error);
+            let error = taior.estimate_mode("mix", payload_len, &discovery, None).unwrap_err();
+            assert!(matches!(error.downcast_ref(), Some(HushError::InvalidInput(_))), "{:#}", error);
+        }
+        taior.estimate_mode("fast", 70_000, &discovery, None).unwrap();
+    }
 }
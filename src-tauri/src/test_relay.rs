@@ -0,0 +1,211 @@
+//! Minimal in-process QUIC relay that echoes everything back, so the transport can be
+//! exercised end to end without a real relay. Only built for unit tests and
+//! with the `test-relay` feature; release builds never contain it.
+//!
+//! Every unidirectional stream is read to the end and sent back on a new
+//! unidirectional stream, bidirectional streams are answered on the same stream and
+//! datagrams are returned as they came. The relay speaks none of the framed protocols
+//! (acks, store-and-forward, forwarding probes), so sends that wait for one of those
+//! time out against it; tests of those protocols pass their own handler to
+//! [`TestRelay::serve`].
+
+use anyhow::{Context, Result};
+use quinn::{Connection, Endpoint, ServerConfig};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use std::future::Future;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+use crate::cert_pins::{self, Fingerprint};
+use crate::close_codes::AppCloseCode;
+use crate::inbound::cert_fingerprint;
+use crate::quic_transport::{QuicTransport, RelayInfo};
+
+/// Relay id [`TestRelay::relay_info`] reports and [`TestRelay::pin`] pins under.
+pub const TEST_RELAY_ID: &str = "test-relay";
+
+/// Largest stream the relay reads before echoing it.
+const MAX_ECHO: usize = 1024 * 1024;
+
+pub struct TestRelay {
+    endpoint: Endpoint,
+    fingerprint: Fingerprint,
+    public_key: Vec<u8>,
+    shutdown: CancellationToken,
+}
+
+impl TestRelay {
+    /// Starts the relay on an ephemeral port on the IPv4 loopback with a fresh
+    /// self-signed certificate.
+    pub fn start() -> Result<Self> {
+        Self::serve(Duration::ZERO, echo)
+    }
+
+    /// Like [`Self::start`], but every connection is handed to `handler` instead of
+    /// being echoed, and each handshake is held back by `handshake_delay` first.
+    pub fn serve<H, F>(handshake_delay: Duration, handler: H) -> Result<Self>
+    where
+        H: Fn(Connection) -> F + Send + Sync + 'static,
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()])
+            .context("Failed to generate test relay certificate")?;
+        let cert_der = CertificateDer::from(cert.serialize_der()?);
+        let key_der = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(cert.serialize_private_key_der()));
+        let fingerprint = cert_fingerprint(&cert_der);
+        let public_key = cert.get_key_pair().public_key_raw().to_vec();
+
+        let server_config = ServerConfig::with_single_cert(vec![cert_der], key_der)
+            .context("Failed to build test relay server config")?;
+        let bind = SocketAddr::from((Ipv4Addr::LOCALHOST, 0));
+        let endpoint = Endpoint::server(server_config, bind)
+            .context("Failed to bind test relay")?;
+
+        let shutdown = CancellationToken::new();
+        tokio::spawn(accept_loop(endpoint.clone(), handshake_delay, Arc::new(handler), shutdown.clone()));
+
+        tracing::debug!("Test relay listening on {}", endpoint.local_addr()?);
+        Ok(Self {
+            endpoint,
+            fingerprint,
+            public_key,
+            shutdown,
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.endpoint.local_addr()?)
+    }
+
+    pub fn fingerprint(&self) -> Fingerprint {
+        self.fingerprint
+    }
+
+    /// Hex fingerprint in the format the pin file and `add_pin` use.
+    pub fn fingerprint_hex(&self) -> String {
+        cert_pins::format_fingerprint(&self.fingerprint)
+    }
+
+    /// Raw key of the certificate, the form a key-pinned `public_key` takes.
+    pub fn public_key(&self) -> &[u8] {
+        &self.public_key
+    }
+
+    /// What to pass to `connect_to_relay` to reach this relay.
+    pub fn relay_info(&self) -> Result<RelayInfo> {
+        let addr = self.local_addr()?;
+        Ok(RelayInfo {
+            id: Some(TEST_RELAY_ID.to_string()),
+            address: addr.ip().to_string(),
+            port: addr.port(),
+            public_key: None,
+            connect_timeout_ms: None,
+            server_name: None,
+        })
+    }
+
+    /// Pins the relay's certificate in `transport`, so the pinned verifier accepts it.
+    pub fn pin(&self, transport: &mut QuicTransport) {
+        transport.pins_mut().add(TEST_RELAY_ID, self.fingerprint);
+    }
+
+    pub fn stop(self) {
+        self.shutdown.cancel();
+        AppCloseCode::Shutdown.close_endpoint(&self.endpoint);
+    }
+}
+
+async fn accept_loop<H, F>(endpoint: Endpoint, handshake_delay: Duration, handler: Arc<H>, shutdown: CancellationToken)
+where
+    H: Fn(Connection) -> F + Send + Sync + 'static,
+    F: Future<Output = ()> + Send + 'static,
+{
+    loop {
+        let incoming = tokio::select! {
+            _ = shutdown.cancelled() => break,
+            incoming = endpoint.accept() => match incoming {
+                Some(incoming) => incoming,
+                None => break,
+            },
+        };
+
+        let shutdown = shutdown.clone();
+        let handler = handler.clone();
+        tokio::spawn(async move {
+            // The client retransmits its Initial until the relay gets round to it
+            tokio::time::sleep(handshake_delay).await;
+            match incoming.await {
+                Ok(connection) => tokio::select! {
+                    _ = shutdown.cancelled() => {}
+                    _ = handler(connection) => {}
+                },
+                Err(e) => tracing::debug!("Test relay handshake failed: {}", e),
+            }
+        });
+    }
+}
+
+async fn echo(connection: Connection) {
+    loop {
+        let result: Result<()> = tokio::select! {
+            stream = connection.accept_uni() => match stream {
+                Ok(mut recv) => async {
+                    let data = recv.read_to_end(MAX_ECHO).await?;
+                    let mut send = connection.open_uni().await?;
+                    send.write_all(&data).await?;
+                    send.finish()?;
+                    Ok::<(), anyhow::Error>(())
+                }
+                .await,
+                Err(_) => break,
+            },
+            stream = connection.accept_bi() => match stream {
+                Ok((mut send, mut recv)) => async {
+                    let data = recv.read_to_end(MAX_ECHO).await?;
+                    send.write_all(&data).await?;
+                    send.finish()?;
+                    Ok::<(), anyhow::Error>(())
+                }
+                .await,
+                Err(_) => break,
+            },
+            datagram = connection.read_datagram() => match datagram {
+                Ok(datagram) => connection.send_datagram(datagram).map_err(Into::into),
+                Err(_) => break,
+            },
+        };
+        if let Err(e) = result {
+            tracing::debug!("Test relay echo failed: {:#}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quic_transport::FinishMode;
+    use crate::send_queue::Priority;
+
+    #[tokio::test]
+    async fn echoes_a_send_back_to_the_transport() {
+        let relay = TestRelay::start().unwrap();
+        let mut transport = QuicTransport::new();
+        relay.pin(&mut transport);
+        transport.connect(relay.relay_info().unwrap()).await.unwrap();
+
+        let mut packet = 5u32.to_be_bytes().to_vec();
+        packet.extend_from_slice(b"hello");
+        packet.extend_from_slice(&[7; 32]);
+        transport.send(&packet, FinishMode::Finish, Priority::default()).await.unwrap();
+
+        let echoed = transport.recv(MAX_ECHO, Duration::from_secs(5)).await.unwrap();
+        assert_eq!(echoed, packet);
+
+        let summary = transport.disconnect().expect("a session to summarize");
+        assert_eq!(summary.messages_sent, 1);
+        assert!(transport.disconnect().is_none());
+        relay.stop();
+    }
+}
@@ -0,0 +1,185 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::State;
+
+use crate::error::HushError;
+
+/// Bytes a relay may burst at full speed after being idle, as a duration of its rate.
+const BURST: Duration = Duration::from_millis(250);
+
+/// Token bucket refilled at `rate` bytes per second up to a burst of [`BURST`] worth
+/// of bytes. A send larger than the tokens left is let through and leaves the bucket
+/// in debt, which later sends wait out, so the average rate holds for any packet size.
+#[derive(Debug, Clone)]
+struct TokenBucket {
+    kbps: u32,
+    rate: f64,
+    capacity: f64,
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    fn new(kbps: u32) -> Self {
+        let rate = f64::from(kbps) * 1000.0 / 8.0;
+        let capacity = rate * BURST.as_secs_f64();
+        Self {
+            kbps,
+            rate,
+            capacity,
+            tokens: capacity,
+            refilled_at: Instant::now(),
+        }
+    }
+
+    /// Takes `bytes` tokens and returns how long the caller must wait before sending.
+    fn reserve(&mut self, bytes: usize) -> Duration {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.refilled_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.refilled_at = now;
+
+        self.tokens -= bytes as f64;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.rate)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BandwidthLimit {
+    pub relay_id: String,
+    pub kbps: u32,
+}
+
+/// Per-relay upload limits, keyed by relay id (`address:port` for relays without
+/// one). Relays without a limit are unthrottled. Shared by the transport's send paths
+/// and the cover traffic tasks, so cover packets count against the same budget.
+#[derive(Clone, Default)]
+pub struct BandwidthLimits {
+    buckets: Arc<Mutex<HashMap<String, TokenBucket>>>,
+}
+
+impl BandwidthLimits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Caps uploads to `relay_id` at `kbps` kilobits per second, or removes the cap
+    /// when `kbps` is `None`.
+    pub fn set(&self, relay_id: &str, kbps: Option<u32>) -> anyhow::Result<()> {
+        if kbps == Some(0) {
+            return Err(HushError::InvalidInput(
+                "Bandwidth limit must be above 0 kbps; omit it to remove the limit".to_string(),
+            )
+            .into());
+        }
+        let mut buckets = self.buckets.lock()
+            .map_err(|_| anyhow::anyhow!("Bandwidth limits poisoned"))?;
+        match kbps {
+            Some(kbps) => {
+                buckets.insert(relay_id.to_string(), TokenBucket::new(kbps));
+                tracing::info!("Bandwidth to relay {} limited to {} kbps", relay_id, kbps);
+            }
+            None => {
+                buckets.remove(relay_id);
+                tracing::info!("Bandwidth limit for relay {} removed", relay_id);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn list(&self) -> Vec<BandwidthLimit> {
+        let Ok(buckets) = self.buckets.lock() else {
+            return Vec::new();
+        };
+        let mut limits: Vec<BandwidthLimit> = buckets.iter()
+            .map(|(relay_id, bucket)| BandwidthLimit {
+                relay_id: relay_id.clone(),
+                kbps: bucket.kbps,
+            })
+            .collect();
+        limits.sort_by(|a, b| a.relay_id.cmp(&b.relay_id));
+        limits
+    }
+
+    /// Waits until `relay_id`'s limit allows `bytes` more. Returns at once for relays
+    /// without a limit.
+    pub async fn acquire(&self, relay_id: &str, bytes: usize) {
+        let wait = match self.buckets.lock() {
+            Ok(mut buckets) => buckets.get_mut(relay_id)
+                .map(|bucket| bucket.reserve(bytes))
+                .unwrap_or(Duration::ZERO),
+            // Sending unthrottled beats failing the send
+            Err(_) => Duration::ZERO,
+        };
+        if !wait.is_zero() {
+            tracing::trace!("Throttling {} bytes to relay {} for {:?}", bytes, relay_id, wait);
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// `kbps` is in kilobits per second; omitting it removes the relay's limit.
+#[tauri::command]
+pub async fn set_relay_bandwidth_limit(
+    relay_id: String,
+    kbps: Option<u32>,
+    limits: State<'_, BandwidthLimits>,
+) -> Result<(), HushError> {
+    limits.set(&relay_id, kbps).map_err(HushError::from)
+}
+
+#[tauri::command]
+pub async fn list_relay_bandwidth_limits(
+    limits: State<'_, BandwidthLimits>,
+) -> Result<Vec<BandwidthLimit>, HushError> {
+    Ok(limits.list())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quic_transport::{FinishMode, QuicTransport};
+    use crate::send_queue::Priority;
+    use crate::test_relay::{TestRelay, TEST_RELAY_ID};
+
+    /// Sends ten 1000 byte messages and returns when each one was written.
+    async fn burst(transport: &QuicTransport) -> Vec<Duration> {
+        let started = Instant::now();
+        let mut written = Vec::new();
+        for _ in 0..10 {
+            transport.send(&[0u8; 1000], FinishMode::Finish, Priority::Normal).await.unwrap();
+            written.push(started.elapsed());
+        }
+        written
+    }
+
+    #[tokio::test]
+    async fn burst_is_spread_over_time_at_the_limit() {
+        let relay = TestRelay::start().unwrap();
+        let mut transport = QuicTransport::new();
+        relay.pin(&mut transport);
+        transport.connect(relay.relay_info().unwrap()).await.unwrap();
+
+        let unlimited = burst(&transport).await;
+        assert!(unlimited[9] < Duration::from_millis(300), "unthrottled burst took {:?}", unlimited[9]);
+
+        // 80 kbps is 10 000 bytes a second, with 2 500 of them available at once
+        transport.bandwidth().set(TEST_RELAY_ID, Some(80)).unwrap();
+        let limited = burst(&transport).await;
+        assert!(limited[1] < Duration::from_millis(150), "burst allowance not used: {:?}", limited);
+        assert!(limited[9] >= Duration::from_millis(700), "burst not spread: {:?}", limited);
+        assert!(limited[9] < Duration::from_millis(1_500), "throttled too hard: {:?}", limited);
+        assert!(limited.windows(2).skip(3).all(|w| w[1] - w[0] >= Duration::from_millis(50)), "{:?}", limited);
+
+        transport.bandwidth().set(TEST_RELAY_ID, None).unwrap();
+        assert!(transport.bandwidth().list().is_empty());
+        assert!(burst(&transport).await[9] < Duration::from_millis(300));
+        relay.stop();
+    }
+}
@@ -0,0 +1,212 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Every transport timeout in one place, in milliseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimeoutConfig {
+    /// Whole connect: endpoint setup plus the QUIC handshake.
+    pub connect_ms: u64,
+    /// The QUIC/TLS handshake alone.
+    pub handshake_ms: u64,
+    /// Writing one message to a stream.
+    pub stream_io_ms: u64,
+    /// Waiting for a relay's acknowledgement of a stream or transfer.
+    pub ack_ms: u64,
+    /// Waiting for a forwarding-probe answer.
+    pub probe_ms: u64,
+    /// Letting in-flight data drain before a connection or endpoint is closed.
+    pub drain_ms: u64,
+}
+
+impl Default for TimeoutConfig {
+    fn default() -> Self {
+        Self {
+            connect_ms: 10_000,
+            handshake_ms: 8_000,
+            stream_io_ms: 10_000,
+            ack_ms: 5_000,
+            probe_ms: 3_000,
+            drain_ms: 2_000,
+        }
+    }
+}
+
+impl TimeoutConfig {
+    pub fn validate(&self) -> Result<()> {
+        for (name, value) in [
+            ("connect_ms", self.connect_ms),
+            ("handshake_ms", self.handshake_ms),
+            ("stream_io_ms", self.stream_io_ms),
+            ("ack_ms", self.ack_ms),
+            ("probe_ms", self.probe_ms),
+            ("drain_ms", self.drain_ms),
+        ] {
+            if value == 0 {
+                anyhow::bail!("{} must be greater than zero", name);
+            }
+        }
+
+        if self.handshake_ms > self.connect_ms {
+            anyhow::bail!(
+                "handshake_ms ({}) cannot exceed connect_ms ({})",
+                self.handshake_ms,
+                self.connect_ms
+            );
+        }
+        if self.probe_ms > self.connect_ms {
+            anyhow::bail!(
+                "probe_ms ({}) cannot exceed connect_ms ({})",
+                self.probe_ms,
+                self.connect_ms
+            );
+        }
+        Ok(())
+    }
+
+    /// These timeouts with a relay's own connect timeout in place of `connect_ms`. The
+    /// handshake keeps the same share of the connect timeout, so a longer override
+    /// extends both and a shorter one shortens both.
+    pub fn for_relay(&self, connect_override_ms: Option<u64>) -> Self {
+        let Some(connect_ms) = connect_override_ms.filter(|ms| *ms > 0) else {
+            return *self;
+        };
+        let share = self.handshake_ms as f64 / self.connect_ms as f64;
+        Self {
+            connect_ms,
+            handshake_ms: ((connect_ms as f64 * share) as u64).clamp(1, connect_ms),
+            ..*self
+        }
+    }
+
+    pub fn connect(&self) -> Duration {
+        Duration::from_millis(self.connect_ms)
+    }
+
+    pub fn handshake(&self) -> Duration {
+        Duration::from_millis(self.handshake_ms)
+    }
+
+    pub fn stream_io(&self) -> Duration {
+        Duration::from_millis(self.stream_io_ms)
+    }
+
+    pub fn ack(&self) -> Duration {
+        Duration::from_millis(self.ack_ms)
+    }
+
+    pub fn probe(&self) -> Duration {
+        Duration::from_millis(self.probe_ms)
+    }
+
+    pub fn drain(&self) -> Duration {
+        Duration::from_millis(self.drain_ms)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::HushError;
+    use crate::quic_transport::QuicTransport;
+    use crate::relay_client::RelayNode;
+    use crate::send_queue::Priority;
+    use crate::test_relay::TestRelay;
+    use std::time::Instant;
+
+    fn short() -> TimeoutConfig {
+        TimeoutConfig { connect_ms: 2_000, handshake_ms: 200, ack_ms: 200, probe_ms: 150, ..TimeoutConfig::default() }
+    }
+
+    /// Fails unless `elapsed` shows the operation gave up at `limit` and not at some
+    /// other, longer timeout.
+    fn cut_off_at(elapsed: Duration, limit: Duration) {
+        assert!(elapsed >= limit, "gave up after {:?}, before {:?}", elapsed, limit);
+        assert!(elapsed < limit + Duration::from_millis(600), "gave up only after {:?}", elapsed);
+    }
+
+    #[test]
+    fn ordering_constraints_are_enforced() {
+        let mut transport = QuicTransport::new();
+        for invalid in [
+            TimeoutConfig { handshake_ms: 2_500, ..short() },
+            TimeoutConfig { probe_ms: 2_500, ..short() },
+            TimeoutConfig { drain_ms: 0, ..short() },
+        ] {
+            assert!(transport.set_timeouts(invalid).is_err());
+        }
+        assert_eq!(transport.timeouts(), TimeoutConfig::default());
+
+        transport.set_timeouts(short()).unwrap();
+        assert_eq!(transport.timeouts(), short());
+    }
+
+    #[tokio::test]
+    async fn configured_timeouts_bound_their_operations() {
+        let mut transport = QuicTransport::new();
+        transport.set_timeouts(short()).unwrap();
+
+        // Handshake: the relay holds its handshake back well past the limit
+        let slow = TestRelay::serve(Duration::from_secs(3), |connection| async move {
+            connection.closed().await;
+        })
+        .unwrap();
+        slow.pin(&mut transport);
+        let started = Instant::now();
+        assert!(transport.connect(slow.relay_info().unwrap()).await.is_err());
+        cut_off_at(started.elapsed(), short().handshake());
+
+        // Probe: the same relay, bounded by the shorter probe timeout
+        let started = Instant::now();
+        let probes = transport.probe_relays(&[("slow".to_string(), slow.relay_info().unwrap())]).await;
+        assert!(probes[0].latency_ms.is_none());
+        cut_off_at(started.elapsed(), short().probe());
+
+        // Ack: the relay takes the stream but never acknowledges it
+        let silent = TestRelay::serve(Duration::ZERO, |connection| async move {
+            connection.closed().await;
+        })
+        .unwrap();
+        silent.pin(&mut transport);
+        transport.connect(silent.relay_info().unwrap()).await.unwrap();
+        let started = Instant::now();
+        let error = transport.send_acked(None, b"unacked", Priority::Normal).await.unwrap_err();
+        assert!(matches!(error.downcast_ref(), Some(HushError::AckTimeout)), "{:#}", error);
+        cut_off_at(started.elapsed(), short().ack());
+
+        slow.stop();
+        silent.stop();
+    }
+
+    #[tokio::test]
+    async fn per_relay_override_outlasts_the_global_timeout() {
+        let distant = TestRelay::serve(Duration::from_millis(800), |connection| async move {
+            connection.closed().await;
+        })
+        .unwrap();
+        let mut transport = QuicTransport::new();
+        transport.set_timeouts(TimeoutConfig { connect_ms: 400, handshake_ms: 300, ..short() }).unwrap();
+        distant.pin(&mut transport);
+        let published = distant.relay_info().unwrap();
+        let node = |connect_timeout_ms| RelayNode {
+            id: published.pin_key(),
+            address: published.address.clone(),
+            port: published.port,
+            public_key: String::new(),
+            latency_ms: None,
+            bandwidth_mbps: None,
+            connect_timeout_ms,
+            network: None,
+        };
+
+        let started = Instant::now();
+        assert!(transport.connect(node(None).to_relay_info()).await.is_err());
+        cut_off_at(started.elapsed(), Duration::from_millis(300));
+
+        let started = Instant::now();
+        transport.connect(node(Some(3_000)).to_relay_info()).await.unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(800));
+        assert_eq!(transport.timeouts().connect_ms, 400);
+        distant.stop();
+    }
+}
@@ -50,6 +50,10 @@ export async function createTauriSession(
 
   await taior.enableCoverTraffic(true, 0.3);
 
+  // The relay fans room traffic out to every member, so it is addressed to our own
+  // identity rather than to a single peer.
+  const roomRecipient = await taior.address();
+
   const quicTransport = new QuicTransport();
   
   try {
@@ -65,8 +69,10 @@ export async function createTauriSession(
   const customTransport = {
     send: async (data: Uint8Array): Promise<void> => {
       const mode: TaiorRouteMode = 'mix';
-      const encrypted = await taior.send(data, mode);
-      await quicTransport.send(encrypted);
+      const { packets } = await taior.send(data, mode, roomRecipient);
+      for (const packet of packets) {
+        await quicTransport.send(packet);
+      }
     },
     onMessage: (callback: (data: Uint8Array, peerId: string) => void): void => {
       quicTransport.setupMessageListener((data) => {
@@ -0,0 +1,35 @@
+/** Error shape every Tauri command rejects with. */
+export type HushErrorKind =
+  | 'not_initialized'
+  | 'not_connected'
+  | 'invalid_address'
+  | 'invalid_input'
+  | 'quic_connect'
+  | 'cert_verification'
+  | 'routing'
+  | 'ack_timeout'
+  | 'queue_full'
+  | 'relay_blocked'
+  | 'state_poisoned'
+  | 'other';
+
+export interface HushError {
+  kind: HushErrorKind;
+  message: string;
+}
+
+export function isHushError(err: unknown): err is HushError {
+  return (
+    typeof err === 'object' &&
+    err !== null &&
+    typeof (err as HushError).kind === 'string' &&
+    typeof (err as HushError).message === 'string'
+  );
+}
+
+/** Human-readable text for anything a command or wrapper may throw. */
+export function errorMessage(err: unknown): string {
+  if (isHushError(err)) return err.message;
+  if (err instanceof Error) return err.message;
+  return String(err);
+}
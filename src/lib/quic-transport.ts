@@ -1,54 +1,155 @@
 import { invoke } from '@tauri-apps/api/core';
 import { listen, type UnlistenFn } from '@tauri-apps/api/event';
+import { errorMessage, isHushError } from './hush-error';
 
 export interface RelayInfo {
+  id?: string;
   address: string;
   port: number;
   public_key?: string;
+  connect_timeout_ms?: number;
+  /** TLS server name; defaults to `address` when it is a hostname. */
+  server_name?: string;
 }
 
 export interface RelayStatus {
   connected: boolean;
   relay_address?: string;
   latency_ms?: number;
+  relay_id?: string;
+  is_default: boolean;
 }
 
+export type MessagePriority = 'low' | 'normal' | 'high';
+
+export interface AckStatus {
+  acknowledged: boolean;
+  status: number;
+  message_id: string | null;
+  ack_us: number;
+}
+
+export interface SentStream {
+  stream_id: number;
+  bytes_written: number;
+}
+
+export interface SendResult {
+  kept_open: number | null;
+  stream: SentStream | null;
+  ack: AckStatus | null;
+  zero_rtt: boolean;
+  queued: string | null;
+}
+
+export interface SessionSummary {
+  relay_address?: string;
+  bytes_sent: number;
+  bytes_received: number;
+  duration_ms: number;
+  messages_sent: number;
+}
+
+/** Live counters of the active relay connection; bytes and datagrams are UDP-level. */
+export interface ConnectionStats {
+  bytes_sent: number;
+  bytes_received: number;
+  datagrams_sent: number;
+  datagrams_received: number;
+  congestion_window: number;
+  lost_packets: number;
+  sent_packets: number;
+  rtt_ms: number;
+}
+
+/** Payload of `relay-connected`, `relay-disconnected` and `relay-error`. */
+export interface RelayLifecycleEvent {
+  relay_address: string;
+  /** Set for `relay-error` and for disconnects the app did not initiate. */
+  error: string | null;
+}
+
+export type RelayLifecycleKind = 'relay-connected' | 'relay-disconnected' | 'relay-error';
+
 export class QuicTransport {
   private unlistenFn?: UnlistenFn;
+  private lifecycleUnlisteners: UnlistenFn[] = [];
 
-  async connectToRelay(relay: RelayInfo): Promise<string> {
+  async connectToRelay(relay: RelayInfo, makeDefault = false): Promise<string> {
     try {
-      const result = await invoke<string>('connect_to_relay', { relay });
+      const result = await invoke<string>('connect_to_relay', { relay, makeDefault });
       console.log('Connected to relay via QUIC:', result);
       return result;
     } catch (err) {
-      throw new Error(`Failed to connect to relay: ${err}`);
+      throw new Error(`Failed to connect to relay: ${errorMessage(err)}`);
     }
   }
 
-  async disconnect(): Promise<void> {
+  async disconnect(relayId?: string): Promise<SessionSummary | null> {
     try {
-      await invoke('disconnect_relay');
+      const summary = await invoke<SessionSummary | null>('disconnect_relay', { relayId });
       console.log('Disconnected from relay');
+      return summary;
     } catch (err) {
       console.error('Failed to disconnect:', err);
+      return null;
     }
   }
 
-  async send(data: Uint8Array): Promise<void> {
+  /**
+   * Moves traffic to `relay` without a connectivity gap: the current connection is
+   * closed only once the new one is up, and kept if `relay` is unreachable.
+   */
+  async switchRelay(relay: RelayInfo): Promise<SessionSummary | null> {
     try {
-      await invoke('send_via_quic', { data: Array.from(data) });
+      return await invoke<SessionSummary | null>('switch_relay', { relay });
     } catch (err) {
-      throw new Error(`Failed to send via QUIC: ${err}`);
+      throw new Error(`Failed to switch relay: ${errorMessage(err)}`);
+    }
+  }
+
+  /** With `requireAck`, rejects with kind `ack_timeout` if the relay never answers. */
+  async send(
+    data: Uint8Array,
+    priority: MessagePriority = 'normal',
+    relayId?: string,
+    requireAck = false
+  ): Promise<SendResult> {
+    try {
+      return await invoke<SendResult>('send_via_quic', {
+        data: Array.from(data),
+        relayId,
+        priority,
+        requireAck
+      });
+    } catch (err) {
+      // Passed through untouched so callers can retry on the kind
+      if (isHushError(err) && err.kind === 'ack_timeout') throw err;
+      throw new Error(`Failed to send via QUIC: ${errorMessage(err)}`);
     }
   }
 
   async getStatus(): Promise<RelayStatus> {
+    const statuses = await this.getAllStatus();
+    return statuses.find((status) => status.is_default) ?? { connected: false, is_default: true };
+  }
+
+  async getAllStatus(): Promise<RelayStatus[]> {
     try {
-      return await invoke<RelayStatus>('get_relay_status');
+      return await invoke<RelayStatus[]>('get_relay_status');
     } catch (err) {
       console.error('Failed to get relay status:', err);
-      return { connected: false };
+      return [];
+    }
+  }
+
+  /** `null` when no relay is connected. */
+  async getConnectionStats(): Promise<ConnectionStats | null> {
+    try {
+      return await invoke<ConnectionStats | null>('get_connection_stats');
+    } catch (err) {
+      console.error('Failed to get connection stats:', err);
+      return null;
     }
   }
 
@@ -58,27 +159,41 @@ export class QuicTransport {
     });
   }
 
+  async setupLifecycleListener(
+    callback: (kind: RelayLifecycleKind, event: RelayLifecycleEvent) => void
+  ): Promise<void> {
+    const kinds: RelayLifecycleKind[] = ['relay-connected', 'relay-disconnected', 'relay-error'];
+    for (const kind of kinds) {
+      this.lifecycleUnlisteners.push(
+        await listen<RelayLifecycleEvent>(kind, (event) => callback(kind, event.payload))
+      );
+    }
+  }
+
   cleanup(): void {
     if (this.unlistenFn) {
       this.unlistenFn();
     }
+    this.lifecycleUnlisteners.forEach((unlisten) => unlisten());
+    this.lifecycleUnlisteners = [];
   }
 }
 
-// Relay public keys must be the SHA-256 fingerprint of the relay's TLS certificate.
-// These are verified by PinnedCertVerifier on the Tauri/QUIC side.
-// In production, these MUST be replaced with real certificate fingerprints.
+// Relay public keys are the relay's Ed25519/ECDSA key, hex or base64 encoded, and are
+// matched against the certificate's SPKI by PinnedCertVerifier on the Tauri/QUIC side
+// when no certificate fingerprint is pinned for the relay.
+// In production, these MUST be replaced with the real relay keys.
 export const DEFAULT_RELAYS: RelayInfo[] = [
   {
     address: 'relay1.taior.net',
     port: 4433,
-    // TODO: Replace with real relay certificate SHA-256 fingerprint
+    // TODO: Replace with the real relay public key
     public_key: undefined
   },
   {
     address: 'relay2.taior.net',
     port: 4433,
-    // TODO: Replace with real relay certificate SHA-256 fingerprint
+    // TODO: Replace with the real relay public key
     public_key: undefined
   },
   {
@@ -1,11 +1,22 @@
 import { invoke } from '@tauri-apps/api/core';
 import { writable, type Readable } from 'svelte/store';
+import { errorMessage } from './hush-error';
 
 export type TaiorRouteMode = 'fast' | 'reinforced' | 'mix' | 'adaptive';
 
+/** A routed message: `id` tags its `message-delivery` events. */
+export interface TaiorSendResult {
+  id: string;
+  /** Taior address the packets are routed to. */
+  recipient: string;
+  /** One packet, or one per chunk in order when the payload exceeds the max payload. */
+  packets: Uint8Array[];
+}
+
 export type TaiorClient = {
   status: Readable<'disconnected' | 'connecting' | 'connected'>;
-  send: (payload: Uint8Array, mode: TaiorRouteMode) => Promise<Uint8Array>;
+  /** Rejects with an `invalid_input` error if `recipient` is not a `taior://` address. */
+  send: (payload: Uint8Array, mode: TaiorRouteMode, recipient: string) => Promise<TaiorSendResult>;
   disconnect: () => void;
   address: () => Promise<string>;
   enableCoverTraffic: (enabled: boolean, ratio: number) => Promise<void>;
@@ -32,27 +43,36 @@ export async function createTauriTaiorClient(config?: TaiorConfig): Promise<Taio
   } catch (err) {
     console.error('Failed to initialize Tauri Taior:', err);
     status.set('disconnected');
-    throw new Error(`Taior initialization failed: ${err}`);
+    throw new Error(`Taior initialization failed: ${errorMessage(err)}`);
   }
 
-  const send = async (payload: Uint8Array, mode: TaiorRouteMode): Promise<Uint8Array> => {
+  const send = async (
+    payload: Uint8Array,
+    mode: TaiorRouteMode,
+    recipient: string
+  ): Promise<TaiorSendResult> => {
     try {
       const modeStr = mode === 'reinforced' ? 'mix' : mode;
       
-      const result = await invoke<number[]>('taior_send', {
+      const result = await invoke<{ id: string; recipient: string; packets: number[][] }>('taior_send', {
         payload: Array.from(payload),
-        mode: modeStr
+        mode: modeStr,
+        recipient
       });
 
-      if (!result || result.length === 0) {
+      if (!result || result.packets.length === 0) {
         throw new Error('AORP routing returned empty result');
       }
 
-      console.log(`Message routed via AORP (${modeStr}): ${result.length} bytes`);
-      return new Uint8Array(result);
+      console.log(`Message ${result.id} routed via AORP (${modeStr}) to ${result.recipient}: ${result.packets.length} packets`);
+      return {
+        id: result.id,
+        recipient: result.recipient,
+        packets: result.packets.map((packet) => new Uint8Array(packet))
+      };
     } catch (err) {
       throw new Error(
-        `CRITICAL: AORP routing failed. Message NOT sent. ${err instanceof Error ? err.message : String(err)}`
+        `CRITICAL: AORP routing failed. Message NOT sent. ${errorMessage(err)}`
       );
     }
   };